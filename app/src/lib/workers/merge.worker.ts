@@ -23,11 +23,20 @@ const resolveBase = (basePath?: string): string => {
 };
 const toAbsoluteUrl = (url: string): string => new URL(url, self.location.origin).toString();
 
+// One engine input: file bytes plus the name reported in decode errors
+interface WasmInput {
+	data: Uint8Array;
+	name: string;
+	mimeType: string;
+}
+
 // WASM module interface
 interface WasmModule {
 	default: (path: string) => Promise<void>;
 	greet: () => string;
-	merge_images: (images: Uint8Array[], options: Record<string, unknown>) => Uint8Array;
+	merge_images: (images: WasmInput[], options: Record<string, unknown>) => Uint8Array;
+	// Only in the threaded build
+	initThreadPool?: (numThreads: number) => Promise<void>;
 }
 
 const isWasmModule = (value: unknown): value is WasmModule => {
@@ -47,26 +56,65 @@ const isWasmModule = (value: unknown): value is WasmModule => {
 let wasmModule: WasmModule | null = null;
 let initPromise: Promise<void> | null = null;
 
+// Smallest module using a SIMD instruction (i8x16.popcnt); it only validates
+// where WebAssembly SIMD is supported
+const SIMD_PROBE = new Uint8Array([
+	0, 97, 115, 109, 1, 0, 0, 0, 1, 5, 1, 96, 0, 1, 123, 3, 2, 1, 0, 10, 10, 1, 8, 0, 65, 0, 253, 15,
+	253, 98, 11
+]);
+const supportsSimd = (): boolean => WebAssembly.validate(SIMD_PROBE);
+
+/**
+ * Load and initialize the engine build in `dir` (e.g. `wasm/`).
+ */
+async function loadEngine(resolvedBase: string, dir: string): Promise<WasmModule> {
+	// Import the WASM module
+	const moduleUrl = toAbsoluteUrl(`${resolvedBase}${dir}merge_images_engine.js`);
+	const wasmUrl = toAbsoluteUrl(`${resolvedBase}${dir}merge_images_engine_bg.wasm`);
+	const module = (await import(/* @vite-ignore */ moduleUrl)) as unknown;
+
+	if (!isWasmModule(module)) {
+		throw new Error('Invalid WASM module shape');
+	}
+
+	// Initialize WASM with the .wasm file path
+	await module.default(wasmUrl);
+	return module;
+}
+
 /**
- * Initialize the WASM module (lazy, once).
+ * Load the threaded engine build and start its thread pool. Shared memory
+ * needs a cross-origin isolated page; returns null when the page is not, or
+ * when the threaded build is missing or fails to start.
+ */
+async function loadThreadedEngine(resolvedBase: string): Promise<WasmModule | null> {
+	if (!self.crossOriginIsolated) return null;
+	try {
+		const module = await loadEngine(resolvedBase, 'wasm-threads/');
+		if (!module.initThreadPool) return null;
+		await module.initThreadPool(navigator.hardwareConcurrency || 1);
+		return module;
+	} catch {
+		return null;
+	}
+}
+
+/**
+ * Initialize the WASM module (lazy, once), preferring the threaded build and
+ * falling back to the sequential one. Both use WebAssembly SIMD, so browsers
+ * without it get the scalar build in `wasm-nosimd/`.
  */
 async function ensureInitialized(basePath?: string): Promise<void> {
 	if (wasmModule) return;
 
 	initPromise ??= (async () => {
 		const resolvedBase = resolveBase(basePath);
-		// Import the WASM module
-		const moduleUrl = toAbsoluteUrl(`${resolvedBase}wasm/merge_images_engine.js`);
-		const wasmUrl = toAbsoluteUrl(`${resolvedBase}wasm/merge_images_engine_bg.wasm`);
-		const module = (await import(/* @vite-ignore */ moduleUrl)) as unknown;
-
-		if (!isWasmModule(module)) {
-			throw new Error('Invalid WASM module shape');
+		if (!supportsSimd()) {
+			wasmModule = await loadEngine(resolvedBase, 'wasm-nosimd/');
+			return;
 		}
-
-		// Initialize WASM with the .wasm file path
-		await module.default(wasmUrl);
-		wasmModule = module;
+		wasmModule =
+			(await loadThreadedEngine(resolvedBase)) ?? (await loadEngine(resolvedBase, 'wasm/'));
 	})();
 
 	await initPromise;
@@ -123,8 +171,12 @@ self.onmessage = async (event: MessageEvent<MergeRequest>) => {
 		// Read all files as ArrayBuffers
 		const buffers = await Promise.all(request.files.map((file) => file.arrayBuffer()));
 
-		// Convert to Uint8Array[]
-		const arrays = buffers.map((buf) => new Uint8Array(buf));
+		// Wrap each file's bytes with its name and type
+		const inputs: WasmInput[] = buffers.map((buf, i) => ({
+			data: new Uint8Array(buf),
+			name: request.files[i].name,
+			mimeType: request.files[i].type
+		}));
 
 		// Build options object for WASM
 		const wasmOptions: Record<string, unknown> = {
@@ -134,7 +186,7 @@ self.onmessage = async (event: MessageEvent<MergeRequest>) => {
 		};
 
 		// Call WASM merge function
-		const result = wasmModule.merge_images(arrays, wasmOptions);
+		const result = wasmModule.merge_images(inputs, wasmOptions);
 
 		// Get the underlying ArrayBuffer for transfer
 		const resultBuffer = new Uint8Array(result).buffer;
@@ -180,6 +232,9 @@ self.onmessage = async (event: MessageEvent<MergeRequest>) => {
 			if ('fileIndex' in err && typeof err.fileIndex === 'number') {
 				details.fileIndex = err.fileIndex;
 			}
+			if ('fileName' in err && typeof err.fileName === 'string') {
+				details.fileName = err.fileName;
+			}
 		} else if (error instanceof Error) {
 			response.message = error.message;
 		}
@@ -0,0 +1,141 @@
+//! Canvas background fills painted before any image is composited.
+//!
+//! The canvas starts as the solid `background` color, or a linear gradient
+//! when one is set; an optional background image is then blended over it,
+//! tiled from the top-left corner or stretched to cover the whole canvas
+//! (padding included).
+
+use image::{DynamicImage, Rgba, RgbaImage, imageops};
+
+use crate::scale::scale_image;
+use crate::types::{BackgroundFit, Gradient};
+
+/// Fills the whole canvas with a linear gradient.
+///
+/// Angles follow CSS `linear-gradient`: 0° runs bottom to top, 90° left to
+/// right, 180° top to bottom. The gradient line is long enough for the start
+/// and end colors to land exactly on the corners it points away from and to.
+pub fn paint_gradient(canvas: &mut RgbaImage, gradient: &Gradient) {
+    let (width, height) = canvas.dimensions();
+    let radians = (gradient.angle as f64).to_radians();
+    let (dx, dy) = (radians.sin(), -radians.cos());
+    let length = (width as f64 * dx).abs() + (height as f64 * dy).abs();
+    let (center_x, center_y) = (width as f64 / 2.0, height as f64 / 2.0);
+
+    let (start, end) = (gradient.start, gradient.end);
+    let mix = |a: u8, b: u8, t: f64| -> u8 {
+        (a as f64 + (b as f64 - a as f64) * t)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    for (x, y, pixel) in canvas.enumerate_pixels_mut() {
+        let t = if length > 0.0 {
+            let projected = (x as f64 + 0.5 - center_x) * dx + (y as f64 + 0.5 - center_y) * dy;
+            (projected / length + 0.5).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        *pixel = Rgba([
+            mix(start.r, end.r, t),
+            mix(start.g, end.g, t),
+            mix(start.b, end.b, t),
+            mix(start.a, end.a, t),
+        ]);
+    }
+}
+
+/// Blends `image` over the whole canvas according to `fit`.
+pub fn paint_image(canvas: &mut RgbaImage, image: &DynamicImage, fit: BackgroundFit) {
+    let (width, height) = canvas.dimensions();
+    if width == 0 || height == 0 || image.width() == 0 || image.height() == 0 {
+        return;
+    }
+    match fit {
+        BackgroundFit::Stretch => {
+            let stretched = scale_image(image, width, height).into_rgba8();
+            imageops::overlay(canvas, &stretched, 0, 0);
+        }
+        BackgroundFit::Tile => {
+            let tile = image.to_rgba8();
+            for y in (0..height).step_by(tile.height() as usize) {
+                for x in (0..width).step_by(tile.width() as usize) {
+                    imageops::overlay(canvas, &tile, x as i64, y as i64);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::BackgroundColor;
+
+    fn checker() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 2, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 0, 0, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_tile_repeats_from_top_left() {
+        let mut canvas = RgbaImage::from_pixel(5, 3, Rgba([255, 255, 255, 255]));
+        paint_image(&mut canvas, &checker(), BackgroundFit::Tile);
+        assert_eq!(*canvas.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*canvas.get_pixel(2, 2), Rgba([0, 0, 0, 255]));
+        assert_eq!(*canvas.get_pixel(4, 1), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_stretch_covers_canvas() {
+        let mut canvas = RgbaImage::from_pixel(8, 4, Rgba([255, 255, 255, 255]));
+        let blue = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([0, 0, 255, 255])));
+        paint_image(&mut canvas, &blue, BackgroundFit::Stretch);
+        assert!(canvas.pixels().all(|p| *p == Rgba([0, 0, 255, 255])));
+    }
+
+    #[test]
+    fn test_transparent_image_keeps_color_beneath() {
+        let mut canvas = RgbaImage::from_pixel(2, 2, Rgba([0, 255, 0, 255]));
+        let clear = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 0])));
+        paint_image(&mut canvas, &clear, BackgroundFit::Tile);
+        assert!(canvas.pixels().all(|p| *p == Rgba([0, 255, 0, 255])));
+    }
+
+    #[test]
+    fn test_gradient_top_to_bottom() {
+        let mut canvas = RgbaImage::new(4, 100);
+        paint_gradient(
+            &mut canvas,
+            &Gradient {
+                start: BackgroundColor::black(),
+                end: BackgroundColor::white(),
+                angle: 180.0,
+            },
+        );
+        assert!(canvas.get_pixel(0, 0)[0] < 5);
+        assert!(canvas.get_pixel(0, 99)[0] > 250);
+        assert_eq!(canvas.get_pixel(0, 50), canvas.get_pixel(3, 50));
+        assert!(canvas.get_pixel(0, 25)[0] < canvas.get_pixel(0, 75)[0]);
+    }
+
+    #[test]
+    fn test_gradient_left_to_right() {
+        let mut canvas = RgbaImage::new(100, 4);
+        paint_gradient(
+            &mut canvas,
+            &Gradient {
+                start: BackgroundColor::new(255, 0, 0, 255),
+                end: BackgroundColor::new(0, 0, 255, 255),
+                angle: 90.0,
+            },
+        );
+        assert!(canvas.get_pixel(0, 2)[0] > 250);
+        assert!(canvas.get_pixel(99, 2)[2] > 250);
+        assert_eq!(canvas.get_pixel(50, 0), canvas.get_pixel(50, 3));
+    }
+}
@@ -0,0 +1,431 @@
+//! Chrome-strip pre-pass for Smart merges.
+//!
+//! Many screenshot sources include repeated browser/app chrome (headers/footers)
+//! on every capture. This confuses overlap detection because these repeated bars
+//! can appear as strong matches. This module detects near-identical top/bottom
+//! regions between adjacent images and trims them so the overlap detector sees
+//! mostly content.
+
+use image::{DynamicImage, GrayImage, imageops::FilterType};
+
+use crate::simd::abs_diff;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChromeTrim {
+    /// Pixels to trim from the top of this image.
+    pub top: u32,
+    /// Pixels to trim from the bottom of this image.
+    pub bottom: u32,
+}
+
+/// Smallest proxy width; narrower images are compared at full resolution.
+const MIN_PROXY_WIDTH: u32 = 320;
+/// Largest proxy width chosen automatically, bounding the cost on very wide captures.
+const MAX_PROXY_WIDTH: u32 = 1280;
+/// Automatic proxies shrink by at most this factor, so thin separators on
+/// high-DPI captures are not smoothed into neighboring rows.
+const MAX_PROXY_DOWNSCALE: u32 = 3;
+const MARGIN_PERCENT: f32 = 0.025;
+
+const PIXEL_DELTA: u8 = 12;
+const ROW_MATCH_FRACTION: f32 = 0.97;
+const ROW_MEAN_ABS_DELTA_MAX: f32 = 6.0;
+
+/// Up to this many short differing runs per row are ignored as temporal noise.
+const MAX_NOISE_RUNS: usize = 2;
+/// Longest run ignored as noise, as a fraction of the compared row width.
+const NOISE_RUN_MAX_FRACTION: f32 = 0.04;
+
+const MAX_TRIM_PX: u32 = 240;
+const MAX_TRIM_FRACTION: f32 = 0.20;
+const MIN_CONTENT_PX: u32 = 50;
+
+/// Computes chrome trims for each image in a sequence.
+///
+/// Returns one trim per image, and the indices of images whose detected
+/// chrome was kept because trimming it would leave too little content.
+/// Unless `trim_ends`
+/// is set, the first image will have `top = 0` and the last image will have
+/// `bottom = 0`; with it, they get the chrome found in their only neighbor.
+///
+/// Images are compared through grayscale proxies `proxy_width` pixels wide,
+/// or, when `None`, a width adapted to each image (see [`adaptive_proxy_width`]).
+pub fn compute_chrome_trims(
+    images: &[DynamicImage],
+    proxy_width: Option<u32>,
+    trim_ends: bool,
+) -> (Vec<ChromeTrim>, Vec<usize>) {
+    let n = images.len();
+    if n == 0 {
+        return (vec![], vec![]);
+    }
+
+    let proxies: Vec<GrayImage> = images
+        .iter()
+        .map(|img| {
+            build_proxy(
+                img,
+                proxy_width.unwrap_or_else(|| adaptive_proxy_width(img.width())),
+            )
+        })
+        .collect();
+    let mut trims = vec![ChromeTrim::default(); n];
+
+    for i in 0..n.saturating_sub(1) {
+        let (prev, curr) = (&proxies[i], &proxies[i + 1]);
+        let top_rows = count_common_rows_top(prev, curr);
+        let bottom_rows = count_common_rows_bottom(prev, curr);
+
+        // Apply top trim to the current image.
+        let curr_px = proxy_rows_to_pixels(top_rows, images[i + 1].height(), curr.height());
+        trims[i + 1].top = clamp_trim(curr_px, images[i + 1].height());
+
+        // Apply bottom trim to the previous image.
+        let prev_px = proxy_rows_to_pixels(bottom_rows, images[i].height(), prev.height());
+        trims[i].bottom = clamp_trim(prev_px, images[i].height());
+
+        // The outer chrome only repeats in the neighbor.
+        if trim_ends && i == 0 {
+            let first_px = proxy_rows_to_pixels(top_rows, images[0].height(), prev.height());
+            trims[0].top = clamp_trim(first_px, images[0].height());
+        }
+        if trim_ends && i + 2 == n {
+            let last_px = proxy_rows_to_pixels(bottom_rows, images[n - 1].height(), curr.height());
+            trims[n - 1].bottom = clamp_trim(last_px, images[n - 1].height());
+        }
+    }
+
+    // Ensure we don't trim away the entire image.
+    let mut fallbacks = Vec::new();
+    for (i, img) in images.iter().enumerate() {
+        let enforced = enforce_min_content(trims[i], img.height());
+        if enforced != trims[i] {
+            fallbacks.push(i);
+        }
+        trims[i] = enforced;
+    }
+
+    if !trim_ends {
+        // First top and last bottom must survive.
+        if let Some(first) = trims.first_mut() {
+            first.top = 0;
+        }
+        if let Some(last) = trims.last_mut() {
+            last.bottom = 0;
+        }
+    }
+
+    (trims, fallbacks)
+}
+
+/// Proxy width for an image `width` pixels wide: at most a
+/// `MAX_PROXY_DOWNSCALE` reduction, kept within `MIN_PROXY_WIDTH..=MAX_PROXY_WIDTH`.
+pub fn adaptive_proxy_width(width: u32) -> u32 {
+    width
+        .div_ceil(MAX_PROXY_DOWNSCALE)
+        .clamp(MIN_PROXY_WIDTH, MAX_PROXY_WIDTH)
+}
+
+fn build_proxy(img: &DynamicImage, proxy_width: u32) -> GrayImage {
+    let w = img.width().max(1);
+    let h = img.height().max(1);
+
+    let target_w = proxy_width.min(w).max(1);
+    let target_h =
+        round_half_up_u64((h as u64) * (target_w as u64), w as u64).clamp(1, h as u64) as u32;
+
+    // Convert to grayscale first, then scale deterministically.
+    let gray = img.to_luma8();
+    if gray.width() == target_w && gray.height() == target_h {
+        return gray;
+    }
+    image::imageops::resize(&gray, target_w, target_h, FilterType::Triangle)
+}
+
+fn proxy_rows_to_pixels(rows: u32, orig_h: u32, proxy_h: u32) -> u32 {
+    if rows == 0 || orig_h == 0 || proxy_h == 0 {
+        return 0;
+    }
+    round_half_up_u64((rows as u64) * (orig_h as u64), proxy_h as u64) as u32
+}
+
+fn clamp_trim(trim: u32, height: u32) -> u32 {
+    if height == 0 {
+        return 0;
+    }
+    let max_by_fraction = ((height as f32) * MAX_TRIM_FRACTION).round() as u32;
+    trim.min(MAX_TRIM_PX).min(max_by_fraction).min(height)
+}
+
+fn enforce_min_content(trim: ChromeTrim, height: u32) -> ChromeTrim {
+    if height == 0 {
+        return ChromeTrim::default();
+    }
+    let mut t = trim;
+    let total = t.top.saturating_add(t.bottom);
+    let min_content = MIN_CONTENT_PX.min(height);
+    if total > height.saturating_sub(min_content) {
+        // If we would trim too much, fall back to trimming nothing.
+        t.top = 0;
+        t.bottom = 0;
+    }
+    t
+}
+
+fn count_common_rows_top(a: &GrayImage, b: &GrayImage) -> u32 {
+    let max_rows = a.height().min(b.height());
+    if max_rows == 0 {
+        return 0;
+    }
+    let (ax0, aw) = common_span(a.width(), b.width());
+    if aw == 0 {
+        return 0;
+    }
+
+    let mut rows = 0;
+    for y in 0..max_rows {
+        if !rows_similar(a, b, ax0, aw, y, y) {
+            break;
+        }
+        rows += 1;
+    }
+    rows
+}
+
+fn count_common_rows_bottom(a: &GrayImage, b: &GrayImage) -> u32 {
+    let max_rows = a.height().min(b.height());
+    if max_rows == 0 {
+        return 0;
+    }
+    let (ax0, aw) = common_span(a.width(), b.width());
+    if aw == 0 {
+        return 0;
+    }
+
+    let mut rows = 0;
+    for i in 0..max_rows {
+        let ay = a.height() - 1 - i;
+        let by = b.height() - 1 - i;
+        if !rows_similar(a, b, ax0, aw, ay, by) {
+            break;
+        }
+        rows += 1;
+    }
+    rows
+}
+
+fn common_span(wa: u32, wb: u32) -> (u32, u32) {
+    let common_w = wa.min(wb);
+    if common_w == 0 {
+        return (0, 0);
+    }
+    let margin = ((common_w as f32) * MARGIN_PERCENT) as u32;
+    let x0 = margin.min(common_w);
+    let x1 = common_w.saturating_sub(margin);
+    let w = x1.saturating_sub(x0);
+    (x0, w)
+}
+
+fn rows_similar(a: &GrayImage, b: &GrayImage, x0: u32, w: u32, ay: u32, by: u32) -> bool {
+    if w == 0 {
+        return false;
+    }
+
+    let mut diffs = vec![0; w as usize];
+    abs_diff(row_span(a, ay, x0, w), row_span(b, by, x0, w), &mut diffs);
+    let noise = noise_mask(&diffs);
+
+    let mut counted: u32 = 0;
+    let mut match_count: u32 = 0;
+    let mut sum_abs: u32 = 0;
+    for (&diff, &is_noise) in diffs.iter().zip(&noise) {
+        if is_noise {
+            continue;
+        }
+        counted += 1;
+        if diff <= PIXEL_DELTA {
+            match_count += 1;
+        }
+        sum_abs += diff as u32;
+    }
+    if counted == 0 {
+        return false;
+    }
+
+    let denom = counted as f32;
+    let frac = (match_count as f32) / denom;
+    if frac < ROW_MATCH_FRACTION {
+        return false;
+    }
+
+    let mean_abs = (sum_abs as f32) / denom;
+    mean_abs <= ROW_MEAN_ABS_DELTA_MAX
+}
+
+/// Pixels `x0..x0 + w` of row `y`.
+fn row_span(img: &GrayImage, y: u32, x0: u32, w: u32) -> &[u8] {
+    let start = (y * img.width() + x0) as usize;
+    &img.as_raw()[start..start + w as usize]
+}
+
+/// Marks pixels to ignore as temporal noise: a few short, isolated runs of
+/// differing pixels, such as a blinking caret or a clock's seconds.
+///
+/// Rows with more or longer differing runs are real differences, so nothing
+/// is masked for them.
+fn noise_mask(diffs: &[u8]) -> Vec<bool> {
+    let max_run = ((diffs.len() as f32 * NOISE_RUN_MAX_FRACTION) as usize).max(1);
+    let mut runs = Vec::new();
+    let mut x = 0;
+    while x < diffs.len() {
+        if diffs[x] <= PIXEL_DELTA {
+            x += 1;
+            continue;
+        }
+        let start = x;
+        while x < diffs.len() && diffs[x] > PIXEL_DELTA {
+            x += 1;
+        }
+        runs.push(start..x);
+    }
+
+    let mut mask = vec![false; diffs.len()];
+    if runs.len() <= MAX_NOISE_RUNS && runs.iter().all(|run| run.len() <= max_run) {
+        for run in runs {
+            mask[run].fill(true);
+        }
+    }
+    mask
+}
+
+fn round_half_up_u64(num: u64, den: u64) -> u64 {
+    if den == 0 {
+        return 0;
+    }
+    (num + (den / 2)) / den
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn build_bar_image(width: u32, height: u32, top: u32, bottom: u32, seed: u32) -> DynamicImage {
+        let mut img = RgbaImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let px = if y < top || y >= height.saturating_sub(bottom) {
+                    Rgba([20, 20, 20, 255])
+                } else {
+                    // Deterministic busy content.
+                    let v = x.wrapping_mul(37) ^ y.wrapping_mul(131) ^ seed.wrapping_mul(7919);
+                    let g = (v % 251) as u8;
+                    Rgba([g, g, g, 255])
+                };
+                img.put_pixel(x, y, px);
+            }
+        }
+        DynamicImage::ImageRgba8(img)
+    }
+
+    #[test]
+    fn test_compute_chrome_trims_detects_top_and_bottom() {
+        let a = build_bar_image(200, 340, 20, 20, 1);
+        let b = build_bar_image(200, 340, 20, 20, 2);
+        let (trims, _) = compute_chrome_trims(&[a, b], None, false);
+        assert_eq!(trims.len(), 2);
+
+        // First top and last bottom must remain.
+        assert_eq!(trims[0].top, 0);
+        assert_eq!(trims[1].bottom, 0);
+
+        // Allow small tolerance due to proxy scaling.
+        assert!(
+            trims[0].bottom.abs_diff(20) <= 2,
+            "bottom={}",
+            trims[0].bottom
+        );
+        assert!(trims[1].top.abs_diff(20) <= 2, "top={}", trims[1].top);
+    }
+
+    #[test]
+    fn test_compute_chrome_trims_reports_min_content_fallbacks() {
+        // Short enough that the repeated bars would leave under 50 rows
+        let a = build_bar_image(200, 60, 20, 20, 1);
+        let b = build_bar_image(200, 60, 20, 20, 2);
+        let (trims, fallbacks) = compute_chrome_trims(&[a, b], None, false);
+        assert_eq!(trims, vec![ChromeTrim::default(); 2]);
+        assert_eq!(fallbacks, vec![0, 1]);
+
+        let a = build_bar_image(200, 340, 20, 20, 1);
+        let b = build_bar_image(200, 340, 20, 20, 2);
+        let (_, fallbacks) = compute_chrome_trims(&[a, b], None, false);
+        assert!(fallbacks.is_empty());
+    }
+
+    #[test]
+    fn test_live_clock_and_caret_in_chrome_are_noise() {
+        // The header shows a clock whose seconds change between captures and
+        // a blinking caret.
+        let build = |seed: u32| {
+            let mut img = build_bar_image(200, 340, 20, 20, seed).to_rgba8();
+            let digit = Rgba([(60 * seed) as u8, 0, 0, 255]);
+            for y in 5..15 {
+                for x in 150..156 {
+                    img.put_pixel(x, y, digit);
+                }
+                if seed == 1 {
+                    img.put_pixel(60, y, Rgba([255, 255, 255, 255]));
+                }
+            }
+            DynamicImage::ImageRgba8(img)
+        };
+        let (trims, _) = compute_chrome_trims(&[build(1), build(2)], None, false);
+        assert!(trims[1].top.abs_diff(20) <= 2, "top={}", trims[1].top);
+    }
+
+    #[test]
+    fn test_noise_mask_only_masks_few_short_runs() {
+        let mut diffs = vec![0u8; 100];
+        diffs[10..13].fill(200);
+        diffs[50] = 200;
+        assert_eq!(noise_mask(&diffs).iter().filter(|m| **m).count(), 4);
+
+        // Too long to be a caret or a digit.
+        let mut diffs = vec![0u8; 100];
+        diffs[10..20].fill(200);
+        assert!(noise_mask(&diffs).iter().all(|m| !m));
+
+        // Too many runs.
+        let mut diffs = vec![0u8; 100];
+        for x in [10, 30, 50] {
+            diffs[x] = 200;
+        }
+        assert!(noise_mask(&diffs).iter().all(|m| !m));
+    }
+
+    #[test]
+    fn test_adaptive_proxy_width() {
+        assert_eq!(adaptive_proxy_width(200), MIN_PROXY_WIDTH);
+        assert_eq!(adaptive_proxy_width(1290), 430);
+        assert_eq!(adaptive_proxy_width(3840), MAX_PROXY_WIDTH);
+    }
+
+    #[test]
+    fn test_high_dpi_trim_keeps_thin_separator_with_header() {
+        // 4K capture: a 100 px header closed by a 2 px separator line. A
+        // 320 px proxy averages the busy content into flat gray, which then
+        // looks like repeated chrome.
+        let build = |seed: u32| {
+            let mut img = build_bar_image(3840, 1200, 100, 0, seed).to_rgba8();
+            for y in 100..102 {
+                for x in 0..3840 {
+                    img.put_pixel(x, y, Rgba([220, 220, 220, 255]));
+                }
+            }
+            DynamicImage::ImageRgba8(img)
+        };
+        let (trims, _) = compute_chrome_trims(&[build(1), build(2)], None, false);
+        assert!(trims[1].top.abs_diff(102) <= 3, "top={}", trims[1].top);
+    }
+}
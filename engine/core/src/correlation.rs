@@ -0,0 +1,551 @@
+//! Normalized cross-correlation with an FFT backend for large search regions.
+//!
+//! Scores match `imageproc`'s `CrossCorrelationNormalized`: the sum of
+//! products of image and template pixels, divided by the square root of the
+//! product of their squared sums. Small searches slide the template directly
+//! (with the SIMD kernels from `simd`); large ones compute the products for
+//! every offset at once with FFTs.
+//!
+//! The FFT path uses overlap-save: the image is cut into horizontal blocks a
+//! few template heights tall, so memory stays bounded for tall screenshots.
+//! Sums are kept in `f64` so scores agree with the direct path well within
+//! the matching thresholds.
+//!
+//! Wide search regions (4K screenshots) are matched coarse-to-fine: a pass
+//! on 4x box-downscaled proxies finds the candidate rows, which are then
+//! re-scored at full resolution in narrow bands.
+
+use image::{GrayImage, ImageBuffer, Luma};
+
+use crate::hooks::Interrupt;
+use crate::simd::{dot, sum_and_squares};
+
+/// Score map with one entry per template placement.
+pub type Scores = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Direct matching is used unless it costs this many times the FFT work.
+const FFT_CROSSOVER: f64 = 4.0;
+
+/// Block height, in template heights, for the overlap-save FFT path.
+const BLOCK_TEMPLATE_HEIGHTS: u32 = 4;
+
+/// Downscale factor of the coarse pass.
+const COARSE_SCALE: u32 = 4;
+/// Search regions at least this wide are matched coarse-to-fine.
+const COARSE_MIN_WIDTH: u32 = 1600;
+/// Smallest coarse template height that still carries enough detail.
+const COARSE_MIN_TEMPLATE_HEIGHT: u32 = 8;
+/// Coarse peaks re-scored at full resolution.
+const COARSE_CANDIDATES: usize = 3;
+/// Score lead the best coarse peak needs over the rest; flatter coarse maps
+/// (fine texture that averages out) are matched at full resolution instead.
+const COARSE_MIN_GAP: f32 = 0.002;
+
+/// Slides `template` over `image`, locating candidates on downscaled proxies
+/// first when the search region is wide.
+///
+/// Rows near the strongest coarse peaks hold full-resolution scores. Other
+/// rows hold the coarse score of their cell, capped at the best refined
+/// score, which is enough to judge ambiguity but never wins outright.
+pub fn match_template_coarse_to_fine(
+    image: &GrayImage,
+    template: &GrayImage,
+    interrupt: &mut Interrupt,
+) -> Scores {
+    let scale = COARSE_SCALE;
+    let (width, height) = image.dimensions();
+    let (t_width, t_height) = template.dimensions();
+    if width < COARSE_MIN_WIDTH
+        || t_height / scale < COARSE_MIN_TEMPLATE_HEIGHT
+        || height < t_height * scale
+    {
+        return match_template_ncc(image, template, interrupt);
+    }
+
+    let coarse = match_template_ncc(
+        &downscale(image, scale),
+        &downscale(template, scale),
+        interrupt,
+    );
+    let Some(peaks) = coarse_peaks(&coarse, t_height / scale) else {
+        return match_template_ncc(image, template, interrupt);
+    };
+
+    let (out_w, out_h) = (width - t_width + 1, height - t_height + 1);
+    let mut scores = Scores::from_fn(out_w, out_h, |x, y| {
+        let cx = (x / scale).min(coarse.width() - 1);
+        let cy = (y / scale).min(coarse.height() - 1);
+        *coarse.get_pixel(cx, cy)
+    });
+    let mut refined = vec![false; out_h as usize];
+    let mut refined_max = f32::NEG_INFINITY;
+    let half_window = t_height / 2 + 2 * scale;
+    for peak in peaks {
+        let center = peak * scale;
+        let y0 = center.saturating_sub(half_window);
+        let y1 = (center + half_window).min(out_h - 1);
+        let band = image::imageops::crop_imm(image, 0, y0, width, y1 - y0 + t_height).to_image();
+        let fine = match_template_ncc(&band, template, interrupt);
+        for (x, y, score) in fine.enumerate_pixels() {
+            scores.put_pixel(x, y0 + y, *score);
+            if score[0].is_finite() {
+                refined_max = refined_max.max(score[0]);
+            }
+        }
+        refined[y0 as usize..=y1 as usize].fill(true);
+    }
+
+    for (y, row) in scores.rows_mut().enumerate() {
+        if !refined[y] {
+            for score in row {
+                score[0] = score[0].min(refined_max);
+            }
+        }
+    }
+    scores
+}
+
+/// Rows of the strongest coarse peaks, at least `spacing` rows apart, or
+/// `None` if the best peak does not stand out.
+fn coarse_peaks(coarse: &Scores, spacing: u32) -> Option<Vec<u32>> {
+    let mut rows: Vec<(u32, f32)> = coarse
+        .rows()
+        .enumerate()
+        .map(|(y, row)| {
+            let best = row
+                .map(|p| p[0])
+                .filter(|v| v.is_finite())
+                .fold(f32::NEG_INFINITY, f32::max);
+            (y as u32, best)
+        })
+        .filter(|(_, best)| best.is_finite())
+        .collect();
+    rows.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let &(best_row, best_score) = rows.first()?;
+    let runner_up = rows
+        .iter()
+        .find(|(y, _)| y.abs_diff(best_row) > spacing)
+        .map_or(f32::NEG_INFINITY, |(_, score)| *score);
+    if best_score - runner_up < COARSE_MIN_GAP {
+        return None;
+    }
+
+    let mut peaks: Vec<u32> = Vec::with_capacity(COARSE_CANDIDATES);
+    for (y, _) in rows {
+        if peaks.len() == COARSE_CANDIDATES {
+            break;
+        }
+        if peaks.iter().all(|peak| peak.abs_diff(y) > spacing) {
+            peaks.push(y);
+        }
+    }
+    Some(peaks)
+}
+
+/// Box-averages `image` down by `factor`, dropping partial edge cells.
+fn downscale(image: &GrayImage, factor: u32) -> GrayImage {
+    let area = factor * factor;
+    GrayImage::from_fn(image.width() / factor, image.height() / factor, |x, y| {
+        let mut sum = 0;
+        for dy in 0..factor {
+            for dx in 0..factor {
+                sum += image.get_pixel(x * factor + dx, y * factor + dy)[0] as u32;
+            }
+        }
+        Luma([((sum + area / 2) / area) as u8])
+    })
+}
+
+/// Slides `template` over `image`, picking the faster backend.
+///
+/// The result is `image.width() - template.width() + 1` by
+/// `image.height() - template.height() + 1`, as for `match_template`. Once
+/// `interrupt` fires, the remaining rows are left unscored.
+///
+/// # Panics
+/// If the template is larger than the image in either dimension.
+pub fn match_template_ncc(
+    image: &GrayImage,
+    template: &GrayImage,
+    interrupt: &mut Interrupt,
+) -> Scores {
+    if use_fft(image.dimensions(), template.dimensions()) {
+        match_template_fft(image, template, interrupt)
+    } else {
+        match_template_direct(image, template, interrupt)
+    }
+}
+
+/// Direct implementation of `match_template_ncc`, one dot product per
+/// template row and placement.
+fn match_template_direct(
+    image: &GrayImage,
+    template: &GrayImage,
+    interrupt: &mut Interrupt,
+) -> Scores {
+    let (width, height) = image.dimensions();
+    let (t_width, t_height) = template.dimensions();
+    assert!(
+        t_width <= width && t_height <= height,
+        "template larger than image"
+    );
+    let template_sq = sum_and_squares(template.as_raw()).1 as f64;
+    let image_sq = SquaredSums::new(image);
+    let (pixels, template_pixels) = (image.as_raw(), template.as_raw());
+    let (width, t_width) = (width as usize, t_width as usize);
+
+    let mut scores = Scores::new(width as u32 - t_width as u32 + 1, height - t_height + 1);
+    let row_work = scores.width() as u64 * template_pixels.len() as u64;
+    for y in 0..scores.height() {
+        for x in 0..scores.width() {
+            let score: u64 = template_pixels
+                .chunks_exact(t_width)
+                .enumerate()
+                .map(|(row, template_row)| {
+                    let start = (y as usize + row) * width + x as usize;
+                    dot(&pixels[start..start + t_width], template_row)
+                })
+                .sum();
+            let window_sq = image_sq.window(x, y, t_width as u32, t_height);
+            scores.put_pixel(
+                x,
+                y,
+                Luma([normalize(score as f64, window_sq, template_sq)]),
+            );
+        }
+        if interrupt.tick(row_work) {
+            break;
+        }
+    }
+    scores
+}
+
+/// Divides a correlation by its norm; flat (all-zero) windows keep the raw score.
+fn normalize(score: f64, window_sq: f64, template_sq: f64) -> f32 {
+    let norm = (window_sq * template_sq).sqrt();
+    (if norm > 0.0 { score / norm } else { score }) as f32
+}
+
+/// True if the FFT path is expected to be faster for these sizes.
+fn use_fft((width, height): (u32, u32), (t_width, t_height): (u32, u32)) -> bool {
+    let out_w = (width - t_width + 1) as f64;
+    let out_h = (height - t_height + 1) as f64;
+    let direct = out_w * out_h * t_width as f64 * t_height as f64;
+
+    let (block_w, block_h) = block_size((width, height), t_height);
+    let block_len = (block_w * block_h) as f64;
+    let blocks = (out_h / (block_h - t_height as usize + 1) as f64).ceil();
+    // One forward and one inverse transform per block, plus the template's
+    let fft = (2.0 * blocks + 1.0) * block_len * block_len.log2();
+    direct > FFT_CROSSOVER * fft
+}
+
+/// Power-of-two transform size for one overlap-save block.
+fn block_size((width, height): (u32, u32), t_height: u32) -> (usize, usize) {
+    let block_w = (width as usize).next_power_of_two();
+    let block_h = ((t_height * BLOCK_TEMPLATE_HEIGHTS) as usize)
+        .next_power_of_two()
+        .min((height as usize).next_power_of_two());
+    (block_w, block_h)
+}
+
+/// FFT implementation of `match_template_ncc`.
+pub fn match_template_fft(
+    image: &GrayImage,
+    template: &GrayImage,
+    interrupt: &mut Interrupt,
+) -> Scores {
+    let (width, height) = image.dimensions();
+    let (t_width, t_height) = template.dimensions();
+    assert!(
+        t_width <= width && t_height <= height,
+        "template larger than image"
+    );
+    let (out_w, out_h) = (width - t_width + 1, height - t_height + 1);
+    let (block_w, block_h) = block_size((width, height), t_height);
+    let step = block_h - t_height as usize + 1;
+
+    let template_sq = sum_and_squares(template.as_raw()).1 as f64;
+    let image_sq = SquaredSums::new(image);
+
+    // Conjugated template spectrum turns convolution into correlation
+    let mut kernel = vec![Complex::ZERO; block_w * block_h];
+    for (x, y, p) in template.enumerate_pixels() {
+        kernel[y as usize * block_w + x as usize].re = p[0] as f64;
+    }
+    fft_2d(&mut kernel, block_w, block_h, false);
+    for value in &mut kernel {
+        value.im = -value.im;
+    }
+
+    let mut scores = Scores::new(out_w, out_h);
+    let mut block = vec![Complex::ZERO; block_w * block_h];
+    let scale = 1.0 / (block_w * block_h) as f64;
+    // Two transforms per block
+    let block_work = 2 * (block_w * block_h) as u64 * (block_w * block_h).ilog2() as u64;
+    for y0 in (0..out_h as usize).step_by(step) {
+        block.fill(Complex::ZERO);
+        let rows = block_h.min(height as usize - y0);
+        for row in 0..rows {
+            for x in 0..width as usize {
+                let pixel = image.get_pixel(x as u32, (y0 + row) as u32)[0];
+                block[row * block_w + x].re = pixel as f64;
+            }
+        }
+        fft_2d(&mut block, block_w, block_h, false);
+        for (value, k) in block.iter_mut().zip(&kernel) {
+            *value = value.mul(*k);
+        }
+        fft_2d(&mut block, block_w, block_h, true);
+
+        for row in 0..step.min(out_h as usize - y0) {
+            let y = (y0 + row) as u32;
+            for x in 0..out_w {
+                let score = block[row * block_w + x as usize].re * scale;
+                let window_sq = image_sq.window(x, y, t_width, t_height);
+                scores.put_pixel(x, y, Luma([normalize(score, window_sq, template_sq)]));
+            }
+        }
+        if interrupt.tick(block_work) {
+            break;
+        }
+    }
+    scores
+}
+
+/// Summed-area table of squared pixel values.
+struct SquaredSums {
+    stride: usize,
+    sums: Vec<f64>,
+}
+
+impl SquaredSums {
+    fn new(image: &GrayImage) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let stride = width + 1;
+        let mut sums = vec![0.0; stride * (height + 1)];
+        for (y, pixels) in image.as_raw().chunks_exact(width.max(1)).enumerate() {
+            let mut row = 0.0;
+            for (x, &pixel) in pixels.iter().enumerate() {
+                row += (pixel as f64).powi(2);
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row;
+            }
+        }
+        SquaredSums { stride, sums }
+    }
+
+    fn window(&self, x: u32, y: u32, width: u32, height: u32) -> f64 {
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = (x0 + width as usize, y0 + height as usize);
+        let at = |x: usize, y: usize| self.sums[y * self.stride + x];
+        // Clamp tiny negative rounding residue on flat regions
+        (at(x1, y1) - at(x0, y1) - at(x1, y0) + at(x0, y0)).max(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+}
+
+/// In-place 2D FFT over a row-major `width` by `height` buffer.
+///
+/// The inverse transform is unscaled.
+fn fft_2d(data: &mut [Complex], width: usize, height: usize, inverse: bool) {
+    let row_twiddles = twiddles(width, inverse);
+    for row in data.chunks_exact_mut(width) {
+        fft(row, &row_twiddles);
+    }
+    let column_twiddles = twiddles(height, inverse);
+    let mut column = vec![Complex::ZERO; height];
+    for x in 0..width {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = data[y * width + x];
+        }
+        fft(&mut column, &column_twiddles);
+        for (y, value) in column.iter().enumerate() {
+            data[y * width + x] = *value;
+        }
+    }
+}
+
+/// `exp(∓2πik/n)` for `k < n/2`, the factors used by a length-`n` transform.
+fn twiddles(n: usize, inverse: bool) -> Vec<Complex> {
+    let sign = if inverse { 1.0 } else { -1.0 };
+    (0..n / 2)
+        .map(|k| {
+            let angle = sign * 2.0 * std::f64::consts::PI * k as f64 / n as f64;
+            Complex {
+                re: angle.cos(),
+                im: angle.sin(),
+            }
+        })
+        .collect()
+}
+
+/// Iterative radix-2 FFT; `data.len()` must be a power of two.
+fn fft(data: &mut [Complex], twiddles: &[Complex]) {
+    let n = data.len();
+    if n < 2 {
+        return;
+    }
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let stride = n / len;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let a = data[start + k];
+                let b = data[start + k + len / 2].mul(twiddles[k * stride]);
+                data[start + k] = Complex {
+                    re: a.re + b.re,
+                    im: a.im + b.im,
+                };
+                data[start + k + len / 2] = Complex {
+                    re: a.re - b.re,
+                    im: a.im - b.im,
+                };
+            }
+        }
+        len *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use imageproc::template_matching::{MatchTemplateMethod, match_template};
+
+    /// Deterministic texture with no flat regions.
+    fn noise(width: u32, height: u32, seed: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            let mut h = x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263) ^ seed;
+            h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+            Luma([(h >> 24) as u8])
+        })
+    }
+
+    #[test]
+    fn test_fft_matches_direct_scores() {
+        let image = noise(70, 90, 1);
+        let template = image::imageops::crop_imm(&image, 5, 40, 50, 12).to_image();
+        let direct = match_template(
+            &image,
+            &template,
+            MatchTemplateMethod::CrossCorrelationNormalized,
+        );
+        let fft = match_template_fft(&image, &template, &mut Interrupt::none());
+        assert_eq!(fft.dimensions(), direct.dimensions());
+        for (a, b) in fft.pixels().zip(direct.pixels()) {
+            assert!((a[0] - b[0]).abs() < 1e-4, "{} vs {}", a[0], b[0]);
+        }
+        assert!((fft.get_pixel(5, 40)[0] - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_direct_matches_imageproc() {
+        let image = noise(60, 40, 5);
+        let template = image::imageops::crop_imm(&image, 7, 9, 45, 10).to_image();
+        let reference = match_template(
+            &image,
+            &template,
+            MatchTemplateMethod::CrossCorrelationNormalized,
+        );
+        let direct = match_template_direct(&image, &template, &mut Interrupt::none());
+        assert_eq!(direct.dimensions(), reference.dimensions());
+        for (a, b) in direct.pixels().zip(reference.pixels()) {
+            assert!((a[0] - b[0]).abs() < 1e-4, "{} vs {}", a[0], b[0]);
+        }
+    }
+
+    #[test]
+    fn test_fft_handles_flat_regions() {
+        let mut image = noise(40, 60, 2);
+        for y in 0..20 {
+            for x in 0..40 {
+                image.put_pixel(x, y, Luma([0]));
+            }
+        }
+        let template = noise(20, 8, 3);
+        let direct = match_template(
+            &image,
+            &template,
+            MatchTemplateMethod::CrossCorrelationNormalized,
+        );
+        let fft = match_template_fft(&image, &template, &mut Interrupt::none());
+        for (a, b) in fft.pixels().zip(direct.pixels()) {
+            assert!((a[0] - b[0]).abs() < 1e-4, "{} vs {}", a[0], b[0]);
+        }
+    }
+
+    /// Text-like rows: bars of varying length, a few pixels tall.
+    fn lines(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            let line = y / 12;
+            let length = (line.wrapping_mul(2_654_435_761) >> 20) % width;
+            let ink = y % 12 < 7 && x < length && (x / 9 + line) % 5 != 0;
+            Luma([if ink { 30 } else { 235 }])
+        })
+    }
+
+    #[test]
+    fn test_coarse_to_fine_finds_exact_match() {
+        let image = lines(1700, 900);
+        let template = image::imageops::crop_imm(&image, 0, 517, 1700, 80).to_image();
+        let coarse = match_template_ncc(
+            &downscale(&image, 4),
+            &downscale(&template, 4),
+            &mut Interrupt::none(),
+        );
+        assert!(coarse_peaks(&coarse, 20).is_some());
+        let scores = match_template_coarse_to_fine(&image, &template, &mut Interrupt::none());
+        assert_eq!(scores.dimensions(), (1, 821));
+        let extremes = imageproc::template_matching::find_extremes(&scores);
+        assert_eq!(extremes.max_value_location, (0, 517));
+        assert!((extremes.max_value - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_coarse_to_fine_falls_back_on_flat_proxies() {
+        // A one-pixel checkerboard averages to flat gray at 4x
+        let image = GrayImage::from_fn(1600, 200, |x, y| Luma([((x + y) % 2 * 255) as u8]));
+        let template = image::imageops::crop_imm(&image, 0, 100, 1600, 40).to_image();
+        let coarse = match_template_ncc(
+            &downscale(&image, 4),
+            &downscale(&template, 4),
+            &mut Interrupt::none(),
+        );
+        assert!(coarse_peaks(&coarse, 10).is_none());
+        assert_eq!(
+            match_template_coarse_to_fine(&image, &template, &mut Interrupt::none()),
+            match_template_ncc(&image, &template, &mut Interrupt::none())
+        );
+    }
+
+    #[test]
+    fn test_backend_selection() {
+        // A thin strip searched over a short region stays direct
+        assert!(!use_fft((100, 40), (90, 8)));
+        // Tall screenshots with a wide template go through the FFT
+        assert!(use_fft((1000, 3000), (960, 48)));
+    }
+}
@@ -0,0 +1,464 @@
+use crate::types::{Align, Direction, Padding, ScaleMode};
+
+/// Computes the target dimension for scaling.
+///
+/// - Vertical and grid merges: returns maximum width among inputs
+/// - Horizontal merge: returns maximum height among inputs
+///
+/// With `ScaleMode::Min` the minimum is returned instead. `ScaleMode::None`
+/// returns the maximum, which then only sizes the canvas.
+pub fn compute_target_dimension(
+    dimensions: &[(u32, u32)],
+    direction: Direction,
+    scale_mode: ScaleMode,
+) -> u32 {
+    if dimensions.is_empty() {
+        return 0;
+    }
+
+    let cross = dimensions.iter().map(|(w, h)| match direction {
+        Direction::Vertical | Direction::Smart | Direction::Tiles | Direction::Grid => *w,
+        Direction::Horizontal => *h,
+    });
+
+    match scale_mode {
+        ScaleMode::Max | ScaleMode::None => cross.max().unwrap_or(0),
+        ScaleMode::Min => cross.min().unwrap_or(0),
+    }
+}
+
+/// Computes the new dimensions after scaling to the target.
+///
+/// - Vertical and grid merges: scale to target width, computes height preserving aspect ratio
+/// - Horizontal merge: scales to target height, computes width preserving aspect ratio
+///
+/// Uses deterministic rounding: `round(value)` via `(value + 0.5).floor()`.
+pub fn compute_scaled_dimensions(
+    width: u32,
+    height: u32,
+    target: u32,
+    direction: Direction,
+) -> (u32, u32) {
+    if width == 0 || height == 0 || target == 0 {
+        return (0, 0);
+    }
+
+    match direction {
+        Direction::Vertical | Direction::Smart | Direction::Tiles | Direction::Grid => {
+            // Scale to target width
+            let scale = target as f64 / width as f64;
+            let new_height = round_half_up(height as f64 * scale);
+            (target, new_height.max(1))
+        }
+        Direction::Horizontal => {
+            // Scale to target height
+            let scale = target as f64 / height as f64;
+            let new_width = round_half_up(width as f64 * scale);
+            (new_width.max(1), target)
+        }
+    }
+}
+
+/// Round half up: 0.5 rounds up to 1.
+fn round_half_up(value: f64) -> u32 {
+    (value + 0.5).floor() as u32
+}
+
+/// Computes total output dimensions given scaled image dimensions.
+///
+/// - Vertical merge: width = max width, height = sum of heights
+/// - Horizontal merge: width = sum of widths, height = max height
+///
+/// `spacing` pixels are added between each adjacent pair along the stacking axis.
+/// Grid merges are sized by [`compute_grid_size`] instead; here they count as a
+/// single column.
+pub fn compute_output_size(
+    scaled_dimensions: &[(u32, u32)],
+    direction: Direction,
+    spacing: u32,
+) -> (u64, u64) {
+    if scaled_dimensions.is_empty() {
+        return (0, 0);
+    }
+
+    let gaps = (scaled_dimensions.len() as u64 - 1) * spacing as u64;
+
+    match direction {
+        Direction::Vertical | Direction::Smart | Direction::Tiles | Direction::Grid => {
+            let width = scaled_dimensions
+                .iter()
+                .map(|(w, _)| *w as u64)
+                .max()
+                .unwrap_or(0);
+            let height: u64 = scaled_dimensions.iter().map(|(_, h)| *h as u64).sum();
+            (width, height + gaps)
+        }
+        Direction::Horizontal => {
+            let width: u64 = scaled_dimensions
+                .iter()
+                .map(|(w, _)| *w as u64)
+                .sum::<u64>()
+                + gaps;
+            let height = scaled_dimensions
+                .iter()
+                .map(|(_, h)| *h as u64)
+                .max()
+                .unwrap_or(0);
+            (width, height)
+        }
+    }
+}
+
+/// Computes the output size of a grid, `columns` across.
+///
+/// Every column is as wide as the widest image, and rows are sized by
+/// [`grid_row_heights`]; `spacing` pixels separate adjacent columns and rows.
+pub fn compute_grid_size(
+    scaled_dimensions: &[(u32, u32)],
+    columns: u32,
+    spacing: u32,
+    balance_rows: bool,
+) -> (u64, u64) {
+    if scaled_dimensions.is_empty() || columns == 0 {
+        return (0, 0);
+    }
+
+    let columns = (scaled_dimensions.len() as u64).min(columns as u64);
+    let rows = grid_row_heights(scaled_dimensions, columns as u32, balance_rows);
+    let gaps = |cells: u64| (cells - 1) * spacing as u64;
+    let width = columns * grid_column_width(scaled_dimensions) as u64 + gaps(columns);
+    let height = rows.iter().map(|h| *h as u64).sum::<u64>() + gaps(rows.len() as u64);
+    (width, height)
+}
+
+/// Width of every grid column: that of the widest image.
+pub fn grid_column_width(scaled_dimensions: &[(u32, u32)]) -> u32 {
+    scaled_dimensions.iter().map(|(w, _)| *w).max().unwrap_or(0)
+}
+
+/// Height of every row of a grid `columns` across: that of the tallest image
+/// in the row when `balance_rows` is set, else that of the tallest image
+/// overall, so all cells are equal.
+pub fn grid_row_heights(
+    scaled_dimensions: &[(u32, u32)],
+    columns: u32,
+    balance_rows: bool,
+) -> Vec<u32> {
+    let tallest = |dims: &[(u32, u32)]| dims.iter().map(|(_, h)| *h).max().unwrap_or(0);
+    let rows = scaled_dimensions.chunks(columns.max(1) as usize);
+    if balance_rows {
+        rows.map(tallest).collect()
+    } else {
+        vec![tallest(scaled_dimensions); rows.len()]
+    }
+}
+
+/// Linear scale factor applied when resizing `from` to `to` (largest axis).
+pub fn scale_factor(from: (u32, u32), to: (u32, u32)) -> f64 {
+    if from.0 == 0 || from.1 == 0 {
+        return 1.0;
+    }
+    let sx = to.0 as f64 / from.0 as f64;
+    let sy = to.1 as f64 / from.1 as f64;
+    sx.max(sy)
+}
+
+/// Shrinks `size` to fit within `max` on both axes, keeping aspect ratio.
+///
+/// Sizes that already fit are returned unchanged.
+pub fn fit_within(size: (u32, u32), max: u32) -> (u32, u32) {
+    let (width, height) = size;
+    let longest = width.max(height);
+    if longest <= max || longest == 0 {
+        return size;
+    }
+    let scale = max as f64 / longest as f64;
+    (
+        round_half_up(width as f64 * scale).max(1),
+        round_half_up(height as f64 * scale).max(1),
+    )
+}
+
+/// Rescales `size` from `source_dpi` to `target_dpi`, keeping physical size.
+pub fn normalize_dpi(size: (u32, u32), source_dpi: f32, target_dpi: f32) -> (u32, u32) {
+    if source_dpi <= 0.0 || target_dpi <= 0.0 || source_dpi == target_dpi {
+        return size;
+    }
+    let scale = target_dpi as f64 / source_dpi as f64;
+    (
+        round_half_up(size.0 as f64 * scale).max(1),
+        round_half_up(size.1 as f64 * scale).max(1),
+    )
+}
+
+/// Shrinks `size` to at most `max_width` wide, keeping aspect ratio.
+///
+/// Sizes that already fit are returned unchanged.
+pub fn fit_width(size: (u32, u32), max_width: u32) -> (u32, u32) {
+    let (width, height) = size;
+    if width <= max_width || width == 0 {
+        return size;
+    }
+    let scale = max_width as f64 / width as f64;
+    (max_width, round_half_up(height as f64 * scale).max(1))
+}
+
+/// Offset that places an item of `size` within `available` space.
+pub fn align_offset(available: u32, size: u32, align: Align) -> u32 {
+    let free = available.saturating_sub(size);
+    match align {
+        Align::Start => 0,
+        Align::Center => free / 2,
+        Align::End => free,
+    }
+}
+
+/// Grows a content size by the outer padding on each side.
+pub fn apply_padding(size: (u64, u64), padding: Padding) -> (u64, u64) {
+    (
+        size.0 + padding.left as u64 + padding.right as u64,
+        size.1 + padding.top as u64 + padding.bottom as u64,
+    )
+}
+
+/// Rounds `value` up to the next multiple of `multiple` (no-op for `None`, 0 or 1).
+pub fn snap_to_multiple(value: u64, multiple: Option<u32>) -> u64 {
+    match multiple {
+        Some(m) if m > 1 => value.div_ceil(m as u64) * m as u64,
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_grid_size() {
+        let dims = vec![(100, 50), (80, 70), (100, 40), (90, 60), (60, 30)];
+        assert_eq!(grid_column_width(&dims), 100);
+        assert_eq!(compute_grid_size(&dims, 2, 0, false), (200, 210));
+        assert_eq!(compute_grid_size(&dims, 3, 10, false), (320, 150));
+        // More columns than images: a single row
+        assert_eq!(compute_grid_size(&dims, 8, 0, false), (500, 70));
+        assert_eq!(compute_grid_size(&[], 2, 0, false), (0, 0));
+    }
+
+    #[test]
+    fn test_grid_row_heights_balanced() {
+        let dims = vec![(100, 50), (80, 70), (100, 40), (90, 60), (60, 30)];
+        assert_eq!(grid_row_heights(&dims, 2, false), vec![70, 70, 70]);
+        assert_eq!(grid_row_heights(&dims, 2, true), vec![70, 60, 30]);
+        assert_eq!(compute_grid_size(&dims, 2, 5, true), (205, 170));
+    }
+
+    #[test]
+    fn test_compute_target_vertical() {
+        let dims = vec![(100, 200), (150, 100), (80, 300)];
+        let target = compute_target_dimension(&dims, Direction::Vertical, ScaleMode::Max);
+        assert_eq!(target, 150); // max width
+    }
+
+    #[test]
+    fn test_compute_target_horizontal() {
+        let dims = vec![(100, 200), (150, 100), (80, 300)];
+        let target = compute_target_dimension(&dims, Direction::Horizontal, ScaleMode::Max);
+        assert_eq!(target, 300); // max height
+    }
+
+    #[test]
+    fn test_compute_target_min() {
+        let dims = vec![(100, 200), (150, 100), (80, 300)];
+        assert_eq!(
+            compute_target_dimension(&dims, Direction::Vertical, ScaleMode::Min),
+            80
+        );
+        assert_eq!(
+            compute_target_dimension(&dims, Direction::Horizontal, ScaleMode::Min),
+            100
+        );
+    }
+
+    #[test]
+    fn test_compute_target_empty() {
+        let dims: Vec<(u32, u32)> = vec![];
+        assert_eq!(
+            compute_target_dimension(&dims, Direction::Vertical, ScaleMode::Max),
+            0
+        );
+        assert_eq!(
+            compute_target_dimension(&dims, Direction::Horizontal, ScaleMode::Max),
+            0
+        );
+    }
+
+    #[test]
+    fn test_scaled_dimensions_vertical() {
+        // 100x200 scaled to width 150: height = 200 * 1.5 = 300
+        let (w, h) = compute_scaled_dimensions(100, 200, 150, Direction::Vertical);
+        assert_eq!(w, 150);
+        assert_eq!(h, 300);
+    }
+
+    #[test]
+    fn test_scaled_dimensions_horizontal() {
+        // 100x200 scaled to height 400: width = 100 * 2 = 200
+        let (w, h) = compute_scaled_dimensions(100, 200, 400, Direction::Horizontal);
+        assert_eq!(w, 200);
+        assert_eq!(h, 400);
+    }
+
+    #[test]
+    fn test_scaled_dimensions_no_change() {
+        // Already at target
+        let (w, h) = compute_scaled_dimensions(100, 200, 100, Direction::Vertical);
+        assert_eq!(w, 100);
+        assert_eq!(h, 200);
+    }
+
+    #[test]
+    fn test_scaled_dimensions_downscale() {
+        // 200x400 scaled to width 100: height = 400 * 0.5 = 200
+        let (w, h) = compute_scaled_dimensions(200, 400, 100, Direction::Vertical);
+        assert_eq!(w, 100);
+        assert_eq!(h, 200);
+    }
+
+    #[test]
+    fn test_round_half_up() {
+        // 100x150 scaled to width 200: height = 150 * 2 = 300 (exact)
+        let (_, h) = compute_scaled_dimensions(100, 150, 200, Direction::Vertical);
+        assert_eq!(h, 300);
+
+        // 100x151 scaled to width 200: height = 151 * 2 = 302 (exact)
+        let (_, h) = compute_scaled_dimensions(100, 151, 200, Direction::Vertical);
+        assert_eq!(h, 302);
+
+        // 100x101 scaled to width 150: height = 101 * 1.5 = 151.5 -> 152 (round up)
+        let (_, h) = compute_scaled_dimensions(100, 101, 150, Direction::Vertical);
+        assert_eq!(h, 152);
+    }
+
+    #[test]
+    fn test_scaled_dimensions_zero() {
+        assert_eq!(
+            compute_scaled_dimensions(0, 100, 200, Direction::Vertical),
+            (0, 0)
+        );
+        assert_eq!(
+            compute_scaled_dimensions(100, 0, 200, Direction::Vertical),
+            (0, 0)
+        );
+        assert_eq!(
+            compute_scaled_dimensions(100, 200, 0, Direction::Vertical),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn test_output_size_vertical() {
+        // Three images scaled to width 150
+        let dims = vec![(150, 300), (150, 150), (150, 200)];
+        let (w, h) = compute_output_size(&dims, Direction::Vertical, 0);
+        assert_eq!(w, 150);
+        assert_eq!(h, 650); // 300 + 150 + 200
+    }
+
+    #[test]
+    fn test_output_size_horizontal() {
+        // Three images scaled to height 300
+        let dims = vec![(200, 300), (150, 300), (100, 300)];
+        let (w, h) = compute_output_size(&dims, Direction::Horizontal, 0);
+        assert_eq!(w, 450); // 200 + 150 + 100
+        assert_eq!(h, 300);
+    }
+
+    #[test]
+    fn test_output_size_empty() {
+        let dims: Vec<(u32, u32)> = vec![];
+        assert_eq!(compute_output_size(&dims, Direction::Vertical, 10), (0, 0));
+    }
+
+    #[test]
+    fn test_output_size_with_spacing() {
+        let dims = vec![(150, 300), (150, 150), (150, 200)];
+        // Two gaps between three images.
+        assert_eq!(
+            compute_output_size(&dims, Direction::Vertical, 10),
+            (150, 670)
+        );
+
+        let dims = vec![(200, 300), (150, 300)];
+        assert_eq!(
+            compute_output_size(&dims, Direction::Horizontal, 8),
+            (358, 300)
+        );
+
+        // A single image has no gaps.
+        assert_eq!(
+            compute_output_size(&[(100, 100)], Direction::Vertical, 10),
+            (100, 100)
+        );
+    }
+
+    #[test]
+    fn test_apply_padding() {
+        let padding = Padding {
+            top: 1,
+            right: 2,
+            bottom: 3,
+            left: 4,
+        };
+        assert_eq!(apply_padding((100, 50), padding), (106, 54));
+        assert_eq!(apply_padding((100, 50), Padding::default()), (100, 50));
+    }
+
+    #[test]
+    fn test_align_offset() {
+        assert_eq!(align_offset(100, 40, Align::Start), 0);
+        assert_eq!(align_offset(100, 40, Align::Center), 30);
+        assert_eq!(align_offset(100, 40, Align::End), 60);
+        // Oversized items never produce a negative offset.
+        assert_eq!(align_offset(10, 40, Align::End), 0);
+    }
+
+    #[test]
+    fn test_snap_to_multiple() {
+        assert_eq!(snap_to_multiple(101, Some(2)), 102);
+        assert_eq!(snap_to_multiple(100, Some(16)), 112);
+        assert_eq!(snap_to_multiple(112, Some(16)), 112);
+        assert_eq!(snap_to_multiple(101, None), 101);
+        assert_eq!(snap_to_multiple(101, Some(0)), 101);
+        assert_eq!(snap_to_multiple(101, Some(1)), 101);
+    }
+
+    #[test]
+    fn test_scale_factor() {
+        assert_eq!(scale_factor((100, 50), (300, 150)), 3.0);
+        assert_eq!(scale_factor((100, 50), (50, 25)), 0.5);
+        assert_eq!(scale_factor((0, 50), (50, 25)), 1.0);
+    }
+
+    #[test]
+    fn test_fit_within() {
+        assert_eq!(fit_within((400, 100), 200), (200, 50));
+        assert_eq!(fit_within((100, 400), 200), (50, 200));
+        assert_eq!(fit_within((100, 50), 200), (100, 50));
+        assert_eq!(fit_within((1000, 1), 10), (10, 1));
+    }
+
+    #[test]
+    fn test_fit_width() {
+        assert_eq!(fit_width((1024, 8000), 256), (256, 2000));
+        assert_eq!(fit_width((100, 3000), 256), (100, 3000));
+        assert_eq!(fit_width((4000, 1), 256), (256, 1));
+    }
+
+    #[test]
+    fn test_normalize_dpi() {
+        assert_eq!(normalize_dpi((850, 1100), 100.0, 300.0), (2550, 3300));
+        assert_eq!(normalize_dpi((2550, 3300), 300.0, 150.0), (1275, 1650));
+        assert_eq!(normalize_dpi((10, 10), 300.0, 300.0), (10, 10));
+        assert_eq!(normalize_dpi((10, 10), 0.0, 300.0), (10, 10));
+    }
+}
@@ -0,0 +1,136 @@
+//! Resolution metadata for scanned inputs.
+//!
+//! Best-effort DPI extraction from the JFIF header of JPEG files and the
+//! `pHYs` chunk of PNG files. Only the horizontal density is read; pixels
+//! are assumed to be square. Other formats, and files that only record an
+//! aspect ratio, return `None`. The output records its density the same
+//! way PNG inputs do, in a `pHYs` chunk.
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const CM_PER_INCH: f32 = 2.54;
+const METERS_PER_INCH: f32 = 0.0254;
+
+/// Reads the resolution in dots per inch recorded in the image bytes.
+pub fn read_dpi(bytes: &[u8]) -> Option<f32> {
+    let dpi = if bytes.starts_with(&[0xFF, 0xD8]) {
+        read_jfif_dpi(bytes)
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        read_png_dpi(bytes)
+    } else {
+        None
+    };
+    dpi.filter(|d| d.is_finite() && *d > 0.0)
+}
+
+/// Pixels per meter for `dpi` dots per inch, as a `pHYs` chunk records it.
+pub fn pixels_per_meter(dpi: f32) -> u32 {
+    (dpi / METERS_PER_INCH).round() as u32
+}
+
+/// JFIF APP0 segment: "JFIF\0", version (2), units (1), Xdensity (2), Ydensity (2).
+fn read_jfif_dpi(bytes: &[u8]) -> Option<f32> {
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        if length < 2 {
+            return None;
+        }
+        let data = bytes.get(pos + 4..pos + 2 + length)?;
+        if marker == 0xE0 && data.len() >= 12 && data.starts_with(b"JFIF\0") {
+            let density = u16::from_be_bytes([data[8], data[9]]) as f32;
+            return match data[7] {
+                1 => Some(density),
+                2 => Some(density * CM_PER_INCH),
+                _ => None,
+            };
+        }
+        // Stop at start of scan; image data follows
+        if marker == 0xDA {
+            return None;
+        }
+        pos += 2 + length;
+    }
+    None
+}
+
+/// PNG pHYs chunk: pixels per unit X (4), pixels per unit Y (4), unit (1).
+fn read_png_dpi(bytes: &[u8]) -> Option<f32> {
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().ok()?) as usize;
+        let chunk_type = &bytes[pos + 4..pos + 8];
+        let data = bytes.get(pos + 8..(pos + 8).checked_add(length)?)?;
+        match chunk_type {
+            b"pHYs" if data.len() >= 9 => {
+                let per_unit = u32::from_be_bytes(data[0..4].try_into().ok()?) as f32;
+                // Unit 1 is the meter; unit 0 only gives an aspect ratio
+                return (data[8] == 1).then_some(per_unit * METERS_PER_INCH);
+            }
+            // pHYs must come before the image data
+            b"IDAT" | b"IEND" => return None,
+            _ => {}
+        }
+        // Length, type, data and CRC
+        pos += 12 + length;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jfif(units: u8, density: u16) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        bytes.extend_from_slice(b"JFIF\0");
+        bytes.extend_from_slice(&[1, 1, units]);
+        bytes.extend_from_slice(&density.to_be_bytes());
+        bytes.extend_from_slice(&density.to_be_bytes());
+        bytes.extend_from_slice(&[0, 0, 0xFF, 0xD9]);
+        bytes
+    }
+
+    fn png_with_phys(per_meter: u32, unit: u8) -> Vec<u8> {
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&13u32.to_be_bytes());
+        bytes.extend_from_slice(b"IHDR");
+        bytes.extend_from_slice(&[0; 13 + 4]);
+        bytes.extend_from_slice(&9u32.to_be_bytes());
+        bytes.extend_from_slice(b"pHYs");
+        bytes.extend_from_slice(&per_meter.to_be_bytes());
+        bytes.extend_from_slice(&per_meter.to_be_bytes());
+        bytes.push(unit);
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    #[test]
+    fn test_read_jfif_dpi() {
+        assert_eq!(read_dpi(&jfif(1, 300)), Some(300.0));
+        assert_eq!(read_dpi(&jfif(2, 100)), Some(254.0));
+        assert_eq!(read_dpi(&jfif(0, 1)), None);
+    }
+
+    #[test]
+    fn test_read_png_dpi() {
+        // 11811 px/m is 300 dpi
+        let dpi = read_dpi(&png_with_phys(11811, 1)).unwrap();
+        assert!((dpi - 300.0).abs() < 0.1);
+        assert_eq!(read_dpi(&png_with_phys(1, 0)), None);
+    }
+
+    #[test]
+    fn test_pixels_per_meter_round_trips() {
+        assert_eq!(pixels_per_meter(300.0), 11811);
+        assert_eq!(pixels_per_meter(72.0), 2835);
+        let dpi = read_dpi(&png_with_phys(pixels_per_meter(96.0), 1)).unwrap();
+        assert!((dpi - 96.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_read_dpi_other_formats() {
+        assert_eq!(read_dpi(b"GIF89a"), None);
+        assert_eq!(read_dpi(&[]), None);
+    }
+}
@@ -0,0 +1,687 @@
+use std::fmt;
+
+use crate::hooks::HookStage;
+
+/// Errors that can occur during merge operations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeError {
+    /// No images provided to merge.
+    NoImages,
+
+    /// Failed to decode an image.
+    DecodeError {
+        /// Zero-based index of the failing image.
+        index: usize,
+        /// Original filename if available.
+        file_name: Option<String>,
+        /// Error message from the decoder.
+        message: String,
+    },
+
+    /// Failed to decode the background image.
+    BackgroundDecodeError { message: String },
+
+    /// Internal encoding error.
+    EncodeError { message: String },
+
+    /// Output would exceed the configured pixel limit.
+    OutputTooLarge {
+        /// Computed output width in pixels.
+        width: u64,
+        /// Computed output height in pixels.
+        height: u64,
+        /// Configured maximum number of output pixels.
+        max_pixels: u64,
+    },
+
+    /// The merge's estimated peak memory exceeds the configured budget.
+    OutOfMemory {
+        /// Estimated bytes needed by decoded inputs, scaled copies and canvas.
+        required_bytes: u64,
+        /// Configured memory budget in bytes.
+        max_bytes: u64,
+    },
+
+    /// An input is larger than `max_input_pixels` or `max_input_dimension`
+    /// allow, going by its header.
+    InputTooLarge {
+        /// Zero-based index of the image.
+        index: usize,
+        /// Original filename if available.
+        file_name: Option<String>,
+        /// Width in pixels stored in the header.
+        width: u32,
+        /// Height in pixels stored in the header.
+        height: u32,
+        /// Configured maximum number of pixels per input.
+        max_pixels: Option<u64>,
+        /// Configured maximum width or height per input.
+        max_dimension: Option<u32>,
+    },
+
+    /// An image would be enlarged beyond the allowed factor (hard error mode).
+    UpscaleTooLarge {
+        /// Zero-based index of the image.
+        index: usize,
+        /// Linear scale factor that would be applied.
+        factor: f64,
+        /// Configured maximum upscale factor.
+        max_factor: f64,
+    },
+
+    /// A pipeline hook rejected the merge.
+    HookRejected {
+        /// Stage whose hook rejected the merge.
+        stage: HookStage,
+        /// Reason given by the hook.
+        message: String,
+    },
+
+    /// The host cancelled the merge before it finished.
+    Cancelled,
+
+    /// Strict mode refused a fallback the merge would otherwise take silently.
+    StrictViolation {
+        /// Zero-based index of the image involved (the upper image of a pair).
+        index: usize,
+        /// The fallback that was refused.
+        message: String,
+    },
+
+    /// The `order` option is not a permutation of the input indices.
+    InvalidOrder {
+        /// Number of input images the order must list.
+        count: usize,
+    },
+}
+
+/// Non-fatal degradations reported alongside a successful merge.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeWarning {
+    /// An image was enlarged beyond the configured quality threshold.
+    Upscaled {
+        /// Zero-based index of the image.
+        index: usize,
+        /// Linear scale factor that was applied.
+        factor: f64,
+        /// Configured threshold that was exceeded.
+        max_factor: f64,
+    },
+    /// The output was shrunk to fit the pixel limit.
+    Downscaled {
+        /// Linear scale factor that was applied.
+        factor: f64,
+        /// Output size after shrinking.
+        width: u64,
+        height: u64,
+    },
+    /// Smart mode found no overlap between two adjacent images, so they were
+    /// stacked edge to edge.
+    OverlapNotFound {
+        /// Zero-based index of the pair; it joins images `pair` and `pair + 1`.
+        pair: usize,
+    },
+}
+
+impl fmt::Display for MergeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeWarning::Upscaled {
+                index,
+                factor,
+                max_factor,
+            } => write!(
+                f,
+                "Image at index {} was upscaled {:.2}x (above {:.2}x) and may look blurry",
+                index, factor, max_factor
+            ),
+            MergeWarning::Downscaled {
+                factor,
+                width,
+                height,
+            } => write!(
+                f,
+                "Output was downscaled {:.2}x to {}x{} to stay within the pixel limit",
+                factor, width, height
+            ),
+            MergeWarning::OverlapNotFound { pair } => write!(
+                f,
+                "No overlap found between images at index {} and {}; they were concatenated",
+                pair,
+                pair + 1
+            ),
+        }
+    }
+}
+
+impl MergeWarning {
+    /// Machine-readable parameters, as for [`MergeError::params`].
+    pub fn params(&self) -> Vec<(&'static str, ErrorParam)> {
+        match self {
+            MergeWarning::Upscaled {
+                index,
+                factor,
+                max_factor,
+            } => vec![
+                ("fileIndex", ErrorParam::Number(*index as f64)),
+                ("factor", ErrorParam::Number(*factor)),
+                ("maxFactor", ErrorParam::Number(*max_factor)),
+            ],
+            MergeWarning::Downscaled {
+                factor,
+                width,
+                height,
+            } => vec![
+                ("factor", ErrorParam::Number(*factor)),
+                ("width", ErrorParam::Number(*width as f64)),
+                ("height", ErrorParam::Number(*height as f64)),
+            ],
+            MergeWarning::OverlapNotFound { pair } => {
+                vec![("pairIndex", ErrorParam::Number(*pair as f64))]
+            }
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            MergeWarning::Upscaled { .. } => "UPSCALED",
+            MergeWarning::Downscaled { .. } => "DOWNSCALED",
+            MergeWarning::OverlapNotFound { .. } => "OVERLAP_NOT_FOUND",
+        }
+    }
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::NoImages => {
+                write!(f, "No images provided")
+            }
+            MergeError::DecodeError {
+                index,
+                file_name,
+                message,
+            } => {
+                write!(f, "Failed to decode image at index {}: {}", index, message)?;
+                if let Some(name) = file_name {
+                    write!(f, " (file: {})", name)?;
+                }
+                Ok(())
+            }
+            MergeError::BackgroundDecodeError { message } => {
+                write!(f, "Failed to decode background image: {}", message)
+            }
+            MergeError::EncodeError { message } => {
+                write!(f, "Failed to encode output: {}", message)
+            }
+            MergeError::OutputTooLarge {
+                width,
+                height,
+                max_pixels,
+            } => {
+                write!(
+                    f,
+                    "Output of {}x{} pixels exceeds the limit of {} pixels",
+                    width, height, max_pixels
+                )
+            }
+            MergeError::OutOfMemory {
+                required_bytes,
+                max_bytes,
+            } => {
+                write!(
+                    f,
+                    "Merge needs about {} bytes of memory, above the budget of {} bytes",
+                    required_bytes, max_bytes
+                )
+            }
+            MergeError::InputTooLarge {
+                index,
+                file_name,
+                width,
+                height,
+                max_pixels,
+                max_dimension,
+            } => {
+                write!(f, "Image at index {} is {}x{} pixels", index, width, height)?;
+                match (max_dimension, max_pixels) {
+                    (Some(max), _) if *width.max(height) > *max => {
+                        write!(f, ", above the limit of {} pixels per side", max)?
+                    }
+                    (_, Some(max)) => write!(f, ", above the limit of {} pixels", max)?,
+                    _ => {}
+                }
+                if let Some(name) = file_name {
+                    write!(f, " (file: {})", name)?;
+                }
+                Ok(())
+            }
+            MergeError::UpscaleTooLarge {
+                index,
+                factor,
+                max_factor,
+            } => {
+                write!(
+                    f,
+                    "Image at index {} would be upscaled {:.2}x, above the limit of {:.2}x",
+                    index, factor, max_factor
+                )
+            }
+            MergeError::HookRejected { stage, message } => {
+                write!(f, "Merge rejected by {} hook: {}", stage.name(), message)
+            }
+            MergeError::Cancelled => write!(f, "Merge was cancelled"),
+            MergeError::StrictViolation { index, message } => {
+                write!(
+                    f,
+                    "Strict mode rejected image at index {}: {}",
+                    index, message
+                )
+            }
+            MergeError::InvalidOrder { count } => {
+                write!(
+                    f,
+                    "Order must list every index from 0 to {} exactly once",
+                    count.saturating_sub(1)
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// An invalid merge option, from the JS options object or
+/// [`MergeOptionsBuilder::build`](crate::MergeOptionsBuilder::build).
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionsError {
+    /// Path of the offending field, e.g. `imageOptions[1].dpi`; empty when
+    /// the options value itself is malformed.
+    pub field: String,
+    pub message: String,
+}
+
+impl OptionsError {
+    pub(crate) fn new(field: &str, message: impl Into<String>) -> Self {
+        OptionsError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "Invalid options: {}", self.message)
+        } else {
+            write!(f, "Invalid option `{}`: {}", self.field, self.message)
+        }
+    }
+}
+
+impl std::error::Error for OptionsError {}
+
+/// A machine-readable error parameter value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorParam {
+    Number(f64),
+    Text(String),
+}
+
+/// Error code strings for the worker protocol.
+impl MergeError {
+    /// Machine-readable parameters, keyed by the names used in JS error objects
+    /// and message templates.
+    pub fn params(&self) -> Vec<(&'static str, ErrorParam)> {
+        match self {
+            MergeError::NoImages | MergeError::Cancelled => vec![],
+            MergeError::DecodeError {
+                index,
+                file_name,
+                message,
+            } => {
+                let mut params = vec![("fileIndex", ErrorParam::Number(*index as f64))];
+                if let Some(name) = file_name {
+                    params.push(("fileName", ErrorParam::Text(name.clone())));
+                }
+                params.push(("reason", ErrorParam::Text(message.clone())));
+                params
+            }
+            MergeError::BackgroundDecodeError { message } | MergeError::EncodeError { message } => {
+                vec![("reason", ErrorParam::Text(message.clone()))]
+            }
+            MergeError::OutputTooLarge {
+                width,
+                height,
+                max_pixels,
+            } => vec![
+                ("width", ErrorParam::Number(*width as f64)),
+                ("height", ErrorParam::Number(*height as f64)),
+                ("maxPixels", ErrorParam::Number(*max_pixels as f64)),
+            ],
+            MergeError::OutOfMemory {
+                required_bytes,
+                max_bytes,
+            } => vec![
+                ("requiredBytes", ErrorParam::Number(*required_bytes as f64)),
+                ("maxBytes", ErrorParam::Number(*max_bytes as f64)),
+            ],
+            MergeError::InputTooLarge {
+                index,
+                file_name,
+                width,
+                height,
+                max_pixels,
+                max_dimension,
+            } => {
+                let mut params = vec![("fileIndex", ErrorParam::Number(*index as f64))];
+                if let Some(name) = file_name {
+                    params.push(("fileName", ErrorParam::Text(name.clone())));
+                }
+                params.push(("width", ErrorParam::Number(*width as f64)));
+                params.push(("height", ErrorParam::Number(*height as f64)));
+                if let Some(max) = max_pixels {
+                    params.push(("maxPixels", ErrorParam::Number(*max as f64)));
+                }
+                if let Some(max) = max_dimension {
+                    params.push(("maxDimension", ErrorParam::Number(*max as f64)));
+                }
+                params
+            }
+            MergeError::UpscaleTooLarge {
+                index,
+                factor,
+                max_factor,
+            } => vec![
+                ("fileIndex", ErrorParam::Number(*index as f64)),
+                ("factor", ErrorParam::Number(*factor)),
+                ("maxFactor", ErrorParam::Number(*max_factor)),
+            ],
+            MergeError::HookRejected { stage, message } => vec![
+                ("stage", ErrorParam::Text(stage.name().to_string())),
+                ("reason", ErrorParam::Text(message.clone())),
+            ],
+            MergeError::StrictViolation { index, message } => vec![
+                ("fileIndex", ErrorParam::Number(*index as f64)),
+                ("reason", ErrorParam::Text(message.clone())),
+            ],
+            MergeError::InvalidOrder { count } => {
+                vec![("imageCount", ErrorParam::Number(*count as f64))]
+            }
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            MergeError::NoImages => "NO_IMAGES",
+            MergeError::DecodeError { .. } => "DECODE_FAILED",
+            MergeError::BackgroundDecodeError { .. } => "BACKGROUND_DECODE_FAILED",
+            MergeError::EncodeError { .. } => "INTERNAL_ERROR",
+            MergeError::OutputTooLarge { .. } => "OUTPUT_TOO_LARGE",
+            MergeError::OutOfMemory { .. } => "OUT_OF_MEMORY",
+            MergeError::InputTooLarge { .. } => "INPUT_TOO_LARGE",
+            MergeError::UpscaleTooLarge { .. } => "UPSCALE_TOO_LARGE",
+            MergeError::HookRejected { .. } => "HOOK_REJECTED",
+            MergeError::Cancelled => "CANCELLED",
+            MergeError::StrictViolation { .. } => "STRICT_VIOLATION",
+            MergeError::InvalidOrder { .. } => "INVALID_ORDER",
+        }
+    }
+}
+
+/// Renders a message template, replacing `{name}` placeholders via `lookup`.
+///
+/// Placeholders that `lookup` does not resolve are kept verbatim.
+pub fn render_template(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) => {
+                let name = &after[..end];
+                match lookup(name) {
+                    Some(value) => out.push_str(&value),
+                    None => {
+                        out.push('{');
+                        out.push_str(name);
+                        out.push('}');
+                    }
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display_no_images() {
+        let err = MergeError::NoImages;
+        assert_eq!(err.to_string(), "No images provided");
+        assert_eq!(err.code(), "NO_IMAGES");
+    }
+
+    #[test]
+    fn test_error_display_out_of_memory() {
+        let err = MergeError::OutOfMemory {
+            required_bytes: 2_000_000,
+            max_bytes: 1_000_000,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Merge needs about 2000000 bytes of memory, above the budget of 1000000 bytes"
+        );
+        assert_eq!(err.code(), "OUT_OF_MEMORY");
+        assert_eq!(
+            err.params(),
+            vec![
+                ("requiredBytes", ErrorParam::Number(2_000_000.0)),
+                ("maxBytes", ErrorParam::Number(1_000_000.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_display_input_too_large() {
+        let err = MergeError::InputTooLarge {
+            index: 1,
+            file_name: Some("bomb.png".to_string()),
+            width: 100_000,
+            height: 100_000,
+            max_pixels: Some(50_000_000),
+            max_dimension: None,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Image at index 1 is 100000x100000 pixels, above the limit of 50000000 pixels (file: bomb.png)"
+        );
+        assert_eq!(err.code(), "INPUT_TOO_LARGE");
+        assert_eq!(
+            err.params(),
+            vec![
+                ("fileIndex", ErrorParam::Number(1.0)),
+                ("fileName", ErrorParam::Text("bomb.png".to_string())),
+                ("width", ErrorParam::Number(100_000.0)),
+                ("height", ErrorParam::Number(100_000.0)),
+                ("maxPixels", ErrorParam::Number(50_000_000.0)),
+            ]
+        );
+
+        let err = MergeError::InputTooLarge {
+            index: 0,
+            file_name: None,
+            width: 20_000,
+            height: 10,
+            max_pixels: Some(50_000_000),
+            max_dimension: Some(16_384),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Image at index 0 is 20000x10 pixels, above the limit of 16384 pixels per side"
+        );
+    }
+
+    #[test]
+    fn test_error_display_invalid_order() {
+        let err = MergeError::InvalidOrder { count: 3 };
+        assert_eq!(
+            err.to_string(),
+            "Order must list every index from 0 to 2 exactly once"
+        );
+        assert_eq!(err.code(), "INVALID_ORDER");
+        assert_eq!(err.params(), vec![("imageCount", ErrorParam::Number(3.0))]);
+    }
+
+    #[test]
+    fn test_error_display_cancelled() {
+        let err = MergeError::Cancelled;
+        assert_eq!(err.to_string(), "Merge was cancelled");
+        assert_eq!(err.code(), "CANCELLED");
+        assert!(err.params().is_empty());
+    }
+
+    #[test]
+    fn test_error_display_decode() {
+        let err = MergeError::DecodeError {
+            index: 2,
+            file_name: Some("photo.jpg".to_string()),
+            message: "invalid PNG header".to_string(),
+        };
+        assert!(err.to_string().contains("index 2"));
+        assert!(err.to_string().contains("invalid PNG header"));
+        assert!(err.to_string().contains("photo.jpg"));
+        assert_eq!(err.code(), "DECODE_FAILED");
+    }
+
+    #[test]
+    fn test_error_display_decode_no_filename() {
+        let err = MergeError::DecodeError {
+            index: 2,
+            file_name: None,
+            message: "invalid PNG header".to_string(),
+        };
+        assert!(err.to_string().contains("index 2"));
+        assert!(!err.to_string().contains("file:"));
+        assert_eq!(err.code(), "DECODE_FAILED");
+    }
+
+    #[test]
+    fn test_error_display_encode() {
+        let err = MergeError::EncodeError {
+            message: "PNG write failed".to_string(),
+        };
+        assert!(err.to_string().contains("PNG write failed"));
+        assert_eq!(err.code(), "INTERNAL_ERROR");
+    }
+
+    #[test]
+    fn test_error_display_background_decode() {
+        let err = MergeError::BackgroundDecodeError {
+            message: "unsupported format".to_string(),
+        };
+        assert!(err.to_string().contains("background image"));
+        assert_eq!(err.code(), "BACKGROUND_DECODE_FAILED");
+    }
+
+    #[test]
+    fn test_error_display_output_too_large() {
+        let err = MergeError::OutputTooLarge {
+            width: 1000,
+            height: 20000,
+            max_pixels: 8_000_000,
+        };
+        assert!(err.to_string().contains("1000x20000"));
+        assert!(err.to_string().contains("8000000"));
+        assert_eq!(err.code(), "OUTPUT_TOO_LARGE");
+    }
+
+    #[test]
+    fn test_error_params() {
+        let err = MergeError::DecodeError {
+            index: 1,
+            file_name: Some("a.png".to_string()),
+            message: "bad".to_string(),
+        };
+        assert_eq!(
+            err.params(),
+            vec![
+                ("fileIndex", ErrorParam::Number(1.0)),
+                ("fileName", ErrorParam::Text("a.png".to_string())),
+                ("reason", ErrorParam::Text("bad".to_string())),
+            ]
+        );
+        assert!(MergeError::NoImages.params().is_empty());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let lookup = |name: &str| match name {
+            "width" => Some("1.000".to_string()),
+            "height" => Some("20".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            render_template("Größe {width}×{height} ({unknown})", lookup),
+            "Größe 1.000×20 ({unknown})"
+        );
+        assert_eq!(render_template("open {brace", lookup), "open {brace");
+        assert_eq!(render_template("plain", lookup), "plain");
+    }
+
+    #[test]
+    fn test_upscale_error_and_warning() {
+        let err = MergeError::UpscaleTooLarge {
+            index: 1,
+            factor: 3.0,
+            max_factor: 2.0,
+        };
+        assert!(err.to_string().contains("3.00x"));
+        assert_eq!(err.code(), "UPSCALE_TOO_LARGE");
+
+        let warning = MergeWarning::Upscaled {
+            index: 1,
+            factor: 3.0,
+            max_factor: 2.0,
+        };
+        assert!(warning.to_string().contains("index 1"));
+        assert_eq!(warning.code(), "UPSCALED");
+        assert_eq!(warning.params()[0], ("fileIndex", ErrorParam::Number(1.0)));
+    }
+
+    #[test]
+    fn test_strict_violation_error() {
+        let err = MergeError::StrictViolation {
+            index: 2,
+            message: "chrome kept".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Strict mode rejected image at index 2: chrome kept"
+        );
+        assert_eq!(err.code(), "STRICT_VIOLATION");
+        assert_eq!(err.params()[0], ("fileIndex", ErrorParam::Number(2.0)));
+    }
+
+    #[test]
+    fn test_overlap_not_found_warning() {
+        let warning = MergeWarning::OverlapNotFound { pair: 2 };
+        assert!(warning.to_string().contains("index 2 and 3"));
+        assert_eq!(warning.code(), "OVERLAP_NOT_FOUND");
+        assert_eq!(
+            warning.params(),
+            vec![("pairIndex", ErrorParam::Number(2.0))]
+        );
+    }
+}
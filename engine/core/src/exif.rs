@@ -1,7 +1,7 @@
 //! EXIF orientation parsing and image normalization.
 //!
-//! Best-effort EXIF orientation extraction for JPEG images.
-//! Other formats (PNG, GIF, WebP) don't carry EXIF orientation and return Normal.
+//! Best-effort EXIF orientation extraction for JPEG and WebP images.
+//! Other formats (PNG, GIF) don't carry EXIF orientation and return Normal.
 
 use image::DynamicImage;
 
@@ -46,19 +46,58 @@ impl From<u16> for Orientation {
 
 /// Extract EXIF orientation from image bytes.
 ///
-/// Currently only supports JPEG. Other formats return `Orientation::Normal`.
+/// Supports JPEG (APP1 segment) and WebP (`EXIF` chunk).
+/// Other formats return `Orientation::Normal`.
 pub fn extract_orientation(bytes: &[u8]) -> Orientation {
-    // Check for JPEG magic bytes
-    if bytes.len() < 2 || bytes[0] != 0xFF || bytes[1] != 0xD8 {
-        return Orientation::Normal;
+    extract_exif(bytes)
+        .and_then(parse_tiff_orientation)
+        .unwrap_or(Orientation::Normal)
+}
+
+/// Locate the EXIF block of image bytes: the TIFF data (starting with its
+/// byte-order mark) of a JPEG APP1 segment or WebP `EXIF` chunk.
+pub fn extract_exif(bytes: &[u8]) -> Option<&[u8]> {
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        // Parse JPEG segments looking for APP1 (EXIF)
+        find_jpeg_exif(bytes)
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        find_webp_exif(bytes)
+    } else {
+        None
     }
+}
 
-    // Parse JPEG segments looking for APP1 (EXIF)
-    parse_jpeg_exif(bytes).unwrap_or(Orientation::Normal)
+/// Parse WebP RIFF chunks to find the TIFF data of the `EXIF` chunk.
+fn find_webp_exif(bytes: &[u8]) -> Option<&[u8]> {
+    let mut pos = 12; // Skip "RIFF", file size and "WEBP"
+
+    while pos + 8 <= bytes.len() {
+        let fourcc = &bytes[pos..pos + 4];
+        let length = u32::from_le_bytes([
+            bytes[pos + 4],
+            bytes[pos + 5],
+            bytes[pos + 6],
+            bytes[pos + 7],
+        ]) as usize;
+        let chunk_start = pos + 8;
+        let chunk_end = chunk_start.checked_add(length)?;
+
+        if fourcc == b"EXIF" {
+            let chunk = bytes.get(chunk_start..chunk_end)?;
+            // The chunk holds bare TIFF data, but some writers keep the
+            // JPEG-style "Exif\0\0" prefix
+            return Some(chunk.strip_prefix(b"Exif\0\0").unwrap_or(chunk));
+        }
+
+        // Chunks are padded to an even length
+        pos = chunk_end.checked_add(length & 1)?;
+    }
+
+    None
 }
 
-/// Parse JPEG EXIF data to find orientation tag.
-fn parse_jpeg_exif(bytes: &[u8]) -> Option<Orientation> {
+/// Parse JPEG segments to find the TIFF data of the EXIF APP1 segment.
+fn find_jpeg_exif(bytes: &[u8]) -> Option<&[u8]> {
     let mut pos = 2; // Skip SOI marker
 
     while pos + 4 <= bytes.len() {
@@ -86,15 +125,15 @@ fn parse_jpeg_exif(bytes: &[u8]) -> Option<Orientation> {
         }
         let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
 
-        // APP1 marker (0xE1) contains EXIF
+        // APP1 marker (0xE1) contains EXIF (or XMP, which is skipped)
         if marker == 0xE1 {
             let segment_start = pos + 4;
             let segment_end = pos + 2 + length;
-            if segment_end <= bytes.len() {
-                let segment = &bytes[segment_start..segment_end];
-                if let Some(orientation) = parse_exif_segment(segment) {
-                    return Some(orientation);
-                }
+            if let Some(segment) = bytes.get(segment_start..segment_end)
+                && segment.len() >= 14
+                && segment.starts_with(b"Exif\0\0")
+            {
+                return Some(&segment[6..]);
             }
         }
 
@@ -105,15 +144,8 @@ fn parse_jpeg_exif(bytes: &[u8]) -> Option<Orientation> {
     None
 }
 
-/// Parse EXIF segment to find orientation tag.
-fn parse_exif_segment(segment: &[u8]) -> Option<Orientation> {
-    // Check for "Exif\0\0" header
-    if segment.len() < 14 || &segment[0..6] != b"Exif\0\0" {
-        return None;
-    }
-
-    let tiff_data = &segment[6..];
-
+/// Parse TIFF data (as found in an EXIF block) to find the orientation tag.
+fn parse_tiff_orientation(tiff_data: &[u8]) -> Option<Orientation> {
     // Parse TIFF header to determine endianness
     let (is_little_endian, ifd_offset) = parse_tiff_header(tiff_data)?;
 
@@ -269,6 +301,59 @@ mod tests {
         assert_eq!(extract_orientation(&jpeg_bytes), Orientation::Normal);
     }
 
+    /// Little-endian TIFF data holding only an orientation tag.
+    fn tiff_with_orientation(value: u16) -> Vec<u8> {
+        let mut tiff = b"II".to_vec();
+        tiff.extend_from_slice(&42u16.to_le_bytes());
+        tiff.extend_from_slice(&8u32.to_le_bytes()); // IFD0 offset
+        tiff.extend_from_slice(&1u16.to_le_bytes()); // Entry count
+        tiff.extend_from_slice(&ORIENTATION_TAG.to_le_bytes());
+        tiff.extend_from_slice(&3u16.to_le_bytes()); // SHORT
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&value.to_le_bytes());
+        tiff.extend_from_slice(&[0, 0]);
+        tiff.extend_from_slice(&0u32.to_le_bytes()); // No next IFD
+        tiff
+    }
+
+    /// A WebP container with the given chunks.
+    fn webp_with_chunks(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut body = b"WEBP".to_vec();
+        for (fourcc, data) in chunks {
+            body.extend_from_slice(*fourcc);
+            body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            body.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    #[test]
+    fn test_extract_orientation_webp() {
+        let tiff = tiff_with_orientation(6);
+        // An odd-length chunk before the EXIF one exercises the padding
+        let webp =
+            webp_with_chunks(&[(b"VP8X", &[0; 10]), (b"ICCP", &[1, 2, 3]), (b"EXIF", &tiff)]);
+        assert_eq!(extract_orientation(&webp), Orientation::Rotate90);
+
+        let prefixed = [b"Exif\0\0".as_slice(), &tiff_with_orientation(3)].concat();
+        let webp = webp_with_chunks(&[(b"EXIF", &prefixed)]);
+        assert_eq!(extract_orientation(&webp), Orientation::Rotate180);
+
+        let webp = webp_with_chunks(&[(b"VP8L", &[0; 5])]);
+        assert_eq!(extract_orientation(&webp), Orientation::Normal);
+
+        // A chunk length running past the end of the file
+        let mut truncated = webp_with_chunks(&[(b"EXIF", &tiff)]);
+        truncated.truncate(truncated.len() - 4);
+        assert_eq!(extract_orientation(&truncated), Orientation::Normal);
+    }
+
     #[test]
     fn test_normalize_identity() {
         let img = DynamicImage::new_rgba8(10, 20);
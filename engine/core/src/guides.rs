@@ -0,0 +1,157 @@
+//! Composition guides: thirds grid, center lines and a safe-area rectangle.
+//!
+//! Guides are laid out on the finished output, after rotation, so they match
+//! what the viewer sees. Lines are collected into a coverage mask first and
+//! blended once, so crossings are not darker than the lines themselves.
+
+use image::{GrayImage, Luma, RgbaImage};
+
+use crate::shadow::blend_over;
+use crate::types::Guides;
+
+/// Blends the configured guides onto `canvas`.
+///
+/// A transparent canvas yields a standalone overlay layer.
+pub fn render_guides(canvas: &mut RgbaImage, guides: &Guides) {
+    let (width, height) = canvas.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+    let mask = guide_mask(width, height, guides);
+    let alpha = guides.color.a as f32 / 255.0;
+    for (x, y, coverage) in mask.enumerate_pixels() {
+        if coverage[0] > 0 {
+            let pixel = canvas.get_pixel_mut(x, y);
+            *pixel = blend_over(*pixel, &guides.color, alpha);
+        }
+    }
+}
+
+fn guide_mask(width: u32, height: u32, guides: &Guides) -> GrayImage {
+    let mut mask = GrayImage::new(width, height);
+    let line_width = guides.line_width.max(1);
+    let (w, h) = (width as f32, height as f32);
+
+    let mut columns = Vec::new();
+    let mut rows = Vec::new();
+    if guides.thirds {
+        columns.extend([w / 3.0, w * 2.0 / 3.0]);
+        rows.extend([h / 3.0, h * 2.0 / 3.0]);
+    }
+    if guides.center {
+        columns.push(w / 2.0);
+        rows.push(h / 2.0);
+    }
+    for x in columns {
+        fill_column(&mut mask, x, 0, height, line_width);
+    }
+    for y in rows {
+        fill_row(&mut mask, y, 0, width, line_width);
+    }
+
+    let margin = guides.safe_margin.clamp(0.0, 0.5);
+    if margin > 0.0 {
+        let (left, top) = (w * margin, h * margin);
+        let (right, bottom) = (w - left, h - top);
+        let (y0, y1) = (top.round() as u32, bottom.round() as u32);
+        let (x0, x1) = (left.round() as u32, right.round() as u32);
+        fill_column(&mut mask, left, y0, y1, line_width);
+        fill_column(&mut mask, right, y0, y1, line_width);
+        fill_row(&mut mask, top, x0, x1, line_width);
+        fill_row(&mut mask, bottom, x0, x1, line_width);
+    }
+    mask
+}
+
+/// First pixel of a `line_width` band centered on `center`, kept inside `0..size`.
+fn band_start(center: f32, line_width: u32, size: u32) -> u32 {
+    let start = (center - line_width as f32 / 2.0).round().max(0.0) as u32;
+    start.min(size.saturating_sub(line_width))
+}
+
+fn fill_column(mask: &mut GrayImage, x: f32, y0: u32, y1: u32, line_width: u32) {
+    let start = band_start(x, line_width, mask.width());
+    for px in start..(start + line_width).min(mask.width()) {
+        for py in y0..y1.min(mask.height()) {
+            mask.put_pixel(px, py, Luma([255]));
+        }
+    }
+}
+
+fn fill_row(mask: &mut GrayImage, y: f32, x0: u32, x1: u32, line_width: u32) {
+    let start = band_start(y, line_width, mask.height());
+    for py in start..(start + line_width).min(mask.height()) {
+        for px in x0..x1.min(mask.width()) {
+            mask.put_pixel(px, py, Luma([255]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::BackgroundColor;
+    use image::Rgba;
+
+    fn guides() -> Guides {
+        Guides {
+            thirds: false,
+            center: false,
+            safe_margin: 0.0,
+            line_width: 1,
+            color: BackgroundColor::new(255, 0, 0, 255),
+            separate_layer: false,
+        }
+    }
+
+    #[test]
+    fn test_thirds_grid() {
+        let mut canvas = RgbaImage::from_pixel(90, 60, Rgba([255, 255, 255, 255]));
+        render_guides(
+            &mut canvas,
+            &Guides {
+                thirds: true,
+                ..guides()
+            },
+        );
+        let red = Rgba([255, 0, 0, 255]);
+        assert_eq!(*canvas.get_pixel(30, 5), red);
+        assert_eq!(*canvas.get_pixel(60, 5), red);
+        assert_eq!(*canvas.get_pixel(5, 20), red);
+        assert_eq!(*canvas.get_pixel(5, 40), red);
+        assert_eq!(*canvas.get_pixel(45, 30), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_safe_margin_on_transparent_layer() {
+        let mut layer = RgbaImage::new(100, 100);
+        render_guides(
+            &mut layer,
+            &Guides {
+                safe_margin: 0.1,
+                line_width: 2,
+                color: BackgroundColor::new(0, 0, 255, 128),
+                ..guides()
+            },
+        );
+        assert_eq!(*layer.get_pixel(10, 50), Rgba([0, 0, 255, 128]));
+        assert_eq!(*layer.get_pixel(50, 90), Rgba([0, 0, 255, 128]));
+        // Outside the rectangle and inside it stays clear.
+        assert_eq!(layer.get_pixel(5, 5)[3], 0);
+        assert_eq!(layer.get_pixel(50, 50)[3], 0);
+    }
+
+    #[test]
+    fn test_crossings_blend_once() {
+        let mut canvas = RgbaImage::from_pixel(9, 9, Rgba([255, 255, 255, 255]));
+        render_guides(
+            &mut canvas,
+            &Guides {
+                center: true,
+                color: BackgroundColor::new(0, 0, 0, 128),
+                ..guides()
+            },
+        );
+        assert_eq!(canvas.get_pixel(4, 0), canvas.get_pixel(4, 4));
+    }
+}
@@ -0,0 +1,158 @@
+//! Extension points invoked between merge pipeline stages.
+//!
+//! Hooks receive intermediate results and may adjust them in place (e.g. a
+//! host-side detector that found a sticky header can enlarge a chrome trim)
+//! or reject the merge by returning an error message.
+
+pub use crate::chrome_strip::ChromeTrim;
+
+/// Pipeline stage a hook runs after; reported when a hook rejects the merge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    AfterDecode,
+    AfterTrims,
+    AfterMatching,
+    AfterAlignment,
+}
+
+impl HookStage {
+    /// Name of the stage as used by the JS API.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookStage::AfterDecode => "afterDecode",
+            HookStage::AfterTrims => "afterTrims",
+            HookStage::AfterMatching => "afterMatching",
+            HookStage::AfterAlignment => "afterAlignment",
+        }
+    }
+}
+
+/// A step of the pipeline that just finished or is about to start, reported
+/// so hosts can show a determinate progress bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// Input `index` (0-based) of `total` was decoded.
+    Decoded { index: usize, total: usize },
+    /// Input `index` of `total` was scaled to its layout size.
+    Scaled { index: usize, total: usize },
+    /// Smart mode: overlap detection finished for pair `index` of `total`.
+    Overlap { index: usize, total: usize },
+    /// Images are being drawn onto the canvas.
+    Compositing,
+    /// The canvas is being encoded.
+    Encoding,
+}
+
+impl Progress {
+    /// Name of the step as used by the JS API.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Progress::Decoded { .. } => "decoded",
+            Progress::Scaled { .. } => "scaled",
+            Progress::Overlap { .. } => "overlap",
+            Progress::Compositing => "compositing",
+            Progress::Encoding => "encoding",
+        }
+    }
+
+    /// `(index, total)` for the per-item steps.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match *self {
+            Progress::Decoded { index, total }
+            | Progress::Scaled { index, total }
+            | Progress::Overlap { index, total } => Some((index, total)),
+            Progress::Compositing | Progress::Encoding => None,
+        }
+    }
+}
+
+/// Callbacks run between pipeline stages. Every method defaults to a no-op.
+pub trait MergeHooks {
+    /// Called as each pipeline step completes; cannot alter the merge.
+    fn progress(&mut self, _progress: Progress) {}
+
+    /// Polled whenever progress is reported, and while scaling and matching
+    /// a large image about every 16 million multiply-adds; returning `true`
+    /// aborts the merge with
+    /// [`MergeError::Cancelled`](crate::MergeError::Cancelled).
+    fn is_cancelled(&mut self) -> bool {
+        false
+    }
+
+    /// Called with the oriented size of every decoded image.
+    fn after_decode(&mut self, _dimensions: &[(u32, u32)]) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Smart mode: called with the chrome trims for each (scaled) image.
+    fn after_trims(&mut self, _trims: &mut [ChromeTrim]) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Smart mode: called with the detected overlap for each adjacent pair;
+    /// setting an entry to 0 vetoes that overlap.
+    fn after_matching(&mut self, _overlaps: &mut [u32]) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Smart mode: called with the horizontal jitter correction for each
+    /// adjacent pair, in pixels the lower image shifts right (0 where
+    /// nothing matched).
+    fn after_alignment(&mut self, _x_shifts: &mut [i32]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Hooks that change nothing; used by the plain merge entry points.
+pub struct NoHooks;
+
+impl MergeHooks for NoHooks {}
+
+/// Work between two polls of [`MergeHooks::is_cancelled`] from inside a
+/// long loop, in multiply-adds (a few milliseconds).
+const CANCEL_QUANTUM: u64 = 1 << 24;
+
+/// Polls [`MergeHooks::is_cancelled`] from inside scaling and matching loops,
+/// once per [`CANCEL_QUANTUM`] of work, so a cancelled merge stops partway
+/// through a large image rather than at the next progress step.
+pub(crate) struct Interrupt<'a> {
+    hooks: Option<&'a mut dyn MergeHooks>,
+    /// Work counted since the last poll.
+    work: u64,
+    cancelled: bool,
+}
+
+impl<'a> Interrupt<'a> {
+    pub(crate) fn new(hooks: &'a mut dyn MergeHooks) -> Self {
+        Interrupt {
+            hooks: Some(hooks),
+            work: 0,
+            cancelled: false,
+        }
+    }
+
+    /// An interrupt that never fires, for work that cannot be cancelled.
+    pub(crate) fn none() -> Self {
+        Interrupt {
+            hooks: None,
+            work: 0,
+            cancelled: false,
+        }
+    }
+
+    /// Counts `work` multiply-adds, polling the hooks once a quantum has
+    /// built up. Returns `true` once the merge is cancelled; the caller
+    /// should then stop, and its partial result is discarded.
+    pub(crate) fn tick(&mut self, work: u64) -> bool {
+        self.work += work;
+        if self.work >= CANCEL_QUANTUM && !self.cancelled {
+            self.work = 0;
+            self.cancelled = self.hooks.as_mut().is_some_and(|h| h.is_cancelled());
+        }
+        self.cancelled
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+}
@@ -0,0 +1,206 @@
+//! Entry selection in ICO files, which hold the same icon at several sizes.
+//!
+//! The `image` crate's ICO decoder picks the entry with the most bits per
+//! pixel, so a 32-bit 16x16 entry wins over an 8-bit 256x256 one. Entries are
+//! chosen here instead and the file is rewritten with only that entry, which
+//! the decoder then has no choice about.
+
+use std::borrow::Cow;
+
+/// Size of the ICO file header and of one directory entry.
+const HEADER_SIZE: usize = 6;
+const ENTRY_SIZE: usize = 16;
+
+/// The bytes of an ICO holding only the entry of `bytes` to merge: the one
+/// whose larger side is `size` pixels, or the largest when `None`. Between
+/// entries of one size, the one with the most bits per pixel is used.
+///
+/// Other images are borrowed unchanged, unless a size was asked for.
+pub(crate) fn select_icon(bytes: &[u8], size: Option<u32>) -> Result<Cow<'_, [u8]>, String> {
+    let Some(entries) = entries(bytes) else {
+        return match size {
+            Some(size) => Err(format!(
+                "Icon size {size} requested, but the image is not an ICO"
+            )),
+            None => Ok(Cow::Borrowed(bytes)),
+        };
+    };
+    let entry = match size {
+        Some(size) => entries
+            .iter()
+            .filter(|entry| entry.side() == size)
+            .max_by_key(|entry| entry.bits_per_pixel)
+            .ok_or_else(|| {
+                let mut sizes: Vec<u32> = entries.iter().map(Entry::side).collect();
+                sizes.sort_unstable();
+                sizes.dedup();
+                let sizes: Vec<String> = sizes.iter().map(u32::to_string).collect();
+                format!(
+                    "Icon size {size} requested, but the icon holds sizes {}",
+                    sizes.join(", ")
+                )
+            })?,
+        None => entries
+            .iter()
+            .max_by_key(|entry| {
+                (
+                    entry.width as u64 * entry.height as u64,
+                    entry.bits_per_pixel,
+                )
+            })
+            .expect("an ICO has entries"),
+    };
+    if entries.len() == 1 {
+        return Ok(Cow::Borrowed(bytes));
+    }
+
+    let data = &bytes[entry.data.clone()];
+    let mut icon = Vec::with_capacity(HEADER_SIZE + ENTRY_SIZE + data.len());
+    icon.extend_from_slice(&bytes[..4]);
+    icon.extend_from_slice(&1u16.to_le_bytes());
+    icon.extend_from_slice(&bytes[entry.directory..entry.directory + 12]);
+    icon.extend_from_slice(&((HEADER_SIZE + ENTRY_SIZE) as u32).to_le_bytes());
+    icon.extend_from_slice(data);
+    Ok(Cow::Owned(icon))
+}
+
+/// One image in an ICO, with the size of the image itself (the directory
+/// only holds sizes up to 256).
+struct Entry {
+    directory: usize,
+    width: u32,
+    height: u32,
+    bits_per_pixel: u16,
+    data: std::ops::Range<usize>,
+}
+
+impl Entry {
+    /// The larger side, which `icon_size` is matched against.
+    fn side(&self) -> u32 {
+        self.width.max(self.height)
+    }
+}
+
+/// The entries of an ICO whose data lies within `bytes`, or `None` when
+/// `bytes` are not an ICO or hold no readable entry.
+fn entries(bytes: &[u8]) -> Option<Vec<Entry>> {
+    let u16_at = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let u32_at = |at: usize| Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    // Reserved 0, then type 1 (icon rather than cursor)
+    if u16_at(0)? != 0 || u16_at(2)? != 1 {
+        return None;
+    }
+    let entries: Vec<Entry> = (0..u16_at(4)? as usize)
+        .filter_map(|index| {
+            let directory = HEADER_SIZE + index * ENTRY_SIZE;
+            let listed = |at: usize| match *bytes.get(at)? {
+                0 => Some(256),
+                side => Some(side as u32),
+            };
+            let length = u32_at(directory + 8)? as usize;
+            let start = u32_at(directory + 12)? as usize;
+            let data = start..start.checked_add(length)?;
+            let image = bytes.get(data.clone())?;
+            let (width, height) =
+                embedded_size(image).unwrap_or((listed(directory)?, listed(directory + 1)?));
+            Some(Entry {
+                directory,
+                width,
+                height,
+                bits_per_pixel: u16_at(directory + 6)?,
+                data,
+            })
+        })
+        .collect();
+    (!entries.is_empty()).then_some(entries)
+}
+
+/// Size of an entry's image, read from its PNG or BMP header.
+fn embedded_size(image: &[u8]) -> Option<(u32, u32)> {
+    let u32_at = |at: usize, big_endian: bool| {
+        let field: [u8; 4] = image.get(at..at + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(field)
+        } else {
+            u32::from_le_bytes(field)
+        })
+    };
+    if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // IHDR is the first chunk
+        return Some((u32_at(16, true)?, u32_at(20, true)?));
+    }
+    // A BMP info header without its file header; the height covers both the
+    // color and the AND mask, so is twice the icon's
+    let width = u32_at(4, false)? as i32;
+    let height = u32_at(8, false)? as i32;
+    Some((width.unsigned_abs(), height.unsigned_abs() / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::ico::{IcoEncoder, IcoFrame};
+    use image::codecs::png::PngEncoder;
+    use image::{ExtendedColorType, ImageEncoder};
+
+    /// An ICO with one solid PNG entry per `(side, bits_per_pixel)`. Embedded
+    /// PNGs must be RGBA, so the directory's bit depth is only a label.
+    fn create_test_ico(entries: &[(u32, u16)]) -> Vec<u8> {
+        let frames: Vec<IcoFrame> = entries
+            .iter()
+            .map(|&(side, bits)| {
+                let pixels = vec![side as u8; (side * side * 4) as usize];
+                let mut png = Vec::new();
+                PngEncoder::new(&mut png)
+                    .write_image(&pixels, side, side, ExtendedColorType::Rgba8)
+                    .unwrap();
+                let color = match bits {
+                    32 => ExtendedColorType::Rgba8,
+                    24 => ExtendedColorType::Rgb8,
+                    _ => ExtendedColorType::L8,
+                };
+                IcoFrame::with_encoded(png, side, side, color).unwrap()
+            })
+            .collect();
+        let mut bytes = Vec::new();
+        IcoEncoder::new(&mut bytes).encode_images(&frames).unwrap();
+        bytes
+    }
+
+    fn side_of(bytes: &[u8]) -> u32 {
+        let img = image::load_from_memory(bytes).unwrap();
+        assert_eq!(img.width(), img.height());
+        img.width()
+    }
+
+    #[test]
+    fn test_select_icon_prefers_the_largest() {
+        // The decoder alone would pick a 32-bit entry over the 8-bit 48x48 one
+        let ico = create_test_ico(&[(16, 32), (48, 8), (32, 32)]);
+        assert_eq!(side_of(&ico), 32);
+        assert_eq!(side_of(&select_icon(&ico, None).unwrap()), 48);
+    }
+
+    #[test]
+    fn test_select_icon_by_size() {
+        let ico = create_test_ico(&[(16, 32), (48, 8), (32, 32)]);
+        assert_eq!(side_of(&select_icon(&ico, Some(32)).unwrap()), 32);
+        assert_eq!(side_of(&select_icon(&ico, Some(48)).unwrap()), 48);
+        assert_eq!(
+            select_icon(&ico, Some(64)).unwrap_err(),
+            "Icon size 64 requested, but the icon holds sizes 16, 32, 48"
+        );
+    }
+
+    #[test]
+    fn test_select_icon_of_other_images() {
+        let single = create_test_ico(&[(16, 32)]);
+        assert!(matches!(select_icon(&single, None), Ok(Cow::Borrowed(_))));
+        let png = b"\x89PNG\r\n\x1a\n";
+        assert!(matches!(select_icon(png, None), Ok(Cow::Borrowed(_))));
+        assert_eq!(
+            select_icon(png, Some(16)).unwrap_err(),
+            "Icon size 16 requested, but the image is not an ICO"
+        );
+    }
+}
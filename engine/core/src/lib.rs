@@ -0,0 +1,56 @@
+//! Image merging core: decoding, scaling, Smart mode overlap detection,
+//! compositing and encoding, with no dependency on the browser.
+//!
+//! The `merge-images-engine` crate wraps this for WebAssembly; native callers
+//! use [`merge`] and its variants directly with [`MergeOptions`] (see
+//! [`MergeOptions::builder`]).
+
+mod background;
+mod chrome_strip;
+mod correlation;
+mod dimension;
+mod dpi;
+mod error;
+mod exif;
+mod guides;
+mod hooks;
+mod ico;
+mod margins;
+mod merge;
+mod metadata;
+mod overlap;
+mod pagination;
+mod pdf;
+mod placeholder;
+mod repro;
+mod scale;
+mod session;
+mod shadow;
+mod simd;
+mod stats;
+mod stepped;
+mod text;
+mod tiff_pages;
+mod types;
+
+pub use error::{ErrorParam, MergeError, MergeWarning, OptionsError, render_template};
+pub use hooks::{ChromeTrim, HookStage, MergeHooks, NoHooks, Progress};
+pub use image::ImageFormat;
+pub use merge::{
+    analyze, execute_plan, input_blur_hashes, merge, merge_raw, merge_split, merge_tiled,
+    merge_to_animation, merge_to_pdf, merge_to_writer, merge_with_hooks, merge_with_metadata,
+    paginate, pixel_hash, probe,
+};
+pub use repro::ReproBundle;
+pub use session::Session;
+pub use stats::Clock;
+pub use stepped::SteppedMerge;
+pub use types::{
+    Align, AnimationFormat, AnimationOutput, BackgroundColor, BackgroundFit, BackgroundImage,
+    Border, CaptionPosition, ChromeMode, ContactSheet, Corner, Direction, Divider, ExifCopy,
+    Gradient, Guides, ImageAnalysis, ImageInput, ImageOptions, ImageProbe, InputSource, InputStats,
+    LowColorScaling, MergeAnalysis, MergeOptions, MergeOptionsBuilder, MergeOutput, MergeStats,
+    OverlapRegion, Padding, Page, PageNumbers, PairAnalysis, PdfLayout, PdfOutput, Placement,
+    PreviewOutput, RawOutput, Rotation, ScaleMode, SeamBreak, SeamLines, Shadow, SplitMergeOutput,
+    SplitReason, StageDurations, TiledOutput, TrimMargins, VerticalAlign, to_hex,
+};
@@ -0,0 +1,124 @@
+//! Solid-color margins around an input (letterboxing, a flat backdrop
+//! around a window and its shadow), found so they can be cropped away
+//! before layout.
+//!
+//! Each side is scanned inwards from the edge: rows (or columns) whose every
+//! pixel is within the tolerance of that side's outermost pixel are margin.
+//! Fully transparent pixels all count as the same color.
+
+use image::{DynamicImage, GenericImageView, Rgba};
+
+/// Content rectangle `(x, y, width, height)` of `img` without its uniform
+/// margins, or `None` when there is nothing to trim.
+///
+/// `tolerance` is the largest per-channel difference still counted as the
+/// margin color. An image that is one color throughout is left alone, and
+/// so are columns when the rows left after trimming are one color.
+pub fn content_bounds(img: &DynamicImage, tolerance: u8) -> Option<(u32, u32, u32, u32)> {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let matches = |x: u32, y: u32, reference: Rgba<u8>| {
+        let pixel = img.get_pixel(x, y);
+        (pixel[3] == 0 && reference[3] == 0)
+            || pixel
+                .0
+                .iter()
+                .zip(reference.0)
+                .all(|(a, b)| a.abs_diff(b) <= tolerance)
+    };
+
+    let row = |y: u32, reference| (0..width).all(|x| matches(x, y, reference));
+    let reference = img.get_pixel(0, 0);
+    let top = (0..height).find(|&y| !row(y, reference))?;
+    let reference = img.get_pixel(0, height - 1);
+    let bottom = (top..height)
+        .rev()
+        .find(|&y| !row(y, reference))
+        .map_or(height, |y| y + 1);
+
+    let column = |x: u32, reference| (top..bottom).all(|y| matches(x, y, reference));
+    let reference = img.get_pixel(0, top);
+    let (left, right) = match (0..width).find(|&x| !column(x, reference)) {
+        Some(left) => {
+            let reference = img.get_pixel(width - 1, top);
+            let right = (left..width)
+                .rev()
+                .find(|&x| !column(x, reference))
+                .map_or(width, |x| x + 1);
+            (left, right)
+        }
+        None => (0, width),
+    };
+
+    let bounds = (left, top, right - left, bottom - top);
+    (bounds != (0, 0, width, height)).then_some(bounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    const RED: Rgba<u8> = Rgba([200, 30, 30, 255]);
+
+    /// `width` x `height` of `margin`, with a red block at `(x, y, w, h)`.
+    fn framed(size: (u32, u32), block: (u32, u32, u32, u32), margin: Rgba<u8>) -> DynamicImage {
+        let (x0, y0, w, h) = block;
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(size.0, size.1, |x, y| {
+            if (x0..x0 + w).contains(&x) && (y0..y0 + h).contains(&y) {
+                RED
+            } else {
+                margin
+            }
+        }))
+    }
+
+    #[test]
+    fn test_content_bounds_trims_every_side() {
+        let img = framed((20, 16), (3, 2, 10, 9), Rgba([255, 255, 255, 255]));
+        assert_eq!(content_bounds(&img, 0), Some((3, 2, 10, 9)));
+    }
+
+    #[test]
+    fn test_content_bounds_letterbox_keeps_width() {
+        let img = framed((12, 20), (0, 4, 12, 10), Rgba([0, 0, 0, 255]));
+        assert_eq!(content_bounds(&img, 0), Some((0, 4, 12, 10)));
+    }
+
+    #[test]
+    fn test_content_bounds_tolerance() {
+        // A margin with slight noise, as left by JPEG compression
+        let mut img = framed((10, 10), (2, 2, 6, 6), Rgba([250, 250, 250, 255])).to_rgba8();
+        img.put_pixel(0, 5, Rgba([244, 247, 252, 255]));
+        let img = DynamicImage::ImageRgba8(img);
+        assert_eq!(content_bounds(&img, 0), Some((0, 2, 8, 6)));
+        assert_eq!(content_bounds(&img, 8), Some((2, 2, 6, 6)));
+    }
+
+    #[test]
+    fn test_content_bounds_transparent_margin() {
+        let mut img = framed((10, 10), (2, 3, 5, 4), Rgba([0, 0, 0, 0])).to_rgba8();
+        img.put_pixel(0, 0, Rgba([255, 255, 255, 0]));
+        let img = DynamicImage::ImageRgba8(img);
+        assert_eq!(content_bounds(&img, 0), Some((2, 3, 5, 4)));
+    }
+
+    #[test]
+    fn test_content_bounds_nothing_to_trim() {
+        let uniform = framed((8, 8), (0, 0, 0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(content_bounds(&uniform, 0), None);
+        let edge_to_edge = framed((8, 8), (0, 0, 8, 8), Rgba([255, 255, 255, 255]));
+        assert_eq!(content_bounds(&edge_to_edge, 0), None);
+        // Black bars above a white page: the page is not trimmed away sideways
+        let barred = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |_, y| {
+            if y < 2 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }));
+        assert_eq!(content_bounds(&barred, 0), Some((0, 2, 8, 6)));
+    }
+}
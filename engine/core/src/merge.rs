@@ -0,0 +1,6804 @@
+use image::buffer::ConvertBuffer;
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::webp::WebPDecoder;
+use image::{
+    AnimationDecoder, ColorType, Delay, DynamicImage, Frame, Frames, ImageBuffer, ImageDecoder,
+    ImageFormat, ImageReader, Pixel, Rgba, RgbaImage,
+};
+use sha2::{Digest, Sha256};
+use std::borrow::{Borrow, Cow};
+use std::io::{Cursor, Write};
+
+use crate::background::{paint_gradient, paint_image};
+use crate::chrome_strip::{ChromeTrim, compute_chrome_trims};
+use crate::dimension::{
+    align_offset, apply_padding, compute_grid_size, compute_output_size, compute_scaled_dimensions,
+    compute_target_dimension, fit_width, fit_within, grid_column_width, grid_row_heights,
+    normalize_dpi, scale_factor, snap_to_multiple,
+};
+use crate::dpi::{pixels_per_meter, read_dpi};
+use crate::error::{MergeError, MergeWarning};
+use crate::exif::{Orientation, extract_exif, extract_orientation, normalize_orientation};
+use crate::guides::render_guides;
+use crate::hooks::{HookStage, Interrupt, MergeHooks, NoHooks, Progress};
+use crate::ico::select_icon;
+use crate::margins::content_bounds;
+use crate::metadata::{PROVENANCE_KEYWORD, provenance, select_exif};
+use crate::overlap::{
+    OverlapParams, OverlapResult, detect_overlaps_with_trims, detect_pair_overlap_with_trims,
+};
+use crate::pagination::{page_breaks, page_breaks_between};
+use crate::pdf::write_pdf;
+use crate::placeholder::blur_hash;
+use crate::repro::{ReproBundle, build_repro_bundle};
+use crate::scale::{Resampling, is_low_color_png, scale_image_nearest, scale_image_with};
+use crate::shadow::{frame_mask, render_shadow, shadow_scratch_bytes};
+use crate::stats::Stopwatch;
+use crate::text::{caption_strip_height, draw_caption, draw_corner_label};
+use crate::tiff_pages::select_page;
+use crate::types::{
+    Align, AnimationFormat, AnimationOutput, BackgroundColor, Border, CaptionPosition, ChromeMode,
+    Direction, Divider, ImageAnalysis, ImageInput, ImageOptions, ImageProbe, InputSource,
+    InputStats, LowColorScaling, MergeAnalysis, MergeOptions, MergeOutput, MergeStats,
+    OverlapRegion, Padding, Page, PairAnalysis, PdfLayout, PdfOutput, Placement, PreviewOutput,
+    RawOutput, Rotation, ScaleMode, SeamBreak, SeamLines, SplitMergeOutput, SplitReason,
+    StageDurations, TiledOutput, to_hex,
+};
+
+/// Smallest scale `downscale_to_fit` will apply before giving up.
+const MIN_FIT_FACTOR: f64 = 0.05;
+
+/// Most pixels a stepped merge composites per step (a band of rows).
+const DRAW_BAND_PIXELS: u64 = 1 << 20;
+
+/// Spacing and padding of a contact sheet whose options leave them at 0.
+const CONTACT_SHEET_GAP: u32 = 16;
+
+/// Size of a contact sheet's header relative to `caption_size`.
+const CONTACT_SHEET_HEADER_SCALE: f32 = 1.5;
+
+/// Compressed bytes per PNG `IDAT` chunk when streaming the output.
+const PNG_CHUNK_BYTES: usize = 1 << 16;
+
+/// Decodes an image from raw bytes.
+fn decode_image(bytes: &[u8]) -> Result<DynamicImage, String> {
+    decode_image_as(bytes, None)
+}
+
+/// Decodes an image from raw bytes in `format`, or a guessed format if `None`.
+fn decode_image_as(bytes: &[u8], format: Option<ImageFormat>) -> Result<DynamicImage, String> {
+    let reader = image_reader(bytes, format).map_err(|e| e.to_string())?;
+    reader.decode().map_err(|e| e.to_string())
+}
+
+/// Decodes frame `frame` of an animated GIF or WebP, composited over the
+/// frames before it; a still image only has frame 0.
+fn decode_frame(
+    bytes: &[u8],
+    format: Option<ImageFormat>,
+    frame: u32,
+) -> Result<DynamicImage, String> {
+    let Some(frames) = animation_frames(bytes, format)? else {
+        if frame == 0 {
+            return decode_image_as(bytes, format);
+        }
+        return Err(format!(
+            "Frame {frame} requested, but the image is not animated"
+        ));
+    };
+    let mut count = 0;
+    for (index, result) in frames.enumerate() {
+        let decoded = result.map_err(|e| e.to_string())?;
+        if index == frame as usize {
+            return Ok(DynamicImage::ImageRgba8(decoded.into_buffer()));
+        }
+        count = index + 1;
+    }
+    Err(format!(
+        "Frame {frame} requested, but the animation has {count} frames"
+    ))
+}
+
+/// The frames of a GIF or animated WebP, or `None` for other images.
+fn animation_frames(
+    bytes: &[u8],
+    format: Option<ImageFormat>,
+) -> Result<Option<Frames<'_>>, String> {
+    let format = match format {
+        Some(format) => format,
+        None => image::guess_format(bytes).map_err(|e| e.to_string())?,
+    };
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(bytes))
+            .map_err(|e| e.to_string())?
+            .into_frames(),
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(frames))
+}
+
+/// Whether `input` is an animation that will be flattened to its first
+/// frame because no `frame` was chosen.
+pub(crate) fn flattens_animation(input: &ImageInput) -> bool {
+    input.frame.is_none()
+        && input.encoded().is_some_and(|bytes| {
+            animation_frames(bytes, input.format)
+                .ok()
+                .flatten()
+                .is_some_and(|frames| frames.take(2).count() > 1)
+        })
+}
+
+/// A reader for `bytes` in `format`, or a guessed format if `None`.
+fn image_reader(
+    bytes: &[u8],
+    format: Option<ImageFormat>,
+) -> std::io::Result<ImageReader<Cursor<&[u8]>>> {
+    match format {
+        Some(format) => Ok(ImageReader::with_format(Cursor::new(bytes), format)),
+        None => ImageReader::new(Cursor::new(bytes)).with_guessed_format(),
+    }
+}
+
+/// Bytes an image will occupy once decoded, read from its header; 0 when the
+/// header cannot be read (decoding then reports the error).
+fn decoded_size(input: &ImageInput) -> u64 {
+    let bytes = match &input.source {
+        InputSource::Encoded(bytes) => bytes,
+        InputSource::Rgba { pixels, .. } => return pixels.len() as u64,
+    };
+    image_reader(bytes, input.format)
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+        .map_or(0, |decoder| decoder.total_bytes())
+}
+
+/// Stored size of an input (of its chosen page or icon entry), read from
+/// its header; `None` when the header cannot be read (decoding then reports
+/// the error).
+fn header_dimensions(input: &ImageInput) -> Option<(u32, u32)> {
+    let bytes = match &input.source {
+        InputSource::Encoded(bytes) => bytes,
+        InputSource::Rgba { width, height, .. } => return Some((*width, *height)),
+    };
+    let bytes = select_page(bytes, input.page.unwrap_or(0)).ok()?;
+    let bytes = select_icon(&bytes, input.icon_size).ok()?;
+    let decoder = image_reader(&bytes, input.format)
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    Some(decoder.dimensions())
+}
+
+/// Fails with [`MergeError::InputTooLarge`] when the image at `index`,
+/// `width` by `height` pixels, exceeds `max_input_pixels` or
+/// `max_input_dimension`.
+pub(crate) fn check_input_size(
+    index: usize,
+    file_name: Option<&str>,
+    (width, height): (u32, u32),
+    options: &MergeOptions,
+) -> Result<(), MergeError> {
+    let too_many_pixels = options
+        .max_input_pixels
+        .is_some_and(|max| width as u64 * height as u64 > max);
+    let too_long = options
+        .max_input_dimension
+        .is_some_and(|max| width.max(height) > max);
+    if too_many_pixels || too_long {
+        return Err(MergeError::InputTooLarge {
+            index,
+            file_name: file_name.map(str::to_string),
+            width,
+            height,
+            max_pixels: options.max_input_pixels,
+            max_dimension: options.max_input_dimension,
+        });
+    }
+    Ok(())
+}
+
+/// Merges multiple images into a single output image.
+///
+/// # Arguments
+/// * `images_data` - Vector of raw image bytes for each input image
+/// * `options` - Merge options (direction, background)
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - PNG-encoded output image bytes
+/// * `Err(MergeError)` - Error details if merge fails
+pub fn merge(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+) -> Result<Vec<u8>, MergeError> {
+    merge_with_metadata(images_data, options).map(|output| output.bytes)
+}
+
+/// Merges multiple images and returns the encoded bytes with output metadata.
+///
+/// Same pipeline as [`merge`]; the result additionally carries the output
+/// dimensions and can report its byte length and checksum.
+pub fn merge_with_metadata(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+) -> Result<MergeOutput, MergeError> {
+    // Check for empty input
+    if images_data.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    // Step 1: Decode all images and normalize EXIF orientation
+    let mut stopwatch = Stopwatch::start(options.clock);
+    let (decoded_images, options) = prepare_inputs(images_data, options, &mut NoHooks)?;
+    let decode_ms = stopwatch.lap();
+    let output = merge_decoded(&decoded_images, &options, &mut NoHooks, None)?;
+    Ok(with_decode_time(output, decode_ms))
+}
+
+/// Merges like [`merge_with_metadata`], but streams the encoded PNG into
+/// `sink` as it is compressed instead of collecting it, so the encoded output
+/// never has to fit in memory next to the canvas.
+///
+/// Only the encoding streams: the canvas is still composited whole before
+/// its first row is written, even when no rotation, preview, thumbnail or
+/// BlurHash needs all of it. Peak memory is the estimate checked against
+/// `max_memory_bytes` (decoded inputs, scaled copies, canvas), plus any
+/// previews and about one 64 KiB IDAT chunk of encoder buffers.
+///
+/// The returned output's `bytes` is empty; its other fields are filled in as
+/// usual. Write errors fail the merge with [`MergeError::EncodeError`].
+pub fn merge_to_writer(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+    sink: &mut dyn Write,
+) -> Result<MergeOutput, MergeError> {
+    if images_data.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    let mut stopwatch = Stopwatch::start(options.clock);
+    let (decoded_images, options) = prepare_inputs(images_data, options, &mut NoHooks)?;
+    let decode_ms = stopwatch.lap();
+    let output = merge_decoded(&decoded_images, &options, &mut NoHooks, Some(sink))?;
+    Ok(with_decode_time(output, decode_ms))
+}
+
+/// Merges like [`merge_with_metadata`], calling `hooks` between pipeline stages.
+///
+/// Hooks may adjust chrome trims and overlaps in place, or reject the merge,
+/// which fails with [`MergeError::HookRejected`]. Hooks that report
+/// cancellation stop the merge at the next progress step with
+/// [`MergeError::Cancelled`].
+pub fn merge_with_hooks(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+    hooks: &mut dyn MergeHooks,
+) -> Result<MergeOutput, MergeError> {
+    if images_data.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    let mut stopwatch = Stopwatch::start(options.clock);
+    let (decoded_images, options) = prepare_inputs(images_data, options, hooks)?;
+    let decode_ms = stopwatch.lap();
+    let output = merge_decoded(&decoded_images, &options, hooks, None)?;
+    Ok(with_decode_time(output, decode_ms))
+}
+
+/// Merges images already decoded with [`decode_input`], such as those kept by
+/// a [`Session`](crate::session::Session), like [`merge_with_metadata`].
+///
+/// The images are borrowed in merge order, so `options.order` is ignored;
+/// `files` holds the original file bytes of each encoded image, from which the
+/// same per-image facts are read as during a normal merge.
+pub(crate) fn merge_decoded_inputs(
+    decoded_images: &[&DynamicImage],
+    files: &[Option<&[u8]>],
+    options: MergeOptions,
+) -> Result<MergeOutput, MergeError> {
+    if decoded_images.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    let names = options.file_names.clone();
+    let options = expand_contact_sheet(read_input_facts(options, files), &names);
+    if let Some(trim) = options.trim_margins {
+        let trimmed: Vec<Cow<DynamicImage>> = decoded_images
+            .iter()
+            .map(|&img| crop_to_content(img, trim.tolerance).map_or(Cow::Borrowed(img), Cow::Owned))
+            .collect();
+        return merge_decoded(&trimmed, &options, &mut NoHooks, None);
+    }
+    merge_decoded(decoded_images, &options, &mut NoHooks, None)
+}
+
+/// Adds the decode stage to the output's statistics, if collected.
+fn with_decode_time(mut output: MergeOutput, decode_ms: f64) -> MergeOutput {
+    if let Some(stats) = &mut output.stats {
+        stats.durations.decode_ms = decode_ms;
+        stats.durations.total_ms += decode_ms;
+    }
+    output
+}
+
+/// Merges like [`merge_with_metadata`] but returns the unencoded RGBA canvas,
+/// skipping PNG encoding for interactive display.
+///
+/// Output rotation and drawn-in guides are applied; previews, thumbnails,
+/// BlurHash and separate guide layers are not produced.
+pub fn merge_raw(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+) -> Result<RawOutput, MergeError> {
+    if images_data.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    let (decoded_images, options) = prepare_inputs(images_data, options, &mut NoHooks)?;
+    let (canvas, _) = composite_decoded(&decoded_images, &options, &mut NoHooks)?;
+    let mut output = rotate_output(canvas.output, options.output_rotation);
+    if let Some(guides) = options.guides.filter(|g| !g.separate_layer) {
+        render_guides(&mut output, &guides);
+    }
+    let (width, height) = output.dimensions();
+    Ok(RawOutput {
+        pixels: output.into_raw(),
+        width,
+        height,
+        warnings: canvas.warnings,
+    })
+}
+
+/// Merges images in Smart mode, splitting the sequence into several outputs
+/// at seams where overlap detection fails or is less confident than
+/// `options.split_min_confidence`.
+///
+/// Each output is produced exactly as [`merge_with_metadata`] would for its
+/// run of images. Other directions never split and yield a single output.
+pub fn merge_split(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+) -> Result<SplitMergeOutput, MergeError> {
+    if images_data.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    let (decoded_images, options) = prepare_inputs(images_data, options, &mut NoHooks)?;
+    let total = decoded_images.len();
+    let breaks = if options.direction == Direction::Smart {
+        find_seam_breaks(&decoded_images, &options)?
+    } else {
+        vec![]
+    };
+
+    let mut outputs = Vec::with_capacity(breaks.len() + 1);
+    let mut remaining = decoded_images.into_iter();
+    let mut start = 0;
+    for end in breaks
+        .iter()
+        .map(|b| b.after_index + 1)
+        .chain(std::iter::once(total))
+    {
+        let group: Vec<DynamicImage> = remaining.by_ref().take(end - start).collect();
+        let group_options = options_for_range(&options, start, end);
+        let mut output = merge_decoded(&group, &group_options, &mut NoHooks, None)
+            .map_err(|e| offset_error_index(e, start))?;
+        output.warnings = output
+            .warnings
+            .into_iter()
+            .map(|w| offset_warning_index(w, start))
+            .collect();
+        outputs.push(output);
+        start = end;
+    }
+
+    Ok(SplitMergeOutput { outputs, breaks })
+}
+
+/// Cuts a tall image, such as a merged output or one long capture, into
+/// pages at most `page_height` rows tall for platforms that limit image
+/// height, each encoded as PNG.
+///
+/// Each cut is made at the calmest seam among the `search_rows` rows above
+/// the page limit (default: a fifth of `page_height`), which usually lies
+/// between lines of text; see [`page_breaks`]. Every page keeps the full
+/// width. The image is decoded and EXIF-oriented as for a merge.
+pub fn paginate(
+    image_data: impl Into<ImageInput>,
+    page_height: u32,
+    search_rows: Option<u32>,
+) -> Result<Vec<Page>, MergeError> {
+    let img = decode_images(&[image_data.into()], &mut NoHooks)?
+        .pop()
+        .ok_or(MergeError::NoImages)?;
+    let search_rows = search_rows.unwrap_or(page_height / 5);
+    let breaks = page_breaks(&img, page_height, search_rows);
+    encode_pages(&img, &breaks)
+}
+
+/// Merges like [`merge_raw`], then cuts the result into PNG tiles at most
+/// `tile_height` rows tall, so a merge too tall for one file or canvas
+/// still comes out as sequentially numbered images that reassemble into it.
+///
+/// Cuts come from the layout: each falls between two placed images when a
+/// seam between them lies in the lower half of the tile, at the calmest such
+/// row (spacing rather than a caption). Only otherwise does a cut go through
+/// an image, at its calmest row as for [`paginate`]. `max_output_pixels`
+/// limits every tile rather than the whole merge, shortening the tiles of a
+/// wide merge; the whole canvas must still fit in `max_memory_bytes`.
+pub fn merge_tiled(
+    images_data: Vec<impl Into<ImageInput>>,
+    mut options: MergeOptions,
+    tile_height: u32,
+) -> Result<TiledOutput, MergeError> {
+    if images_data.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    let max_pixels = options.max_output_pixels.take();
+    let (decoded_images, options) = prepare_inputs(images_data, options, &mut NoHooks)?;
+    let (mut canvas, _) = composite_decoded(&decoded_images, &options, &mut NoHooks)?;
+    let warnings = std::mem::take(&mut canvas.warnings);
+    let (output, placements) = flatten_canvas(canvas, &options);
+    let spans: Vec<(u32, u32)> = placements
+        .iter()
+        .map(|placement| (placement.y, placement.y + placement.height))
+        .collect();
+    let (width, height) = output.dimensions();
+
+    let mut tile_height = tile_height;
+    if let Some(max_pixels) = max_pixels {
+        let rows = max_pixels / width.max(1) as u64;
+        if rows == 0 {
+            return Err(MergeError::OutputTooLarge {
+                width: width as u64,
+                height: 1,
+                max_pixels,
+            });
+        }
+        tile_height = tile_height.min(rows.min(u32::MAX as u64) as u32);
+    }
+    let output = DynamicImage::ImageRgba8(output);
+    let breaks = page_breaks_between(&output, tile_height, tile_height / 2, &spans);
+    Ok(TiledOutput {
+        tiles: encode_pages(&output, &breaks)?,
+        width,
+        height,
+        warnings,
+    })
+}
+
+/// Merges like [`merge_raw`] and exports the result as a PDF document,
+/// either as one long page or with one page per image (see [`PdfLayout`]).
+///
+/// Pixels are embedded losslessly. Pages are sized from `options.dpi`
+/// (72 DPI, one point per pixel, when unset), so a merge tagged with its
+/// capture density prints at its physical size.
+pub fn merge_to_pdf(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+    layout: PdfLayout,
+) -> Result<PdfOutput, MergeError> {
+    if images_data.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    let (decoded_images, options) = prepare_inputs(images_data, options, &mut NoHooks)?;
+    let (mut canvas, _) = composite_decoded(&decoded_images, &options, &mut NoHooks)?;
+    let warnings = std::mem::take(&mut canvas.warnings);
+    let (output, placements) = flatten_canvas(canvas, &options);
+    let pages = match layout {
+        PdfLayout::LongPage => vec![output],
+        PdfLayout::PerImage => placements
+            .iter()
+            .filter(|p| p.width > 0 && p.height > 0)
+            .map(|p| image::imageops::crop_imm(&output, p.x, p.y, p.width, p.height).to_image())
+            .collect(),
+    };
+    let points_per_pixel = options.dpi.map_or(1.0, |dpi| 72.0 / dpi);
+    Ok(PdfOutput {
+        bytes: write_pdf(&pages, points_per_pixel),
+        page_count: pages.len() as u32,
+        warnings,
+    })
+}
+
+/// Assembles the inputs as the frames of a looping animation instead of
+/// stacking them, each shown for `frame_delay_ms`.
+///
+/// Every frame is rendered as a merge of that image alone, so padding,
+/// borders, captions and the other options apply to each. Frames of
+/// different sizes are centered on a canvas of the largest, filled with
+/// `background`.
+pub fn merge_to_animation(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+    format: AnimationFormat,
+    frame_delay_ms: u32,
+) -> Result<AnimationOutput, MergeError> {
+    if images_data.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    let (decoded_images, options) = prepare_inputs(images_data, options, &mut NoHooks)?;
+    let mut warnings = Vec::new();
+    let mut frames = Vec::with_capacity(decoded_images.len());
+    for (index, img) in decoded_images.iter().enumerate() {
+        let frame_options = options_for_range(&options, index, index + 1);
+        let (mut canvas, _) =
+            composite_decoded(std::slice::from_ref(img), &frame_options, &mut NoHooks)
+                .map_err(|e| offset_error_index(e, index))?;
+        warnings.extend(
+            canvas
+                .warnings
+                .drain(..)
+                .map(|w| offset_warning_index(w, index)),
+        );
+        frames.push(flatten_canvas(canvas, &frame_options).0);
+    }
+
+    let width = frames.iter().map(RgbaImage::width).max().unwrap_or(0);
+    let height = frames.iter().map(RgbaImage::height).max().unwrap_or(0);
+    let background = options.background;
+    let frames: Vec<RgbaImage> = frames
+        .into_iter()
+        .map(|frame| {
+            if frame.dimensions() == (width, height) {
+                return frame;
+            }
+            let mut padded = RgbaImage::from_pixel(
+                width,
+                height,
+                Rgba([background.r, background.g, background.b, background.a]),
+            );
+            image::imageops::replace(
+                &mut padded,
+                &frame,
+                align_offset(width, frame.width(), Align::Center) as i64,
+                align_offset(height, frame.height(), Align::Center) as i64,
+            );
+            padded
+        })
+        .collect();
+
+    let bytes = match format {
+        AnimationFormat::Apng => encode_apng(&frames, (width, height), frame_delay_ms),
+        AnimationFormat::Gif => encode_gif(frames, frame_delay_ms),
+    }
+    .map_err(|message| MergeError::EncodeError { message })?;
+    Ok(AnimationOutput {
+        bytes,
+        width,
+        height,
+        frame_count: decoded_images.len() as u32,
+        warnings,
+    })
+}
+
+/// Encodes equally sized frames as an endlessly looping APNG.
+fn encode_apng(frames: &[RgbaImage], size: (u32, u32), delay_ms: u32) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, size.0, size.1);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(frames.len() as u32, 0)
+        .map_err(|e| e.to_string())?;
+    encoder
+        .set_frame_delay(delay_ms.min(u16::MAX as u32) as u16, 1000)
+        .map_err(|e| e.to_string())?;
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    for frame in frames {
+        writer
+            .write_image_data(frame.as_raw())
+            .map_err(|e| e.to_string())?;
+    }
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Encodes frames as an endlessly looping GIF, quantized per frame.
+fn encode_gif(frames: Vec<RgbaImage>, delay_ms: u32) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut encoder = GifEncoder::new(&mut bytes);
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .map_err(|e| e.to_string())?;
+    let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+    encoder
+        .encode_frames(
+            frames
+                .into_iter()
+                .map(|frame| Frame::from_parts(frame, 0, 0, delay)),
+        )
+        .map_err(|e| e.to_string())?;
+    drop(encoder);
+    Ok(bytes)
+}
+
+/// The 8-bit canvas as output, rotated and with drawn-in guides, and where
+/// each image ended up on it.
+fn flatten_canvas(canvas: Canvas, options: &MergeOptions) -> (RgbaImage, Vec<Placement>) {
+    let dimensions = canvas.output.dimensions();
+    let placements = canvas
+        .placements
+        .into_iter()
+        .map(|placement| rotate_placement(placement, dimensions, options.output_rotation))
+        .collect();
+    let mut output = rotate_output(canvas.output, options.output_rotation);
+    if let Some(guides) = options.guides.filter(|g| !g.separate_layer) {
+        render_guides(&mut output, &guides);
+    }
+    (output, placements)
+}
+
+/// Encodes the full-width pages of `img` starting at each row of `breaks`.
+fn encode_pages(img: &DynamicImage, breaks: &[u32]) -> Result<Vec<Page>, MergeError> {
+    let ends = breaks.iter().skip(1).copied().chain([img.height()]);
+    breaks
+        .iter()
+        .zip(ends)
+        .map(|(&y, end)| {
+            let page = img.crop_imm(0, y, img.width(), end - y);
+            Ok(Page {
+                bytes: encode_png(&page)?,
+                width: page.width(),
+                height: page.height(),
+                y,
+            })
+        })
+        .collect()
+}
+
+/// Reads an image file's format, size, orientation and pixel layout from its
+/// header without decoding the pixels, so hosts can describe files and turn
+/// away unsupported ones before merging.
+///
+/// Fails with the [`MergeError::DecodeError`] merging the file would report
+/// when its format is unrecognized, not built in, or its header is invalid.
+pub fn probe(bytes: &[u8]) -> Result<ImageProbe, MergeError> {
+    let decode_error = |message: String| MergeError::DecodeError {
+        index: 0,
+        file_name: None,
+        message,
+    };
+    // An ICO reports the entry a merge of it would use
+    let bytes = &*select_icon(bytes, None).map_err(decode_error)?;
+    let reader = image_reader(bytes, None).map_err(|e| decode_error(e.to_string()))?;
+    let format = reader
+        .format()
+        .ok_or_else(|| decode_error("The image format could not be determined".to_string()))?;
+    let decoder = reader
+        .into_decoder()
+        .map_err(|e| decode_error(e.to_string()))?;
+    let (width, height) = decoder.dimensions();
+    let color = decoder.color_type();
+    Ok(ImageProbe {
+        format,
+        width,
+        height,
+        orientation: extract_orientation(bytes) as u8,
+        has_alpha: color.has_alpha(),
+        bit_depth: (color.bits_per_pixel() / color.channel_count() as u16) as u8,
+    })
+}
+
+/// Lowercase hex SHA-256 digest of an image file's decoded pixels, so hosts
+/// and tests can check that a merge produces the same pixels across browsers
+/// and engine versions even when the encoded bytes differ (compression,
+/// metadata).
+///
+/// The digest covers the width and height as little-endian `u32`s, then every
+/// pixel as RGBA in row order: one byte per channel, or two big-endian bytes
+/// for images stored with more than 8 bits per channel. EXIF orientation is
+/// not applied. Fails with [`MergeError::DecodeError`] like [`probe`].
+pub fn pixel_hash(bytes: &[u8]) -> Result<String, MergeError> {
+    let img = decode_image(bytes).map_err(|message| MergeError::DecodeError {
+        index: 0,
+        file_name: None,
+        message,
+    })?;
+    let mut hasher = Sha256::new();
+    hasher.update(img.width().to_le_bytes());
+    hasher.update(img.height().to_le_bytes());
+    let color = img.color();
+    if color.bytes_per_pixel() > color.channel_count() {
+        for sample in img.to_rgba16().into_raw() {
+            hasher.update(sample.to_be_bytes());
+        }
+    } else {
+        hasher.update(img.to_rgba8().as_raw());
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// BlurHash placeholder for every input image, after EXIF orientation.
+pub fn input_blur_hashes(images_data: &[ImageInput]) -> Result<Vec<String>, MergeError> {
+    Ok(decode_images(images_data, &mut NoHooks)?
+        .iter()
+        .map(|img| blur_hash(img).unwrap_or_default())
+        .collect())
+}
+
+/// Runs decoding, layout and Smart mode detection without compositing, and
+/// reports what [`merge_with_metadata`] would produce.
+///
+/// Intended for previews that let users adjust options before the expensive
+/// merge; no image is composited or encoded.
+pub fn analyze(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+) -> Result<MergeAnalysis, MergeError> {
+    if images_data.is_empty() {
+        return Err(MergeError::NoImages);
+    }
+
+    let (decoded_images, options) = prepare_inputs(images_data, options, &mut NoHooks)?;
+
+    if options.direction == Direction::Tiles {
+        let (placements, content) = tile_layout(&decoded_images, &options);
+        if content.0 == 0 || content.1 == 0 {
+            return Err(MergeError::NoImages);
+        }
+        check_canvas_size(content, &options)?;
+        let images = decoded_images
+            .iter()
+            .zip(placements)
+            .map(|(img, (y, height))| ImageAnalysis {
+                source_size: (img.width(), img.height()),
+                scaled_size: (img.width(), height),
+                trim_top: 0,
+                trim_bottom: img.height() - height,
+                position: (options.padding.left, options.padding.top + y as u32),
+            })
+            .collect();
+        let (width, height) = rotated_output_size(canvas_size(content, &options), &options);
+        return Ok(MergeAnalysis {
+            width,
+            height,
+            images,
+            pairs: vec![],
+            warnings: vec![],
+        });
+    }
+
+    let plan = plan_merge(&decoded_images, &options, &mut NoHooks)?;
+    let removed = removed_chrome(&plan.chrome_trims, options.chrome_mode);
+    let positions = image_positions(&plan, &options);
+    let images = plan
+        .dimensions
+        .iter()
+        .zip(plan.scaled_dimensions.iter())
+        .zip(positions)
+        .enumerate()
+        .map(|(i, ((source_size, scaled_size), position))| {
+            let trim = removed.get(i).copied().unwrap_or_default();
+            ImageAnalysis {
+                source_size: *source_size,
+                scaled_size: *scaled_size,
+                trim_top: trim.top,
+                trim_bottom: trim.bottom,
+                position,
+            }
+        })
+        .collect();
+    let pairs = pair_analyses(&plan);
+    let content = (plan.content_width as u64, plan.content_height as u64);
+    let (width, height) = rotated_output_size(canvas_size(content, &options), &options);
+
+    Ok(MergeAnalysis {
+        width,
+        height,
+        images,
+        pairs,
+        warnings: plan.warnings,
+    })
+}
+
+/// Composites a plan made by [`analyze`], possibly edited by the host.
+///
+/// In Smart mode every image keeps the chrome trims given in `plan.images`
+/// and every pair is joined at the overlap and horizontal shift given in
+/// `plan.pairs`, without detecting them again. Everything else in the plan is
+/// derived and ignored. `images_data` and `options` must be the ones the plan
+/// was made from.
+pub fn execute_plan(
+    images_data: Vec<impl Into<ImageInput>>,
+    mut options: MergeOptions,
+    plan: &MergeAnalysis,
+) -> Result<MergeOutput, MergeError> {
+    if options.direction == Direction::Smart {
+        options.overlap_overrides = plan.pairs.iter().map(|p| Some(p.overlap)).collect();
+    }
+    let mut hooks = PlannedLayout {
+        plan,
+        chrome_mode: options.chrome_mode,
+    };
+    merge_with_hooks(images_data, options, &mut hooks)
+}
+
+/// Pins Smart mode trims and horizontal shifts to those of a plan.
+struct PlannedLayout<'a> {
+    plan: &'a MergeAnalysis,
+    chrome_mode: ChromeMode,
+}
+
+impl MergeHooks for PlannedLayout<'_> {
+    fn after_trims(&mut self, trims: &mut [ChromeTrim]) -> Result<(), String> {
+        // Plans report kept chrome as untrimmed; the detected bars must stay
+        if self.chrome_mode == ChromeMode::KeepAll {
+            return Ok(());
+        }
+        for (trim, image) in trims.iter_mut().zip(&self.plan.images) {
+            trim.top = image.trim_top;
+            trim.bottom = image.trim_bottom;
+        }
+        Ok(())
+    }
+
+    fn after_alignment(&mut self, x_shifts: &mut [i32]) -> Result<(), String> {
+        for (shift, pair) in x_shifts.iter_mut().zip(&self.plan.pairs) {
+            *shift = pair.x_shift;
+        }
+        Ok(())
+    }
+}
+
+/// Final output size for a canvas, after `output_rotation`.
+fn rotated_output_size(canvas: (u64, u64), options: &MergeOptions) -> (u32, u32) {
+    let (width, height) = (canvas.0 as u32, canvas.1 as u32);
+    match options.output_rotation {
+        Rotation::Clockwise90 | Rotation::Clockwise270 => (height, width),
+        Rotation::None | Rotation::Clockwise180 => (width, height),
+    }
+}
+
+/// Decodes every input and normalizes its EXIF orientation.
+///
+/// With the `threads` feature the inputs are decoded in parallel; the error
+/// reported is still the one for the lowest failing index, and progress is
+/// reported once every input is decoded.
+fn decode_images(
+    images_data: &[ImageInput],
+    hooks: &mut dyn MergeHooks,
+) -> Result<Vec<DynamicImage>, MergeError> {
+    let total = images_data.len();
+    #[cfg(feature = "threads")]
+    let results: Vec<_> = {
+        use rayon::prelude::*;
+        images_data.par_iter().map(decode_input).collect()
+    };
+    #[cfg(not(feature = "threads"))]
+    let results = images_data.iter().map(decode_input);
+
+    results
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| {
+            let image = result.map_err(|message| MergeError::DecodeError {
+                index,
+                file_name: images_data[index].name.clone(),
+                message,
+            })?;
+            report_progress(hooks, Progress::Decoded { index, total })?;
+            Ok(image)
+        })
+        .collect()
+}
+
+/// Decodes one input and normalizes its EXIF orientation.
+pub(crate) fn decode_input(input: &ImageInput) -> Result<DynamicImage, String> {
+    match &input.source {
+        InputSource::Encoded(data) => {
+            let data = &*select_page(data, input.page.unwrap_or(0))?;
+            let data = &*select_icon(data, input.icon_size)?;
+            let img = match input.frame {
+                Some(frame) => decode_frame(data, input.format, frame)?,
+                None => decode_image_as(data, input.format)?,
+            };
+            let orientation = extract_orientation(data);
+            Ok(normalize_orientation(img, orientation))
+        }
+        InputSource::Rgba {
+            width,
+            height,
+            pixels,
+        } => {
+            if *width == 0 || *height == 0 {
+                return Err(format!("RGBA input is {width}x{height}"));
+            }
+            let expected = *width as u64 * *height as u64 * 4;
+            if pixels.len() as u64 != expected {
+                return Err(format!(
+                    "RGBA input holds {} bytes, expected {expected} for {width}x{height}",
+                    pixels.len()
+                ));
+            }
+            let img = RgbaImage::from_raw(*width, *height, pixels.clone())
+                .expect("buffer length checked above");
+            Ok(DynamicImage::ImageRgba8(img))
+        }
+    }
+}
+
+/// Puts the inputs in merge order and decodes them, and records per-image
+/// facts read from their bytes (low-color PNGs, scan resolution) in the
+/// options.
+///
+/// The returned options no longer carry `order`, which has been applied.
+fn prepare_inputs(
+    images_data: Vec<impl Into<ImageInput>>,
+    options: MergeOptions,
+    hooks: &mut dyn MergeHooks,
+) -> Result<(Vec<DynamicImage>, MergeOptions), MergeError> {
+    let (images_data, options) = check_inputs(images_data, options)?;
+    let decoded_images = decode_images(&images_data, hooks)?;
+    Ok(finish_inputs(decoded_images, &images_data, options))
+}
+
+/// Names the inputs and puts them in merge order, then checks them against
+/// the options and memory budget before anything is decoded.
+pub(crate) fn check_inputs(
+    images_data: Vec<impl Into<ImageInput>>,
+    mut options: MergeOptions,
+) -> Result<(Vec<ImageInput>, MergeOptions), MergeError> {
+    let mut images_data: Vec<ImageInput> = images_data.into_iter().map(Into::into).collect();
+    for (input, name) in images_data.iter_mut().zip(&options.file_names) {
+        if input.name.is_none() {
+            input.name.clone_from(name);
+        }
+    }
+    if let Some(order) = options.order.take() {
+        images_data = reorder_inputs(images_data, &order)?;
+    }
+    check_animated_inputs(images_data.iter().map(flattens_animation), &options)?;
+    if options.max_input_pixels.is_some() || options.max_input_dimension.is_some() {
+        for (index, input) in images_data.iter().enumerate() {
+            if let Some(dimensions) = header_dimensions(input) {
+                check_input_size(index, input.name.as_deref(), dimensions, &options)?;
+            }
+        }
+    }
+    let input_bytes = images_data.iter().map(decoded_size).sum();
+    check_memory_budget(input_bytes, &options)?;
+    Ok((images_data, options))
+}
+
+/// Trims the margins of the decoded inputs and records per-image facts read
+/// from their bytes in the options.
+pub(crate) fn finish_inputs(
+    decoded_images: Vec<DynamicImage>,
+    images_data: &[ImageInput],
+    options: MergeOptions,
+) -> (Vec<DynamicImage>, MergeOptions) {
+    let decoded_images = trim_input_margins(decoded_images, &options);
+    let files: Vec<_> = images_data.iter().map(ImageInput::encoded).collect();
+    let names: Vec<_> = images_data.iter().map(|input| input.name.clone()).collect();
+    let options = expand_contact_sheet(read_input_facts(options, &files), &names);
+    (decoded_images, options)
+}
+
+/// Expands the `contact_sheet` preset for inputs named `names` (in merge
+/// order): grid mode, file names for the missing captions, spacing and
+/// padding where they are 0, and room above the grid for the header.
+fn expand_contact_sheet(mut options: MergeOptions, names: &[Option<String>]) -> MergeOptions {
+    let Some(sheet) = &options.contact_sheet else {
+        return options;
+    };
+    options.direction = Direction::Grid;
+    if sheet.columns.is_some() {
+        options.columns = sheet.columns;
+    }
+    if options.captions.len() < names.len() {
+        options.captions.resize(names.len(), String::new());
+    }
+    for (caption, name) in options.captions.iter_mut().zip(names) {
+        if caption.trim().is_empty()
+            && let Some(name) = name
+        {
+            caption.clone_from(name);
+        }
+    }
+    if options.spacing == 0 {
+        options.spacing = CONTACT_SHEET_GAP;
+    }
+    if options.padding == Padding::default() {
+        options.padding = Padding::uniform(CONTACT_SHEET_GAP);
+    }
+    if let Some((header, size)) = contact_sheet_header(&options) {
+        options.padding.top += caption_strip_height(Some(header), size);
+    }
+    options
+}
+
+/// Text and font size of the contact sheet header, if there is one.
+fn contact_sheet_header(options: &MergeOptions) -> Option<(&str, f32)> {
+    let header = options.contact_sheet.as_ref()?.header.as_deref()?;
+    let size = options.caption_size * CONTACT_SHEET_HEADER_SCALE;
+    (caption_strip_height(Some(header), size) > 0).then_some((header, size))
+}
+
+/// Whether `order` lists every index below `len` exactly once.
+pub(crate) fn is_permutation(order: &[usize], len: usize) -> bool {
+    let mut seen = vec![false; len];
+    order.len() == len
+        && order
+            .iter()
+            .all(|&i| i < len && !std::mem::replace(&mut seen[i], true))
+}
+
+/// Moves the item at `order[i]` to position `i`.
+fn reorder_inputs<T>(items: Vec<T>, order: &[usize]) -> Result<Vec<T>, MergeError> {
+    if !is_permutation(order, items.len()) {
+        return Err(MergeError::InvalidOrder { count: items.len() });
+    }
+    let mut items: Vec<_> = items.into_iter().map(Some).collect();
+    Ok(order
+        .iter()
+        .map(|&i| items[i].take().expect("order is a permutation"))
+        .collect())
+}
+
+/// Crops the uniform margins off decoded images, when `trim_margins` asks.
+fn trim_input_margins(images: Vec<DynamicImage>, options: &MergeOptions) -> Vec<DynamicImage> {
+    let Some(trim) = options.trim_margins else {
+        return images;
+    };
+    images
+        .into_iter()
+        .map(|img| crop_to_content(&img, trim.tolerance).unwrap_or(img))
+        .collect()
+}
+
+/// `img` cropped to its content, or `None` when it has no uniform margins to
+/// trim.
+fn crop_to_content(img: &DynamicImage, tolerance: u8) -> Option<DynamicImage> {
+    content_bounds(img, tolerance).map(|(x, y, width, height)| img.crop_imm(x, y, width, height))
+}
+
+/// Records per-image facts read from the file bytes of encoded inputs
+/// (low-color PNGs, scan resolution, EXIF to copy) in the options.
+fn read_input_facts(options: MergeOptions, files: &[Option<&[u8]>]) -> MergeOptions {
+    let options = mark_low_color_inputs(options, files);
+    let options = read_input_dpi(options, files);
+    copy_input_exif(options, files)
+}
+
+/// Fills in the output EXIF from the fields of the first input's EXIF that
+/// `copy_exif` selects, unless it is set explicitly.
+fn copy_input_exif(mut options: MergeOptions, files: &[Option<&[u8]>]) -> MergeOptions {
+    if options.exif.is_none()
+        && !options.strip_metadata
+        && let Some(copy) = &options.copy_exif
+        && let Some(exif) = files.first().copied().flatten().and_then(extract_exif)
+    {
+        options.exif = select_exif(exif, copy);
+    }
+    options
+}
+
+/// Per-image options for image `index`, growing the list as needed.
+fn image_options_mut(options: &mut MergeOptions, index: usize) -> &mut ImageOptions {
+    if options.image_options.len() <= index {
+        options
+            .image_options
+            .resize(index + 1, ImageOptions::default());
+    }
+    &mut options.image_options[index]
+}
+
+/// Flags 1-bit and paletted PNG inputs as low-color when their scaling is
+/// configured to differ from other images.
+fn mark_low_color_inputs(mut options: MergeOptions, files: &[Option<&[u8]>]) -> MergeOptions {
+    if options.low_color_scaling == LowColorScaling::Smooth {
+        return options;
+    }
+    for (i, file) in files.iter().enumerate() {
+        if file.is_some_and(is_low_color_png) {
+            image_options_mut(&mut options, i).low_color = true;
+        }
+    }
+    options
+}
+
+/// Fills in the resolution of inputs without an explicit `dpi` from their
+/// JFIF or pHYs metadata, when DPI normalization is requested.
+fn read_input_dpi(mut options: MergeOptions, files: &[Option<&[u8]>]) -> MergeOptions {
+    if options.target_dpi.is_none() {
+        return options;
+    }
+    for (i, file) in files.iter().enumerate() {
+        if options.image_options.get(i).and_then(|o| o.dpi).is_none()
+            && let Some(dpi) = file.and_then(read_dpi)
+        {
+            image_options_mut(&mut options, i).dpi = Some(dpi);
+        }
+    }
+    options
+}
+
+/// Oriented image sizes rescaled to `target_dpi`, for images whose resolution
+/// is known; these are the sizes layout works from.
+fn physical_dimensions(dimensions: &[(u32, u32)], options: &MergeOptions) -> Vec<(u32, u32)> {
+    dimensions
+        .iter()
+        .enumerate()
+        .map(|(i, size)| {
+            let source_dpi = options.image_options.get(i).and_then(|o| o.dpi);
+            match (source_dpi, options.target_dpi) {
+                (Some(source_dpi), Some(target_dpi)) => {
+                    normalize_dpi(*size, source_dpi, target_dpi)
+                }
+                _ => *size,
+            }
+        })
+        .collect()
+}
+
+/// Input `index` mirrored as its `flip_h` and `flip_v` ask.
+fn flip_input<'a>(
+    img: &'a DynamicImage,
+    index: usize,
+    options: &MergeOptions,
+) -> Cow<'a, DynamicImage> {
+    let flips = options
+        .image_options
+        .get(index)
+        .map_or((false, false), |o| (o.flip_h, o.flip_v));
+    let orientation = match flips {
+        (false, false) => return Cow::Borrowed(img),
+        (true, false) => Orientation::FlipHorizontal,
+        (false, true) => Orientation::FlipVertical,
+        (true, true) => Orientation::Rotate180,
+    };
+    Cow::Owned(normalize_orientation(img.clone(), orientation))
+}
+
+/// Scales input `index` to its layout size, after flipping it, honoring
+/// low-color scaling.
+fn scale_input(
+    img: &DynamicImage,
+    size: (u32, u32),
+    index: usize,
+    options: &MergeOptions,
+    interrupt: &mut Interrupt,
+) -> DynamicImage {
+    let img = flip_input(img, index, options);
+    let img = img.as_ref();
+    let (width, height) = size;
+    let nearest = options.low_color_scaling == LowColorScaling::Nearest
+        && options.is_low_color(index)
+        && scale_factor((img.width(), img.height()), size) > 1.0;
+    if nearest {
+        scale_image_nearest(img, width, height)
+    } else {
+        scale_image_with(img, width, height, resampling(options), interrupt)
+    }
+}
+
+/// How inputs and previews are resampled.
+fn resampling(options: &MergeOptions) -> Resampling {
+    Resampling {
+        linear: options.linear_scaling,
+        premultiplied: options.premultiplied_alpha,
+    }
+}
+
+/// Runs the layout, compositing and encoding steps on decoded images,
+/// streaming the encoded output into `sink` when one is given.
+fn merge_decoded(
+    decoded_images: &[impl Borrow<DynamicImage>],
+    options: &MergeOptions,
+    hooks: &mut dyn MergeHooks,
+    sink: Option<&mut dyn Write>,
+) -> Result<MergeOutput, MergeError> {
+    let inputs = image_dimensions(decoded_images);
+    let mut stopwatch = Stopwatch::start(options.clock);
+    let (canvas, info) = composite_decoded(decoded_images, options, hooks)?;
+    let composite_ms = stopwatch.lap();
+    encode_merge(canvas, info, &inputs, composite_ms, options, hooks, sink)
+}
+
+/// Encodes a composited canvas, adding statistics gathered from the `inputs`
+/// (decoded sizes) and the time spent compositing.
+pub(crate) fn encode_merge(
+    canvas: Canvas,
+    info: CompositeInfo,
+    inputs: &[(u32, u32)],
+    composite_ms: f64,
+    options: &MergeOptions,
+    hooks: &mut dyn MergeHooks,
+    sink: Option<&mut dyn Write>,
+) -> Result<MergeOutput, MergeError> {
+    let mut stopwatch = Stopwatch::start(options.clock);
+    report_progress(hooks, Progress::Encoding)?;
+    let (mut output, encoded_bytes) = finish_output(canvas, options, sink)?;
+    output.seams = info.pairs;
+    if options.collect_stats {
+        let durations = StageDurations {
+            composite_ms,
+            encode_ms: stopwatch.lap(),
+            ..info.durations
+        };
+        output.stats = Some(merge_stats(
+            &output,
+            encoded_bytes,
+            inputs,
+            durations,
+            info.peak_bytes,
+            options,
+        ));
+    }
+    Ok(output)
+}
+
+/// Seam results, layout time and memory gathered while compositing, for
+/// statistics.
+pub(crate) struct CompositeInfo {
+    pairs: Vec<PairAnalysis>,
+    durations: StageDurations,
+    peak_bytes: u64,
+}
+
+fn merge_stats(
+    output: &MergeOutput,
+    encoded_bytes: usize,
+    inputs: &[(u32, u32)],
+    mut durations: StageDurations,
+    peak_bytes_estimate: u64,
+    options: &MergeOptions,
+) -> MergeStats {
+    durations.total_ms = durations.layout_ms + durations.composite_ms + durations.encode_ms;
+    MergeStats {
+        direction: options.direction,
+        inputs: inputs
+            .iter()
+            .map(|&(width, height)| InputStats { width, height })
+            .collect(),
+        output_width: output.width,
+        output_height: output.height,
+        output_bytes: encoded_bytes,
+        failed_seams: output
+            .seams
+            .iter()
+            .filter(|s| s.confidence.is_none())
+            .count(),
+        seams: output.seams.clone(),
+        durations,
+        peak_bytes_estimate,
+        warnings: output
+            .warnings
+            .iter()
+            .map(|w| w.code().to_string())
+            .collect(),
+    }
+}
+
+/// Composited canvas, before rotation and encoding, with what was learned
+/// while producing it.
+pub(crate) struct Canvas {
+    output: RgbaImage,
+    /// 16-bit original of `output`, when `high_bit_depth` was honored.
+    deep: Option<Rgba16Image>,
+    warnings: Vec<MergeWarning>,
+    repro_bundles: Vec<ReproBundle>,
+    /// Where each image was drawn, before output rotation.
+    placements: Vec<Placement>,
+}
+
+/// Runs the layout and compositing steps on decoded images.
+fn composite_decoded(
+    decoded_images: &[impl Borrow<DynamicImage>],
+    options: &MergeOptions,
+    hooks: &mut dyn MergeHooks,
+) -> Result<(Canvas, CompositeInfo), MergeError> {
+    let (mut drawing, info) = begin_composite(decoded_images, options, hooks)?;
+    while drawing.remaining(options) > 0 {
+        drawing.draw_next(options);
+    }
+    Ok((drawing.finish(options), info))
+}
+
+/// Runs the layout steps on decoded images and starts compositing them;
+/// tiles are composited whole.
+pub(crate) fn begin_composite(
+    decoded_images: &[impl Borrow<DynamicImage>],
+    options: &MergeOptions,
+    hooks: &mut dyn MergeHooks,
+) -> Result<(Drawing, CompositeInfo), MergeError> {
+    let mut stopwatch = Stopwatch::start(options.clock);
+    if options.direction == Direction::Tiles {
+        hooks
+            .after_decode(&image_dimensions(decoded_images))
+            .map_err(|message| hook_rejected(HookStage::AfterDecode, message))?;
+        report_progress(hooks, Progress::Compositing)?;
+        let canvas = merge_tiles(decoded_images, options)?;
+        let info = CompositeInfo {
+            pairs: vec![],
+            durations: StageDurations::default(),
+            peak_bytes: image_bytes(decoded_images) + canvas.output.as_raw().len() as u64,
+        };
+        return Ok((Drawing::done(canvas), info));
+    }
+    let plan = plan_merge(decoded_images, options, hooks)?;
+    begin_drawing(plan, stopwatch.lap(), options, hooks)
+}
+
+/// Starts compositing a finished plan that took `layout_ms` to build.
+pub(crate) fn begin_drawing(
+    plan: MergePlan,
+    layout_ms: f64,
+    options: &MergeOptions,
+    hooks: &mut dyn MergeHooks,
+) -> Result<(Drawing, CompositeInfo), MergeError> {
+    let deep_peak_bytes = deep_peak_bytes(&plan, options);
+    let mut info = CompositeInfo {
+        pairs: pair_analyses(&plan),
+        durations: StageDurations {
+            layout_ms,
+            scale_ms: plan.scale_ms,
+            analysis_ms: plan.analysis_ms,
+            ..Default::default()
+        },
+        peak_bytes: plan.peak_bytes,
+    };
+    report_progress(hooks, Progress::Compositing)?;
+    let drawing = Drawing::begin(plan, options)?;
+    if drawing.canvas.deep.is_some() {
+        info.peak_bytes = info.peak_bytes.max(deep_peak_bytes);
+    }
+    Ok((drawing, info))
+}
+
+/// Draws a line at the junction between each image and the next, given
+/// where each was drawn: a vertical line when the next sits beside it
+/// (horizontal merges, grid rows), otherwise a horizontal one across all
+/// images (stacked merges, grid row breaks).
+///
+/// Lines run midway through any gap between the images, or along the top
+/// of the later image where the two touch or overlap.
+fn draw_seam_lines(output: &mut RgbaImage, placements: &[Placement], seams: &SeamLines) {
+    let color = Rgba([seams.color.r, seams.color.g, seams.color.b, seams.color.a]);
+    let half = seams.width / 2;
+    let left = placements.iter().map(|p| p.x).min().unwrap_or(0);
+    let right = placements.iter().map(|p| p.x + p.width).max().unwrap_or(0);
+    let mut bottom = 0;
+    for pair in placements.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        bottom = bottom.max(a.y + a.height);
+        let beside = b.x >= a.x + a.width && b.y < a.y + a.height;
+        if beside {
+            let x = (a.x + a.width).midpoint(b.x).saturating_sub(half);
+            let top = a.y.min(b.y);
+            let height = (a.y + a.height).max(b.y + b.height) - top;
+            fill_rect(output, x, top, seams.width, height, color);
+        } else {
+            let y = if b.y >= bottom {
+                bottom.midpoint(b.y)
+            } else {
+                b.y
+            };
+            let y = y.saturating_sub(half);
+            fill_rect(output, left, y, right - left, seams.width, color);
+        }
+    }
+}
+
+/// Smart mode seam results of a plan, one per adjacent pair.
+fn pair_analyses(plan: &MergePlan) -> Vec<PairAnalysis> {
+    plan.overlaps
+        .iter()
+        .zip(plan.confidences.iter())
+        .zip(plan.x_shifts.iter())
+        .map(|((overlap, confidence), x_shift)| PairAnalysis {
+            overlap: *overlap,
+            confidence: *confidence,
+            x_shift: *x_shift,
+        })
+        .collect()
+}
+
+/// Everything decided about a merge before compositing: sizes, Smart mode
+/// trims and overlaps, and the resulting canvas size.
+pub(crate) struct MergePlan {
+    /// Oriented size of every decoded image.
+    dimensions: Vec<(u32, u32)>,
+    scaled_images: Vec<DynamicImage>,
+    scaled_dimensions: Vec<(u32, u32)>,
+    framed_dimensions: Vec<(u32, u32)>,
+    caption_heights: Vec<u32>,
+    slot_dimensions: Vec<(u32, u32)>,
+    spacing: u32,
+    gap: u32,
+    border: Option<Border>,
+    divider: Option<Divider>,
+    /// Canvas size before padding and snapping.
+    content_width: u32,
+    content_height: u32,
+    chrome_trims: Vec<ChromeTrim>,
+    overlaps: Vec<u32>,
+    /// Detection confidence per adjacent pair; `None` where nothing matched.
+    confidences: Vec<Option<f32>>,
+    /// Horizontal jitter correction per adjacent pair; 0 where nothing matched.
+    x_shifts: Vec<i32>,
+    warnings: Vec<MergeWarning>,
+    repro_bundles: Vec<ReproBundle>,
+    /// Time spent sizing and scaling the inputs, then on Smart mode
+    /// trimming and detection.
+    scale_ms: f64,
+    analysis_ms: f64,
+    /// Estimated memory held once the canvas is allocated.
+    peak_bytes: u64,
+}
+
+fn image_dimensions(images: &[impl Borrow<DynamicImage>]) -> Vec<(u32, u32)> {
+    images
+        .iter()
+        .map(|img| (img.borrow().width(), img.borrow().height()))
+        .collect()
+}
+
+/// Runs decoding-independent layout and Smart mode detection (steps 2-7).
+fn plan_merge(
+    decoded_images: &[impl Borrow<DynamicImage>],
+    options: &MergeOptions,
+    hooks: &mut dyn MergeHooks,
+) -> Result<MergePlan, MergeError> {
+    let mut planner = Planner::begin(decoded_images, options, hooks)?;
+    while planner.remaining(options) > 0 {
+        planner.step(decoded_images, options, hooks)?;
+    }
+    planner.finish(options, hooks)
+}
+
+/// A merge plan built in bounded steps: the layout, then each input scaled,
+/// then for Smart mode the chrome trims and each pair's overlap.
+pub(crate) struct Planner {
+    /// The plan so far; its scaled images, trims and overlaps fill in as
+    /// the steps run.
+    plan: MergePlan,
+    /// Smart mode detection, once the chrome trims are known.
+    analysis: Option<Analysis>,
+    input_bytes: u64,
+    scaled_bytes: u64,
+}
+
+/// Smart mode detection in progress.
+struct Analysis {
+    regions: Vec<Option<OverlapRegion>>,
+    params: Vec<OverlapParams>,
+    /// Results for the pairs detected so far.
+    detections: Vec<Option<OverlapResult>>,
+}
+
+impl Planner {
+    /// Lays the merge out (steps 2-6) without scaling anything yet.
+    pub(crate) fn begin(
+        decoded_images: &[impl Borrow<DynamicImage>],
+        options: &MergeOptions,
+        hooks: &mut dyn MergeHooks,
+    ) -> Result<Self, MergeError> {
+        let mut stopwatch = Stopwatch::start(options.clock);
+        // Step 2: Get dimensions (from normalized images)
+        let dimensions = image_dimensions(decoded_images);
+        hooks
+            .after_decode(&dimensions)
+            .map_err(|message| hook_rejected(HookStage::AfterDecode, message))?;
+
+        // Step 3: Compute target dimension (from sizes normalized to `target_dpi`)
+        let physical = physical_dimensions(&dimensions, options);
+        let target = compute_target_dimension(&physical, options.direction, options.scale_mode);
+        if target == 0 {
+            return Err(MergeError::NoImages);
+        }
+
+        // Step 4: Compute scaled dimensions for each image
+        let mut scaled_dimensions = compute_layout_dimensions(&physical, target, options);
+
+        // Step 5: Compute output size
+        // For Smart mode, we treat it as Vertical for dimension calculation initially
+        let direction_for_sizing = match options.direction {
+            Direction::Smart => Direction::Vertical,
+            d => d,
+        };
+        let spacing = match options.direction {
+            Direction::Smart => 0,
+            _ => options.spacing,
+        };
+        let border = match options.direction {
+            Direction::Smart => None,
+            _ => options.border.filter(|b| b.width > 0),
+        };
+        let border_width = border.map_or(0, |b| b.width);
+        // A divider line sits in the middle of the gap between adjacent images
+        let divider = match options.direction {
+            Direction::Smart | Direction::Grid => None,
+            _ => options.divider.filter(|d| d.width > 0),
+        };
+        let gap = spacing.saturating_add(divider.map_or(0, |d| d.width));
+        // Caption strips extend each image's slot above or below its frame
+        let caption_heights: Vec<u32> = (0..scaled_dimensions.len())
+            .map(|i| match options.direction {
+                Direction::Smart => 0,
+                _ => caption_strip_height(
+                    options.captions.get(i).map(String::as_str),
+                    options.caption_size,
+                ),
+            })
+            .collect();
+        let slot_layout = |scaled_dimensions: &[(u32, u32)]| {
+            let framed_dimensions: Vec<(u32, u32)> = scaled_dimensions
+                .iter()
+                .map(|(w, h)| {
+                    (
+                        w.saturating_add(border_width.saturating_mul(2)),
+                        h.saturating_add(border_width.saturating_mul(2)),
+                    )
+                })
+                .collect();
+            let slot_dimensions: Vec<(u32, u32)> = framed_dimensions
+                .iter()
+                .zip(caption_heights.iter())
+                .map(|((w, h), caption_h)| (*w, h.saturating_add(*caption_h)))
+                .collect();
+            let content = match options.direction {
+                Direction::Grid => {
+                    let columns = grid_columns(&slot_dimensions, gap, options);
+                    compute_grid_size(&slot_dimensions, columns, gap, options.columns.is_some())
+                }
+                _ => compute_output_size(&slot_dimensions, direction_for_sizing, gap),
+            };
+            (framed_dimensions, slot_dimensions, content)
+        };
+        let (mut framed_dimensions, mut slot_dimensions, mut content) =
+            slot_layout(&scaled_dimensions);
+
+        // Step 6 inputs: the decoded images are held throughout, while the scaled
+        // copies and the canvas shrink with the layout
+        let input_bytes = image_bytes(decoded_images);
+        let scaled_bytes_of = |scaled_dimensions: &[(u32, u32)]| -> u64 {
+            decoded_images
+                .iter()
+                .zip(scaled_dimensions.iter())
+                .map(|(img, &(width, height))| {
+                    width as u64 * height as u64 * img.borrow().color().bytes_per_pixel() as u64
+                })
+                .sum()
+        };
+
+        // Step 5.5: Degrade instead of failing when the projected canvas is too
+        // large or the projected peak memory too high
+        let mut warnings = Vec::new();
+        if options.downscale_to_fit
+            && (options.max_output_pixels.is_some() || options.max_memory_bytes.is_some())
+        {
+            // Area ratio that brings the layout under every limit, or `None` when
+            // it is already within them
+            let overshoot = |scaled_dimensions: &[(u32, u32)],
+                             framed_dimensions: &[(u32, u32)],
+                             content: (u64, u64)| {
+                let (width, height) = canvas_size(content, options);
+                let mut ratio = None::<f64>;
+                if let Some(max_pixels) = options.max_output_pixels
+                    && exceeds_pixel_limit((width, height), max_pixels)
+                {
+                    ratio = Some(max_pixels as f64 / (width as f64 * height as f64));
+                }
+                if let Some(max_bytes) = options.max_memory_bytes {
+                    let shrinking = scaled_bytes_of(scaled_dimensions)
+                        .saturating_add(canvas_bytes(content, options))
+                        .saturating_add(shadow_bytes(framed_dimensions, content, options));
+                    if input_bytes.saturating_add(shrinking) > max_bytes {
+                        let fits = max_bytes.saturating_sub(input_bytes) as f64 / shrinking as f64;
+                        ratio = Some(ratio.map_or(fits, |r| r.min(fits)));
+                    }
+                }
+                ratio
+            };
+            let full_size = scaled_dimensions.clone();
+            let mut factor = 1.0;
+            while let Some(ratio) = overshoot(&scaled_dimensions, &framed_dimensions, content)
+                && factor > MIN_FIT_FACTOR
+            {
+                // Padding, borders and captions do not shrink, so step at least 1%
+                factor = (factor * ratio.sqrt().min(0.99)).max(MIN_FIT_FACTOR);
+                scaled_dimensions = shrink_dimensions(&full_size, factor);
+                (framed_dimensions, slot_dimensions, content) = slot_layout(&scaled_dimensions);
+            }
+            if factor < 1.0 {
+                let (width, height) = canvas_size(content, options);
+                warnings.push(MergeWarning::Downscaled {
+                    factor,
+                    width,
+                    height,
+                });
+            }
+        }
+        let (content_width, content_height) = content;
+        check_canvas_size(content, options)?;
+
+        // Step 5.6: Flag images enlarged beyond the quality threshold
+        for (index, (dims, scaled)) in dimensions.iter().zip(scaled_dimensions.iter()).enumerate() {
+            let factor = scale_factor(*dims, *scaled);
+            if factor > options.max_upscale_factor {
+                if options.error_on_upscale {
+                    return Err(MergeError::UpscaleTooLarge {
+                        index,
+                        factor,
+                        max_factor: options.max_upscale_factor,
+                    });
+                }
+                warnings.push(MergeWarning::Upscaled {
+                    index,
+                    factor,
+                    max_factor: options.max_upscale_factor,
+                });
+            }
+        }
+
+        let content_width = content_width as u32;
+        let content_height = content_height as u32;
+
+        // Step 6: Refuse before allocating scaled copies the budget cannot hold
+        let scaled_bytes = scaled_bytes_of(&scaled_dimensions);
+        check_memory_budget(input_bytes.saturating_add(scaled_bytes), options)?;
+
+        let count = decoded_images.len();
+        Ok(Planner {
+            plan: MergePlan {
+                dimensions,
+                scaled_images: Vec::with_capacity(count),
+                scaled_dimensions,
+                framed_dimensions,
+                caption_heights,
+                slot_dimensions,
+                spacing,
+                gap,
+                border,
+                divider,
+                content_width,
+                content_height,
+                chrome_trims: vec![],
+                overlaps: vec![],
+                confidences: vec![],
+                x_shifts: vec![],
+                warnings,
+                repro_bundles: vec![],
+                scale_ms: stopwatch.lap(),
+                analysis_ms: 0.0,
+                peak_bytes: 0,
+            },
+            analysis: None,
+            input_bytes,
+            scaled_bytes,
+        })
+    }
+
+    /// Number of steps left before [`finish`](Self::finish): one per input
+    /// still to scale, then for Smart mode one for the chrome trims and one
+    /// per pair.
+    pub(crate) fn remaining(&self, options: &MergeOptions) -> usize {
+        let images = self.plan.dimensions.len();
+        let scaling = images - self.plan.scaled_images.len();
+        if options.direction != Direction::Smart {
+            return scaling;
+        }
+        let pairs = images.saturating_sub(1);
+        scaling
+            + match &self.analysis {
+                None => 1 + pairs,
+                Some(analysis) => pairs - analysis.detections.len(),
+            }
+    }
+
+    /// Scales the next input (step 7), or runs the next part of Smart mode
+    /// detection (step 7.5): the chrome trims of every input, then the
+    /// overlap of one pair.
+    pub(crate) fn step(
+        &mut self,
+        decoded_images: &[impl Borrow<DynamicImage>],
+        options: &MergeOptions,
+        hooks: &mut dyn MergeHooks,
+    ) -> Result<(), MergeError> {
+        let mut stopwatch = Stopwatch::start(options.clock);
+        let plan = &mut self.plan;
+        let index = plan.scaled_images.len();
+        if index < decoded_images.len() {
+            let size = plan.scaled_dimensions[index];
+            let mut interrupt = Interrupt::new(hooks);
+            let scaled = scale_input(
+                decoded_images[index].borrow(),
+                size,
+                index,
+                options,
+                &mut interrupt,
+            );
+            if interrupt.is_cancelled() {
+                return Err(MergeError::Cancelled);
+            }
+            plan.scaled_images.push(scaled);
+            report_progress(
+                hooks,
+                Progress::Scaled {
+                    index,
+                    total: decoded_images.len(),
+                },
+            )?;
+            plan.scale_ms += stopwatch.lap();
+            return Ok(());
+        }
+        match &mut self.analysis {
+            None => {
+                check_pair_widths(&plan.scaled_images, options)?;
+                let mut trims = chrome_trims(&plan.scaled_images, options)?;
+                hooks
+                    .after_trims(&mut trims)
+                    .map_err(|message| hook_rejected(HookStage::AfterTrims, message))?;
+                plan.chrome_trims = trims;
+                let pairs = plan.scaled_images.len().saturating_sub(1);
+                self.analysis = Some(Analysis {
+                    regions: scale_overlap_regions(
+                        &options.overlap_regions,
+                        &plan.dimensions,
+                        &plan.scaled_dimensions,
+                    ),
+                    params: overlap_params(options, pairs),
+                    detections: Vec::with_capacity(pairs),
+                });
+            }
+            Some(analysis) => {
+                let index = analysis.detections.len();
+                let mut interrupt = Interrupt::new(hooks);
+                let detection = detect_pair_overlap_with_trims(
+                    &plan.scaled_images,
+                    &plan.chrome_trims,
+                    &analysis.params,
+                    &analysis.regions,
+                    &options.overlap_overrides,
+                    index,
+                    &mut interrupt,
+                );
+                if interrupt.is_cancelled() {
+                    return Err(MergeError::Cancelled);
+                }
+                analysis.detections.push(detection);
+                report_progress(
+                    hooks,
+                    Progress::Overlap {
+                        index,
+                        total: analysis.params.len(),
+                    },
+                )?;
+            }
+        }
+        plan.analysis_ms += stopwatch.lap();
+        Ok(())
+    }
+
+    /// Settles the Smart mode overlaps and the canvas size once every step
+    /// ran.
+    pub(crate) fn finish(
+        self,
+        options: &MergeOptions,
+        hooks: &mut dyn MergeHooks,
+    ) -> Result<MergePlan, MergeError> {
+        let mut stopwatch = Stopwatch::start(options.clock);
+        let Planner {
+            mut plan,
+            analysis,
+            input_bytes,
+            scaled_bytes,
+        } = self;
+        if let Some(analysis) = analysis {
+            finish_analysis(&mut plan, analysis, options, hooks)?;
+        }
+
+        // The canvas is known once Smart mode has trimmed and overlapped
+        let content = (plan.content_width as u64, plan.content_height as u64);
+        plan.peak_bytes = input_bytes
+            .saturating_add(scaled_bytes)
+            .saturating_add(canvas_bytes(content, options))
+            .saturating_add(shadow_bytes(&plan.framed_dimensions, content, options));
+        check_memory_budget(plan.peak_bytes, options)?;
+        plan.analysis_ms += stopwatch.lap();
+        Ok(plan)
+    }
+}
+
+/// Settles the Smart mode overlaps and alignment from the detections (the
+/// rest of step 7.5), shrinking the canvas by what is trimmed and overlapped.
+fn finish_analysis(
+    plan: &mut MergePlan,
+    analysis: Analysis,
+    options: &MergeOptions,
+    hooks: &mut dyn MergeHooks,
+) -> Result<(), MergeError> {
+    let Analysis {
+        params, detections, ..
+    } = analysis;
+    let mut overlaps: Vec<u32> = detections
+        .iter()
+        .map(|r| r.map_or(0, |r| r.overlap_pixels))
+        .collect();
+    plan.confidences = detections.iter().map(|r| r.map(|r| r.confidence)).collect();
+    hooks
+        .after_matching(&mut overlaps)
+        .map_err(|message| hook_rejected(HookStage::AfterMatching, message))?;
+    // A vetoed overlap leaves nothing to line up.
+    let mut x_shifts: Vec<i32> = detections
+        .iter()
+        .zip(&overlaps)
+        .map(|(r, overlap)| match r {
+            Some(r) if *overlap > 0 => r.x_shift,
+            _ => 0,
+        })
+        .collect();
+    hooks
+        .after_alignment(&mut x_shifts)
+        .map_err(|message| hook_rejected(HookStage::AfterAlignment, message))?;
+
+    // An explicit override of 0 asks for concatenation; anything else
+    // that ended at 0 is a seam that could not be lined up.
+    let missed: Vec<usize> = overlaps
+        .iter()
+        .enumerate()
+        .filter(|(i, overlap)| {
+            **overlap == 0
+                && options
+                    .overlap_overrides
+                    .get(*i)
+                    .copied()
+                    .flatten()
+                    .is_none()
+        })
+        .map(|(i, _)| i)
+        .collect();
+    plan.warnings.extend(
+        missed
+            .iter()
+            .map(|&pair| MergeWarning::OverlapNotFound { pair }),
+    );
+
+    let trims = &plan.chrome_trims;
+    if options.repro_bundles {
+        plan.repro_bundles = missed
+            .iter()
+            .filter_map(|&i| {
+                build_repro_bundle(
+                    i,
+                    &plan.scaled_images[i],
+                    &plan.scaled_images[i + 1],
+                    &params[i],
+                    trims[i].bottom,
+                    trims[i + 1].top,
+                )
+            })
+            .collect();
+    }
+
+    let removed = removed_chrome(trims, options.chrome_mode);
+    let total_trim_top: u32 = removed.iter().map(|t| t.top).sum();
+    let total_trim_bottom: u32 = removed.iter().map(|t| t.bottom).sum();
+    let total_overlap: u32 = overlaps.iter().sum();
+
+    plan.content_height = plan
+        .content_height
+        .saturating_sub(total_trim_top)
+        .saturating_sub(total_trim_bottom)
+        .saturating_sub(total_overlap);
+
+    let (_, jitter_spread) = jitter_offsets(&x_shifts);
+    if jitter_spread > 0 {
+        plan.content_width = plan.content_width.saturating_add(jitter_spread);
+        let content = (plan.content_width as u64, plan.content_height as u64);
+        check_canvas_size(content, options)?;
+    }
+    plan.overlaps = overlaps;
+    plan.x_shifts = x_shifts;
+    Ok(())
+}
+
+/// A planned merge being composited one image at a time (steps 8-9), so a
+/// [`SteppedMerge`](crate::stepped::SteppedMerge) can return to its host
+/// between images.
+pub(crate) struct Drawing {
+    canvas: Canvas,
+    /// The plan being drawn, its scaled images moved to `images`; `None`
+    /// when the canvas was drawn in one go (tiles, 16-bit canvases).
+    plan: Option<MergePlan>,
+    images: Vec<RgbaImage>,
+    slots: Positions,
+    positions: Positions,
+    /// Visible area (x, y, width, height) of every image drawn, for page numbers
+    image_rects: Vec<(u32, u32, u32, u32)>,
+    next: usize,
+    /// Visible rows of image `next` already drawn.
+    row: u32,
+}
+
+impl Drawing {
+    /// A canvas with nothing left to draw but seam lines.
+    fn done(canvas: Canvas) -> Self {
+        Drawing {
+            canvas,
+            plan: None,
+            images: vec![],
+            slots: vec![],
+            positions: vec![],
+            image_rects: vec![],
+            next: 0,
+            row: 0,
+        }
+    }
+
+    /// Creates the canvas for `plan` and draws the shadows beneath the
+    /// images; a 16-bit canvas is drawn whole.
+    fn begin(mut plan: MergePlan, options: &MergeOptions) -> Result<Self, MergeError> {
+        let placements = plan_placements(&plan, options);
+        let stacked = options.direction != Direction::Smart;
+        let (slots, positions) = if stacked {
+            stacked_layout(&plan, options)
+        } else {
+            (vec![], smart_positions(&plan, options))
+        };
+        if let Some(deep) = render_deep(&plan, &positions, options)? {
+            return Ok(Drawing::done(Canvas {
+                output: deep.convert(),
+                deep: Some(deep),
+                warnings: plan.warnings,
+                repro_bundles: plan.repro_bundles,
+                placements,
+            }));
+        }
+
+        // Step 8: Create output canvas with background color
+        let content = (plan.content_width as u64, plan.content_height as u64);
+        let mut output = new_canvas(content, options)?;
+
+        // Step 9: Composite images onto canvas
+        let images: Vec<RgbaImage> = std::mem::take(&mut plan.scaled_images)
+            .into_iter()
+            .map(|img| {
+                let mut rgba_img = img.into_rgba8();
+                if stacked && options.corner_radius > 0 {
+                    round_corners(&mut rgba_img, options.corner_radius);
+                }
+                rgba_img
+            })
+            .collect();
+
+        // Every slot is placed first so shadows can go beneath all of the images
+        if stacked && let Some(shadow) = &options.shadow {
+            let border_width = plan.border.map_or(0, |b| b.width);
+            for (rgba_img, (x, y)) in images.iter().zip(positions.iter()) {
+                let mask = frame_mask(rgba_img, border_width)?;
+                render_shadow(&mut output, &mask, *x as i64, *y as i64, shadow)?;
+            }
+        }
+
+        Ok(Drawing {
+            canvas: Canvas {
+                output,
+                deep: None,
+                warnings: vec![],
+                repro_bundles: vec![],
+                placements,
+            },
+            image_rects: Vec::with_capacity(images.len()),
+            plan: Some(plan),
+            images,
+            slots,
+            positions,
+            next: 0,
+            row: 0,
+        })
+    }
+
+    /// Number of steps still to draw: every image takes one band of rows
+    /// per step, at most [`DRAW_BAND_PIXELS`] each.
+    pub(crate) fn remaining(&self, options: &MergeOptions) -> usize {
+        let Some(plan) = &self.plan else {
+            return 0;
+        };
+        let count = self.images.len().min(self.positions.len());
+        let bands: usize = (self.next..count)
+            .map(|i| {
+                let rows = visible_rows(&self.runs(plan, i, options));
+                rows.div_ceil(band_rows(self.images[i].width())).max(1) as usize
+            })
+            .sum();
+        let drawn = if self.next < count {
+            (self.row / band_rows(self.images[self.next].width())) as usize
+        } else {
+            0
+        };
+        bands - drawn
+    }
+
+    /// Where the rows of image `i` go: runs of source rows, each drawn at
+    /// an (x, y) position. Smart mode skips trimmed and overlapped rows.
+    fn runs(&self, plan: &MergePlan, i: usize, options: &MergeOptions) -> Vec<RowRun> {
+        let (x, y) = self.positions[i];
+        let height = self.images[i].height();
+        if options.direction != Direction::Smart {
+            let border_width = plan.border.map_or(0, |b| b.width);
+            return vec![(0..height, x + border_width, y + border_width)];
+        }
+        let mut y = y;
+        smart_segments(&plan.chrome_trims, &plan.overlaps, i, height, options)
+            .into_iter()
+            .map(|(crop_top, crop_bottom)| {
+                let top = crop_top.min(height);
+                let bottom = height - crop_bottom.min(height - top);
+                let run = (top..bottom, x, y);
+                y += bottom - top;
+                run
+            })
+            .collect()
+    }
+
+    /// Draws the next band of rows of the next image, if any remain.
+    pub(crate) fn draw_next(&mut self, options: &MergeOptions) {
+        let Some(plan) = &self.plan else {
+            return;
+        };
+        if self.next >= self.images.len().min(self.positions.len()) {
+            return;
+        }
+        let i = self.next;
+        let runs = self.runs(plan, i, options);
+        let rgba_img = &self.images[i];
+        let output = &mut self.canvas.output;
+        let stacked = options.direction != Direction::Smart;
+        if stacked
+            && self.row == 0
+            && let Some(border) = plan.border
+        {
+            let (x, y) = self.positions[i];
+            let (framed_w, framed_h) = plan.framed_dimensions[i];
+            draw_frame(output, x, y, framed_w, framed_h, &border);
+        }
+
+        // Rows `start..end` of the visible rows, walked across the runs
+        let visible = visible_rows(&runs);
+        let start = self.row;
+        let end = (start + band_rows(rgba_img.width())).min(visible);
+        let mut offset = 0;
+        for (rows, x, y) in &runs {
+            let from = start.max(offset) - offset + rows.start;
+            let to = end.min(offset + rows.len() as u32).max(offset) - offset + rows.start;
+            if from < to {
+                composite_rows(output, rgba_img, *x, y + (from - rows.start), from..to);
+            }
+            offset += rows.len() as u32;
+        }
+        if end < visible {
+            self.row = end;
+            return;
+        }
+
+        let (x, y) = runs.first().map_or(self.positions[i], |(_, x, y)| (*x, *y));
+        let height = if stacked { rgba_img.height() } else { visible };
+        self.image_rects.push((x, y, rgba_img.width(), height));
+        self.next += 1;
+        self.row = 0;
+    }
+
+    /// Draws what goes over the images (dividers, captions, page numbers,
+    /// seam lines) and returns the canvas.
+    pub(crate) fn finish(self, options: &MergeOptions) -> Canvas {
+        let Drawing {
+            mut canvas,
+            plan,
+            slots,
+            positions,
+            image_rects,
+            ..
+        } = self;
+        if let Some(plan) = plan {
+            draw_decorations(&mut canvas.output, &plan, &slots, &positions, options);
+            if let Some(page_numbers) = &options.page_numbers {
+                let total = image_rects.len();
+                for (i, rect) in image_rects.iter().enumerate() {
+                    draw_corner_label(
+                        &mut canvas.output,
+                        &format!("{}/{}", i + 1, total),
+                        *rect,
+                        page_numbers.corner,
+                        page_numbers.size,
+                        &page_numbers.color,
+                    );
+                }
+            }
+            canvas.warnings = plan.warnings;
+            canvas.repro_bundles = plan.repro_bundles;
+        }
+        if let Some(seams) = &options.draw_seams {
+            draw_seam_lines(&mut canvas.output, &canvas.placements, seams);
+        }
+        canvas
+    }
+}
+
+/// Source rows of an image drawn at an (x, y) position on the canvas.
+type RowRun = (std::ops::Range<u32>, u32, u32);
+
+fn visible_rows(runs: &[RowRun]) -> u32 {
+    runs.iter().map(|(rows, ..)| rows.len() as u32).sum()
+}
+
+/// Rows of an image `width` pixels wide drawn per step.
+fn band_rows(width: u32) -> u32 {
+    (DRAW_BAND_PIXELS / width.max(1) as u64).clamp(1, u32::MAX as u64) as u32
+}
+
+/// Stacked directions: draws the dividers between slots, the captions and
+/// the contact sheet header.
+fn draw_decorations(
+    output: &mut RgbaImage,
+    plan: &MergePlan,
+    slots: &Positions,
+    positions: &Positions,
+    options: &MergeOptions,
+) {
+    if options.direction == Direction::Smart {
+        return;
+    }
+    let padding = options.padding;
+    let (content_width, content_height) = (plan.content_width, plan.content_height);
+    if let Some(divider) = plan.divider {
+        let color = Rgba([
+            divider.color.r,
+            divider.color.g,
+            divider.color.b,
+            divider.color.a,
+        ]);
+        for ((x, y), (slot_w, slot_h)) in slots
+            .iter()
+            .zip(plan.slot_dimensions.iter())
+            .take(slots.len().saturating_sub(1))
+        {
+            if options.direction == Direction::Vertical {
+                let line_y = y + slot_h + plan.spacing / 2;
+                fill_rect(
+                    output,
+                    padding.left,
+                    line_y,
+                    content_width,
+                    divider.width,
+                    color,
+                );
+            } else {
+                let line_x = x + slot_w + plan.spacing / 2;
+                fill_rect(
+                    output,
+                    line_x,
+                    padding.top,
+                    divider.width,
+                    content_height,
+                    color,
+                );
+            }
+        }
+    }
+
+    for (i, ((x, y), (framed_w, framed_h))) in positions
+        .iter()
+        .zip(plan.framed_dimensions.iter())
+        .enumerate()
+    {
+        let caption_h = plan.caption_heights[i];
+        if caption_h == 0 {
+            continue;
+        }
+        let caption_y = match options.caption_position {
+            CaptionPosition::Above => y - caption_h,
+            CaptionPosition::Below => y + framed_h,
+        };
+        draw_caption(
+            output,
+            &options.captions[i],
+            *x,
+            caption_y,
+            *framed_w,
+            caption_h,
+            options.caption_size,
+            &options.caption_color,
+        );
+    }
+
+    if let Some((header, size)) = contact_sheet_header(options) {
+        let header_h = caption_strip_height(Some(header), size);
+        draw_caption(
+            output,
+            header,
+            padding.left,
+            padding.top - header_h,
+            content_width,
+            header_h,
+            size,
+            &options.caption_color,
+        );
+    }
+}
+
+/// RGBA canvas with 16 bits per sample.
+type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// Whether `high_bit_depth` can be honored for a plan: every scaled image
+/// kept 16 bits per sample, and nothing is drawn that only has an 8-bit
+/// implementation (frames, captions, shadows, gradients and the like).
+fn keeps_high_bit_depth(plan: &MergePlan, options: &MergeOptions) -> bool {
+    let deep_input = |img: &DynamicImage| {
+        matches!(
+            img.color(),
+            ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16
+        )
+    };
+    options.high_bit_depth
+        && plan.scaled_images.iter().all(deep_input)
+        && plan.border.is_none()
+        && plan.divider.is_none()
+        && options.draw_seams.is_none()
+        && plan.caption_heights.iter().all(|h| *h == 0)
+        && options.shadow.is_none()
+        && options.corner_radius == 0
+        && options.page_numbers.is_none()
+        && options.background_gradient.is_none()
+        && options.background_image.is_none()
+        && options.guides.as_ref().is_none_or(|g| g.separate_layer)
+        && contact_sheet_header(options).is_none()
+}
+
+/// Scratch memory for blurring the largest shadow, when shadows are drawn.
+fn shadow_bytes(
+    framed_dimensions: &[(u32, u32)],
+    content: (u64, u64),
+    options: &MergeOptions,
+) -> u64 {
+    match &options.shadow {
+        Some(shadow) if options.direction != Direction::Smart => {
+            let canvas = canvas_size(content, options);
+            framed_dimensions
+                .iter()
+                .map(|&caster| shadow_scratch_bytes(caster, canvas, shadow.blur_radius))
+                .max()
+                .unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+/// Estimated memory held by a 16-bit merge: the scaled inputs, the 16-bit
+/// canvas and the 8-bit copy used for previews and raw output.
+fn deep_peak_bytes(plan: &MergePlan, options: &MergeOptions) -> u64 {
+    let content = (plan.content_width as u64, plan.content_height as u64);
+    image_bytes(&plan.scaled_images)
+        .saturating_add(canvas_bytes(content, options).saturating_mul(3))
+}
+
+/// Composites a plan at 16 bits per sample, or returns `None` when
+/// `high_bit_depth` is off or cannot be honored.
+fn render_deep(
+    plan: &MergePlan,
+    positions: &Positions,
+    options: &MergeOptions,
+) -> Result<Option<Rgba16Image>, MergeError> {
+    if !keeps_high_bit_depth(plan, options) {
+        return Ok(None);
+    }
+    check_memory_budget(deep_peak_bytes(plan, options), options)?;
+    let content = (plan.content_width as u64, plan.content_height as u64);
+    let (width, height) = canvas_size(content, options);
+    let widen = |v: u8| v as u16 * 257;
+    let background = options.background;
+    let mut canvas = Rgba16Image::from_pixel(
+        width as u32,
+        height as u32,
+        Rgba([
+            widen(background.r),
+            widen(background.g),
+            widen(background.b),
+            widen(background.a),
+        ]),
+    );
+    for (i, (img, &(x, top))) in plan.scaled_images.iter().zip(positions).enumerate() {
+        let img = img.to_rgba16();
+        if options.direction != Direction::Smart {
+            composite_deep(&mut canvas, &img, (x, top), 0, 0);
+            continue;
+        }
+        let mut y = top;
+        for (crop_top, crop_bottom) in
+            smart_segments(&plan.chrome_trims, &plan.overlaps, i, img.height(), options)
+        {
+            composite_deep(&mut canvas, &img, (x, y), crop_top, crop_bottom);
+            y += img
+                .height()
+                .saturating_sub(crop_top)
+                .saturating_sub(crop_bottom);
+        }
+    }
+    Ok(Some(canvas))
+}
+
+/// [`composite_image_with_vertical_crop`] at 16 bits per sample.
+fn composite_deep(
+    dest: &mut Rgba16Image,
+    src: &Rgba16Image,
+    (x_offset, y_offset): (u32, u32),
+    crop_top: u32,
+    crop_bottom: u32,
+) {
+    let end_y_exclusive = src.height().saturating_sub(crop_bottom);
+    for y in crop_top.min(end_y_exclusive)..end_y_exclusive {
+        let dest_y = y_offset + (y - crop_top);
+        for x in 0..src.width() {
+            let dest_x = x_offset + x;
+            if dest_x < dest.width() && dest_y < dest.height() {
+                let blended =
+                    source_over_deep(*src.get_pixel(x, y), *dest.get_pixel(dest_x, dest_y));
+                dest.put_pixel(dest_x, dest_y, blended);
+            }
+        }
+    }
+}
+
+/// [`blend_with_background`] at 16 bits per sample.
+fn source_over_deep(pixel: Rgba<u16>, backdrop: Rgba<u16>) -> Rgba<u16> {
+    const MAX: f32 = u16::MAX as f32;
+    if pixel[3] == u16::MAX {
+        return pixel;
+    }
+    if pixel[3] == 0 {
+        return backdrop;
+    }
+    let alpha = pixel[3] as f32 / MAX;
+    let bg_alpha = backdrop[3] as f32 / MAX * (1.0 - alpha);
+    let out_alpha = alpha + bg_alpha;
+    let blend = |c: usize| {
+        ((pixel[c] as f32 * alpha + backdrop[c] as f32 * bg_alpha) / out_alpha).round() as u16
+    };
+    Rgba([
+        blend(0),
+        blend(1),
+        blend(2),
+        (out_alpha * MAX).round() as u16,
+    ])
+}
+
+/// Canvas rectangle and undrawn rows of every image in a plan.
+fn plan_placements(plan: &MergePlan, options: &MergeOptions) -> Vec<Placement> {
+    image_positions(plan, options)
+        .into_iter()
+        .zip(plan.scaled_dimensions.iter())
+        .enumerate()
+        .map(|(i, ((x, y), &(width, height)))| {
+            if options.direction != Direction::Smart {
+                return Placement {
+                    x,
+                    y,
+                    width,
+                    height,
+                    ..Default::default()
+                };
+            }
+            let segments = smart_segments(&plan.chrome_trims, &plan.overlaps, i, height, options);
+            let drawn: u32 = segments
+                .iter()
+                .map(|(top, bottom)| height.saturating_sub(top + bottom))
+                .sum();
+            let crop_bottom = segments.last().map_or(0, |(_, bottom)| *bottom);
+            Placement {
+                x,
+                y,
+                width,
+                height: drawn,
+                crop_top: height.saturating_sub(drawn + crop_bottom),
+                crop_bottom,
+            }
+        })
+        .collect()
+}
+
+/// Canvas position of the top-left drawn pixel of every image in a plan.
+fn image_positions(plan: &MergePlan, options: &MergeOptions) -> Positions {
+    if options.direction == Direction::Smart {
+        return smart_positions(plan, options);
+    }
+    let border_width = plan.border.map_or(0, |b| b.width);
+    let (_, positions) = stacked_layout(plan, options);
+    positions
+        .into_iter()
+        .map(|(x, y)| (x + border_width, y + border_width))
+        .collect()
+}
+
+/// Canvas positions, one `(x, y)` per image.
+type Positions = Vec<(u32, u32)>;
+
+/// Stacked directions: the canvas position of every slot, and of the framed
+/// image inside it (below a caption placed above).
+fn stacked_layout(plan: &MergePlan, options: &MergeOptions) -> (Positions, Positions) {
+    let padding = options.padding;
+    let mut slots: Positions = Vec::with_capacity(plan.slot_dimensions.len());
+    let mut offset: u32 = 0;
+    let grid = (options.direction == Direction::Grid).then(|| {
+        let columns = grid_columns(&plan.slot_dimensions, plan.gap, options);
+        let rows = grid_row_heights(&plan.slot_dimensions, columns, options.columns.is_some());
+        let mut row_tops = Vec::with_capacity(rows.len());
+        let mut top = 0;
+        for row_h in &rows {
+            row_tops.push(top);
+            top += row_h + plan.gap;
+        }
+        (
+            columns as usize,
+            grid_column_width(&plan.slot_dimensions),
+            rows,
+            row_tops,
+        )
+    });
+    for (i, (slot_w, slot_h)) in plan.slot_dimensions.iter().enumerate() {
+        if let Some((columns, cell_w, rows, row_tops)) = &grid {
+            // Row by row, each image aligned within its cell
+            let column = (i % columns) as u32;
+            let row = i / columns;
+            let x = padding.left
+                + column * (cell_w + plan.gap)
+                + align_offset(*cell_w, *slot_w, options.align_for(i));
+            let y = padding.top
+                + row_tops[row]
+                + align_offset(rows[row], *slot_h, options.vertical_align.into());
+            slots.push((x, y));
+        } else if options.direction == Direction::Vertical {
+            // Align horizontally if width is smaller than output width
+            let x = padding.left + align_offset(plan.content_width, *slot_w, options.align_for(i));
+            slots.push((x, padding.top + offset));
+            offset += slot_h + plan.gap;
+        } else {
+            // Align vertically if height is smaller than output height
+            let y = padding.top
+                + align_offset(plan.content_height, *slot_h, options.vertical_align.into());
+            slots.push((padding.left + offset, y));
+            offset += slot_w + plan.gap;
+        }
+    }
+    let positions = slots
+        .iter()
+        .zip(plan.caption_heights.iter())
+        .map(|((x, y), caption_h)| match options.caption_position {
+            CaptionPosition::Above => (*x, y + caption_h),
+            CaptionPosition::Below => (*x, *y),
+        })
+        .collect();
+    (slots, positions)
+}
+
+/// Grid mode: `columns` when set, else the column count whose output,
+/// padding and rotation included, comes closest to `target_aspect_ratio`
+/// (square when unset). Ties go to fewer columns.
+fn grid_columns(slot_dimensions: &[(u32, u32)], gap: u32, options: &MergeOptions) -> u32 {
+    if let Some(columns) = options.columns {
+        return columns.max(1);
+    }
+    let target = options.target_aspect_ratio.unwrap_or(1.0) as f64;
+    let mut best = (f64::INFINITY, 1);
+    for columns in 1..=slot_dimensions.len() as u32 {
+        let grid = compute_grid_size(slot_dimensions, columns, gap, false);
+        let canvas = canvas_size(grid, options);
+        let (width, height) = rotated_output_size(canvas, options);
+        // Compared on a log scale, so 2:1 and 1:2 are equally far from 1:1
+        let distance = (width as f64 / height.max(1) as f64 / target).ln().abs();
+        if distance < best.0 {
+            best = (distance, columns);
+        }
+    }
+    best.1
+}
+
+/// Smart mode: the canvas x of every image and the y of its first drawn row.
+fn smart_positions(plan: &MergePlan, options: &MergeOptions) -> Positions {
+    let (jitter, jitter_spread) = jitter_offsets(&plan.x_shifts);
+    let aligned_width = plan.content_width - jitter_spread;
+    let mut y = options.padding.top;
+    plan.scaled_dimensions
+        .iter()
+        .enumerate()
+        .map(|(i, (w, h))| {
+            let x = options.padding.left
+                + align_offset(aligned_width, *w, options.align_for(i))
+                + jitter.get(i).copied().unwrap_or(0);
+            let top = y;
+            for (crop_top, crop_bottom) in
+                smart_segments(&plan.chrome_trims, &plan.overlaps, i, *h, options)
+            {
+                y += h.saturating_sub(crop_top).saturating_sub(crop_bottom);
+            }
+            (x, top)
+        })
+        .collect()
+}
+
+/// Smart mode: row ranges of image `i` (of `height` rows) drawn as
+/// `(crop_top, crop_bottom)`: the content below the duplicated rows, plus the
+/// chrome bars when they are kept.
+fn smart_segments(
+    trims: &[ChromeTrim],
+    overlaps: &[u32],
+    i: usize,
+    height: u32,
+    options: &MergeOptions,
+) -> Vec<(u32, u32)> {
+    let trim = trims.get(i).copied().unwrap_or_default();
+    let overlap_from_prev = if i > 0 {
+        overlaps.get(i - 1).copied().unwrap_or(0)
+    } else {
+        0
+    };
+    let content = (trim.top.saturating_add(overlap_from_prev), trim.bottom);
+    if options.chrome_mode == ChromeMode::KeepAll {
+        vec![
+            (0, height.saturating_sub(trim.top)),
+            content,
+            (height.saturating_sub(trim.bottom), 0),
+        ]
+    } else {
+        vec![content]
+    }
+}
+
+/// Tiles mode: places every image unscaled at its known vertical offset.
+///
+/// Each tile starts at `image_options[i].tile_offset`, or right after the
+/// previous tile, and contributes its top `tile_height` rows (default: all).
+/// Later tiles are drawn over earlier ones where they overlap.
+fn merge_tiles(
+    decoded_images: &[impl Borrow<DynamicImage>],
+    options: &MergeOptions,
+) -> Result<Canvas, MergeError> {
+    let (placements, (content_width, content_height)) = tile_layout(decoded_images, options);
+    if content_width == 0 || content_height == 0 {
+        return Err(MergeError::NoImages);
+    }
+    check_canvas_size((content_width, content_height), options)?;
+    check_memory_budget(
+        image_bytes(decoded_images)
+            .saturating_add(canvas_bytes((content_width, content_height), options)),
+        options,
+    )?;
+
+    let mut output = new_canvas((content_width, content_height), options)?;
+    let mut tile_placements = Vec::with_capacity(decoded_images.len());
+    for (i, (img, (y, height))) in decoded_images.iter().zip(placements).enumerate() {
+        let rgba_img = flip_input(img.borrow(), i, options).to_rgba8();
+        let crop_bottom = rgba_img.height() - height;
+        let (x, y) = (options.padding.left, options.padding.top + y as u32);
+        composite_image_with_vertical_crop(&mut output, &rgba_img, x, y, 0, crop_bottom);
+        tile_placements.push(Placement {
+            x,
+            y,
+            width: rgba_img.width(),
+            height,
+            crop_top: 0,
+            crop_bottom,
+        });
+    }
+
+    Ok(Canvas {
+        output,
+        deep: None,
+        warnings: vec![],
+        repro_bundles: vec![],
+        placements: tile_placements,
+    })
+}
+
+/// Tiles mode: the `(y, rows used)` of every tile and the content size.
+fn tile_layout(
+    decoded_images: &[impl Borrow<DynamicImage>],
+    options: &MergeOptions,
+) -> (Vec<(u64, u32)>, (u64, u64)) {
+    let mut placements: Vec<(u64, u32)> = Vec::with_capacity(decoded_images.len());
+    let mut next_y: u64 = 0;
+    for (i, img) in decoded_images.iter().map(Borrow::borrow).enumerate() {
+        let tile = options.image_options.get(i);
+        let height = tile
+            .and_then(|t| t.tile_height)
+            .map_or(img.height(), |h| h.min(img.height()));
+        let y = tile.and_then(|t| t.tile_offset).map_or(next_y, u64::from);
+        next_y = y + height as u64;
+        placements.push((y, height));
+    }
+
+    let content_width = decoded_images
+        .iter()
+        .map(|img| img.borrow().width() as u64)
+        .max()
+        .unwrap_or(0);
+    let content_height = placements
+        .iter()
+        .map(|(y, height)| y + *height as u64)
+        .max()
+        .unwrap_or(0);
+    (placements, (content_width, content_height))
+}
+
+/// Canvas size for `content` once padding and snapping are applied.
+fn canvas_size(content: (u64, u64), options: &MergeOptions) -> (u64, u64) {
+    let (width, height) = apply_padding(content, options.padding);
+    (
+        snap_to_multiple(width, options.dimension_multiple),
+        snap_to_multiple(height, options.dimension_multiple),
+    )
+}
+
+fn exceeds_pixel_limit((width, height): (u64, u64), max_pixels: u64) -> bool {
+    width.saturating_mul(height) > max_pixels
+}
+
+/// Scales every dimension by `factor`, rounding down but keeping at least 1 px.
+fn shrink_dimensions(dimensions: &[(u32, u32)], factor: f64) -> Vec<(u32, u32)> {
+    dimensions
+        .iter()
+        .map(|(w, h)| {
+            (
+                ((*w as f64 * factor).floor() as u32).max(1),
+                ((*h as f64 * factor).floor() as u32).max(1),
+            )
+        })
+        .collect()
+}
+
+/// Fails if the padded, snapped canvas for `content` is too large to produce.
+fn check_canvas_size(content: (u64, u64), options: &MergeOptions) -> Result<(), MergeError> {
+    let (output_width, output_height) = canvas_size(content, options);
+
+    if let Some(max_pixels) = options.max_output_pixels
+        && exceeds_pixel_limit((output_width, output_height), max_pixels)
+    {
+        return Err(MergeError::OutputTooLarge {
+            width: output_width,
+            height: output_height,
+            max_pixels,
+        });
+    }
+
+    if output_width > u32::MAX as u64 || output_height > u32::MAX as u64 {
+        return Err(MergeError::EncodeError {
+            message: "Output dimensions exceed supported size".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Bytes of the RGBA canvas holding `content` plus padding and snapping,
+/// saturating at `u64::MAX`; sums including it must saturate too.
+fn canvas_bytes(content: (u64, u64), options: &MergeOptions) -> u64 {
+    let (width, height) = canvas_size(content, options);
+    width.saturating_mul(height).saturating_mul(4)
+}
+
+/// Bytes held by decoded images.
+fn image_bytes(images: &[impl Borrow<DynamicImage>]) -> u64 {
+    images
+        .iter()
+        .map(|img| img.borrow().as_bytes().len() as u64)
+        .sum()
+}
+
+/// Fails with [`MergeError::OutOfMemory`] when `required_bytes` exceeds
+/// `options.max_memory_bytes`.
+fn check_memory_budget(required_bytes: u64, options: &MergeOptions) -> Result<(), MergeError> {
+    match options.max_memory_bytes {
+        Some(max_bytes) if required_bytes > max_bytes => Err(MergeError::OutOfMemory {
+            required_bytes,
+            max_bytes,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Background-filled canvas holding `content` plus padding and snapping.
+fn new_canvas(content: (u64, u64), options: &MergeOptions) -> Result<RgbaImage, MergeError> {
+    let (canvas_width, canvas_height) = canvas_size(content, options);
+    let mut canvas = RgbaImage::from_pixel(
+        canvas_width as u32,
+        canvas_height as u32,
+        Rgba([
+            options.background.r,
+            options.background.g,
+            options.background.b,
+            options.background.a,
+        ]),
+    );
+    if let Some(gradient) = &options.background_gradient {
+        paint_gradient(&mut canvas, gradient);
+    }
+    if let Some(background_image) = &options.background_image {
+        let data = &background_image.bytes;
+        let image =
+            decode_image(data).map_err(|message| MergeError::BackgroundDecodeError { message })?;
+        let image = normalize_orientation(image, extract_orientation(data));
+        paint_image(&mut canvas, &image, background_image.fit);
+    }
+    Ok(canvas)
+}
+
+/// Rotates, adds guides and renditions to, and encodes a composited canvas.
+///
+/// The PNG goes into `sink` when one is given, leaving `bytes` empty;
+/// returns the output with the encoded length.
+fn finish_output(
+    canvas: Canvas,
+    options: &MergeOptions,
+    sink: Option<&mut dyn Write>,
+) -> Result<(MergeOutput, usize), MergeError> {
+    let Canvas {
+        output,
+        deep,
+        warnings,
+        repro_bundles,
+        placements,
+    } = canvas;
+    let canvas_size = output.dimensions();
+    let placements: Vec<_> = placements
+        .into_iter()
+        .map(|p| rotate_placement(p, canvas_size, options.output_rotation))
+        .collect();
+    let mut output = rotate_output(output, options.output_rotation);
+    let deep = deep.map(|deep| rotate_output(deep, options.output_rotation));
+    let (width, height) = output.dimensions();
+    let mut guide_layer = None;
+    if let Some(guides) = &options.guides {
+        if guides.separate_layer {
+            let mut layer = RgbaImage::new(width, height);
+            render_guides(&mut layer, guides);
+            guide_layer = Some(PreviewOutput {
+                bytes: encode_png(&DynamicImage::ImageRgba8(layer))?,
+                width,
+                height,
+            });
+        } else {
+            render_guides(&mut output, guides);
+        }
+    }
+    let output = DynamicImage::ImageRgba8(output);
+    let preview = options
+        .also_preview
+        .then(|| {
+            let size = fit_within((width, height), options.preview_max_dimension);
+            render_preview(
+                &output,
+                size,
+                options.preview_proof_quality,
+                resampling(options),
+            )
+        })
+        .transpose()?;
+    let output_blur_hash = if options.blur_hash {
+        blur_hash(&output)
+    } else {
+        None
+    };
+    let thumbnail = options
+        .thumbnail_width
+        .map(|max_width| {
+            let size = fit_width((width, height), max_width.max(1));
+            render_preview(&output, size, None, resampling(options))
+        })
+        .transpose()?;
+    let mut output_bytes = Vec::new();
+    let pixels = match &deep {
+        Some(deep) => PngPixels::Sixteen(deep.as_raw()),
+        None => PngPixels::Eight(output.as_bytes()),
+    };
+    let encoded_bytes = write_png(
+        pixels,
+        (width, height),
+        options,
+        &placements,
+        sink.unwrap_or(&mut output_bytes),
+    )?;
+
+    let output = MergeOutput {
+        bytes: output_bytes,
+        width,
+        height,
+        warnings,
+        preview,
+        thumbnail,
+        blur_hash: output_blur_hash,
+        guide_layer,
+        seams: vec![],
+        stats: None,
+        repro_bundles,
+        placements,
+    };
+    Ok((output, encoded_bytes))
+}
+
+/// Moves a placement on a `canvas`-sized image to where `rotation` puts it.
+fn rotate_placement(placement: Placement, canvas: (u32, u32), rotation: Rotation) -> Placement {
+    let Placement {
+        x,
+        y,
+        width,
+        height,
+        ..
+    } = placement;
+    let (canvas_width, canvas_height) = canvas;
+    let (x, y, width, height) = match rotation {
+        Rotation::None => (x, y, width, height),
+        Rotation::Clockwise90 => (canvas_height - y - height, x, height, width),
+        Rotation::Clockwise180 => (
+            canvas_width - x - width,
+            canvas_height - y - height,
+            width,
+            height,
+        ),
+        Rotation::Clockwise270 => (y, canvas_width - x - width, height, width),
+    };
+    Placement {
+        x,
+        y,
+        width,
+        height,
+        ..placement
+    }
+}
+
+fn rotate_output<P: Pixel + 'static>(
+    output: ImageBuffer<P, Vec<P::Subpixel>>,
+    rotation: Rotation,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    match rotation {
+        Rotation::None => output,
+        Rotation::Clockwise90 => image::imageops::rotate90(&output),
+        Rotation::Clockwise180 => image::imageops::rotate180(&output),
+        Rotation::Clockwise270 => image::imageops::rotate270(&output),
+    }
+}
+
+/// Scales the finished canvas to `size` and encodes it, optionally
+/// soft-proofed at a JPEG quality.
+fn render_preview(
+    output: &DynamicImage,
+    size: (u32, u32),
+    proof_quality: Option<u8>,
+    resampling: Resampling,
+) -> Result<PreviewOutput, MergeError> {
+    let (width, height) = size;
+    let mut image = scale_image_with(output, width, height, resampling, &mut Interrupt::none());
+    if let Some(quality) = proof_quality {
+        image = proof_jpeg(&image, quality)?;
+    }
+    Ok(PreviewOutput {
+        bytes: encode_png(&image)?,
+        width,
+        height,
+    })
+}
+
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, MergeError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+    image
+        .write_with_encoder(encoder)
+        .map_err(|e| MergeError::EncodeError {
+            message: e.to_string(),
+        })?;
+    Ok(bytes)
+}
+
+/// Encodes RGBA `pixels` as a PNG into `sink` one row at a time, with the
+/// settings of `image`'s default PNG encoder, so neither a filtered copy of
+/// the image nor the whole compressed stream is held in memory. The output
+/// `exif` and `dpi` of `options`, if any, are written as `eXIf` and `pHYs`
+/// chunks, and with `provenance` a `tEXt` chunk recording `placements`; no
+/// other ancillary chunk is ever written, and `strip_metadata` drops all but
+/// `pHYs`. Returns the number of bytes written.
+fn write_png(
+    pixels: PngPixels,
+    (width, height): (u32, u32),
+    options: &MergeOptions,
+    placements: &[Placement],
+    sink: &mut dyn Write,
+) -> Result<usize, MergeError> {
+    let encode_error = |message: String| MergeError::EncodeError { message };
+    let mut sink = CountingWriter {
+        inner: sink,
+        written: 0,
+    };
+    let mut info = png::Info::with_size(width, height);
+    if !options.strip_metadata {
+        info.exif_metadata = options.exif.as_deref().map(Cow::Borrowed);
+        if options.provenance {
+            info.uncompressed_latin1_text
+                .push(png::text_metadata::TEXtChunk::new(
+                    PROVENANCE_KEYWORD,
+                    provenance(options, placements),
+                ));
+        }
+    }
+    info.pixel_dims = options.dpi.map(|dpi| {
+        let per_meter = pixels_per_meter(dpi);
+        png::PixelDimensions {
+            xppu: per_meter,
+            yppu: per_meter,
+            unit: png::Unit::Meter,
+        }
+    });
+    let mut encoder =
+        png::Encoder::with_info(&mut sink, info).map_err(|e| encode_error(e.to_string()))?;
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(match pixels {
+        PngPixels::Eight(_) => png::BitDepth::Eight,
+        PngPixels::Sixteen(_) => png::BitDepth::Sixteen,
+    });
+    encoder.set_compression(png::Compression::Balanced);
+    encoder.set_filter(png::Filter::Adaptive);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| encode_error(e.to_string()))?;
+    let mut stream = writer
+        .stream_writer_with_size(PNG_CHUNK_BYTES)
+        .map_err(|e| encode_error(e.to_string()))?;
+    match pixels {
+        PngPixels::Eight(bytes) => stream.write_all(bytes),
+        // PNG stores 16-bit samples big-endian; converted a chunk at a time
+        PngPixels::Sixteen(samples) => samples.chunks(PNG_CHUNK_BYTES / 2).try_for_each(|chunk| {
+            let bytes: Vec<u8> = chunk.iter().flat_map(|s| s.to_be_bytes()).collect();
+            stream.write_all(&bytes)
+        }),
+    }
+    .map_err(|e| encode_error(e.to_string()))?;
+    stream.finish().map_err(|e| encode_error(e.to_string()))?;
+    writer.finish().map_err(|e| encode_error(e.to_string()))?;
+    Ok(sink.written)
+}
+
+/// RGBA samples of the canvas to encode.
+#[derive(Clone, Copy)]
+enum PngPixels<'a> {
+    Eight(&'a [u8]),
+    Sixteen(&'a [u16]),
+}
+
+/// Passes writes through to `inner`, counting the bytes accepted.
+struct CountingWriter<'a> {
+    inner: &'a mut dyn Write,
+    written: usize,
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Round-trips `image` through JPEG at `quality` (1-100) so its compression
+/// artifacts can be judged. Alpha is dropped, as JPEG has none.
+fn proof_jpeg(image: &DynamicImage, quality: u8) -> Result<DynamicImage, MergeError> {
+    let encode_error = |e: image::ImageError| MergeError::EncodeError {
+        message: e.to_string(),
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    let encoder =
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality.clamp(1, 100));
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(encoder)
+        .map_err(encode_error)?;
+    image::load_from_memory_with_format(&bytes, image::ImageFormat::Jpeg).map_err(encode_error)
+}
+
+/// Size each image is drawn at: pinned images keep native size, as do images
+/// that would be enlarged when upscaling is disallowed.
+fn compute_layout_dimensions(
+    dimensions: &[(u32, u32)],
+    target: u32,
+    options: &MergeOptions,
+) -> Vec<(u32, u32)> {
+    dimensions
+        .iter()
+        .enumerate()
+        .map(|(i, (w, h))| {
+            let native = options.is_pinned(i)
+                || options.scale_mode == ScaleMode::None
+                || (options.is_low_color(i)
+                    && options.low_color_scaling == LowColorScaling::Native);
+            if native {
+                return (*w, *h);
+            }
+            let scaled = compute_scaled_dimensions(*w, *h, target, options.direction);
+            if !options.allow_upscale && scale_factor((*w, *h), scaled) > 1.0 {
+                (*w, *h)
+            } else {
+                scaled
+            }
+        })
+        .collect()
+}
+
+/// Per-image x offsets that apply the pairwise jitter shifts, normalized so
+/// the leftmost image sits at 0, and the extra width they span.
+fn jitter_offsets(x_shifts: &[i32]) -> (Vec<u32>, u32) {
+    let mut positions = vec![0i64];
+    for shift in x_shifts {
+        positions.push(positions[positions.len() - 1] + *shift as i64);
+    }
+    let min = positions.iter().copied().min().unwrap_or(0);
+    let max = positions.iter().copied().max().unwrap_or(0);
+    let offsets = positions.iter().map(|p| (p - min) as u32).collect();
+    (offsets, (max - min) as u32)
+}
+
+/// Rows actually cut from each image for the chrome mode; `KeepAll` only
+/// uses the detected chrome to steer matching.
+fn removed_chrome(trims: &[ChromeTrim], mode: ChromeMode) -> Vec<ChromeTrim> {
+    match mode {
+        ChromeMode::KeepAll => vec![ChromeTrim::default(); trims.len()],
+        ChromeMode::KeepEnds | ChromeMode::RemoveAll => trims.to_vec(),
+    }
+}
+
+/// Smart mode chrome trims, or no trims when chrome stripping is disabled.
+fn chrome_trims(
+    scaled_images: &[DynamicImage],
+    options: &MergeOptions,
+) -> Result<Vec<ChromeTrim>, MergeError> {
+    if !options.strip_chrome {
+        return Ok(vec![ChromeTrim::default(); scaled_images.len()]);
+    }
+    let (trims, fallbacks) = compute_chrome_trims(
+        scaled_images,
+        options.chrome_proxy_width,
+        options.chrome_mode == ChromeMode::RemoveAll,
+    );
+    match fallbacks.first() {
+        Some(&index) if options.strict => Err(MergeError::StrictViolation {
+            index,
+            message: "repeated chrome was kept because trimming it would leave too little content"
+                .to_string(),
+        }),
+        _ => Ok(trims),
+    }
+}
+
+/// In strict mode, refuses animated inputs about to be flattened to their
+/// first frame; `flattened` says which inputs would be, in merge order.
+pub(crate) fn check_animated_inputs(
+    flattened: impl IntoIterator<Item = bool>,
+    options: &MergeOptions,
+) -> Result<(), MergeError> {
+    if !options.strict {
+        return Ok(());
+    }
+    match flattened.into_iter().position(|flattened| flattened) {
+        Some(index) => Err(MergeError::StrictViolation {
+            index,
+            message: "the input is animated and only its first frame would be merged; choose one with `frame`"
+                .to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// In strict mode, refuses Smart mode pairs whose widths differ too much to
+/// be matched, which would otherwise be concatenated without detection.
+fn check_pair_widths(
+    scaled_images: &[DynamicImage],
+    options: &MergeOptions,
+) -> Result<(), MergeError> {
+    if !options.strict {
+        return Ok(());
+    }
+    for (index, pair) in scaled_images.windows(2).enumerate() {
+        if options
+            .overlap_overrides
+            .get(index)
+            .copied()
+            .flatten()
+            .is_some()
+        {
+            continue;
+        }
+        let (top, bottom) = (pair[0].width(), pair[1].width());
+        let ratio = top.min(bottom) as f32 / top.max(bottom) as f32;
+        if ratio < options.width_ratio_threshold {
+            return Err(MergeError::StrictViolation {
+                index,
+                message: format!(
+                    "widths {top} and {bottom} of images {index} and {} are too different to match",
+                    index + 1
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Matching parameters for each of `pairs` adjacent pairs, applying any
+/// per-pair sensitivity.
+fn overlap_params(options: &MergeOptions, pairs: usize) -> Vec<OverlapParams> {
+    (0..pairs)
+        .map(|i| OverlapParams {
+            sensitivity: options
+                .overlap_sensitivities
+                .get(i)
+                .copied()
+                .flatten()
+                .unwrap_or(options.overlap_sensitivity),
+            min_overlap_pixels: options.min_overlap_pixels,
+            width_ratio_threshold: options.width_ratio_threshold,
+        })
+        .collect()
+}
+
+/// Finds the Smart mode seams to split at, laid out as a full merge would be.
+fn find_seam_breaks(
+    decoded_images: &[DynamicImage],
+    options: &MergeOptions,
+) -> Result<Vec<SeamBreak>, MergeError> {
+    let dimensions = image_dimensions(decoded_images);
+    let physical = physical_dimensions(&dimensions, options);
+    let target = compute_target_dimension(&physical, options.direction, options.scale_mode);
+    if target == 0 {
+        return Ok(vec![]);
+    }
+
+    let scaled_dimensions = compute_layout_dimensions(&physical, target, options);
+    let scaled_images: Vec<DynamicImage> = decoded_images
+        .iter()
+        .zip(scaled_dimensions.iter())
+        .enumerate()
+        .map(|(i, (img, size))| scale_input(img, *size, i, options, &mut Interrupt::none()))
+        .collect();
+    check_pair_widths(&scaled_images, options)?;
+    let trims = chrome_trims(&scaled_images, options)?;
+    let regions = scale_overlap_regions(&options.overlap_regions, &dimensions, &scaled_dimensions);
+
+    Ok(detect_overlaps_with_trims(
+        &scaled_images,
+        &trims,
+        &overlap_params(options, scaled_images.len().saturating_sub(1)),
+        &regions,
+        &options.overlap_overrides,
+    )
+    .into_iter()
+    .enumerate()
+    .filter_map(|(after_index, result)| {
+        let reason = match result {
+            None => SplitReason::NoOverlap,
+            Some(r) if r.confidence < options.split_min_confidence => SplitReason::LowConfidence {
+                confidence: r.confidence,
+            },
+            Some(_) => return None,
+        };
+        Some(SeamBreak {
+            after_index,
+            reason,
+        })
+    })
+    .collect())
+}
+
+/// Options for merging images `start..end` on their own.
+fn options_for_range(options: &MergeOptions, start: usize, end: usize) -> MergeOptions {
+    let clamp = |len: usize, to: usize| start.min(len)..to.min(len);
+    MergeOptions {
+        overlap_regions: options.overlap_regions
+            [clamp(options.overlap_regions.len(), end.saturating_sub(1))]
+        .to_vec(),
+        overlap_overrides: options.overlap_overrides
+            [clamp(options.overlap_overrides.len(), end.saturating_sub(1))]
+        .to_vec(),
+        overlap_sensitivities: options.overlap_sensitivities
+            [clamp(options.overlap_sensitivities.len(), end.saturating_sub(1))]
+        .to_vec(),
+        image_options: options.image_options[clamp(options.image_options.len(), end)].to_vec(),
+        captions: options.captions[clamp(options.captions.len(), end)].to_vec(),
+        ..options.clone()
+    }
+}
+
+/// Tells `hooks` about `progress`, failing with [`MergeError::Cancelled`] if
+/// the host has cancelled the merge since.
+fn report_progress(hooks: &mut dyn MergeHooks, progress: Progress) -> Result<(), MergeError> {
+    hooks.progress(progress);
+    if hooks.is_cancelled() {
+        return Err(MergeError::Cancelled);
+    }
+    Ok(())
+}
+
+fn hook_rejected(stage: HookStage, message: String) -> MergeError {
+    MergeError::HookRejected { stage, message }
+}
+
+/// Shifts an image index reported for a run that starts at `start`.
+fn offset_error_index(error: MergeError, start: usize) -> MergeError {
+    match error {
+        MergeError::UpscaleTooLarge {
+            index,
+            factor,
+            max_factor,
+        } => MergeError::UpscaleTooLarge {
+            index: index + start,
+            factor,
+            max_factor,
+        },
+        MergeError::StrictViolation { index, message } => MergeError::StrictViolation {
+            index: index + start,
+            message,
+        },
+        other => other,
+    }
+}
+
+fn offset_warning_index(warning: MergeWarning, start: usize) -> MergeWarning {
+    match warning {
+        MergeWarning::Upscaled {
+            index,
+            factor,
+            max_factor,
+        } => MergeWarning::Upscaled {
+            index: index + start,
+            factor,
+            max_factor,
+        },
+        MergeWarning::OverlapNotFound { pair } => {
+            MergeWarning::OverlapNotFound { pair: pair + start }
+        }
+        warning @ MergeWarning::Downscaled { .. } => warning,
+    }
+}
+
+/// Maps per-pair overlap regions from source pixels into scaled pixels.
+///
+/// Region `i` belongs to image `i` (the top image of pair `i`).
+fn scale_overlap_regions(
+    regions: &[Option<OverlapRegion>],
+    dimensions: &[(u32, u32)],
+    scaled_dimensions: &[(u32, u32)],
+) -> Vec<Option<OverlapRegion>> {
+    regions
+        .iter()
+        .enumerate()
+        .map(|(i, region)| {
+            let region = (*region)?;
+            let (w, h) = *dimensions.get(i)?;
+            let (sw, sh) = *scaled_dimensions.get(i)?;
+            if w == 0 || h == 0 {
+                return None;
+            }
+            let sx = sw as f64 / w as f64;
+            let sy = sh as f64 / h as f64;
+            Some(OverlapRegion {
+                x: (region.x as f64 * sx).round() as u32,
+                y: (region.y as f64 * sy).round() as u32,
+                width: (region.width as f64 * sx).round() as u32,
+                height: (region.height as f64 * sy).round() as u32,
+            })
+        })
+        .collect()
+}
+
+/// Fills a rectangle with a solid color, clipped to the canvas.
+fn fill_rect(dest: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    let x_end = x.saturating_add(width).min(dest.width());
+    let y_end = y.saturating_add(height).min(dest.height());
+    for dest_y in y..y_end {
+        for dest_x in x..x_end {
+            dest.put_pixel(dest_x, dest_y, color);
+        }
+    }
+}
+
+/// Fills a `border.width`-thick frame along the inside edge of a rectangle.
+fn draw_frame(dest: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, border: &Border) {
+    let color = Rgba([
+        border.color.r,
+        border.color.g,
+        border.color.b,
+        border.color.a,
+    ]);
+    let thickness = border.width;
+    for dy in 0..height {
+        for dx in 0..width {
+            let on_frame = dx < thickness
+                || dy < thickness
+                || dx >= width.saturating_sub(thickness)
+                || dy >= height.saturating_sub(thickness);
+            let (dest_x, dest_y) = (x + dx, y + dy);
+            if on_frame && dest_x < dest.width() && dest_y < dest.height() {
+                dest.put_pixel(dest_x, dest_y, color);
+            }
+        }
+    }
+}
+
+/// Fades the corners of `img` to transparent along an anti-aliased arc.
+///
+/// Compositing then blends the cut-away corners with the canvas beneath.
+/// The radius is clamped to half the shorter side.
+fn round_corners(img: &mut RgbaImage, radius: u32) {
+    let (width, height) = img.dimensions();
+    let radius = radius.min(width / 2).min(height / 2);
+    if radius == 0 {
+        return;
+    }
+
+    let r = radius as f32;
+    for y in 0..height {
+        // Distance from the arc center along each axis, zero outside the corner bands
+        let dy = if y < radius {
+            r - (y as f32 + 0.5)
+        } else if y >= height - radius {
+            (y - (height - radius)) as f32 + 0.5
+        } else {
+            continue;
+        };
+        for x in 0..width {
+            let dx = if x < radius {
+                r - (x as f32 + 0.5)
+            } else if x >= width - radius {
+                (x - (width - radius)) as f32 + 0.5
+            } else {
+                continue;
+            };
+            let coverage = (r - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+            if coverage < 1.0 {
+                let pixel = img.get_pixel_mut(x, y);
+                pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+            }
+        }
+    }
+}
+
+/// Composites a source image onto a destination canvas, cropping the top and bottom portions.
+/// Used for Smart merge mode to remove chrome and overlapping content.
+fn composite_image_with_vertical_crop(
+    dest: &mut RgbaImage,
+    src: &RgbaImage,
+    x_offset: u32,
+    y_offset: u32,
+    crop_top: u32,
+    crop_bottom: u32,
+) {
+    let src_h = src.height();
+    let crop_top = crop_top.min(src_h);
+    let crop_bottom = crop_bottom.min(src_h - crop_top);
+    composite_rows(dest, src, x_offset, y_offset, crop_top..src_h - crop_bottom);
+}
+
+/// Composites the source `rows` onto a destination canvas, the first of them
+/// at `y_offset`.
+fn composite_rows(
+    dest: &mut RgbaImage,
+    src: &RgbaImage,
+    x_offset: u32,
+    y_offset: u32,
+    rows: std::ops::Range<u32>,
+) {
+    for y in rows.clone() {
+        let dest_y = y_offset + (y - rows.start);
+        if dest_y >= dest.height() {
+            break;
+        }
+        for x in 0..src.width() {
+            let dest_x = x_offset + x;
+            if dest_x >= dest.width() {
+                break;
+            }
+            let blended =
+                blend_with_background(*src.get_pixel(x, y), &backdrop(dest, dest_x, dest_y));
+            dest.put_pixel(dest_x, dest_y, blended);
+        }
+    }
+}
+
+/// Canvas pixel at `(x, y)`, as the color to blend a source pixel onto.
+fn backdrop(canvas: &RgbaImage, x: u32, y: u32) -> BackgroundColor {
+    let Rgba([r, g, b, a]) = *canvas.get_pixel(x, y);
+    BackgroundColor::new(r, g, b, a)
+}
+
+/// Composites a pixel over the background color (Porter-Duff source-over on
+/// unpremultiplied RGBA), so a translucent pixel over a translucent or
+/// transparent background keeps the combined coverage as its alpha.
+fn blend_with_background(pixel: Rgba<u8>, background: &BackgroundColor) -> Rgba<u8> {
+    let alpha = pixel[3] as f32 / 255.0;
+
+    if alpha >= 1.0 {
+        return pixel;
+    }
+
+    if alpha <= 0.0 {
+        return Rgba([background.r, background.g, background.b, background.a]);
+    }
+
+    // Share of the result's coverage contributed by the background
+    let bg_alpha = background.a as f32 / 255.0 * (1.0 - alpha);
+    let out_alpha = alpha + bg_alpha;
+    let blend = |fg: u8, bg: u8| -> u8 {
+        let fg_f = fg as f32;
+        let bg_f = bg as f32;
+        ((fg_f * alpha + bg_f * bg_alpha) / out_alpha).round() as u8
+    };
+
+    Rgba([
+        blend(pixel[0], background.r),
+        blend(pixel[1], background.g),
+        blend(pixel[2], background.b),
+        (out_alpha * 255.0).round() as u8,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        Align, BackgroundFit, BackgroundImage, ContactSheet, Corner, Divider, ExifCopy, Gradient,
+        Guides, ImageOptions, PageNumbers, Shadow, TrimMargins, VerticalAlign,
+    };
+
+    fn create_test_png(width: u32, height: u32, color: Rgba<u8>) -> Vec<u8> {
+        let img = RgbaImage::from_pixel(width, height, color);
+        let mut bytes = Vec::new();
+        let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+        DynamicImage::ImageRgba8(img)
+            .write_with_encoder(encoder)
+            .unwrap();
+        bytes
+    }
+
+    fn create_smart_fixture_png(
+        width: u32,
+        chrome_h: u32,
+        content_h: u32,
+        global_start: u32,
+    ) -> Vec<u8> {
+        let full_h = chrome_h + content_h + chrome_h;
+        let mut img = RgbaImage::from_pixel(width, full_h, Rgba([20, 20, 20, 255]));
+
+        for y in 0..content_h {
+            let gy = global_start + y;
+            for x in 0..width {
+                let mut z = (x as u64).wrapping_mul(0x9E3779B97F4A7C15)
+                    ^ (gy as u64).wrapping_mul(0xBF58476D1CE4E5B9);
+                z ^= z >> 30;
+                z = z.wrapping_mul(0xBF58476D1CE4E5B9);
+                z ^= z >> 27;
+                z = z.wrapping_mul(0x94D049BB133111EB);
+                z ^= z >> 31;
+                let mut g = (z >> 56) as u8;
+
+                // Unique marker band at fixed global Y to make overlap unambiguous.
+                if (210..218).contains(&gy) && x < 80 {
+                    g = (x as u8).wrapping_mul(3) ^ 0xA5;
+                }
+
+                img.put_pixel(x, chrome_h + y, Rgba([g, g, g, 255]));
+            }
+        }
+
+        let mut bytes = Vec::new();
+        let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+        DynamicImage::ImageRgba8(img)
+            .write_with_encoder(encoder)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_merge_no_images() {
+        let result = merge(Vec::<Vec<u8>>::new(), MergeOptions::default());
+        assert!(matches!(result, Err(MergeError::NoImages)));
+    }
+
+    #[test]
+    fn test_merge_single_image() {
+        let img_data = create_test_png(100, 200, Rgba([255, 0, 0, 255]));
+        let result = merge(vec![img_data], MergeOptions::default());
+        assert!(result.is_ok());
+
+        let output_bytes = result.unwrap();
+        let output_img = decode_image(&output_bytes).unwrap();
+        assert_eq!(output_img.width(), 100);
+        assert_eq!(output_img.height(), 200);
+    }
+
+    #[test]
+    fn test_merge_vertical() {
+        let img1 = create_test_png(100, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(100, 50, Rgba([0, 255, 0, 255]));
+
+        let options = MergeOptions {
+            direction: Direction::Vertical,
+            ..Default::default()
+        };
+
+        let result = merge(vec![img1, img2], options);
+        assert!(result.is_ok());
+
+        let output_bytes = result.unwrap();
+        let output_img = decode_image(&output_bytes).unwrap();
+        assert_eq!(output_img.width(), 100);
+        assert_eq!(output_img.height(), 100); // 50 + 50
+    }
+
+    #[test]
+    fn test_merge_with_metadata_reports_dimensions() {
+        let img1 = create_test_png(40, 30, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(40, 20, Rgba([0, 255, 0, 255]));
+
+        let output = merge_with_metadata(vec![img1, img2], MergeOptions::default()).unwrap();
+        assert_eq!((output.width, output.height), (40, 50));
+        assert_eq!(output.byte_length(), output.bytes.len());
+        assert_eq!(output.sha256_hex().len(), 64);
+    }
+
+    #[test]
+    fn test_merge_smart_chrome_strip_and_overlap() {
+        // Two images with repeated chrome and overlapping content.
+        let width = 220;
+        let chrome_h = 20;
+        let content_h = 300;
+        let overlap = 100;
+
+        // Top screenshot covers global rows 0..content_h.
+        // Bottom screenshot covers global rows (content_h - overlap)..
+        let img1 = create_smart_fixture_png(width, chrome_h, content_h, 0);
+        let img2 = create_smart_fixture_png(width, chrome_h, content_h, content_h - overlap);
+
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+
+        let result = merge(vec![img1, img2], options);
+        assert!(result.is_ok());
+
+        let output_bytes = result.unwrap();
+        let output_img = decode_image(&output_bytes).unwrap();
+
+        // Expected height:
+        // 340 + 340 - (top trim 20) - (bottom trim 20) - (overlap 100) = 540
+        assert_eq!(output_img.width(), width);
+        assert_eq!(output_img.height(), 540);
+    }
+
+    #[test]
+    fn test_merge_split_at_missing_overlap() {
+        let width = 220;
+        let chrome_h = 20;
+        let content_h = 300;
+        let overlap = 100;
+
+        // Images 0 and 1 overlap; image 2 shows unrelated content.
+        let img1 = create_smart_fixture_png(width, chrome_h, content_h, 0);
+        let img2 = create_smart_fixture_png(width, chrome_h, content_h, content_h - overlap);
+        let img3 = create_smart_fixture_png(width, chrome_h, content_h, 5000);
+
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+        let split = merge_split(vec![img1, img2, img3], options).unwrap();
+
+        assert_eq!(
+            split.breaks,
+            vec![SeamBreak {
+                after_index: 1,
+                reason: SplitReason::NoOverlap,
+            }]
+        );
+        assert_eq!(split.outputs.len(), 2);
+        assert_eq!(split.outputs[0].height, 540);
+        assert_eq!(split.outputs[1].width, width);
+    }
+
+    #[test]
+    fn test_analyze_smart_reports_trims_and_overlap() {
+        let width = 220;
+        let img1 = create_smart_fixture_png(width, 20, 300, 0);
+        let img2 = create_smart_fixture_png(width, 20, 300, 200);
+
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+        let analysis = analyze(vec![img1, img2], options).unwrap();
+
+        // Matches the composited size from test_merge_smart_chrome_strip_and_overlap
+        assert_eq!((analysis.width, analysis.height), (width, 540));
+        assert_eq!(analysis.images[0].trim_bottom, 20);
+        assert_eq!(analysis.images[1].trim_top, 20);
+        assert_eq!(analysis.pairs.len(), 1);
+        assert_eq!(analysis.pairs[0].overlap, 100);
+        assert!(analysis.pairs[0].confidence.is_some());
+    }
+
+    #[test]
+    fn test_execute_plan_uses_edited_seams() {
+        let width = 220;
+        let images = || {
+            vec![
+                create_smart_fixture_png(width, 20, 300, 0),
+                create_smart_fixture_png(width, 20, 300, 200),
+            ]
+        };
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+
+        let mut plan = analyze(images(), options.clone()).unwrap();
+        let output = execute_plan(images(), options.clone(), &plan).unwrap();
+        let merged = merge_with_metadata(images(), options.clone()).unwrap();
+        assert_eq!(output.bytes, merged.bytes);
+
+        plan.pairs[0].overlap = 0;
+        let output = execute_plan(images(), options, &plan).unwrap();
+        assert_eq!(output.height, merged.height + 100);
+    }
+
+    #[test]
+    fn test_overlap_override_replaces_detection() {
+        let width = 220;
+        let images = || {
+            vec![
+                create_smart_fixture_png(width, 20, 300, 0),
+                create_smart_fixture_png(width, 20, 300, 200),
+                create_smart_fixture_png(width, 20, 300, 400),
+            ]
+        };
+
+        // Pin the first pair; the second is still detected (100 px).
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            overlap_overrides: vec![Some(40)],
+            ..Default::default()
+        };
+        let analysis = analyze(images(), options.clone()).unwrap();
+        assert_eq!(analysis.pairs[0].overlap, 40);
+        assert_eq!(analysis.pairs[0].confidence, Some(1.0));
+        assert_eq!(analysis.pairs[1].overlap, 100);
+
+        // 3 * 340 - 2 * (20 + 20) - 40 - 100
+        let output = decode_image(&merge(images(), options).unwrap()).unwrap();
+        assert_eq!(output.height(), 800);
+
+        // Overrides larger than the remaining content are clamped.
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            overlap_overrides: vec![Some(10_000)],
+            ..Default::default()
+        };
+        let analysis = analyze(images(), options).unwrap();
+        assert_eq!(analysis.pairs[0].overlap, 300);
+    }
+
+    #[test]
+    fn test_smart_merge_lines_up_jittered_capture() {
+        let width = 220;
+        let bottom = decode_image(&create_smart_fixture_png(width, 20, 300, 200))
+            .unwrap()
+            .to_rgba8();
+        // The second capture's content sits 3 px further left.
+        let shifted = RgbaImage::from_fn(width, bottom.height(), |x, y| {
+            *bottom.get_pixel((x + 3).min(width - 1), y)
+        });
+        let mut shifted_png = Vec::new();
+        DynamicImage::ImageRgba8(shifted)
+            .write_with_encoder(image::codecs::png::PngEncoder::new(&mut shifted_png))
+            .unwrap();
+        let images = vec![create_smart_fixture_png(width, 20, 300, 0), shifted_png];
+
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+        let analysis = analyze(images.clone(), options.clone()).unwrap();
+        assert_eq!(analysis.pairs[0].x_shift, 3);
+
+        let output = merge_with_metadata(images, options).unwrap();
+        assert_eq!((output.width, output.height), (width + 3, 540));
+        // The second capture is drawn 3 px right from output row 320, which
+        // is its row 120 (chrome plus overlap), restoring the original columns.
+        let img = decode_image(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(img.get_pixel(10, 400), bottom.get_pixel(10, 200));
+    }
+
+    #[test]
+    fn test_chrome_modes() {
+        let width = 220;
+        let images = || {
+            vec![
+                create_smart_fixture_png(width, 20, 300, 0),
+                create_smart_fixture_png(width, 20, 300, 200),
+            ]
+        };
+        let height = |chrome_mode| {
+            let options = MergeOptions {
+                direction: Direction::Smart,
+                chrome_mode,
+                ..Default::default()
+            };
+            decode_image(&merge(images(), options).unwrap())
+                .unwrap()
+                .height()
+        };
+
+        // 340 + 340 - 100 overlap, minus the chrome bars that are removed
+        assert_eq!(height(ChromeMode::KeepEnds), 540);
+        assert_eq!(height(ChromeMode::RemoveAll), 500);
+        assert_eq!(height(ChromeMode::KeepAll), 580);
+
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            chrome_mode: ChromeMode::RemoveAll,
+            ..Default::default()
+        };
+        let analysis = analyze(images(), options).unwrap();
+        assert_eq!(analysis.images[0].trim_top, 20);
+        assert_eq!(analysis.images[1].trim_bottom, 20);
+    }
+
+    #[test]
+    fn test_concurrent_merges_match_sequential() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<MergeOptions>();
+        assert_send_sync::<MergeOutput>();
+
+        let width = 220;
+        let images = || {
+            vec![
+                create_smart_fixture_png(width, 20, 300, 0),
+                create_smart_fixture_png(width, 20, 300, 200),
+            ]
+        };
+        let options = || MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+        let expected = merge(images(), options()).unwrap();
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| merge(images(), options()).unwrap()))
+                .collect();
+            for handle in handles {
+                assert_eq!(handle.join().unwrap(), expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_seam_report() {
+        let width = 220;
+        let images = || {
+            vec![
+                create_smart_fixture_png(width, 20, 300, 0),
+                create_smart_fixture_png(width, 20, 300, 200),
+                create_test_png(width, 50, Rgba([0, 0, 0, 255])),
+            ]
+        };
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images(), options).unwrap();
+        let [matched, failed] = output.seams.as_slice() else {
+            panic!("expected two seams, got {:?}", output.seams);
+        };
+        assert!(matched.confidence.is_some() && !matched.fell_back());
+        assert!(failed.confidence.is_none() && failed.fell_back());
+
+        let output = merge_with_metadata(images(), MergeOptions::default()).unwrap();
+        assert!(output.seams.is_empty());
+    }
+
+    #[test]
+    fn test_pixel_hash_ignores_encoding() {
+        let red = create_test_png(3, 2, Rgba([255, 0, 0, 255]));
+        let hash = pixel_hash(&red).unwrap();
+        // Pinned so a change to the digest layout is caught
+        assert_eq!(
+            hash,
+            "4d0c5be6b81ba40cc25baaae996e9bcae87c0eab0397f220fd5045d07ce76579"
+        );
+
+        // Same pixels, compressed differently and with a text chunk
+        let mut recoded = Vec::new();
+        let mut encoder = png::Encoder::new(&mut recoded, 3, 2);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_compression(png::Compression::Fastest);
+        encoder
+            .add_text_chunk("Software".to_string(), "test".to_string())
+            .unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[255, 0, 0, 255].repeat(6))
+            .unwrap();
+        writer.finish().unwrap();
+        assert_ne!(recoded, red);
+        assert_eq!(pixel_hash(&recoded).unwrap(), hash);
+
+        let other = create_test_png(3, 2, Rgba([255, 0, 0, 254]));
+        assert_ne!(pixel_hash(&other).unwrap(), hash);
+        let transposed = create_test_png(2, 3, Rgba([255, 0, 0, 255]));
+        assert_ne!(pixel_hash(&transposed).unwrap(), hash);
+        let png16 = create_test_png16(3, 2, Rgba([65535, 0, 0, 65535]));
+        assert_ne!(pixel_hash(&png16).unwrap(), hash);
+        assert!(matches!(
+            pixel_hash(b"junk"),
+            Err(MergeError::DecodeError { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_probe_reads_headers() {
+        let png16 = create_test_png16(12, 7, Rgba([0, 0, 0, 65535]));
+        assert_eq!(
+            probe(&png16).unwrap(),
+            ImageProbe {
+                format: ImageFormat::Png,
+                width: 12,
+                height: 7,
+                orientation: 1,
+                has_alpha: true,
+                bit_depth: 16,
+            }
+        );
+
+        // A JPEG whose EXIF says to rotate it 90° clockwise
+        let tiff: Vec<u8> = [
+            b"II*\0".as_slice(),
+            &8u32.to_le_bytes(),
+            &1u16.to_le_bytes(),
+            &[0x12, 0x01, 3, 0],
+            &1u32.to_le_bytes(),
+            &[6, 0, 0, 0],
+            &0u32.to_le_bytes(),
+        ]
+        .concat();
+        let mut jpeg = Vec::new();
+        image::RgbImage::from_pixel(16, 8, image::Rgb([0, 0, 255]))
+            .write_with_encoder(image::codecs::jpeg::JpegEncoder::new(&mut jpeg))
+            .unwrap();
+        let app1 = [b"Exif\0\0".as_slice(), &tiff].concat();
+        let segment = [
+            &[0xFF, 0xE1][..],
+            &((app1.len() + 2) as u16).to_be_bytes(),
+            &app1,
+        ]
+        .concat();
+        jpeg.splice(2..2, segment);
+        let info = probe(&jpeg).unwrap();
+        assert_eq!(info.format, ImageFormat::Jpeg);
+        assert_eq!((info.width, info.height, info.orientation), (16, 8, 6));
+        assert_eq!((info.has_alpha, info.bit_depth), (false, 8));
+
+        assert!(matches!(
+            probe(b"junk"),
+            Err(MergeError::DecodeError { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_copies_selected_exif_from_first_input() {
+        // Little-endian TIFF: IFD0 with `Make` = "Cam" and `Orientation` = 1
+        let tiff: Vec<u8> = [
+            b"II*\0".as_slice(),
+            &8u32.to_le_bytes(),
+            &2u16.to_le_bytes(),
+            &[0x0F, 0x01, 2, 0],
+            &4u32.to_le_bytes(),
+            b"Cam\0",
+            &[0x12, 0x01, 3, 0],
+            &1u32.to_le_bytes(),
+            &[1, 0, 0, 0],
+            &0u32.to_le_bytes(),
+        ]
+        .concat();
+        let mut jpeg = Vec::new();
+        image::RgbImage::from_pixel(8, 8, image::Rgb([0, 0, 255]))
+            .write_with_encoder(image::codecs::jpeg::JpegEncoder::new(&mut jpeg))
+            .unwrap();
+        let app1 = [b"Exif\0\0".as_slice(), &tiff].concat();
+        let segment = [
+            &[0xFF, 0xE1][..],
+            &((app1.len() + 2) as u16).to_be_bytes(),
+            &app1,
+        ]
+        .concat();
+        jpeg.splice(2..2, segment);
+        let images = || vec![jpeg.clone(), create_test_png(8, 8, Rgba([255, 0, 0, 255]))];
+        let output_exif = |options: MergeOptions| {
+            let output = merge(images(), options).unwrap();
+            let reader = png::Decoder::new(Cursor::new(output)).read_info().unwrap();
+            reader
+                .info()
+                .exif_metadata
+                .as_ref()
+                .map(|exif| exif.to_vec())
+        };
+
+        let camera = ExifCopy {
+            capture_time: false,
+            camera: true,
+            gps: false,
+        };
+        let exif = output_exif(MergeOptions {
+            copy_exif: Some(camera),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(exif.starts_with(b"II*\0"));
+        assert!(exif.windows(4).any(|w| w == b"Cam\0"));
+        // Only `Make` is copied
+        assert_eq!(&exif[8..10], &1u16.to_le_bytes());
+
+        // Nothing selected is present, nothing asked for, or EXIF given directly
+        let time = ExifCopy {
+            capture_time: true,
+            camera: false,
+            gps: false,
+        };
+        assert_eq!(
+            output_exif(MergeOptions {
+                copy_exif: Some(time),
+                ..Default::default()
+            }),
+            None
+        );
+        assert_eq!(output_exif(MergeOptions::default()), None);
+        let explicit = output_exif(MergeOptions {
+            copy_exif: Some(camera),
+            exif: Some(b"MM\0*\0\0\0\0".to_vec()),
+            ..Default::default()
+        });
+        assert_eq!(explicit.as_deref(), Some(b"MM\0*\0\0\0\0".as_slice()));
+    }
+
+    #[test]
+    fn test_merge_strip_metadata_writes_no_metadata_chunks() {
+        let chunk_types = |png: &[u8]| {
+            let mut types = Vec::new();
+            let mut pos = 8;
+            while pos + 8 <= png.len() {
+                let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+                types.push(String::from_utf8_lossy(&png[pos + 4..pos + 8]).into_owned());
+                pos += 12 + len;
+            }
+            types.dedup();
+            types
+        };
+        let images = vec![
+            create_test_png(8, 8, Rgba([255, 0, 0, 255])),
+            create_test_png(8, 8, Rgba([0, 0, 255, 255])),
+        ];
+        let options = MergeOptions {
+            copy_exif: Some(ExifCopy {
+                capture_time: true,
+                camera: true,
+                gps: true,
+            }),
+            exif: Some(b"MM\0*\0\0\0\0".to_vec()),
+            dpi: Some(144.0),
+            ..Default::default()
+        };
+
+        let output = merge(images.clone(), options.clone()).unwrap();
+        assert!(chunk_types(&output).contains(&"eXIf".to_string()));
+
+        let output = merge(
+            images,
+            MergeOptions {
+                strip_metadata: true,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(chunk_types(&output), ["IHDR", "pHYs", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_merge_records_provenance() {
+        let images = vec![
+            create_test_png(10, 20, Rgba([255, 0, 0, 255])),
+            create_test_png(10, 30, Rgba([0, 0, 255, 255])),
+        ];
+        let provenance_text = |options: MergeOptions| {
+            let output = merge(images.clone(), options).unwrap();
+            let reader = png::Decoder::new(Cursor::new(output)).read_info().unwrap();
+            reader
+                .info()
+                .uncompressed_latin1_text
+                .iter()
+                .find(|chunk| chunk.keyword == PROVENANCE_KEYWORD)
+                .map(|chunk| chunk.text.clone())
+        };
+
+        let text = provenance_text(MergeOptions {
+            provenance: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(text.contains(r#""direction":"vertical""#), "{text}");
+        assert!(
+            text.contains(r#"{"x":0,"y":20,"width":10,"height":30}"#),
+            "{text}"
+        );
+
+        assert_eq!(provenance_text(MergeOptions::default()), None);
+        assert_eq!(
+            provenance_text(MergeOptions {
+                provenance: true,
+                strip_metadata: true,
+                ..Default::default()
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_merge_linear_scaling_keeps_fine_detail_brightness() {
+        // One-pixel black-on-white stripes halved by the scale to the wider input
+        let stripes = RgbaImage::from_fn(40, 10, |x, _| {
+            let v = if x % 2 == 0 { 0 } else { 255 };
+            Rgba([v, v, v, 255])
+        });
+        let mut stripes_png = Vec::new();
+        stripes
+            .write_with_encoder(image::codecs::png::PngEncoder::new(&mut stripes_png))
+            .unwrap();
+        let images = || {
+            vec![
+                stripes_png.clone(),
+                create_test_png(20, 10, Rgba([255, 0, 0, 255])),
+            ]
+        };
+        let mean = |options: MergeOptions| {
+            let output = image::load_from_memory(&merge(images(), options).unwrap())
+                .unwrap()
+                .to_rgba8();
+            let row: Vec<u32> = (4..16).map(|x| output.get_pixel(x, 2)[0] as u32).collect();
+            row.iter().sum::<u32>() / row.len() as u32
+        };
+
+        let options = MergeOptions {
+            scale_mode: ScaleMode::Min,
+            ..Default::default()
+        };
+        assert!(mean(options.clone()).abs_diff(128) <= 2);
+        let linear = mean(MergeOptions {
+            linear_scaling: true,
+            ..options
+        });
+        assert!(linear.abs_diff(188) <= 2, "{linear}");
+    }
+
+    #[test]
+    fn test_merge_premultiplied_alpha_avoids_dark_fringes() {
+        // Opaque white columns between transparent black ones, halved
+        let stripes = RgbaImage::from_fn(40, 10, |x, _| {
+            if x % 2 == 0 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+        let mut stripes_png = Vec::new();
+        stripes
+            .write_with_encoder(image::codecs::png::PngEncoder::new(&mut stripes_png))
+            .unwrap();
+        let images = || {
+            vec![
+                stripes_png.clone(),
+                create_test_png(20, 10, Rgba([255, 0, 0, 255])),
+            ]
+        };
+        let pixel = |options: MergeOptions| {
+            let output = image::load_from_memory(&merge(images(), options).unwrap())
+                .unwrap()
+                .to_rgba8();
+            *output.get_pixel(10, 2)
+        };
+
+        let options = MergeOptions {
+            background: BackgroundColor::transparent(),
+            scale_mode: ScaleMode::Min,
+            ..Default::default()
+        };
+        let plain = pixel(options.clone());
+        assert!(plain[0].abs_diff(128) <= 2, "{plain:?}");
+        let premultiplied = pixel(MergeOptions {
+            premultiplied_alpha: true,
+            ..options
+        });
+        assert!(premultiplied[0] >= 253, "{premultiplied:?}");
+        assert_eq!(premultiplied[3], plain[3]);
+    }
+
+    fn create_test_png16(width: u32, height: u32, color: Rgba<u16>) -> Vec<u8> {
+        let img = ImageBuffer::from_pixel(width, height, color);
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba16(img)
+            .write_with_encoder(image::codecs::png::PngEncoder::new(&mut bytes))
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_merge_high_bit_depth_keeps_16_bit_samples() {
+        // Neither shade survives a round trip through 8 bits
+        let top = Rgba([1000, 30_000, 65_000, 65_535]);
+        let bottom = Rgba([12_345, 2, 54_321, 65_535]);
+        let images = || {
+            vec![
+                create_test_png16(8, 4, top),
+                create_test_png16(8, 6, bottom),
+            ]
+        };
+        let options = MergeOptions {
+            high_bit_depth: true,
+            padding: Padding::uniform(1),
+            ..Default::default()
+        };
+
+        let output = image::load_from_memory(&merge(images(), options.clone()).unwrap()).unwrap();
+        let output = match output {
+            DynamicImage::ImageRgba16(output) => output,
+            other => panic!("expected a 16-bit output, got {:?}", other.color()),
+        };
+        assert_eq!(output.dimensions(), (10, 12));
+        assert_eq!(output.get_pixel(0, 0), &Rgba([65_535; 4]));
+        assert_eq!(output.get_pixel(1, 1), &top);
+        assert_eq!(output.get_pixel(8, 10), &bottom);
+
+        // Rotated, and scaled to a common width, it stays 16-bit
+        let rotated = MergeOptions {
+            output_rotation: Rotation::Clockwise90,
+            ..options.clone()
+        };
+        let output = image::load_from_memory(&merge(images(), rotated).unwrap()).unwrap();
+        assert_eq!(output.color(), ColorType::Rgba16);
+        assert_eq!((output.width(), output.height()), (12, 10));
+        let scaled = vec![
+            create_test_png16(8, 4, top),
+            create_test_png16(16, 6, bottom),
+        ];
+        let output = image::load_from_memory(&merge(scaled, options.clone()).unwrap()).unwrap();
+        assert_eq!(output.color(), ColorType::Rgba16);
+        assert_eq!(output.to_rgba16().get_pixel(5, 5), &top);
+
+        // Off by default, and 8-bit with an 8-bit input or an 8-bit decoration
+        let color = |images: Vec<Vec<u8>>, options: MergeOptions| {
+            image::load_from_memory(&merge(images, options).unwrap())
+                .unwrap()
+                .color()
+        };
+        assert_eq!(color(images(), MergeOptions::default()), ColorType::Rgba8);
+        let mixed = vec![
+            create_test_png16(8, 4, top),
+            create_test_png(8, 6, Rgba([0, 0, 0, 255])),
+        ];
+        assert_eq!(color(mixed, options.clone()), ColorType::Rgba8);
+        let captioned = MergeOptions {
+            captions: vec!["Top".to_string()],
+            ..options
+        };
+        assert_eq!(color(images(), captioned), ColorType::Rgba8);
+    }
+
+    #[test]
+    fn test_source_over_deep() {
+        let backdrop = Rgba([0, 0, 65_535, 32_768]);
+        assert_eq!(
+            source_over_deep(Rgba([1, 2, 3, 65_535]), backdrop),
+            Rgba([1, 2, 3, 65_535])
+        );
+        assert_eq!(source_over_deep(Rgba([1, 2, 3, 0]), backdrop), backdrop);
+        // Same coverage arithmetic as the 8-bit blend
+        assert_eq!(
+            source_over_deep(Rgba([65_535, 0, 0, 32_768]), backdrop),
+            Rgba([43_690, 0, 21_845, 49_152])
+        );
+    }
+
+    #[test]
+    fn test_merge_flips_images_per_image_options() {
+        // Left half red, top half of the right half green
+        let img = RgbaImage::from_fn(4, 4, |x, y| match (x < 2, y < 2) {
+            (true, _) => Rgba([255, 0, 0, 255]),
+            (false, true) => Rgba([0, 255, 0, 255]),
+            (false, false) => Rgba([0, 0, 255, 255]),
+        });
+        let mut png = Vec::new();
+        img.write_with_encoder(image::codecs::png::PngEncoder::new(&mut png))
+            .unwrap();
+        let flipped = |flip_h: bool, flip_v: bool, direction: Direction| {
+            let options = MergeOptions {
+                direction,
+                image_options: vec![
+                    ImageOptions::default(),
+                    ImageOptions {
+                        flip_h,
+                        flip_v,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            };
+            let output = merge(vec![png.clone(), png.clone()], options).unwrap();
+            let output = image::load_from_memory(&output).unwrap().to_rgba8();
+            // Corners of the second image
+            [(0, 4), (3, 4), (0, 7), (3, 7)].map(|(x, y)| *output.get_pixel(x, y))
+        };
+        let red = Rgba([255, 0, 0, 255]);
+        let green = Rgba([0, 255, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+
+        assert_eq!(
+            flipped(false, false, Direction::Vertical),
+            [red, green, red, blue]
+        );
+        assert_eq!(
+            flipped(true, false, Direction::Vertical),
+            [green, red, blue, red]
+        );
+        assert_eq!(
+            flipped(false, true, Direction::Vertical),
+            [red, blue, red, green]
+        );
+        assert_eq!(
+            flipped(true, true, Direction::Tiles),
+            [blue, red, green, red]
+        );
+    }
+
+    #[test]
+    fn test_merge_trims_uniform_margins() {
+        // A 6x4 red screenshot on a white backdrop, and a letterboxed blue one
+        let framed = RgbaImage::from_fn(12, 10, |x, y| {
+            if (3..9).contains(&x) && (2..6).contains(&y) {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let letterboxed = RgbaImage::from_fn(6, 8, |_, y| {
+            if (2..6).contains(&y) {
+                Rgba([0, 0, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+        let encode = |img: &RgbaImage| {
+            let mut bytes = Vec::new();
+            img.write_with_encoder(image::codecs::png::PngEncoder::new(&mut bytes))
+                .unwrap();
+            bytes
+        };
+        let images = || vec![encode(&framed), encode(&letterboxed)];
+        let options = MergeOptions {
+            trim_margins: Some(TrimMargins::default()),
+            ..Default::default()
+        };
+
+        let output = image::load_from_memory(&merge(images(), options.clone()).unwrap())
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(output.dimensions(), (6, 8));
+        assert_eq!(output.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(output.get_pixel(5, 7), &Rgba([0, 0, 255, 255]));
+
+        let untrimmed =
+            image::load_from_memory(&merge(images(), MergeOptions::default()).unwrap()).unwrap();
+        assert_eq!((untrimmed.width(), untrimmed.height()), (12, 26));
+
+        let mut session = crate::session::Session::default();
+        for image in images() {
+            session.add_image(image).unwrap();
+        }
+        let output = session.merge(options).unwrap();
+        assert_eq!((output.width, output.height), (6, 8));
+    }
+
+    #[test]
+    fn test_decode_input_applies_webp_exif_orientation() {
+        use image::codecs::webp::WebPEncoder;
+
+        let (width, height) = (10u32, 20u32);
+        let img = RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 255]));
+        let mut plain = Vec::new();
+        WebPEncoder::new_lossless(&mut plain)
+            .encode(img.as_raw(), width, height, image::ExtendedColorType::Rgba8)
+            .unwrap();
+
+        // Extended (VP8X) container: EXIF flag, canvas size minus one
+        let mut vp8x = vec![0x08, 0, 0, 0];
+        vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        // Little-endian TIFF with a single orientation tag: rotate 90° CW
+        let exif: Vec<u8> = [
+            b"II*\0".as_slice(),
+            &8u32.to_le_bytes(),
+            &1u16.to_le_bytes(),
+            &[0x12, 0x01, 3, 0],
+            &1u32.to_le_bytes(),
+            &[6, 0, 0, 0],
+            &0u32.to_le_bytes(),
+        ]
+        .concat();
+        let mut body = b"WEBP".to_vec();
+        body.extend_from_slice(b"VP8X");
+        body.extend_from_slice(&(vp8x.len() as u32).to_le_bytes());
+        body.extend_from_slice(&vp8x);
+        body.extend_from_slice(&plain[12..]); // The VP8L chunk
+        body.extend_from_slice(b"EXIF");
+        body.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+        body.extend_from_slice(&exif);
+        let webp = [
+            b"RIFF".as_slice(),
+            &(body.len() as u32).to_le_bytes(),
+            &body,
+        ]
+        .concat();
+
+        let decoded = decode_input(&ImageInput::from(webp)).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (height, width));
+    }
+
+    #[test]
+    fn test_merge_strict_rejects_silent_fallbacks() {
+        let strict = MergeOptions {
+            direction: Direction::Smart,
+            strict: true,
+            ..Default::default()
+        };
+
+        // Too short to trim the chrome they share
+        let short = || {
+            vec![
+                create_test_png(100, 60, Rgba([90, 90, 90, 255])),
+                create_test_png(100, 60, Rgba([90, 90, 90, 255])),
+            ]
+        };
+        assert!(matches!(
+            merge(short(), strict.clone()),
+            Err(MergeError::StrictViolation { index: 0, .. })
+        ));
+        let lenient = MergeOptions {
+            strict: false,
+            ..strict.clone()
+        };
+        assert!(merge(short(), lenient).is_ok());
+
+        // Kept at native widths, too different to be matched
+        let mismatched = || {
+            vec![
+                create_test_png(100, 100, Rgba([255, 0, 0, 255])),
+                create_test_png(40, 100, Rgba([0, 0, 255, 255])),
+            ]
+        };
+        let native = MergeOptions {
+            scale_mode: ScaleMode::None,
+            strip_chrome: false,
+            ..strict
+        };
+        let err = merge(mismatched(), native.clone()).unwrap_err();
+        assert_eq!(err.code(), "STRICT_VIOLATION");
+        assert!(err.to_string().contains("100 and 40"), "{err}");
+
+        // A pinned overlap skips matching, so nothing falls back
+        let pinned = MergeOptions {
+            overlap_overrides: vec![Some(0)],
+            ..native
+        };
+        assert!(merge(mismatched(), pinned).is_ok());
+    }
+
+    #[test]
+    fn test_merge_warns_when_overlap_not_found() {
+        let width = 220;
+        let images = || {
+            vec![
+                create_smart_fixture_png(width, 20, 300, 0),
+                create_smart_fixture_png(width, 20, 300, 200),
+                create_test_png(width, 50, Rgba([0, 0, 0, 255])),
+            ]
+        };
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images(), options.clone()).unwrap();
+        assert_eq!(
+            output.warnings,
+            vec![MergeWarning::OverlapNotFound { pair: 1 }]
+        );
+
+        // An explicit zero override is a request, not a failure
+        let options = MergeOptions {
+            overlap_overrides: vec![None, Some(0)],
+            ..options
+        };
+        let output = merge_with_metadata(images(), options).unwrap();
+        assert!(output.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_collect_stats() {
+        let width = 220;
+        let images = || {
+            vec![
+                create_smart_fixture_png(width, 20, 300, 0),
+                create_smart_fixture_png(width, 20, 300, 200),
+                create_test_png(width, 50, Rgba([0, 0, 0, 255])),
+            ]
+        };
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            collect_stats: true,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images(), options).unwrap();
+        let stats = output.stats.as_ref().unwrap();
+
+        assert_eq!(stats.direction, Direction::Smart);
+        assert_eq!(stats.inputs.len(), 3);
+        assert_eq!(stats.inputs[0], InputStats { width, height: 340 });
+        assert_eq!(
+            (stats.output_width, stats.output_height),
+            (output.width, output.height)
+        );
+        assert_eq!(stats.output_bytes, output.bytes.len());
+        assert_eq!(stats.seams.len(), 2);
+        assert!(stats.seams[0].confidence.is_some());
+        assert_eq!(stats.failed_seams, 1);
+        let durations = stats.durations;
+        assert!(durations.decode_ms >= 0.0 && durations.encode_ms >= 0.0);
+        let sum = durations.decode_ms
+            + durations.layout_ms
+            + durations.composite_ms
+            + durations.encode_ms;
+        assert!((durations.total_ms - sum).abs() < 1e-6);
+        assert!(durations.scale_ms + durations.analysis_ms <= durations.layout_ms + 1e-6);
+
+        // The estimate is the one the memory budget is checked against
+        let peak = stats.peak_bytes_estimate;
+        let with_budget = |max_bytes| MergeOptions {
+            direction: Direction::Smart,
+            max_memory_bytes: Some(max_bytes),
+            ..Default::default()
+        };
+        assert!(merge_with_metadata(images(), with_budget(peak)).is_ok());
+        assert!(matches!(
+            merge_with_metadata(images(), with_budget(peak - 1)),
+            Err(MergeError::OutOfMemory { .. })
+        ));
+
+        let output = merge_with_metadata(images(), MergeOptions::default()).unwrap();
+        assert!(output.stats.is_none());
+    }
+
+    #[test]
+    fn test_strip_chrome_disabled_keeps_every_row() {
+        let width = 220;
+        let images = vec![
+            create_smart_fixture_png(width, 20, 300, 0),
+            create_smart_fixture_png(width, 20, 300, 200),
+        ];
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            strip_chrome: false,
+            ..Default::default()
+        };
+        let analysis = analyze(images, options).unwrap();
+        assert!(
+            analysis
+                .images
+                .iter()
+                .all(|image| image.trim_top == 0 && image.trim_bottom == 0)
+        );
+    }
+
+    #[test]
+    fn test_per_pair_sensitivity() {
+        let options = MergeOptions {
+            overlap_sensitivity: 50,
+            overlap_sensitivities: vec![None, Some(90)],
+            ..Default::default()
+        };
+        let sensitivities: Vec<u8> = overlap_params(&options, 3)
+            .iter()
+            .map(|p| p.sensitivity)
+            .collect();
+        assert_eq!(sensitivities, vec![50, 90, 50]);
+
+        // Repro bundles record the sensitivity the failed pair was matched with.
+        let width = 220;
+        let images = vec![
+            create_smart_fixture_png(width, 20, 300, 0),
+            create_smart_fixture_png(width, 20, 300, 200),
+            create_smart_fixture_png(width, 20, 300, 5000),
+        ];
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            overlap_sensitivities: vec![None, Some(10)],
+            repro_bundles: true,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images, options).unwrap();
+        assert_eq!(output.repro_bundles.len(), 1);
+        assert_eq!(output.repro_bundles[0].pair_index, 1);
+        assert_eq!(output.repro_bundles[0].sensitivity, 10);
+    }
+
+    #[test]
+    fn test_analyze_matches_merge_size() {
+        let images = || {
+            vec![
+                create_test_png(40, 30, Rgba([255, 0, 0, 255])),
+                create_test_png(20, 20, Rgba([0, 255, 0, 255])),
+            ]
+        };
+        let options = MergeOptions {
+            spacing: 6,
+            padding: Padding::uniform(3),
+            output_rotation: Rotation::Clockwise90,
+            ..Default::default()
+        };
+
+        let analysis = analyze(images(), options.clone()).unwrap();
+        let output = merge_with_metadata(images(), options).unwrap();
+        assert_eq!(
+            (analysis.width, analysis.height),
+            (output.width, output.height)
+        );
+        assert_eq!(analysis.images[1].source_size, (20, 20));
+        assert_eq!(analysis.images[1].scaled_size, (40, 40));
+        assert!(analysis.pairs.is_empty());
+    }
+
+    #[test]
+    fn test_analyze_reports_positions() {
+        let images = vec![
+            create_test_png(10, 10, Rgba([255, 0, 0, 255])),
+            create_test_png(10, 10, Rgba([0, 255, 0, 255])),
+        ];
+        let options = MergeOptions {
+            spacing: 5,
+            padding: Padding::uniform(2),
+            ..Default::default()
+        };
+
+        let analysis = analyze(images, options).unwrap();
+        assert_eq!(analysis.images[0].position, (2, 2));
+        assert_eq!(analysis.images[1].position, (2, 17));
+    }
+
+    #[test]
+    fn test_merge_reports_placements() {
+        let images = || {
+            vec![
+                create_test_png(10, 10, Rgba([255, 0, 0, 255])),
+                create_test_png(10, 20, Rgba([0, 255, 0, 255])),
+            ]
+        };
+        let options = MergeOptions {
+            spacing: 5,
+            ..Default::default()
+        };
+        let placement = |x, y, width, height| Placement {
+            x,
+            y,
+            width,
+            height,
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(images(), options.clone()).unwrap();
+        assert_eq!(
+            output.placements,
+            vec![placement(0, 0, 10, 10), placement(0, 15, 10, 20)]
+        );
+
+        let rotated = MergeOptions {
+            output_rotation: Rotation::Clockwise90,
+            ..options
+        };
+        let output = merge_with_metadata(images(), rotated).unwrap();
+        assert_eq!(
+            output.placements,
+            vec![placement(25, 0, 10, 10), placement(0, 0, 20, 10)]
+        );
+    }
+
+    #[test]
+    fn test_merge_smart_placements_report_crops() {
+        let width = 220;
+        let images = vec![
+            create_smart_fixture_png(width, 20, 300, 0),
+            create_smart_fixture_png(width, 20, 300, 200),
+        ];
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(images, options).unwrap();
+        let [first, second] = output.placements[..] else {
+            panic!("expected two placements");
+        };
+        assert_eq!((first.y, first.height), (0, 320));
+        assert_eq!((first.crop_top, first.crop_bottom), (0, 20));
+        assert_eq!((second.y, second.height), (320, 220));
+        assert_eq!((second.crop_top, second.crop_bottom), (120, 0));
+    }
+
+    #[test]
+    fn test_merge_split_non_smart_is_single_output() {
+        let img1 = create_test_png(50, 100, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(50, 100, Rgba([0, 255, 0, 255]));
+
+        let split = merge_split(vec![img1, img2], MergeOptions::default()).unwrap();
+        assert!(split.breaks.is_empty());
+        assert_eq!(split.outputs.len(), 1);
+        assert_eq!(split.outputs[0].height, 200);
+    }
+
+    #[test]
+    fn test_merge_vertical_with_border() {
+        let img1 = create_test_png(10, 10, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(10, 10, Rgba([0, 255, 0, 255]));
+        let options = MergeOptions {
+            spacing: 4,
+            border: Some(Border {
+                width: 2,
+                color: BackgroundColor::black(),
+            }),
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(vec![img1, img2], options).unwrap();
+        // Each image becomes 14x14 with its frame, plus the 4px gap
+        assert_eq!((output.width, output.height), (14, 32));
+
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        let black = Rgba([0, 0, 0, 255]);
+        assert_eq!(*img.get_pixel(0, 0), black);
+        assert_eq!(*img.get_pixel(13, 13), black);
+        assert_eq!(*img.get_pixel(7, 7), Rgba([255, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(7, 15), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(7, 18), black);
+        assert_eq!(*img.get_pixel(7, 25), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_merge_also_preview() {
+        let img1 = create_test_png(100, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(100, 50, Rgba([0, 255, 0, 255]));
+        let options = MergeOptions {
+            also_preview: true,
+            preview_max_dimension: 50,
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(vec![img1.clone(), img2.clone()], options).unwrap();
+        assert_eq!((output.width, output.height), (100, 100));
+        let preview = output.preview.unwrap();
+        assert_eq!((preview.width, preview.height), (50, 50));
+        let decoded = image::load_from_memory(&preview.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (50, 50));
+
+        let output = merge_with_metadata(vec![img1, img2], MergeOptions::default()).unwrap();
+        assert!(output.preview.is_none());
+        assert!(output.thumbnail.is_none());
+    }
+
+    #[test]
+    fn test_merge_blur_hash_and_input_hashes() {
+        let img1 = create_test_png(40, 20, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(40, 20, Rgba([0, 0, 255, 255]));
+        let options = MergeOptions {
+            blur_hash: true,
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(vec![img1.clone(), img2.clone()], options).unwrap();
+        assert!(output.blur_hash.is_some_and(|hash| !hash.is_empty()));
+
+        let hashes = input_blur_hashes(&[img1.into(), img2.into()]).unwrap();
+        assert_eq!(hashes.len(), 2);
+        assert_ne!(hashes[0], hashes[1]);
+
+        let err = input_blur_hashes(&[b"junk".to_vec().into()]).unwrap_err();
+        assert_eq!(err.code(), "DECODE_FAILED");
+    }
+
+    #[test]
+    fn test_preview_proof_simulates_jpeg() {
+        // Fine checkerboard: JPEG cannot keep its hard edges at low quality
+        let checker = RgbaImage::from_fn(64, 64, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(checker)
+            .write_with_encoder(image::codecs::png::PngEncoder::new(&mut bytes))
+            .unwrap();
+        let is_two_tone = |preview: &PreviewOutput| {
+            let img = image::load_from_memory(&preview.bytes).unwrap().to_rgba8();
+            img.pixels()
+                .all(|p| *p == Rgba([0, 0, 0, 255]) || *p == Rgba([255, 255, 255, 255]))
+        };
+
+        let options = MergeOptions {
+            also_preview: true,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(vec![bytes.clone()], options).unwrap();
+        assert!(is_two_tone(&output.preview.unwrap()));
+
+        let options = MergeOptions {
+            also_preview: true,
+            preview_proof_quality: Some(10),
+            ..Default::default()
+        };
+        let output = merge_with_metadata(vec![bytes], options).unwrap();
+        let preview = output.preview.unwrap();
+        assert_eq!((preview.width, preview.height), (64, 64));
+        assert!(!is_two_tone(&preview));
+    }
+
+    #[test]
+    fn test_merge_thumbnail_keeps_aspect_ratio() {
+        let img1 = create_test_png(100, 300, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(100, 300, Rgba([0, 255, 0, 255]));
+        let options = MergeOptions {
+            thumbnail_width: Some(25),
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(vec![img1, img2], options).unwrap();
+        let thumbnail = output.thumbnail.unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (25, 150));
+        let decoded = image::load_from_memory(&thumbnail.bytes)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(decoded.dimensions(), (25, 150));
+        assert_eq!(*decoded.get_pixel(12, 20), Rgba([255, 0, 0, 255]));
+        assert_eq!(*decoded.get_pixel(12, 130), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_round_corners_masks_only_corners() {
+        let mut img = RgbaImage::from_pixel(20, 10, Rgba([255, 0, 0, 255]));
+        round_corners(&mut img, 4);
+
+        for (x, y) in [(0, 0), (19, 0), (0, 9), (19, 9)] {
+            assert_eq!(img.get_pixel(x, y)[3], 0);
+        }
+        // Edges between the corners and the interior are untouched
+        assert_eq!(img.get_pixel(10, 0)[3], 255);
+        assert_eq!(img.get_pixel(0, 5)[3], 255);
+        assert_eq!(img.get_pixel(4, 4)[3], 255);
+        // Pixels on the arc are partially covered
+        let edge = img.get_pixel(1, 1)[3];
+        assert!(edge > 0 && edge < 255);
+    }
+
+    #[test]
+    fn test_merge_corner_radius_uses_background() {
+        let img1 = create_test_png(20, 20, Rgba([255, 0, 0, 255]));
+        let options = MergeOptions {
+            corner_radius: 6,
+            background: BackgroundColor::black(),
+            ..Default::default()
+        };
+
+        let output = merge(vec![img1], options).unwrap();
+        let img = image::load_from_memory(&output).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(10, 10), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_merge_shadow_beneath_images() {
+        let img1 = create_test_png(10, 10, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(10, 10, Rgba([0, 255, 0, 255]));
+        let options = MergeOptions {
+            spacing: 6,
+            padding: Padding::uniform(6),
+            shadow: Some(Shadow {
+                blur_radius: 0,
+                offset_x: 3,
+                offset_y: 3,
+                color: BackgroundColor::black(),
+            }),
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(vec![img1, img2], options).unwrap();
+        assert_eq!((output.width, output.height), (22, 38));
+
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        // Images stay on top; the offset shadow shows to the right of and below each
+        assert_eq!(*img.get_pixel(15, 15), Rgba([255, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(17, 17), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(17, 10), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(10, 17), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(7, 17), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(2, 2), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(10, 25), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_merge_shadow_blur_counts_against_memory_budget() {
+        let red = Rgba([255, 0, 0, 255]);
+        let images = vec![create_test_png(10, 10, red), create_test_png(10, 10, red)];
+        let with_budget = |max_bytes| MergeOptions {
+            shadow: Some(Shadow {
+                blur_radius: u32::MAX,
+                offset_x: 0,
+                offset_y: 0,
+                color: BackgroundColor::black(),
+            }),
+            max_memory_bytes: Some(max_bytes),
+            ..Default::default()
+        };
+        // Inputs, scaled copies and the 10x20 canvas, then the blur of one
+        // 10x10 caster: clamped to 20 px, it spreads 21 px on every side
+        let required_bytes = 3 * 800 + 100 + 52 * 52 * 8;
+        assert_eq!(
+            merge(images.clone(), with_budget(required_bytes - 1)),
+            Err(MergeError::OutOfMemory {
+                required_bytes,
+                max_bytes: required_bytes - 1,
+            })
+        );
+        assert!(merge(images, with_budget(required_bytes)).is_ok());
+    }
+
+    #[test]
+    fn test_merge_divider_between_images() {
+        let red = Rgba([255, 0, 0, 255]);
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        let divider = Some(Divider {
+            width: 2,
+            color: BackgroundColor::black(),
+        });
+
+        let options = MergeOptions {
+            spacing: 4,
+            divider,
+            ..Default::default()
+        };
+        let images = vec![create_test_png(10, 10, red), create_test_png(10, 10, red)];
+        let output = merge_with_metadata(images.clone(), options).unwrap();
+        assert_eq!((output.width, output.height), (10, 26));
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(5, 11), white);
+        assert_eq!(*img.get_pixel(0, 12), black);
+        assert_eq!(*img.get_pixel(9, 13), black);
+        assert_eq!(*img.get_pixel(5, 14), white);
+
+        let options = MergeOptions {
+            direction: Direction::Horizontal,
+            divider,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images, options).unwrap();
+        assert_eq!((output.width, output.height), (22, 10));
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(9, 5), red);
+        assert_eq!(*img.get_pixel(10, 0), black);
+        assert_eq!(*img.get_pixel(11, 9), black);
+        assert_eq!(*img.get_pixel(12, 5), red);
+    }
+
+    #[test]
+    fn test_merge_draw_seams_at_junctions() {
+        let red = Rgba([255, 0, 0, 255]);
+        let magenta = Rgba([255, 0, 255, 255]);
+        let images = || {
+            (0..4)
+                .map(|_| create_test_png(10, 10, red))
+                .collect::<Vec<_>>()
+        };
+        let seams = Some(SeamLines::default());
+
+        // Stacked images touch, so the line runs along the top of the next
+        let options = MergeOptions {
+            draw_seams: seams,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images()[..2].to_vec(), options).unwrap();
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(5, 9), red);
+        assert_eq!(*img.get_pixel(0, 10), magenta);
+        assert_eq!(*img.get_pixel(9, 10), magenta);
+        assert_eq!(*img.get_pixel(5, 11), red);
+
+        let options = MergeOptions {
+            direction: Direction::Horizontal,
+            spacing: 4,
+            draw_seams: Some(SeamLines {
+                width: 2,
+                color: BackgroundColor::black(),
+            }),
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images()[..2].to_vec(), options).unwrap();
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(10, 5), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(11, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(12, 9), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(14, 5), red);
+
+        // A 2x2 grid: a line between the columns of each row and one across
+        // the row break
+        let options = MergeOptions {
+            direction: Direction::Grid,
+            columns: Some(2),
+            spacing: 2,
+            draw_seams: seams,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images(), options).unwrap();
+        assert_eq!((output.width, output.height), (22, 22));
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(11, 2), magenta);
+        assert_eq!(*img.get_pixel(11, 18), magenta);
+        assert_eq!(*img.get_pixel(0, 11), magenta);
+        assert_eq!(*img.get_pixel(21, 11), magenta);
+        assert_eq!(*img.get_pixel(5, 5), red);
+        assert_eq!(*img.get_pixel(16, 16), red);
+    }
+
+    #[test]
+    fn test_merge_repro_bundles_for_failed_pairs() {
+        let width = 220;
+        let chrome_h = 20;
+        let content_h = 300;
+        let img1 = create_smart_fixture_png(width, chrome_h, content_h, 0);
+        let img2 = create_smart_fixture_png(width, chrome_h, content_h, content_h - 100);
+        let img3 = create_smart_fixture_png(width, chrome_h, content_h, 5000);
+
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            repro_bundles: true,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(vec![img1.clone(), img2, img3], options).unwrap();
+        assert_eq!(output.repro_bundles.len(), 1);
+        assert_eq!(output.repro_bundles[0].pair_index, 1);
+        assert!(!output.repro_bundles[0].top_strip_png.is_empty());
+
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(vec![img1.clone(), img1], options).unwrap();
+        assert!(output.repro_bundles.is_empty());
+    }
+
+    #[test]
+    fn test_merge_captions_add_label_strips() {
+        let red = Rgba([255, 0, 0, 255]);
+        let images = vec![create_test_png(120, 40, red), create_test_png(120, 40, red)];
+        let strip = crate::text::caption_strip_height(Some("First"), 16.0);
+
+        let options = MergeOptions {
+            captions: vec!["First".to_string(), String::new()],
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images.clone(), options).unwrap();
+        assert_eq!((output.width, output.height), (120, 80 + strip));
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        // Caption strip sits below the first image; the second image follows it
+        assert_eq!(*img.get_pixel(60, 39), red);
+        assert_eq!(*img.get_pixel(60, 40 + strip), red);
+        let inked = (40..40 + strip)
+            .flat_map(|y| (0..120).map(move |x| (x, y)))
+            .any(|(x, y)| img.get_pixel(x, y)[0] < 128);
+        assert!(inked);
+
+        let options = MergeOptions {
+            captions: vec!["First".to_string()],
+            caption_position: CaptionPosition::Above,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images, options).unwrap();
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(60, strip), red);
+    }
+
+    struct VetoOverlaps {
+        seen_dimensions: Vec<(u32, u32)>,
+    }
+
+    impl MergeHooks for VetoOverlaps {
+        fn after_decode(&mut self, dimensions: &[(u32, u32)]) -> Result<(), String> {
+            self.seen_dimensions = dimensions.to_vec();
+            Ok(())
+        }
+
+        fn after_matching(&mut self, overlaps: &mut [u32]) -> Result<(), String> {
+            overlaps.fill(0);
+            Ok(())
+        }
+    }
+
+    struct RejectDecode;
+
+    impl MergeHooks for RejectDecode {
+        fn after_decode(&mut self, _dimensions: &[(u32, u32)]) -> Result<(), String> {
+            Err("not today".to_string())
+        }
+    }
+
+    #[test]
+    fn test_merge_hooks_modify_and_reject() {
+        let width = 220;
+        let img1 = create_smart_fixture_png(width, 20, 300, 0);
+        let img2 = create_smart_fixture_png(width, 20, 300, 200);
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+
+        // Vetoing the overlap keeps only the chrome trim: 340 + 340 - 20 - 20
+        let mut hooks = VetoOverlaps {
+            seen_dimensions: vec![],
+        };
+        let output = merge_with_hooks(
+            vec![img1.clone(), img2.clone()],
+            options.clone(),
+            &mut hooks,
+        )
+        .unwrap();
+        assert_eq!(hooks.seen_dimensions, vec![(220, 340), (220, 340)]);
+        assert_eq!(output.height, 640);
+
+        let result = merge_with_hooks(vec![img1, img2], options, &mut RejectDecode);
+        assert_eq!(
+            result,
+            Err(MergeError::HookRejected {
+                stage: HookStage::AfterDecode,
+                message: "not today".to_string(),
+            })
+        );
+    }
+
+    struct RecordProgress(Vec<Progress>);
+
+    impl MergeHooks for RecordProgress {
+        fn progress(&mut self, progress: Progress) {
+            self.0.push(progress);
+        }
+    }
+
+    #[test]
+    fn test_merge_reports_progress() {
+        let width = 220;
+        let images = vec![
+            create_smart_fixture_png(width, 20, 300, 0),
+            create_smart_fixture_png(width, 20, 300, 200),
+        ];
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+        let mut hooks = RecordProgress(vec![]);
+        merge_with_hooks(images, options, &mut hooks).unwrap();
+        assert_eq!(
+            hooks.0,
+            vec![
+                Progress::Decoded { index: 0, total: 2 },
+                Progress::Decoded { index: 1, total: 2 },
+                Progress::Scaled { index: 0, total: 2 },
+                Progress::Scaled { index: 1, total: 2 },
+                Progress::Overlap { index: 0, total: 1 },
+                Progress::Compositing,
+                Progress::Encoding,
+            ]
+        );
+    }
+
+    /// Cancels once `after` progress steps have been reported.
+    struct CancelAfter {
+        after: usize,
+        seen: usize,
+    }
+
+    impl MergeHooks for CancelAfter {
+        fn progress(&mut self, _progress: Progress) {
+            self.seen += 1;
+        }
+
+        fn is_cancelled(&mut self) -> bool {
+            self.seen >= self.after
+        }
+    }
+
+    #[test]
+    fn test_merge_cancellation() {
+        let red = Rgba([255, 0, 0, 255]);
+        let images = vec![create_test_png(10, 10, red), create_test_png(10, 10, red)];
+        // Decode, scale, compositing and encoding steps: 2 + 2 + 1 + 1
+        for after in 1..=6 {
+            let mut hooks = CancelAfter { after, seen: 0 };
+            let result = merge_with_hooks(images.clone(), MergeOptions::default(), &mut hooks);
+            assert_eq!(result, Err(MergeError::Cancelled), "after {after} steps");
+            assert_eq!(hooks.seen, after);
+        }
+        let mut hooks = CancelAfter { after: 7, seen: 0 };
+        assert!(merge_with_hooks(images, MergeOptions::default(), &mut hooks).is_ok());
+    }
+
+    /// Cancels at the first poll from inside a loop, rather than right
+    /// after a progress step.
+    #[derive(Default)]
+    struct CancelMidStep {
+        progress: Vec<Progress>,
+        reported: bool,
+    }
+
+    impl MergeHooks for CancelMidStep {
+        fn progress(&mut self, progress: Progress) {
+            self.progress.push(progress);
+            self.reported = true;
+        }
+
+        fn is_cancelled(&mut self) -> bool {
+            !std::mem::take(&mut self.reported)
+        }
+    }
+
+    #[test]
+    fn test_merge_cancellation_stops_partway_through_a_step() {
+        // Matching a long pair: the inputs need no scaling
+        let images = vec![
+            create_smart_fixture_png(900, 20, 1600, 0),
+            create_smart_fixture_png(900, 20, 1600, 1200),
+        ];
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            ..Default::default()
+        };
+        let mut hooks = CancelMidStep::default();
+        let result = merge_with_hooks(images, options, &mut hooks);
+        assert_eq!(result, Err(MergeError::Cancelled));
+        assert_eq!(
+            hooks.progress.last(),
+            Some(&Progress::Scaled { index: 1, total: 2 })
+        );
+
+        // Scaling a large input
+        let red = Rgba([255, 0, 0, 255]);
+        let images = vec![
+            create_test_png(1000, 1500, red),
+            create_test_png(2000, 100, red),
+        ];
+        let mut hooks = CancelMidStep::default();
+        let result = merge_with_hooks(images, MergeOptions::default(), &mut hooks);
+        assert_eq!(result, Err(MergeError::Cancelled));
+        assert_eq!(
+            hooks.progress.last(),
+            Some(&Progress::Decoded { index: 1, total: 2 })
+        );
+    }
+
+    #[test]
+    fn test_merge_tiles_places_exact_offsets() {
+        let red = Rgba([255, 0, 0, 255]);
+        let green = Rgba([0, 255, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let images = vec![
+            create_test_png(30, 20, red),
+            create_test_png(30, 20, green),
+            create_test_png(20, 20, blue),
+        ];
+        let options = MergeOptions {
+            direction: Direction::Tiles,
+            image_options: vec![
+                ImageOptions::default(),
+                ImageOptions {
+                    tile_height: Some(10),
+                    ..Default::default()
+                },
+                ImageOptions {
+                    tile_offset: Some(35),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(images, options).unwrap();
+        assert_eq!((output.width, output.height), (30, 55));
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(0, 19), red);
+        assert_eq!(*img.get_pixel(0, 20), green);
+        assert_eq!(*img.get_pixel(0, 29), green);
+        // Gap before the explicitly offset tile shows the background
+        assert_eq!(*img.get_pixel(0, 32), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(0, 35), blue);
+        // Narrower tile is not scaled
+        assert_eq!(*img.get_pixel(25, 40), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_page_numbers_stamped_in_each_image() {
+        let white = Rgba([255, 255, 255, 255]);
+        let images = vec![
+            create_test_png(120, 60, white),
+            create_test_png(120, 60, white),
+        ];
+        let options = MergeOptions {
+            page_numbers: Some(PageNumbers {
+                corner: Corner::TopLeft,
+                size: 16.0,
+                color: BackgroundColor::black(),
+            }),
+            ..Default::default()
+        };
+
+        let output_bytes = merge(images, options).unwrap();
+        let img = decode_image(&output_bytes).unwrap().to_rgba8();
+        let inked_rows: Vec<u32> = (0..img.height())
+            .filter(|y| (0..img.width()).any(|x| *img.get_pixel(x, *y) != white))
+            .collect();
+        assert!(!inked_rows.is_empty());
+        // One label near the top of each image, nothing elsewhere
+        assert!(inked_rows.iter().any(|y| *y < 30));
+        assert!(inked_rows.iter().any(|y| (60..90).contains(y)));
+        assert!(inked_rows.iter().all(|y| *y < 30 || (60..90).contains(y)));
+        for y in inked_rows {
+            assert!((0..img.width()).all(|x| x < 60 || *img.get_pixel(x, y) == white));
+        }
+    }
+
+    #[test]
+    fn test_page_numbers_size_capped_at_image_height() {
+        // At full size the glyphs alone would need about 10^18 bytes
+        let white = Rgba([255, 255, 255, 255]);
+        let images = vec![
+            create_test_png(10, 10, white),
+            create_test_png(10, 10, white),
+        ];
+        let options = MergeOptions {
+            page_numbers: Some(PageNumbers {
+                corner: Corner::BottomRight,
+                size: 1e9,
+                color: BackgroundColor::black(),
+            }),
+            ..Default::default()
+        };
+        assert!(merge(images, options).is_ok());
+    }
+
+    #[test]
+    fn test_output_rotation() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let images = vec![create_test_png(20, 10, red), create_test_png(20, 10, blue)];
+        let options = MergeOptions {
+            direction: Direction::Horizontal,
+            output_rotation: Rotation::Clockwise90,
+            also_preview: true,
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(images, options).unwrap();
+        assert_eq!((output.width, output.height), (10, 40));
+        assert_eq!(output.preview.map(|p| (p.width, p.height)), Some((10, 40)));
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        // The left image ends up on top after a clockwise quarter turn
+        assert_eq!(*img.get_pixel(5, 5), red);
+        assert_eq!(*img.get_pixel(5, 35), blue);
+    }
+
+    #[test]
+    fn test_merge_raw_matches_encoded_output() {
+        let images = || {
+            vec![
+                create_test_png(20, 10, Rgba([255, 0, 0, 255])),
+                create_test_png(20, 10, Rgba([0, 0, 255, 128])),
+            ]
+        };
+        let options = MergeOptions {
+            direction: Direction::Horizontal,
+            output_rotation: Rotation::Clockwise90,
+            ..Default::default()
+        };
+
+        let raw = merge_raw(images(), options.clone()).unwrap();
+        let encoded = merge_with_metadata(images(), options).unwrap();
+        assert_eq!((raw.width, raw.height), (encoded.width, encoded.height));
+        let decoded = image::load_from_memory(&encoded.bytes).unwrap().to_rgba8();
+        assert_eq!(raw.pixels, decoded.into_raw());
+    }
+
+    #[test]
+    fn test_guides_drawn_or_returned_as_layer() {
+        let white = Rgba([255, 255, 255, 255]);
+        let guides = Guides {
+            thirds: true,
+            center: false,
+            safe_margin: 0.0,
+            line_width: 1,
+            color: BackgroundColor::new(0, 0, 0, 255),
+            separate_layer: false,
+        };
+        let options = MergeOptions {
+            guides: Some(guides),
+            ..Default::default()
+        };
+        let output = merge_with_metadata(vec![create_test_png(30, 30, white)], options).unwrap();
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(10, 5), Rgba([0, 0, 0, 255]));
+        assert!(output.guide_layer.is_none());
+
+        let options = MergeOptions {
+            guides: Some(Guides {
+                separate_layer: true,
+                ..guides
+            }),
+            ..Default::default()
+        };
+        let output = merge_with_metadata(vec![create_test_png(30, 30, white)], options).unwrap();
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(10, 5), white);
+        let layer = output.guide_layer.unwrap();
+        assert_eq!((layer.width, layer.height), (30, 30));
+        let layer = image::load_from_memory(&layer.bytes).unwrap().to_rgba8();
+        assert_eq!(*layer.get_pixel(10, 5), Rgba([0, 0, 0, 255]));
+        assert_eq!(layer.get_pixel(5, 5)[3], 0);
+    }
+
+    #[test]
+    fn test_background_image_shows_through_padding_and_alpha() {
+        let red = Rgba([255, 0, 0, 255]);
+        let clear = Rgba([0, 0, 0, 0]);
+        let options = MergeOptions {
+            padding: Padding::uniform(4),
+            background_image: Some(BackgroundImage {
+                bytes: create_test_png(1, 1, Rgba([0, 0, 255, 255])),
+                fit: BackgroundFit::Tile,
+            }),
+            ..Default::default()
+        };
+
+        let images = vec![create_test_png(10, 10, red), create_test_png(10, 10, clear)];
+        let output_bytes = merge(images, options).unwrap();
+        let img = decode_image(&output_bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(*img.get_pixel(8, 8), red);
+        assert_eq!(*img.get_pixel(8, 18), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_background_gradient_fills_padding_and_gaps() {
+        let options = MergeOptions {
+            spacing: 10,
+            background_gradient: Some(Gradient {
+                start: BackgroundColor::black(),
+                end: BackgroundColor::white(),
+                angle: 180.0,
+            }),
+            ..Default::default()
+        };
+        let images = vec![
+            create_test_png(10, 10, Rgba([255, 0, 0, 255])),
+            create_test_png(10, 10, Rgba([255, 0, 0, 255])),
+        ];
+        let output_bytes = merge(images, options).unwrap();
+        let img = decode_image(&output_bytes).unwrap().to_rgba8();
+        assert_eq!(*img.get_pixel(5, 5), Rgba([255, 0, 0, 255]));
+        let gap = img.get_pixel(5, 15);
+        assert!(gap[0] > 50 && gap[0] < 200 && gap[0] == gap[2]);
+    }
+
+    #[test]
+    fn test_background_image_decode_error() {
+        let options = MergeOptions {
+            background_image: Some(BackgroundImage {
+                bytes: b"not an image".to_vec(),
+                fit: BackgroundFit::Stretch,
+            }),
+            ..Default::default()
+        };
+        let images = vec![create_test_png(10, 10, Rgba([255, 0, 0, 255]))];
+        let err = merge(images, options).unwrap_err();
+        assert_eq!(err.code(), "BACKGROUND_DECODE_FAILED");
+    }
+
+    fn two_tone_png(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbaImage::from_fn(width, height, |x, _| {
+            if x < width / 2 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let mut bytes = Vec::new();
+        let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+        DynamicImage::ImageRgba8(img)
+            .write_with_encoder(encoder)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_low_color_scaling_nearest_and_native() {
+        let low_color = || ImageOptions {
+            low_color: true,
+            ..Default::default()
+        };
+        let images = || {
+            vec![
+                create_test_png(40, 10, Rgba([255, 0, 0, 255])),
+                two_tone_png(4, 2),
+            ]
+        };
+
+        let options = MergeOptions {
+            low_color_scaling: LowColorScaling::Nearest,
+            image_options: vec![ImageOptions::default(), low_color()],
+            ..Default::default()
+        };
+        let output_bytes = merge(images(), options).unwrap();
+        let img = decode_image(&output_bytes).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (40, 30));
+        for y in 10..30 {
+            for x in 0..40 {
+                let p = *img.get_pixel(x, y);
+                assert!(p == Rgba([0, 0, 0, 255]) || p == Rgba([255, 255, 255, 255]));
+            }
+        }
+
+        let options = MergeOptions {
+            low_color_scaling: LowColorScaling::Native,
+            image_options: vec![ImageOptions::default(), low_color()],
+            ..Default::default()
+        };
+        let output_bytes = merge(images(), options).unwrap();
+        let img = decode_image(&output_bytes).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (40, 12));
+    }
+
+    #[test]
+    fn test_mark_low_color_inputs_only_when_configured() {
+        let mut one_bit = create_test_png(4, 4, Rgba([0, 0, 0, 255]));
+        one_bit[24] = 1;
+        let plain = create_test_png(4, 4, Rgba([0, 0, 0, 255]));
+        let data = [Some(plain.as_slice()), Some(one_bit.as_slice())];
+
+        let options = mark_low_color_inputs(MergeOptions::default(), &data);
+        assert!(!options.is_low_color(1));
+
+        let options = MergeOptions {
+            low_color_scaling: LowColorScaling::Native,
+            ..Default::default()
+        };
+        let options = mark_low_color_inputs(options, &data);
+        assert!(!options.is_low_color(0));
+        assert!(options.is_low_color(1));
+    }
+
+    #[test]
+    fn test_target_dpi_normalizes_physical_size() {
+        // A 150 dpi page and a 300 dpi page of the same physical width
+        let low = create_test_png(50, 20, Rgba([255, 0, 0, 255]));
+        let high = create_test_png(100, 40, Rgba([0, 0, 255, 255]));
+        let options = MergeOptions {
+            scale_mode: ScaleMode::None,
+            target_dpi: Some(300.0),
+            image_options: vec![
+                ImageOptions {
+                    dpi: Some(150.0),
+                    ..Default::default()
+                },
+                ImageOptions {
+                    dpi: Some(300.0),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(vec![low.clone(), high.clone()], options).unwrap();
+        assert_eq!((output.width, output.height), (100, 80));
+
+        // Without a target resolution the native sizes are kept
+        let options = MergeOptions {
+            scale_mode: ScaleMode::None,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(vec![low, high], options).unwrap();
+        assert_eq!((output.width, output.height), (100, 60));
+    }
+
+    #[test]
+    fn test_merge_records_output_dpi() {
+        let images = || vec![create_test_png(10, 10, Rgba([255, 0, 0, 255]))];
+        let options = MergeOptions {
+            dpi: Some(300.0),
+            ..Default::default()
+        };
+        let dpi = read_dpi(&merge(images(), options).unwrap()).unwrap();
+        assert!((dpi - 300.0).abs() < 0.1, "{dpi}");
+
+        assert_eq!(
+            read_dpi(&merge(images(), MergeOptions::default()).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_merge_horizontal() {
+        let img1 = create_test_png(50, 100, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(50, 100, Rgba([0, 255, 0, 255]));
+
+        let options = MergeOptions {
+            direction: Direction::Horizontal,
+            ..Default::default()
+        };
+
+        let result = merge(vec![img1, img2], options);
+        assert!(result.is_ok());
+
+        let output_bytes = result.unwrap();
+        let output_img = decode_image(&output_bytes).unwrap();
+        assert_eq!(output_img.width(), 100); // 50 + 50
+        assert_eq!(output_img.height(), 100);
+    }
+
+    #[test]
+    fn test_merge_spacing_fills_gap_with_background() {
+        let img1 = create_test_png(20, 10, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(20, 10, Rgba([0, 255, 0, 255]));
+
+        let options = MergeOptions {
+            direction: Direction::Vertical,
+            background: BackgroundColor::black(),
+            spacing: 6,
+            ..Default::default()
+        };
+
+        let output_bytes = merge(vec![img1, img2], options).unwrap();
+        let output_img = decode_image(&output_bytes).unwrap().to_rgba8();
+        assert_eq!(output_img.dimensions(), (20, 26));
+        assert_eq!(*output_img.get_pixel(5, 9), Rgba([255, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(5, 12), Rgba([0, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(5, 16), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_merge_padding_surrounds_content() {
+        let img = create_test_png(10, 10, Rgba([255, 0, 0, 255]));
+
+        let options = MergeOptions {
+            background: BackgroundColor::black(),
+            padding: Padding {
+                top: 2,
+                right: 3,
+                bottom: 4,
+                left: 5,
+            },
+            ..Default::default()
+        };
+
+        let output_bytes = merge(vec![img], options).unwrap();
+        let output_img = decode_image(&output_bytes).unwrap().to_rgba8();
+        assert_eq!(output_img.dimensions(), (18, 16));
+        assert_eq!(*output_img.get_pixel(4, 2), Rgba([0, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(5, 2), Rgba([255, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(14, 11), Rgba([255, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(15, 12), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_merge_pinned_image_keeps_native_size() {
+        let wide = create_test_png(40, 10, Rgba([255, 0, 0, 255]));
+        let narrow = create_test_png(20, 10, Rgba([0, 255, 0, 255]));
+
+        let options = MergeOptions {
+            background: BackgroundColor::black(),
+            image_options: vec![
+                ImageOptions::default(),
+                ImageOptions {
+                    pin: true,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let output_bytes = merge(vec![wide, narrow], options).unwrap();
+        let output_img = decode_image(&output_bytes).unwrap().to_rgba8();
+        // Narrow image is not upscaled to 40x20; it is centered and padded.
+        assert_eq!(output_img.dimensions(), (40, 20));
+        assert_eq!(*output_img.get_pixel(9, 15), Rgba([0, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(10, 15), Rgba([0, 255, 0, 255]));
+        assert_eq!(*output_img.get_pixel(29, 15), Rgba([0, 255, 0, 255]));
+        assert_eq!(*output_img.get_pixel(30, 15), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_merge_pinned_image_respects_alignment() {
+        let wide = create_test_png(40, 10, Rgba([255, 0, 0, 255]));
+        let narrow = create_test_png(20, 10, Rgba([0, 255, 0, 255]));
+
+        let options = MergeOptions {
+            background: BackgroundColor::black(),
+            align: Align::Start,
+            image_options: vec![
+                ImageOptions::default(),
+                ImageOptions {
+                    pin: true,
+                    ..Default::default()
+                },
+                ImageOptions {
+                    pin: true,
+                    align: Some(Align::End),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let output_bytes = merge(vec![wide, narrow.clone(), narrow], options).unwrap();
+        let output_img = decode_image(&output_bytes).unwrap().to_rgba8();
+        assert_eq!(output_img.dimensions(), (40, 30));
+        assert_eq!(*output_img.get_pixel(0, 15), Rgba([0, 255, 0, 255]));
+        assert_eq!(*output_img.get_pixel(20, 15), Rgba([0, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(19, 25), Rgba([0, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(39, 25), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_merge_horizontal_vertical_align() {
+        let tall = create_test_png(10, 40, Rgba([255, 0, 0, 255]));
+        let short = create_test_png(10, 20, Rgba([0, 255, 0, 255]));
+        let pinned = vec![
+            ImageOptions::default(),
+            ImageOptions {
+                pin: true,
+                ..Default::default()
+            },
+        ];
+
+        for (vertical_align, green_y, black_y) in [
+            (VerticalAlign::Top, 0, 20),
+            (VerticalAlign::Middle, 10, 5),
+            (VerticalAlign::Bottom, 39, 0),
+        ] {
+            let options = MergeOptions {
+                direction: Direction::Horizontal,
+                background: BackgroundColor::black(),
+                vertical_align,
+                image_options: pinned.clone(),
+                ..Default::default()
+            };
+            let output_bytes = merge(vec![tall.clone(), short.clone()], options).unwrap();
+            let output_img = decode_image(&output_bytes).unwrap().to_rgba8();
+            assert_eq!(output_img.dimensions(), (20, 40));
+            assert_eq!(*output_img.get_pixel(15, green_y), Rgba([0, 255, 0, 255]));
+            assert_eq!(*output_img.get_pixel(15, black_y), Rgba([0, 0, 0, 255]));
+        }
+    }
+
+    #[test]
+    fn test_merge_grid_target_aspect_ratio() {
+        let red = create_test_png(20, 10, Rgba([255, 0, 0, 255]));
+        let green = create_test_png(20, 20, Rgba([0, 255, 0, 255]));
+        let blue = create_test_png(10, 5, Rgba([0, 0, 255, 255]));
+        let images = || vec![red.clone(), green.clone(), blue.clone()];
+        let options = MergeOptions {
+            direction: Direction::Grid,
+            background: BackgroundColor::black(),
+            ..Default::default()
+        };
+
+        // Closest to square: two columns of 20x20 cells, the last one empty
+        let output = merge_with_metadata(images(), options.clone()).unwrap();
+        assert_eq!((output.width, output.height), (40, 40));
+        let origins: Vec<_> = output.placements.iter().map(|p| (p.x, p.y)).collect();
+        assert_eq!(origins, vec![(0, 5), (20, 0), (0, 25)]);
+        let output_img = decode_image(&output.bytes).unwrap().to_rgba8();
+        assert_eq!(*output_img.get_pixel(5, 2), Rgba([0, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(5, 7), Rgba([255, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(25, 2), Rgba([0, 255, 0, 255]));
+        assert_eq!(*output_img.get_pixel(5, 30), Rgba([0, 0, 255, 255]));
+        assert_eq!(*output_img.get_pixel(30, 30), Rgba([0, 0, 0, 255]));
+
+        let wide = MergeOptions {
+            target_aspect_ratio: Some(3.0),
+            ..options.clone()
+        };
+        let output = merge_with_metadata(images(), wide).unwrap();
+        assert_eq!((output.width, output.height), (60, 20));
+
+        // Rotated a quarter turn, a tall grid gives the wide output asked for
+        let rotated = MergeOptions {
+            target_aspect_ratio: Some(3.0),
+            output_rotation: Rotation::Clockwise90,
+            spacing: 4,
+            ..options
+        };
+        let output = merge_with_metadata(images(), rotated).unwrap();
+        assert_eq!((output.width, output.height), (68, 20));
+    }
+
+    #[test]
+    fn test_merge_grid_fixed_columns_balances_rows() {
+        let short = create_test_png(20, 10, Rgba([255, 0, 0, 255]));
+        let tall = create_test_png(20, 20, Rgba([0, 255, 0, 255]));
+        let images = || vec![short.clone(), tall.clone(), short.clone(), short.clone()];
+        let options = MergeOptions {
+            direction: Direction::Grid,
+            columns: Some(2),
+            target_aspect_ratio: Some(10.0),
+            spacing: 2,
+            ..Default::default()
+        };
+
+        // The first row is as tall as the tall image, the second only 10
+        let output = merge_with_metadata(images(), options.clone()).unwrap();
+        assert_eq!((output.width, output.height), (42, 32));
+        let origins: Vec<_> = output.placements.iter().map(|p| (p.x, p.y)).collect();
+        assert_eq!(origins, vec![(0, 5), (22, 0), (0, 22), (22, 22)]);
+
+        let top = MergeOptions {
+            vertical_align: VerticalAlign::Top,
+            ..options
+        };
+        let output = merge_with_metadata(images(), top).unwrap();
+        assert_eq!((output.placements[0].x, output.placements[0].y), (0, 0));
+    }
+
+    #[test]
+    fn test_paginate_reassembles_to_the_source() {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let images: Vec<_> = colors
+            .iter()
+            .map(|&c| create_test_png(30, 90, Rgba(c)))
+            .collect();
+        let merged = merge(images, MergeOptions::default()).unwrap();
+        let source = decode_image(&merged).unwrap().to_rgba8();
+
+        let pages = paginate(merged, 100, None).unwrap();
+        assert_eq!(pages.len(), 3);
+        let mut next_y = 0;
+        for page in &pages {
+            assert_eq!(page.y, next_y);
+            assert!(page.height <= 100);
+            assert_eq!(page.width, 30);
+            let img = decode_image(&page.bytes).unwrap().to_rgba8();
+            assert_eq!(img.dimensions(), (page.width, page.height));
+            for (x, y, pixel) in img.enumerate_pixels() {
+                assert_eq!(pixel, source.get_pixel(x, page.y + y));
+            }
+            next_y += page.height;
+        }
+        assert_eq!(next_y, 270);
+
+        assert!(matches!(
+            paginate(b"junk".to_vec(), 100, None),
+            Err(MergeError::DecodeError { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_tiled_cuts_between_images() {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let images = || -> Vec<_> {
+            colors
+                .iter()
+                .map(|&c| create_test_png(30, 90, Rgba(c)))
+                .collect()
+        };
+        let options = MergeOptions {
+            spacing: 10,
+            ..Default::default()
+        };
+        let merged = merge(images(), options.clone()).unwrap();
+        let source = decode_image(&merged).unwrap().to_rgba8();
+
+        // Each cut lands in the gap under an image, not at the 120-row limit
+        let tiled = merge_tiled(images(), options.clone(), 120).unwrap();
+        assert_eq!((tiled.width, tiled.height), (30, 290));
+        let offsets: Vec<u32> = tiled.tiles.iter().map(|t| t.y).collect();
+        assert_eq!(offsets, vec![0, 100, 200]);
+        for tile in &tiled.tiles {
+            let img = decode_image(&tile.bytes).unwrap().to_rgba8();
+            assert_eq!(img.dimensions(), (tile.width, tile.height));
+            for (x, y, pixel) in img.enumerate_pixels() {
+                assert_eq!(pixel, source.get_pixel(x, tile.y + y));
+            }
+        }
+
+        // A pixel limit that fails a plain merge shortens the tiles instead
+        let limited = MergeOptions {
+            max_output_pixels: Some(30 * 50),
+            ..options
+        };
+        assert!(matches!(
+            merge(images(), limited.clone()),
+            Err(MergeError::OutputTooLarge { .. })
+        ));
+        let tiled = merge_tiled(images(), limited, 120).unwrap();
+        assert!(tiled.tiles.iter().all(|t| t.height <= 50));
+        assert_eq!(tiled.tiles.iter().map(|t| t.height).sum::<u32>(), 290);
+    }
+
+    #[test]
+    fn test_merge_to_pdf_layouts() {
+        let images = || {
+            vec![
+                create_test_png(40, 30, Rgba([255, 0, 0, 255])),
+                create_test_png(40, 50, Rgba([0, 0, 255, 255])),
+            ]
+        };
+        let options = MergeOptions {
+            spacing: 10,
+            dpi: Some(144.0),
+            ..Default::default()
+        };
+        let text = |pdf: &PdfOutput| String::from_utf8_lossy(&pdf.bytes).into_owned();
+
+        let long = merge_to_pdf(images(), options.clone(), PdfLayout::LongPage).unwrap();
+        assert_eq!(long.page_count, 1);
+        assert!(text(&long).contains("/MediaBox [0 0 20 45]"));
+
+        // Spacing is left out; each page holds just its image
+        let per_image = merge_to_pdf(images(), options, PdfLayout::PerImage).unwrap();
+        assert_eq!(per_image.page_count, 2);
+        assert!(text(&per_image).contains("/MediaBox [0 0 20 15]"));
+        assert!(text(&per_image).contains("/MediaBox [0 0 20 25]"));
+        assert!(text(&per_image).starts_with("%PDF-"));
+    }
+
+    #[test]
+    fn test_merge_contact_sheet() {
+        let named = |name: &str| ImageInput {
+            name: Some(name.to_string()),
+            ..create_test_png(40, 20, Rgba([255, 0, 0, 255])).into()
+        };
+        let images = || vec![named("a.png"), named("b.png"), named("c.png")];
+        let sheet = ContactSheet {
+            columns: Some(3),
+            header: None,
+        };
+        let options = MergeOptions {
+            contact_sheet: Some(sheet.clone()),
+            ..Default::default()
+        };
+
+        // Without a header, the preset is a captioned grid with gaps
+        let expected = MergeOptions {
+            direction: Direction::Grid,
+            columns: Some(3),
+            captions: vec!["a.png".into(), "b.png".into(), "c.png".into()],
+            spacing: 16,
+            padding: Padding::uniform(16),
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images(), options).unwrap();
+        assert_eq!(output, merge_with_metadata(images(), expected).unwrap());
+        assert_eq!(output.width, 16 + 3 * 40 + 2 * 16 + 16);
+
+        // The header sits in the top padding, above the grid
+        let options = MergeOptions {
+            contact_sheet: Some(ContactSheet {
+                header: Some("Sprint review".to_string()),
+                ..sheet
+            }),
+            background: BackgroundColor::white(),
+            ..Default::default()
+        };
+        let header_h = caption_strip_height(Some("Sprint review"), 24.0);
+        assert!(header_h > 0);
+        let with_header = merge_with_metadata(images(), options).unwrap();
+        assert_eq!(with_header.height, output.height + header_h);
+        assert_eq!(with_header.placements[0].y, 16 + header_h);
+        let img = decode_image(&with_header.bytes).unwrap().to_rgba8();
+        let inked = (16..16 + header_h)
+            .flat_map(|y| (0..img.width()).map(move |x| (x, y)))
+            .any(|(x, y)| img.get_pixel(x, y) != &Rgba([255, 255, 255, 255]));
+        assert!(inked);
+    }
+
+    #[test]
+    fn test_merge_scale_mode_min_and_none() {
+        let img1 = create_test_png(100, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(200, 50, Rgba([0, 255, 0, 255]));
+
+        let options = MergeOptions {
+            scale_mode: ScaleMode::Min,
+            ..Default::default()
+        };
+        let output_bytes = merge(vec![img1.clone(), img2.clone()], options).unwrap();
+        let output_img = decode_image(&output_bytes).unwrap();
+        // Second image downscaled from 200x50 to 100x25.
+        assert_eq!((output_img.width(), output_img.height()), (100, 75));
+
+        let options = MergeOptions {
+            scale_mode: ScaleMode::None,
+            ..Default::default()
+        };
+        let output_bytes = merge(vec![img1, img2], options).unwrap();
+        let output_img = decode_image(&output_bytes).unwrap();
+        assert_eq!((output_img.width(), output_img.height()), (200, 100));
+    }
+
+    #[test]
+    fn test_merge_dimension_multiple_pads_right_and_bottom() {
+        let img = create_test_png(10, 7, Rgba([255, 0, 0, 255]));
+
+        let options = MergeOptions {
+            background: BackgroundColor::black(),
+            dimension_multiple: Some(4),
+            ..Default::default()
+        };
+        let output_bytes = merge(vec![img], options).unwrap();
+        let output_img = decode_image(&output_bytes).unwrap().to_rgba8();
+        assert_eq!(output_img.dimensions(), (12, 8));
+        assert_eq!(*output_img.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(11, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*output_img.get_pixel(0, 7), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_merge_upscale_warning_and_error() {
+        let small = create_test_png(10, 10, Rgba([255, 0, 0, 255]));
+        let wide = create_test_png(30, 10, Rgba([0, 255, 0, 255]));
+
+        let output =
+            merge_with_metadata(vec![small.clone(), wide.clone()], MergeOptions::default())
+                .unwrap();
+        assert_eq!(
+            output.warnings,
+            vec![MergeWarning::Upscaled {
+                index: 0,
+                factor: 3.0,
+                max_factor: 2.0,
+            }]
+        );
+
+        let options = MergeOptions {
+            max_upscale_factor: 4.0,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(vec![small.clone(), wide.clone()], options).unwrap();
+        assert!(output.warnings.is_empty());
+
+        let options = MergeOptions {
+            error_on_upscale: true,
+            ..Default::default()
+        };
+        let result = merge(vec![small, wide], options);
+        assert!(matches!(
+            result,
+            Err(MergeError::UpscaleTooLarge { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_no_upscale_keeps_native_size() {
+        let small = create_test_png(10, 10, Rgba([255, 0, 0, 255]));
+        let wide = create_test_png(30, 10, Rgba([0, 255, 0, 255]));
+        let options = MergeOptions {
+            allow_upscale: false,
+            ..Default::default()
+        };
+
+        let output = merge_with_metadata(vec![small, wide], options).unwrap();
+        assert_eq!((output.width, output.height), (30, 20));
+        assert!(output.warnings.is_empty());
+
+        let img = image::load_from_memory(&output.bytes).unwrap().to_rgba8();
+        // Small image is centered at native size with background on both sides
+        assert_eq!(*img.get_pixel(0, 5), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(15, 5), Rgba([255, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(29, 5), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_merge_to_writer_streams_same_png() {
+        let images = vec![
+            create_smart_fixture_png(220, 20, 300, 0),
+            create_smart_fixture_png(220, 20, 300, 200),
+        ];
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            collect_stats: true,
+            ..Default::default()
+        };
+        let collected = merge_with_metadata(images.clone(), options.clone()).unwrap();
+        let mut streamed = Vec::new();
+        let output = merge_to_writer(images, options, &mut streamed).unwrap();
+        assert!(output.bytes.is_empty());
+        assert_eq!(streamed, collected.bytes);
+        assert_eq!(output.stats.unwrap().output_bytes, streamed.len());
+    }
+
+    /// Records the size of every write instead of keeping the bytes.
+    #[derive(Default)]
+    struct WriteSizes {
+        total: usize,
+        largest: usize,
+    }
+
+    impl Write for WriteSizes {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.total += buf.len();
+            self.largest = self.largest.max(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_merge_to_writer_memory_bound() {
+        // Noise compresses poorly, so the PNG spans many chunks
+        let images = vec![
+            create_smart_fixture_png(512, 0, 400, 0),
+            create_smart_fixture_png(512, 0, 400, 400),
+        ];
+        let options = MergeOptions {
+            collect_stats: true,
+            ..Default::default()
+        };
+        let collected = merge_with_metadata(images.clone(), options.clone()).unwrap();
+        let peak = collected.stats.unwrap().peak_bytes_estimate;
+
+        // Streaming fits the budget of inputs, scaled copies and canvas: the
+        // encoded output only passes through one IDAT chunk at a time
+        let options = MergeOptions {
+            max_memory_bytes: Some(peak),
+            ..options
+        };
+        let mut sink = WriteSizes::default();
+        let output = merge_to_writer(images, options, &mut sink).unwrap();
+        assert_eq!(output.stats.unwrap().peak_bytes_estimate, peak);
+        assert_eq!(sink.total, collected.bytes.len());
+        assert!(sink.total > 4 * PNG_CHUNK_BYTES, "{}", sink.total);
+        // Length, type and CRC frame each chunk's data
+        assert!(sink.largest <= PNG_CHUNK_BYTES + 12, "{}", sink.largest);
+    }
+
+    #[test]
+    fn test_write_png_round_trips() {
+        let img = RgbaImage::from_fn(70, 50, |x, y| {
+            Rgba([(x * 3) as u8, (y * 5) as u8, (x ^ y) as u8, (x + y) as u8])
+        });
+        let mut bytes = Vec::new();
+        let written = write_png(
+            PngPixels::Eight(img.as_raw()),
+            img.dimensions(),
+            &MergeOptions::default(),
+            &[],
+            &mut bytes,
+        )
+        .unwrap();
+        assert_eq!(written, bytes.len());
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+        assert_eq!(decoded, img);
+    }
+
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_merge_to_writer_reports_write_errors() {
+        let red = Rgba([255, 0, 0, 255]);
+        let result = merge_to_writer(
+            vec![create_test_png(10, 10, red)],
+            MergeOptions::default(),
+            &mut FailingWriter,
+        );
+        assert!(matches!(result, Err(MergeError::EncodeError { .. })));
+    }
+
+    #[test]
+    fn test_merge_max_memory_bytes() {
+        let red = Rgba([255, 0, 0, 255]);
+        let images = vec![
+            create_test_png(100, 100, red),
+            create_test_png(100, 100, red),
+        ];
+        let merge_with_budget = |max_bytes| {
+            let options = MergeOptions {
+                max_memory_bytes: Some(max_bytes),
+                ..Default::default()
+            };
+            merge_with_metadata(images.clone(), options)
+        };
+        // Decoded inputs, then their scaled copies, then the 100x200 canvas
+        for required_bytes in [80_000, 160_000, 240_000] {
+            assert_eq!(
+                merge_with_budget(required_bytes - 1),
+                Err(MergeError::OutOfMemory {
+                    required_bytes,
+                    max_bytes: required_bytes - 1,
+                })
+            );
+        }
+        assert!(merge_with_budget(240_000).is_ok());
+    }
+
+    #[test]
+    fn test_merge_max_memory_bytes_saturates_huge_canvas() {
+        // Snapping to u32::MAX makes the canvas estimate saturate; the sums
+        // built on it must not overflow past the budget
+        let images = vec![create_test_png(10, 10, Rgba([255, 0, 0, 255]))];
+        for downscale_to_fit in [false, true] {
+            let options = MergeOptions {
+                dimension_multiple: Some(u32::MAX),
+                max_memory_bytes: Some(1 << 30),
+                downscale_to_fit,
+                ..Default::default()
+            };
+            assert!(matches!(
+                merge(images.clone(), options),
+                Err(MergeError::OutOfMemory {
+                    required_bytes: u64::MAX,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_merge_max_input_size_checks_headers() {
+        // A 1x1 PNG whose header claims 100000x100000: decoding it would
+        // allocate 40 GB before finding the pixel data missing
+        let mut bomb = create_test_png(1, 1, Rgba([0, 0, 0, 255]));
+        bomb[16..20].copy_from_slice(&100_000u32.to_be_bytes());
+        bomb[20..24].copy_from_slice(&100_000u32.to_be_bytes());
+        let mut crc = flate2::Crc::new();
+        crc.update(&bomb[12..29]);
+        bomb[29..33].copy_from_slice(&crc.sum().to_be_bytes());
+
+        let small = create_test_png(30, 20, Rgba([255, 0, 0, 255]));
+        let options = MergeOptions {
+            max_input_pixels: Some(50_000_000),
+            file_names: vec![Some("bomb.png".to_string())],
+            order: Some(vec![1, 0]),
+            ..Default::default()
+        };
+        assert_eq!(
+            merge(vec![bomb, small.clone()], options),
+            Err(MergeError::InputTooLarge {
+                index: 1,
+                file_name: Some("bomb.png".to_string()),
+                width: 100_000,
+                height: 100_000,
+                max_pixels: Some(50_000_000),
+                max_dimension: None,
+            })
+        );
+
+        let with_limits = |max_pixels, max_dimension| MergeOptions {
+            max_input_pixels: max_pixels,
+            max_input_dimension: max_dimension,
+            ..Default::default()
+        };
+        assert!(merge(vec![small.clone()], with_limits(Some(600), Some(30))).is_ok());
+        for options in [with_limits(Some(599), None), with_limits(None, Some(29))] {
+            assert!(matches!(
+                merge(vec![small.clone()], options),
+                Err(MergeError::InputTooLarge { index: 0, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_merge_max_output_pixels() {
+        let img1 = create_test_png(20, 10, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(20, 10, Rgba([0, 255, 0, 255]));
+
+        let options = MergeOptions {
+            max_output_pixels: Some(399),
+            ..Default::default()
+        };
+        let result = merge(vec![img1.clone(), img2.clone()], options);
+        assert_eq!(
+            result,
+            Err(MergeError::OutputTooLarge {
+                width: 20,
+                height: 20,
+                max_pixels: 399,
+            })
+        );
+
+        let options = MergeOptions {
+            max_output_pixels: Some(400),
+            ..Default::default()
+        };
+        assert!(merge(vec![img1, img2], options).is_ok());
+    }
+
+    #[test]
+    fn test_downscale_to_fit_pixel_limit() {
+        let images = || {
+            vec![
+                create_test_png(200, 100, Rgba([255, 0, 0, 255])),
+                create_test_png(200, 100, Rgba([0, 255, 0, 255])),
+            ]
+        };
+        let options = MergeOptions {
+            max_output_pixels: Some(10_000),
+            downscale_to_fit: true,
+            padding: Padding::uniform(4),
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images(), options).unwrap();
+        assert!(output.width as u64 * output.height as u64 <= 10_000);
+        // Aspect ratio of the content survives; padding does not shrink
+        let (content_width, content_height) = (output.width - 8, output.height - 8);
+        assert!(content_width.abs_diff(content_height) <= 2);
+        match output.warnings.as_slice() {
+            [
+                MergeWarning::Downscaled {
+                    factor,
+                    width,
+                    height,
+                },
+            ] => {
+                assert!(*factor < 0.5);
+                assert_eq!(
+                    (*width, *height),
+                    (output.width as u64, output.height as u64)
+                );
+            }
+            other => panic!("unexpected warnings: {other:?}"),
+        }
+
+        // Within the limit nothing changes
+        let options = MergeOptions {
+            max_output_pixels: Some(80_000),
+            downscale_to_fit: true,
+            ..Default::default()
+        };
+        let output = merge_with_metadata(images(), options).unwrap();
+        assert_eq!((output.width, output.height), (200, 200));
+        assert!(output.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_downscale_to_fit_memory_budget() {
+        let images = || {
+            vec![
+                create_test_png(200, 100, Rgba([255, 0, 0, 255])),
+                create_test_png(200, 100, Rgba([0, 255, 0, 255])),
+            ]
+        };
+        // 160 KB of inputs, plus as much again scaled and again for the
+        // canvas; no pixel limit is set
+        let max_bytes = 250_000;
+        let options = MergeOptions {
+            max_memory_bytes: Some(max_bytes),
+            ..Default::default()
+        };
+        assert!(matches!(
+            merge_with_metadata(images(), options.clone()),
+            Err(MergeError::OutOfMemory { .. })
+        ));
+
+        let options = MergeOptions {
+            downscale_to_fit: true,
+            collect_stats: true,
+            ..options
+        };
+        let output = merge_with_metadata(images(), options).unwrap();
+        assert!(output.width < 200 && output.height < 200);
+        assert!(output.stats.unwrap().peak_bytes_estimate <= max_bytes);
+        match output.warnings.as_slice() {
+            [MergeWarning::Downscaled { factor, .. }] => assert!(*factor < 1.0),
+            other => panic!("unexpected warnings: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_merge_vertical_different_widths() {
+        // Images with different widths should be scaled to max width
+        let img1 = create_test_png(100, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_test_png(200, 50, Rgba([0, 255, 0, 255])); // wider
+
+        let options = MergeOptions {
+            direction: Direction::Vertical,
+            ..Default::default()
+        };
+
+        let result = merge(vec![img1, img2], options);
+        assert!(result.is_ok());
+
+        let output_bytes = result.unwrap();
+        let output_img = decode_image(&output_bytes).unwrap();
+        assert_eq!(output_img.width(), 200); // max width
+        // First image scaled from 100x50 to 200x100
+        assert_eq!(output_img.height(), 150); // 100 + 50
+    }
+
+    #[test]
+    fn test_merge_decode_error() {
+        let valid_img = create_test_png(100, 100, Rgba([255, 0, 0, 255]));
+        let invalid_img = vec![0u8, 1, 2, 3]; // Invalid image data
+
+        let result = merge(vec![valid_img, invalid_img], MergeOptions::default());
+        assert!(matches!(
+            result,
+            Err(MergeError::DecodeError { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_rgba_input_matches_encoded() {
+        let color = Rgba([10, 200, 30, 255]);
+        let raw: ImageInput = InputSource::Rgba {
+            width: 8,
+            height: 6,
+            pixels: RgbaImage::from_pixel(8, 6, color).into_raw(),
+        }
+        .into();
+        let png = create_test_png(12, 12, Rgba([0, 0, 255, 255]));
+
+        let from_raw = merge(vec![raw, png.clone().into()], MergeOptions::default()).unwrap();
+        let encoded = vec![create_test_png(8, 6, color), png];
+        assert_eq!(from_raw, merge(encoded, MergeOptions::default()).unwrap());
+    }
+
+    #[test]
+    fn test_merge_rgba_input_wrong_length() {
+        let raw: ImageInput = InputSource::Rgba {
+            width: 8,
+            height: 6,
+            pixels: vec![0; 8 * 6 * 4 - 1],
+        }
+        .into();
+        let png = create_test_png(8, 6, Rgba([0, 0, 255, 255]));
+
+        let result = merge(vec![png.into(), raw], MergeOptions::default());
+        assert!(matches!(
+            result,
+            Err(MergeError::DecodeError { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_decode_error_names_file() {
+        let named = |bytes: Vec<u8>, name: &str| ImageInput {
+            name: Some(name.to_string()),
+            ..bytes.into()
+        };
+        let images = vec![
+            named(create_test_png(8, 8, Rgba([255, 0, 0, 255])), "a.png"),
+            named(b"junk".to_vec(), "b.png"),
+        ];
+
+        let err = merge(images, MergeOptions::default()).unwrap_err();
+        assert!(matches!(
+            err,
+            MergeError::DecodeError { index: 1, file_name: Some(ref name), .. } if name == "b.png"
+        ));
+    }
+
+    #[test]
+    fn test_merge_file_names_option_names_decode_error() {
+        let images = vec![
+            create_test_png(8, 8, Rgba([255, 0, 0, 255])),
+            b"junk".to_vec(),
+        ];
+        let options = MergeOptions {
+            file_names: vec![Some("a.png".to_string()), Some("b.png".to_string())],
+            ..MergeOptions::default()
+        };
+
+        let err = merge(images, options).unwrap_err();
+        assert!(matches!(
+            err,
+            MergeError::DecodeError { index: 1, file_name: Some(ref name), .. } if name == "b.png"
+        ));
+
+        // A name carried by the input itself wins.
+        let images = vec![ImageInput {
+            name: Some("own.png".to_string()),
+            ..b"junk".to_vec().into()
+        }];
+        let options = MergeOptions {
+            file_names: vec![Some("option.png".to_string())],
+            ..MergeOptions::default()
+        };
+        let err = merge(images, options).unwrap_err();
+        assert!(matches!(
+            err,
+            MergeError::DecodeError { file_name: Some(ref name), .. } if name == "own.png"
+        ));
+    }
+
+    #[test]
+    fn test_merge_order_option() {
+        let red = create_test_png(10, 10, Rgba([255, 0, 0, 255]));
+        let green = create_test_png(10, 20, Rgba([0, 255, 0, 255]));
+        let blue = create_test_png(10, 5, Rgba([0, 0, 255, 255]));
+        let options = MergeOptions {
+            direction: Direction::Vertical,
+            ..Default::default()
+        };
+        let ordered = MergeOptions {
+            order: Some(vec![2, 0, 1]),
+            ..options.clone()
+        };
+
+        let expected = merge(vec![blue.clone(), red.clone(), green.clone()], options).unwrap();
+        let images = || vec![red.clone(), green.clone(), blue.clone()];
+        assert_eq!(merge(images(), ordered.clone()).unwrap(), expected);
+
+        let mut session = crate::session::Session::default();
+        for image in images() {
+            session.add_image(image).unwrap();
+        }
+        assert_eq!(session.merge(ordered).unwrap().bytes, expected);
+
+        for order in [vec![0, 1], vec![0, 1, 1], vec![0, 1, 3]] {
+            let options = MergeOptions {
+                order: Some(order),
+                ..Default::default()
+            };
+            assert_eq!(
+                merge(images(), options.clone()).unwrap_err(),
+                MergeError::InvalidOrder { count: 3 }
+            );
+            assert_eq!(
+                session.merge(options).unwrap_err(),
+                MergeError::InvalidOrder { count: 3 }
+            );
+        }
+
+        // Names stay with their input; the error reports the merge position.
+        let options = MergeOptions {
+            order: Some(vec![1, 0]),
+            file_names: vec![Some("junk.png".to_string()), Some("red.png".to_string())],
+            ..Default::default()
+        };
+        let err = merge(vec![b"junk".to_vec(), red], options).unwrap_err();
+        assert!(matches!(
+            err,
+            MergeError::DecodeError { index: 1, file_name: Some(ref name), .. } if name == "junk.png"
+        ));
+    }
+
+    #[test]
+    fn test_merge_input_format_skips_guessing() {
+        let with_format = |format| ImageInput {
+            format: Some(format),
+            ..create_test_png(8, 8, Rgba([255, 0, 0, 255])).into()
+        };
+
+        assert!(merge(vec![with_format(ImageFormat::Png)], MergeOptions::default()).is_ok());
+        let err = merge(
+            vec![with_format(ImageFormat::Jpeg)],
+            MergeOptions::default(),
+        );
+        assert!(matches!(err, Err(MergeError::DecodeError { index: 0, .. })));
+    }
+
+    /// A looping GIF animation with one 8x8 frame per color.
+    fn create_test_gif(colors: &[Rgba<u8>]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = image::codecs::gif::GifEncoder::new(&mut bytes);
+        let frames = colors
+            .iter()
+            .map(|&color| image::Frame::new(RgbaImage::from_pixel(8, 8, color)));
+        encoder.encode_frames(frames).unwrap();
+        drop(encoder);
+        bytes
+    }
+
+    #[test]
+    fn test_merge_animated_input_frame() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let animation = create_test_gif(&[red, blue]);
+        let at_frame = |frame| ImageInput {
+            frame,
+            ..animation.clone().into()
+        };
+        let first_pixel = |input: ImageInput, options: MergeOptions| {
+            let merged = merge(vec![input], options).unwrap();
+            *decode_image(&merged).unwrap().to_rgba8().get_pixel(0, 0)
+        };
+
+        assert_eq!(first_pixel(at_frame(None), MergeOptions::default()), red);
+        assert_eq!(
+            first_pixel(at_frame(Some(1)), MergeOptions::default()),
+            blue
+        );
+        let Err(MergeError::DecodeError { message, .. }) =
+            merge(vec![at_frame(Some(2))], MergeOptions::default())
+        else {
+            panic!("frame 2 of 2 should not decode");
+        };
+        assert!(message.contains("2 frames"), "{message}");
+
+        // Strict mode refuses to pick the first frame silently
+        let strict = MergeOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let still = create_test_png(8, 8, red);
+        assert!(matches!(
+            merge(vec![still.clone().into(), at_frame(None)], strict.clone()),
+            Err(MergeError::StrictViolation { index: 1, .. })
+        ));
+        assert_eq!(first_pixel(at_frame(Some(0)), strict.clone()), red);
+        assert_eq!(first_pixel(still.clone().into(), strict), red);
+        assert!(matches!(
+            merge(
+                vec![ImageInput {
+                    frame: Some(1),
+                    ..still.into()
+                }],
+                MergeOptions::default()
+            ),
+            Err(MergeError::DecodeError { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_tiff_input_page() {
+        let green = Rgba([0, 255, 0, 255]);
+        let mut tiff = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(6, 4, green))
+            .write_with_encoder(image::codecs::tiff::TiffEncoder::new(Cursor::new(
+                &mut tiff,
+            )))
+            .unwrap();
+        let at_page = |bytes: &Vec<u8>, page| ImageInput {
+            page,
+            ..bytes.clone().into()
+        };
+
+        let output = merge(vec![at_page(&tiff, Some(0))], MergeOptions::default()).unwrap();
+        let img = image::load_from_memory(&output).unwrap().to_rgba8();
+        assert_eq!((img.width(), img.height()), (6, 4));
+        assert_eq!(*img.get_pixel(0, 0), green);
+
+        let png = create_test_png(6, 4, green);
+        for input in [at_page(&tiff, Some(1)), at_page(&png, Some(1))] {
+            assert!(matches!(
+                merge(vec![png.clone().into(), input], MergeOptions::default()),
+                Err(MergeError::DecodeError { index: 1, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_merge_ico_input_size() {
+        use image::ExtendedColorType;
+        use image::codecs::ico::{IcoEncoder, IcoFrame};
+        // A 32-bit 16x16 entry, then a 32x32 one labeled 8-bit
+        let frames: Vec<IcoFrame> = [(16, ExtendedColorType::Rgba8), (32, ExtendedColorType::L8)]
+            .into_iter()
+            .map(|(side, color)| {
+                let png = create_test_png(side, side, Rgba([0, 0, 255, 255]));
+                IcoFrame::with_encoded(png, side, side, color).unwrap()
+            })
+            .collect();
+        let mut ico = Vec::new();
+        IcoEncoder::new(&mut ico).encode_images(&frames).unwrap();
+        let with_size = |icon_size| ImageInput {
+            icon_size,
+            ..ico.clone().into()
+        };
+        let side = |input| {
+            let output = merge(vec![input], MergeOptions::default()).unwrap();
+            image::load_from_memory(&output).unwrap().width()
+        };
+
+        assert_eq!(side(with_size(None)), 32);
+        assert_eq!(side(with_size(Some(16))), 16);
+        assert_eq!(probe(&ico).unwrap().width, 32);
+        assert!(matches!(
+            merge(vec![with_size(Some(48))], MergeOptions::default()),
+            Err(MergeError::DecodeError { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_to_animation() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let images = || vec![create_test_png(20, 10, red), create_test_png(10, 10, blue)];
+        let options = MergeOptions {
+            background: BackgroundColor {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+            ..Default::default()
+        };
+
+        let apng =
+            merge_to_animation(images(), options.clone(), AnimationFormat::Apng, 250).unwrap();
+        assert_eq!((apng.width, apng.height, apng.frame_count), (20, 10, 2));
+        let decoder = image::codecs::png::PngDecoder::new(Cursor::new(&apng.bytes)).unwrap();
+        let frames: Vec<_> = decoder
+            .apng()
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].delay().numer_denom_ms(), (250, 1));
+        // The narrower frame is centered on the background
+        let second = frames[1].buffer();
+        assert_eq!(second.dimensions(), (20, 10));
+        assert_eq!(*second.get_pixel(2, 5), Rgba([0, 0, 0, 255]));
+        assert_eq!(*second.get_pixel(10, 5), blue);
+
+        let gif = merge_to_animation(images(), options, AnimationFormat::Gif, 250).unwrap();
+        assert_eq!(decode_frame(&gif.bytes, None, 1).unwrap().width(), 20);
+        assert!(decode_frame(&gif.bytes, None, 2).is_err());
+    }
+
+    #[test]
+    fn test_merge_decode_error_reports_first_failure() {
+        let valid_img = create_test_png(10, 10, Rgba([255, 0, 0, 255]));
+        let images = vec![
+            valid_img.clone(),
+            valid_img,
+            vec![0u8, 1, 2, 3],
+            vec![4u8, 5, 6, 7],
+        ];
+        let result = merge(images, MergeOptions::default());
+        assert!(matches!(
+            result,
+            Err(MergeError::DecodeError { index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_scale_overlap_regions() {
+        let regions = vec![
+            Some(OverlapRegion {
+                x: 10,
+                y: 20,
+                width: 50,
+                height: 40,
+            }),
+            None,
+        ];
+        let dims = vec![(100, 200), (200, 200)];
+        let scaled = vec![(200, 400), (200, 200)];
+        let result = scale_overlap_regions(&regions, &dims, &scaled);
+        assert_eq!(
+            result,
+            vec![
+                Some(OverlapRegion {
+                    x: 20,
+                    y: 40,
+                    width: 100,
+                    height: 80,
+                }),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blend_with_background_opaque() {
+        let pixel = Rgba([100, 150, 200, 255]);
+        let bg = BackgroundColor::white();
+        let blended = blend_with_background(pixel, &bg);
+        assert_eq!(blended, pixel);
+    }
+
+    #[test]
+    fn test_blend_with_background_transparent() {
+        let pixel = Rgba([100, 150, 200, 0]);
+        let bg = BackgroundColor::white();
+        let blended = blend_with_background(pixel, &bg);
+        assert_eq!(blended, Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_blend_with_background_transparent_bg() {
+        let pixel = Rgba([100, 150, 200, 0]);
+        let bg = BackgroundColor::transparent();
+        let blended = blend_with_background(pixel, &bg);
+        assert_eq!(blended, Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_blend_with_background_semi_transparent() {
+        let pixel = Rgba([0, 0, 0, 128]); // 50% black
+        let bg = BackgroundColor::white();
+        let blended = blend_with_background(pixel, &bg);
+        // Should be roughly 50% gray
+        assert!(blended[0] > 100 && blended[0] < 150);
+        assert_eq!(blended[3], 255); // White background is opaque
+    }
+
+    #[test]
+    fn test_blend_with_background_semi_transparent_bg() {
+        let pixel = Rgba([255, 0, 0, 128]); // 50% red
+        let bg = BackgroundColor {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 128,
+        }; // 50% blue
+        let blended = blend_with_background(pixel, &bg);
+        // Source-over: alpha 0.5 + 0.5 * 0.5 = 0.75, two thirds of it red
+        assert_eq!(blended, Rgba([170, 0, 85, 192]));
+    }
+
+    #[test]
+    fn test_blend_with_background_translucent_over_transparent() {
+        // Nothing to blend with: the pixel is kept as is, alpha included
+        let pixel = Rgba([40, 80, 120, 100]);
+        let blended = blend_with_background(pixel, &BackgroundColor::transparent());
+        assert_eq!(blended, pixel);
+    }
+
+    #[test]
+    fn test_merge_keeps_translucent_pixels_on_transparent_background() {
+        let images = vec![
+            create_test_png(4, 4, Rgba([255, 0, 0, 128])),
+            create_test_png(4, 4, Rgba([0, 0, 255, 255])),
+        ];
+        let options = MergeOptions {
+            background: BackgroundColor::transparent(),
+            ..Default::default()
+        };
+
+        let output = image::load_from_memory(&merge(images, options).unwrap())
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(output.get_pixel(1, 1), &Rgba([255, 0, 0, 128]));
+        assert_eq!(output.get_pixel(1, 5), &Rgba([0, 0, 255, 255]));
+    }
+}
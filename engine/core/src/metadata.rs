@@ -0,0 +1,438 @@
+//! Metadata written into the output: EXIF fields carried from an input and
+//! a record of how the merge was produced.
+//!
+//! Re-encoding discards all metadata, so the fields asked for are copied
+//! out of the input's EXIF block into a fresh, minimal one: IFD0 plus the
+//! Exif and GPS sub-IFDs when they hold anything. Everything else (maker
+//! notes, thumbnails, orientation, which no longer applies) is left behind.
+//! Values keep the byte order of the source, so they are copied verbatim.
+//!
+//! The provenance record is a small JSON object stored in a `tEXt` chunk,
+//! so tooling can tell which engine and settings produced an image and
+//! where each input ended up without re-running the merge.
+
+use crate::types::{Direction, ExifCopy, MergeOptions, Placement};
+
+/// `tEXt` keyword of the provenance chunk.
+pub const PROVENANCE_KEYWORD: &str = "merge-images";
+
+/// IFD0 pointer to the Exif sub-IFD.
+const EXIF_IFD_TAG: u16 = 0x8769;
+/// IFD0 pointer to the GPS sub-IFD.
+const GPS_IFD_TAG: u16 = 0x8825;
+
+/// IFD0 `DateTime`.
+const IFD0_TIME_TAGS: [u16; 1] = [0x0132];
+/// IFD0 `Make`, `Model`.
+const IFD0_CAMERA_TAGS: [u16; 2] = [0x010F, 0x0110];
+/// `DateTimeOriginal`, `DateTimeDigitized`, `OffsetTime`, `OffsetTimeOriginal`,
+/// `OffsetTimeDigitized`.
+const EXIF_TIME_TAGS: [u16; 5] = [0x9003, 0x9004, 0x9010, 0x9011, 0x9012];
+/// `LensMake`, `LensModel`.
+const EXIF_CAMERA_TAGS: [u16; 2] = [0xA433, 0xA434];
+
+/// TIFF type code for LONG, used for the sub-IFD pointers.
+const LONG: u16 = 4;
+
+/// One IFD entry, its value in the byte order of its TIFF block.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: Vec<u8>,
+}
+
+/// A TIFF block being read.
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(0..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let tiff = Tiff {
+            data,
+            little_endian,
+        };
+        (tiff.u16(2)? == 42).then_some(tiff)
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset.checked_add(2)?)?;
+        let bytes = [bytes[0], bytes[1]];
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset.checked_add(4)?)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn ifd0(&self) -> Vec<Entry> {
+        self.u32(4)
+            .and_then(|offset| self.entries(offset as usize))
+            .unwrap_or_default()
+    }
+
+    /// Entries of the sub-IFD that IFD0's `pointer` tag points to.
+    fn sub_ifd(&self, ifd0: &[Entry], pointer: u16) -> Vec<Entry> {
+        ifd0.iter()
+            .find(|e| e.tag == pointer && e.kind == LONG && e.count == 1)
+            .and_then(|e| self.entries(self.decode_u32(&e.value) as usize))
+            .unwrap_or_default()
+    }
+
+    fn decode_u32(&self, value: &[u8]) -> u32 {
+        let bytes = [value[0], value[1], value[2], value[3]];
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    /// Entries of the IFD at `offset`, skipping any whose value is out of bounds.
+    fn entries(&self, offset: usize) -> Option<Vec<Entry>> {
+        let count = self.u16(offset)? as usize;
+        let entries = (0..count)
+            .filter_map(|i| {
+                let at = offset + 2 + i * 12;
+                let tag = self.u16(at)?;
+                let kind = self.u16(at + 2)?;
+                let count = self.u32(at + 4)?;
+                let len = type_size(kind)?.checked_mul(count as usize)?;
+                let value = if len <= 4 {
+                    self.data.get(at + 8..at + 8 + len)?
+                } else {
+                    let start = self.u32(at + 8)? as usize;
+                    self.data.get(start..start.checked_add(len)?)?
+                };
+                Some(Entry {
+                    tag,
+                    kind,
+                    count,
+                    value: value.to_vec(),
+                })
+            })
+            .collect();
+        Some(entries)
+    }
+}
+
+/// Size in bytes of one value of a TIFF field type; `None` for unknown types.
+fn type_size(kind: u16) -> Option<usize> {
+    match kind {
+        1 | 2 | 6 | 7 => Some(1), // BYTE, ASCII, SBYTE, UNDEFINED
+        3 | 8 => Some(2),         // SHORT, SSHORT
+        4 | 9 | 11 => Some(4),    // LONG, SLONG, FLOAT
+        5 | 10 | 12 => Some(8),   // RATIONAL, SRATIONAL, DOUBLE
+        _ => None,
+    }
+}
+
+/// Builds an EXIF block (TIFF data) holding the fields of `exif` selected by
+/// `copy`; `None` when none of them are present.
+pub fn select_exif(exif: &[u8], copy: &ExifCopy) -> Option<Vec<u8>> {
+    let tiff = Tiff::parse(exif)?;
+    let ifd0 = tiff.ifd0();
+    let pick = |entries: &[Entry], tags: &[u16]| -> Vec<Entry> {
+        entries
+            .iter()
+            .filter(|e| tags.contains(&e.tag))
+            .cloned()
+            .collect()
+    };
+
+    let mut ifd0_tags = Vec::new();
+    let mut exif_tags = Vec::new();
+    if copy.capture_time {
+        ifd0_tags.extend(IFD0_TIME_TAGS);
+        exif_tags.extend(EXIF_TIME_TAGS);
+    }
+    if copy.camera {
+        ifd0_tags.extend(IFD0_CAMERA_TAGS);
+        exif_tags.extend(EXIF_CAMERA_TAGS);
+    }
+    let ifd0_entries = pick(&ifd0, &ifd0_tags);
+    let exif_entries = pick(&tiff.sub_ifd(&ifd0, EXIF_IFD_TAG), &exif_tags);
+    let gps_entries = if copy.gps {
+        tiff.sub_ifd(&ifd0, GPS_IFD_TAG)
+    } else {
+        Vec::new()
+    };
+
+    if ifd0_entries.is_empty() && exif_entries.is_empty() && gps_entries.is_empty() {
+        return None;
+    }
+    Some(write_tiff(
+        tiff.little_endian,
+        ifd0_entries,
+        exif_entries,
+        gps_entries,
+    ))
+}
+
+/// Lays out a TIFF block: header, IFD0, then the non-empty Exif and GPS
+/// sub-IFDs, each IFD followed by the values that do not fit inline.
+fn write_tiff(
+    little_endian: bool,
+    mut ifd0: Vec<Entry>,
+    exif: Vec<Entry>,
+    gps: Vec<Entry>,
+) -> Vec<u8> {
+    let u16_bytes = |v: u16| {
+        if little_endian {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        }
+    };
+    let u32_bytes = |v: u32| {
+        if little_endian {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        }
+    };
+
+    // Sub-IFD pointers are known once IFD0's size is, which they are part of
+    let pointers = [&exif, &gps].iter().filter(|e| !e.is_empty()).count();
+    let mut offset = 8 + ifd_size(&ifd0) + pointers * 12;
+    for (tag, entries) in [(EXIF_IFD_TAG, &exif), (GPS_IFD_TAG, &gps)] {
+        if entries.is_empty() {
+            continue;
+        }
+        ifd0.push(Entry {
+            tag,
+            kind: LONG,
+            count: 1,
+            value: u32_bytes(offset as u32).to_vec(),
+        });
+        offset += ifd_size(entries);
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(if little_endian { b"II" } else { b"MM" });
+    out.extend_from_slice(&u16_bytes(42));
+    out.extend_from_slice(&u32_bytes(8));
+    for mut entries in [ifd0, exif, gps] {
+        if entries.is_empty() {
+            continue;
+        }
+        // IFD entries must be sorted by tag
+        entries.sort_by_key(|e| e.tag);
+        let mut data_offset = out.len() + 2 + entries.len() * 12 + 4;
+        let mut data = Vec::new();
+        out.extend_from_slice(&u16_bytes(entries.len() as u16));
+        for entry in &entries {
+            out.extend_from_slice(&u16_bytes(entry.tag));
+            out.extend_from_slice(&u16_bytes(entry.kind));
+            out.extend_from_slice(&u32_bytes(entry.count));
+            if entry.value.len() <= 4 {
+                let mut inline = [0; 4];
+                inline[..entry.value.len()].copy_from_slice(&entry.value);
+                out.extend_from_slice(&inline);
+            } else {
+                out.extend_from_slice(&u32_bytes(data_offset as u32));
+                data.extend_from_slice(&entry.value);
+                // Values start on word boundaries
+                if entry.value.len() % 2 == 1 {
+                    data.push(0);
+                }
+                data_offset += padded_len(entry);
+            }
+        }
+        out.extend_from_slice(&u32_bytes(0)); // No next IFD
+        out.extend_from_slice(&data);
+    }
+    out
+}
+
+/// Bytes an IFD takes: count, entries, next-IFD offset and out-of-line values.
+fn ifd_size(entries: &[Entry]) -> usize {
+    2 + entries.len() * 12 + 4 + entries.iter().map(padded_len).sum::<usize>()
+}
+
+/// Out-of-line bytes of an entry's value, padded to an even length.
+fn padded_len(entry: &Entry) -> usize {
+    match entry.value.len() {
+        len if len <= 4 => 0,
+        len => len + len % 2,
+    }
+}
+
+/// JSON provenance record: engine version, direction, overlap sensitivity
+/// and the output rectangle of every input, in input order.
+pub fn provenance(options: &MergeOptions, placements: &[Placement]) -> String {
+    let direction = match options.direction {
+        Direction::Vertical => "vertical",
+        Direction::Horizontal => "horizontal",
+        Direction::Smart => "smart",
+        Direction::Tiles => "tiles",
+        Direction::Grid => "grid",
+    };
+    let images = placements
+        .iter()
+        .map(|p| {
+            format!(
+                r#"{{"x":{},"y":{},"width":{},"height":{}}}"#,
+                p.x, p.y, p.width, p.height
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        r#"{{"engine":"merge-images-core","version":"{}","direction":"{direction}","overlapSensitivity":{},"images":[{images}]}}"#,
+        env!("CARGO_PKG_VERSION"),
+        options.overlap_sensitivity,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A big-endian TIFF block with `Make`, `Orientation`, an Exif sub-IFD
+    /// holding `DateTimeOriginal` and a GPS sub-IFD holding `GPSLatitudeRef`.
+    fn sample_exif() -> Vec<u8> {
+        let ascii = |tag: u16, text: &[u8]| Entry {
+            tag,
+            kind: 2,
+            count: text.len() as u32,
+            value: text.to_vec(),
+        };
+        let ifd0 = vec![
+            ascii(0x010F, b"Pixel Co.\0"),
+            Entry {
+                tag: 0x0112,
+                kind: 3,
+                count: 1,
+                value: 6u16.to_be_bytes().to_vec(),
+            },
+        ];
+        let exif = vec![ascii(0x9003, b"2026:01:02 03:04:05\0")];
+        let gps = vec![ascii(0x0001, b"N\0")];
+        write_tiff(false, ifd0, exif, gps)
+    }
+
+    fn entries_of(exif: &[u8]) -> (Vec<Entry>, Vec<Entry>, Vec<Entry>) {
+        let tiff = Tiff::parse(exif).unwrap();
+        let ifd0 = tiff.ifd0();
+        let exif = tiff.sub_ifd(&ifd0, EXIF_IFD_TAG);
+        let gps = tiff.sub_ifd(&ifd0, GPS_IFD_TAG);
+        (ifd0, exif, gps)
+    }
+
+    fn tags(entries: &[Entry]) -> Vec<u16> {
+        entries.iter().map(|e| e.tag).collect()
+    }
+
+    #[test]
+    fn test_select_exif_copies_requested_fields() {
+        let source = sample_exif();
+        let all = ExifCopy {
+            capture_time: true,
+            camera: true,
+            gps: true,
+        };
+        let (ifd0, exif, gps) = entries_of(&select_exif(&source, &all).unwrap());
+        // Orientation is dropped: the output is already upright
+        assert_eq!(tags(&ifd0), vec![0x010F, EXIF_IFD_TAG, GPS_IFD_TAG]);
+        assert_eq!(ifd0[0].value, b"Pixel Co.\0");
+        assert_eq!(exif[0].value, b"2026:01:02 03:04:05\0");
+        assert_eq!(tags(&gps), vec![0x0001]);
+
+        let no_gps = ExifCopy { gps: false, ..all };
+        let (ifd0, _, gps) = entries_of(&select_exif(&source, &no_gps).unwrap());
+        assert_eq!(tags(&ifd0), vec![0x010F, EXIF_IFD_TAG]);
+        assert!(gps.is_empty());
+
+        let time_only = ExifCopy {
+            camera: false,
+            ..no_gps
+        };
+        let (ifd0, exif, _) = entries_of(&select_exif(&source, &time_only).unwrap());
+        assert_eq!(tags(&ifd0), vec![EXIF_IFD_TAG]);
+        assert_eq!(tags(&exif), vec![0x9003]);
+    }
+
+    #[test]
+    fn test_select_exif_without_matching_fields() {
+        let gps_only = ExifCopy {
+            capture_time: false,
+            camera: false,
+            gps: true,
+        };
+        let none = ExifCopy {
+            gps: false,
+            ..gps_only
+        };
+        assert!(select_exif(&sample_exif(), &gps_only).is_some());
+        assert_eq!(select_exif(&sample_exif(), &none), None);
+        assert_eq!(select_exif(b"not tiff", &gps_only), None);
+    }
+
+    #[test]
+    fn test_write_tiff_round_trips_little_endian() {
+        let ifd0 = vec![Entry {
+            tag: 0x0110,
+            kind: 2,
+            count: 6,
+            value: b"Model\0".to_vec(),
+        }];
+        let written = write_tiff(true, ifd0.clone(), vec![], vec![]);
+        assert!(written.starts_with(b"II"));
+        assert_eq!(Tiff::parse(&written).unwrap().ifd0(), ifd0);
+    }
+
+    #[test]
+    fn test_provenance_records_settings_and_placements() {
+        let options = MergeOptions {
+            direction: Direction::Smart,
+            overlap_sensitivity: 70,
+            ..Default::default()
+        };
+        let placements = [
+            Placement {
+                width: 10,
+                height: 20,
+                ..Default::default()
+            },
+            Placement {
+                y: 15,
+                width: 10,
+                height: 20,
+                crop_top: 5,
+                ..Default::default()
+            },
+        ];
+
+        let text = provenance(&options, &placements);
+        assert_eq!(
+            text,
+            format!(
+                concat!(
+                    r#"{{"engine":"merge-images-core","version":"{}","direction":"smart","#,
+                    r#""overlapSensitivity":70,"images":[{{"x":0,"y":0,"width":10,"height":20}},"#,
+                    r#"{{"x":0,"y":15,"width":10,"height":20}}]}}"#
+                ),
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+}
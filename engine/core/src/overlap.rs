@@ -4,7 +4,12 @@
 //! overlapping regions between consecutive screenshots.
 
 use image::{DynamicImage, GrayImage, ImageBuffer, Luma};
-use imageproc::template_matching::{MatchTemplateMethod, find_extremes, match_template};
+use imageproc::template_matching::find_extremes;
+
+use crate::correlation::{match_template_coarse_to_fine, match_template_ncc};
+use crate::hooks::Interrupt;
+use crate::simd::sum_and_squares;
+use crate::types::OverlapRegion;
 
 /// Minimum match score threshold for overlap detection (conservative end).
 const MATCH_THRESHOLD_CONSERVATIVE: f32 = 0.86;
@@ -33,12 +38,19 @@ const MIN_TEMPLATE_HEIGHT: u32 = 30;
 /// Minimum template width in pixels.
 const MIN_TEMPLATE_WIDTH: u32 = 50;
 
-/// Minimum overlap to accept (in pixels).
-const MIN_OVERLAP_PIXELS: u32 = 5;
+/// Default minimum overlap to accept (in pixels).
+pub(crate) const MIN_OVERLAP_PIXELS: u32 = 5;
 
 /// Percentage of width to crop from each side to ignore scroll bars.
 const MARGIN_PERCENT: f32 = 0.025;
 
+/// Largest horizontal shift between captures that is corrected, in pixels
+/// (a scroll bar appearing, the window moving).
+const MAX_JITTER_PX: u32 = 5;
+/// Score a shifted match must gain over the column-aligned one to be used,
+/// so noise or repeating patterns do not introduce a shift.
+const MIN_JITTER_GAIN: f32 = 0.02;
+
 /// Search region start and end in the top image.
 const SEARCH_START_PERCENT: f32 = 0.0;
 const SEARCH_END_PERCENT: f32 = 1.0;
@@ -47,19 +59,43 @@ const SEARCH_END_PERCENT: f32 = 1.0;
 const TEMPLATE_START_PERCENT: f32 = 0.0;
 const TEMPLATE_START_FALLBACK_PERCENT: f32 = 0.02;
 
-/// Minimum acceptable width ratio between two images.
-const WIDTH_RATIO_THRESHOLD: f32 = 0.9;
+/// Default minimum acceptable width ratio between two images.
+pub(crate) const WIDTH_RATIO_THRESHOLD: f32 = 0.9;
+
+/// Caller-tunable overlap detection parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlapParams {
+    /// Overlap sensitivity (0-100); higher is more aggressive.
+    pub sensitivity: u8,
+    /// Smallest overlap (in pixels) accepted as a real match.
+    pub min_overlap_pixels: u32,
+    /// Minimum `narrower / wider` width ratio for a pair to be compared.
+    pub width_ratio_threshold: f32,
+}
+
+impl OverlapParams {
+    /// Parameters with the given sensitivity and default thresholds.
+    pub fn with_sensitivity(sensitivity: u8) -> Self {
+        Self {
+            sensitivity,
+            min_overlap_pixels: MIN_OVERLAP_PIXELS,
+            width_ratio_threshold: WIDTH_RATIO_THRESHOLD,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 struct OverlapConfig {
     match_threshold: f32,
     ambiguity_gap: f32,
     min_template_variance: f32,
+    min_overlap_pixels: u32,
     sensitivity: u8,
 }
 
 impl OverlapConfig {
-    fn from_sensitivity(sensitivity: u8) -> Self {
+    fn from_params(params: &OverlapParams) -> Self {
+        let sensitivity = params.sensitivity;
         let clamped = sensitivity.min(100) as f32 / 100.0;
         Self {
             match_threshold: lerp(
@@ -77,6 +113,7 @@ impl OverlapConfig {
                 MIN_TEMPLATE_VARIANCE_AGGRESSIVE,
                 clamped,
             ),
+            min_overlap_pixels: params.min_overlap_pixels,
             sensitivity: sensitivity.min(100),
         }
     }
@@ -93,8 +130,10 @@ pub struct OverlapResult {
     /// This is how much to crop from the top of the second image.
     pub overlap_pixels: u32,
     /// Match confidence score (0.0 - 1.0).
-    #[allow(dead_code)]
     pub confidence: f32,
+    /// Horizontal shift of the second image's content relative to the first;
+    /// drawing the second image this many pixels further right lines it up.
+    pub x_shift: i32,
 }
 
 /// Detects vertical overlap between two images.
@@ -116,37 +155,68 @@ pub fn detect_overlap(
     img_bottom: &DynamicImage,
     sensitivity: u8,
 ) -> Option<OverlapResult> {
-    detect_overlap_with_trims(img_top, img_bottom, sensitivity, 0, 0)
+    detect_overlap_with_trims(
+        img_top,
+        img_bottom,
+        &OverlapParams::with_sensitivity(sensitivity),
+        0,
+        0,
+        None,
+        &mut Interrupt::none(),
+    )
 }
 
 /// Detects vertical overlap between two images, while excluding known chrome.
 ///
+/// `params` carries the sensitivity and acceptance thresholds.
+///
 /// `top_trim_bottom` is the number of pixels that will be trimmed from the
 /// bottom of `img_top`.
 ///
 /// `bottom_trim_top` is the number of pixels that will be trimmed from the
 /// top of `img_bottom`.
+///
+/// `region`, when present, restricts the search to that rectangle of
+/// `img_top` (in `img_top` pixels). Its columns are also used to cut the
+/// template from `img_bottom`, replacing the default scroll-bar margins.
+///
+/// Matching stops with `None` once `interrupt` fires.
 pub fn detect_overlap_with_trims(
     img_top: &DynamicImage,
     img_bottom: &DynamicImage,
-    sensitivity: u8,
+    params: &OverlapParams,
     top_trim_bottom: u32,
     bottom_trim_top: u32,
+    region: Option<OverlapRegion>,
+    interrupt: &mut Interrupt,
 ) -> Option<OverlapResult> {
     let (top_w, top_h) = (img_top.width(), img_top.height());
     let (bottom_w, bottom_h) = (img_bottom.width(), img_bottom.height());
 
     // Images must have similar widths for screenshot stitching.
     let width_ratio = top_w.min(bottom_w) as f32 / top_w.max(bottom_w) as f32;
-    if width_ratio < WIDTH_RATIO_THRESHOLD {
+    if width_ratio < params.width_ratio_threshold {
         return None;
     }
 
     let common_width = top_w.min(bottom_w);
 
-    // Calculate horizontal margins to crop (to ignore scroll bars).
-    let margin = ((common_width as f32) * MARGIN_PERCENT) as u32;
-    let cropped_width = common_width.saturating_sub(margin.saturating_mul(2));
+    // Columns to compare: the user region if given, otherwise the full width
+    // minus horizontal margins (to ignore scroll bars).
+    let (margin, cropped_width) = match region {
+        Some(r) => {
+            let x0 = r.x.min(common_width);
+            let x1 = r.x.saturating_add(r.width).min(common_width);
+            (x0, x1.saturating_sub(x0))
+        }
+        None => {
+            let margin = ((common_width as f32) * MARGIN_PERCENT) as u32;
+            (
+                margin,
+                common_width.saturating_sub(margin.saturating_mul(2)),
+            )
+        }
+    };
 
     if cropped_width < MIN_TEMPLATE_WIDTH {
         return None;
@@ -155,9 +225,14 @@ pub fn detect_overlap_with_trims(
     // Exclude known bottom chrome from search.
     let top_effective_end_y = top_h.saturating_sub(top_trim_bottom);
 
-    // Search region in the top image (almost full height).
-    let search_start_y = ((top_h as f32) * SEARCH_START_PERCENT) as u32;
-    let search_end_y = ((top_h as f32) * SEARCH_END_PERCENT) as u32;
+    // Search region in the top image (almost full height, or the user region).
+    let (search_start_y, search_end_y) = match region {
+        Some(r) => (r.y, r.y.saturating_add(r.height)),
+        None => (
+            ((top_h as f32) * SEARCH_START_PERCENT) as u32,
+            ((top_h as f32) * SEARCH_END_PERCENT) as u32,
+        ),
+    };
     let search_end_y = search_end_y.min(top_effective_end_y);
 
     if search_end_y <= search_start_y {
@@ -177,7 +252,7 @@ pub fn detect_overlap_with_trims(
         search_height,
     )?;
 
-    let config = OverlapConfig::from_sensitivity(sensitivity);
+    let config = OverlapConfig::from_params(params);
 
     // Exclude known top chrome from the template source image.
     if bottom_trim_top >= bottom_h {
@@ -235,16 +310,109 @@ pub fn detect_overlap_with_trims(
                 &search_region,
                 &template,
                 search_start_y,
+                0,
                 top_effective_end_y,
                 bottom_effective_height,
                 &config,
+                interrupt,
             ) {
-                return Some(result);
+                return Some(refine_x_shift(
+                    img_top,
+                    &template,
+                    margin,
+                    top_effective_end_y,
+                    result,
+                ));
+            }
+            if interrupt.is_cancelled() {
+                return None;
             }
         }
     }
 
-    None
+    // Nothing lines up column for column: retry the base template against a
+    // search region widened for horizontal jitter.
+    let jitter_x0 = margin.saturating_sub(MAX_JITTER_PX);
+    let jitter_x1 = margin
+        .saturating_add(cropped_width)
+        .saturating_add(MAX_JITTER_PX)
+        .min(top_w);
+    if jitter_x1 - jitter_x0 == cropped_width {
+        return None;
+    }
+    let template_height = TEMPLATE_HEIGHT_PX
+        .min(bottom_effective_height)
+        .min(search_height.saturating_sub(1));
+    if template_height < MIN_TEMPLATE_HEIGHT {
+        return None;
+    }
+    let template = extract_grayscale_region(
+        img_bottom,
+        margin,
+        bottom_trim_top,
+        cropped_width,
+        template_height,
+    )?;
+    if template_variance(&template) < config.min_template_variance {
+        return None;
+    }
+    let wide_region = extract_grayscale_region(
+        img_top,
+        jitter_x0,
+        search_start_y,
+        jitter_x1 - jitter_x0,
+        search_height,
+    )?;
+    perform_matching(
+        &wide_region,
+        &template,
+        search_start_y,
+        margin - jitter_x0,
+        top_effective_end_y,
+        bottom_effective_height,
+        &config,
+        interrupt,
+    )
+}
+
+/// Re-scores a column-aligned match at nearby horizontal shifts, keeping the
+/// best one, so small jitter between captures is corrected.
+fn refine_x_shift(
+    img_top: &DynamicImage,
+    template: &GrayImage,
+    margin: u32,
+    top_effective_end_y: u32,
+    result: OverlapResult,
+) -> OverlapResult {
+    let match_y = top_effective_end_y.saturating_sub(result.overlap_pixels);
+    let x0 = margin.saturating_sub(MAX_JITTER_PX);
+    let x1 = margin
+        .saturating_add(template.width())
+        .saturating_add(MAX_JITTER_PX)
+        .min(img_top.width());
+    let Some(window) = extract_grayscale_region(img_top, x0, match_y, x1 - x0, template.height())
+    else {
+        return result;
+    };
+
+    // One row of scores, too little work to cancel
+    let scores = match_template_ncc(&window, template, &mut Interrupt::none());
+    let aligned_x = margin - x0;
+    let mut best = (aligned_x, result.confidence + MIN_JITTER_GAIN);
+    for x in 0..scores.width() {
+        let score = scores.get_pixel(x, 0)[0];
+        if score.is_finite() && score > best.1 {
+            best = (x, score);
+        }
+    }
+    if best.0 == aligned_x {
+        return result;
+    }
+    OverlapResult {
+        confidence: best.1,
+        x_shift: best.0 as i32 - aligned_x as i32,
+        ..result
+    }
 }
 
 /// Extracts a grayscale region from an image.
@@ -322,40 +490,38 @@ fn build_template_heights(
 }
 
 fn template_variance(template: &GrayImage) -> f32 {
-    let mut sum = 0.0f32;
-    let mut sum_sq = 0.0f32;
-    let count = (template.width() * template.height()) as f32;
+    let count = (template.width() * template.height()) as f64;
 
     if count == 0.0 {
         return 0.0;
     }
 
-    for pixel in template.pixels() {
-        let value = pixel[0] as f32;
-        sum += value;
-        sum_sq += value * value;
-    }
-
-    let mean = sum / count;
-    let variance = (sum_sq / count) - (mean * mean);
-    variance.max(0.0)
+    let (sum, sum_sq) = sum_and_squares(template.as_raw());
+    let mean = sum as f64 / count;
+    let variance = (sum_sq as f64 / count) - (mean * mean);
+    variance.max(0.0) as f32
 }
 
 /// Performs template matching and returns overlap result.
+///
+/// `aligned_x` is the column of `search_region` where the template sits when
+/// the two images are not shifted horizontally.
+#[allow(clippy::too_many_arguments)]
 fn perform_matching(
     search_region: &GrayImage,
     template: &GrayImage,
     search_start_y: u32,
+    aligned_x: u32,
     top_effective_end_y: u32,
     bottom_effective_height: u32,
     config: &OverlapConfig,
+    interrupt: &mut Interrupt,
 ) -> Option<OverlapResult> {
-    // Perform template matching using NCC.
-    let result = match_template(
-        search_region,
-        template,
-        MatchTemplateMethod::CrossCorrelationNormalized,
-    );
+    // Perform template matching using NCC, coarse-to-fine on wide regions.
+    let result = match_template_coarse_to_fine(search_region, template, interrupt);
+    if interrupt.is_cancelled() {
+        return None;
+    }
 
     // Find best match.
     let extremes = find_extremes(&result);
@@ -379,13 +545,15 @@ fn perform_matching(
     let overlap_pixels = top_effective_end_y.saturating_sub(match_y_in_original);
 
     // Sanity check: overlap should be reasonable.
-    if overlap_pixels < MIN_OVERLAP_PIXELS || overlap_pixels > bottom_effective_height {
+    if overlap_pixels < config.min_overlap_pixels.max(1) || overlap_pixels > bottom_effective_height
+    {
         return None;
     }
 
     Some(OverlapResult {
         overlap_pixels,
         confidence: best_score,
+        x_shift: best_pos.0 as i32 - aligned_x as i32,
     })
 }
 
@@ -438,42 +606,92 @@ pub fn compute_overlaps(images: &[DynamicImage], sensitivity: u8) -> Vec<u32> {
         .collect()
 }
 
-/// Computes overlaps for a sequence of images, while excluding per-image chrome trims.
+/// Detects overlaps for a sequence of images, while excluding per-image chrome trims.
+///
+/// Returns the full detection result (including confidence) for each pair
+/// `(i, i+1)`; `None` where no overlap was found.
 ///
 /// `trims` must have the same length as `images`. For overlap between i and i+1,
 /// this excludes `trims[i].bottom` from the top image and `trims[i+1].top` from
 /// the bottom image.
-pub fn compute_overlaps_with_trims(
+///
+/// `params[i]` tunes matching for pair `(i, i+1)`; pairs without an entry
+/// are not matched.
+///
+/// `regions[i]`, when present, restricts the search for pair `(i, i+1)` to a
+/// rectangle of image `i`. `regions` may be shorter than the number of pairs.
+///
+/// `overrides[i]`, when present, skips matching for pair `(i, i+1)` and is
+/// reported with full confidence, clamped to the untrimmed height of image
+/// `i+1`. `overrides` may be shorter than the number of pairs.
+pub fn detect_overlaps_with_trims(
     images: &[DynamicImage],
     trims: &[crate::chrome_strip::ChromeTrim],
-    sensitivity: u8,
-) -> Vec<u32> {
+    params: &[OverlapParams],
+    regions: &[Option<OverlapRegion>],
+    overrides: &[Option<u32>],
+) -> Vec<Option<OverlapResult>> {
     if images.len() < 2 {
         return vec![];
     }
     if images.len() != trims.len() {
-        return vec![0; images.len().saturating_sub(1)];
+        return vec![None; images.len().saturating_sub(1)];
     }
 
-    images
-        .windows(2)
-        .enumerate()
-        .map(|(i, pair)| {
-            let top_trim_bottom = trims[i].bottom;
-            let bottom_trim_top = trims[i + 1].top;
-            detect_overlap_with_trims(
-                &pair[0],
-                &pair[1],
-                sensitivity,
-                top_trim_bottom,
-                bottom_trim_top,
+    (0..images.len() - 1)
+        .map(|i| {
+            let mut interrupt = Interrupt::none();
+            detect_pair_overlap_with_trims(
+                images,
+                trims,
+                params,
+                regions,
+                overrides,
+                i,
+                &mut interrupt,
             )
-            .map(|r| r.overlap_pixels)
-            .unwrap_or(0)
         })
         .collect()
 }
 
+/// Detects the overlap of pair `(i, i+1)` as [`detect_overlaps_with_trims`]
+/// does, so callers can run pairs one at a time.
+///
+/// `trims` must have the same length as `images` and `i + 1` must be a valid
+/// image index. Matching stops with `None` once `interrupt` fires.
+pub fn detect_pair_overlap_with_trims(
+    images: &[DynamicImage],
+    trims: &[crate::chrome_strip::ChromeTrim],
+    params: &[OverlapParams],
+    regions: &[Option<OverlapRegion>],
+    overrides: &[Option<u32>],
+    i: usize,
+    interrupt: &mut Interrupt,
+) -> Option<OverlapResult> {
+    let top_trim_bottom = trims[i].bottom;
+    let bottom_trim_top = trims[i + 1].top;
+    if let Some(pixels) = overrides.get(i).copied().flatten() {
+        let available = images[i + 1]
+            .height()
+            .saturating_sub(bottom_trim_top)
+            .saturating_sub(trims[i + 1].bottom);
+        return Some(OverlapResult {
+            overlap_pixels: pixels.min(available),
+            confidence: 1.0,
+            x_shift: 0,
+        });
+    }
+    detect_overlap_with_trims(
+        &images[i],
+        &images[i + 1],
+        params.get(i)?,
+        top_trim_bottom,
+        bottom_trim_top,
+        regions.get(i).copied().flatten(),
+        interrupt,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -481,6 +699,10 @@ mod tests {
 
     const TEST_SENSITIVITY: u8 = 35;
 
+    fn test_params() -> OverlapParams {
+        OverlapParams::with_sensitivity(TEST_SENSITIVITY)
+    }
+
     fn create_solid_image(width: u32, height: u32, color: Rgba<u8>) -> DynamicImage {
         DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, color))
     }
@@ -603,19 +825,171 @@ mod tests {
 
         let result = detect_overlap(&img1, &img2, TEST_SENSITIVITY);
         if let Some(r) = result {
-            let config = OverlapConfig::from_sensitivity(TEST_SENSITIVITY);
+            let config =
+                OverlapConfig::from_params(&OverlapParams::with_sensitivity(TEST_SENSITIVITY));
             assert!(r.confidence >= config.match_threshold);
             assert!(r.overlap_pixels > 0);
         }
     }
 
+    /// Moves the content of `img` `dx` pixels sideways, filling with black.
+    fn shift_horizontally(img: &DynamicImage, dx: i32) -> DynamicImage {
+        let src = img.to_rgba8();
+        let shifted = RgbaImage::from_fn(src.width(), src.height(), |x, y| {
+            let sx = x as i32 - dx;
+            if sx < 0 || sx >= src.width() as i32 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                *src.get_pixel(sx as u32, y)
+            }
+        });
+        DynamicImage::ImageRgba8(shifted)
+    }
+
+    #[test]
+    fn test_overlap_corrects_horizontal_jitter() {
+        let (top, bottom) = create_chrome_overlap_pair(220, 20, 300, 100);
+        for dx in [-3, 5] {
+            let shifted = shift_horizontally(&bottom, dx);
+            let result = detect_overlap_with_trims(
+                &top,
+                &shifted,
+                &test_params(),
+                20,
+                20,
+                None,
+                &mut Interrupt::none(),
+            )
+            .unwrap_or_else(|| panic!("no overlap with dx={dx}"));
+            assert_eq!(result.x_shift, -dx);
+            assert!(result.overlap_pixels.abs_diff(100) <= 3);
+        }
+
+        let aligned = detect_overlap_with_trims(
+            &top,
+            &bottom,
+            &test_params(),
+            20,
+            20,
+            None,
+            &mut Interrupt::none(),
+        );
+        assert_eq!(aligned.unwrap().x_shift, 0);
+    }
+
     #[test]
     fn test_overlap_with_trims_ignores_chrome() {
         let (top, bottom) = create_chrome_overlap_pair(220, 20, 300, 100);
-        let result = detect_overlap_with_trims(&top, &bottom, TEST_SENSITIVITY, 20, 20);
+        let result = detect_overlap_with_trims(
+            &top,
+            &bottom,
+            &test_params(),
+            20,
+            20,
+            None,
+            &mut Interrupt::none(),
+        );
         assert!(result.is_some(), "expected overlap to be detected");
         let overlap = result.unwrap().overlap_pixels;
         // Allow small tolerance due to template height/selection.
         assert!(overlap.abs_diff(100) <= 3, "overlap={}", overlap);
     }
+
+    #[test]
+    fn test_overlap_on_wide_capture() {
+        // Wide enough for the coarse-to-fine path; the seam must stay exact.
+        let (top, bottom) = create_chrome_overlap_pair(1800, 20, 400, 150);
+        let result = detect_overlap_with_trims(
+            &top,
+            &bottom,
+            &test_params(),
+            20,
+            20,
+            None,
+            &mut Interrupt::none(),
+        )
+        .expect("expected overlap to be detected");
+        assert_eq!(result.overlap_pixels, 150);
+        assert_eq!(result.x_shift, 0);
+    }
+
+    #[test]
+    fn test_overlap_region_containing_seam() {
+        let (top, bottom) = create_chrome_overlap_pair(220, 20, 300, 100);
+        // The overlap starts at y = 20 + 300 - 100 = 220 in the top image.
+        let region = OverlapRegion {
+            x: 10,
+            y: 180,
+            width: 180,
+            height: 160,
+        };
+        let result = detect_overlap_with_trims(
+            &top,
+            &bottom,
+            &test_params(),
+            20,
+            20,
+            Some(region),
+            &mut Interrupt::none(),
+        );
+        assert!(result.is_some(), "expected overlap inside region");
+        let overlap = result.unwrap().overlap_pixels;
+        assert!(overlap.abs_diff(100) <= 3, "overlap={}", overlap);
+    }
+
+    #[test]
+    fn test_overlap_region_excluding_seam() {
+        let (top, bottom) = create_chrome_overlap_pair(220, 20, 300, 100);
+        let region = OverlapRegion {
+            x: 10,
+            y: 20,
+            width: 180,
+            height: 150,
+        };
+        let result = detect_overlap_with_trims(
+            &top,
+            &bottom,
+            &test_params(),
+            20,
+            20,
+            Some(region),
+            &mut Interrupt::none(),
+        );
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_min_overlap_pixels_rejects_small_overlap() {
+        let (top, bottom) = create_chrome_overlap_pair(220, 20, 300, 100);
+        let params = OverlapParams {
+            min_overlap_pixels: 150,
+            ..test_params()
+        };
+        let result =
+            detect_overlap_with_trims(&top, &bottom, &params, 20, 20, None, &mut Interrupt::none());
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_width_ratio_threshold_is_configurable() {
+        let (top, bottom) = create_chrome_overlap_pair(220, 20, 300, 100);
+        // Narrower capture of the same content: ratio 190 / 220 ~= 0.86.
+        let bottom = bottom.crop_imm(0, 0, 190, bottom.height());
+
+        let strict = test_params();
+        assert!(
+            detect_overlap_with_trims(&top, &bottom, &strict, 20, 20, None, &mut Interrupt::none())
+                .is_none()
+        );
+
+        let loose = OverlapParams {
+            width_ratio_threshold: 0.8,
+            ..test_params()
+        };
+        let result =
+            detect_overlap_with_trims(&top, &bottom, &loose, 20, 20, None, &mut Interrupt::none());
+        assert!(result.is_some(), "expected overlap with looser ratio");
+        let overlap = result.unwrap().overlap_pixels;
+        assert!(overlap.abs_diff(100) <= 3, "overlap={}", overlap);
+    }
 }
@@ -0,0 +1,147 @@
+//! Page breaks for cutting a tall image (a merged output, a long capture)
+//! into pages no taller than a limit.
+//!
+//! Each break is placed at the calmest seam in a band just above the limit
+//! rather than at exactly every `page_height` rows. A row's activity is the
+//! contrast between horizontally adjacent pixels: rows through a line of text
+//! are busy, while the gaps between lines are flat, so cutting through text
+//! is avoided whenever the band holds a gap.
+
+use image::{DynamicImage, GenericImageView};
+
+/// First row of every page when `img` is cut into pages at most
+/// `page_height` rows tall; the first page always starts at row 0.
+///
+/// Each cut is searched for among the `search_rows` seams ending at the page
+/// limit; ties go to the seam nearest the limit, so pages stay as full as
+/// possible. With `search_rows` 0 the image is cut at exactly every
+/// `page_height` rows.
+pub fn page_breaks(img: &DynamicImage, page_height: u32, search_rows: u32) -> Vec<u32> {
+    page_breaks_between(img, page_height, search_rows, &[])
+}
+
+/// Like [`page_breaks`], but preferring cuts that split none of `spans`,
+/// the `(top, bottom)` row ranges of drawn images: a page only cuts through
+/// an image when no seam between images lies in the search band.
+pub fn page_breaks_between(
+    img: &DynamicImage,
+    page_height: u32,
+    search_rows: u32,
+    spans: &[(u32, u32)],
+) -> Vec<u32> {
+    let height = img.height();
+    let page_height = page_height.max(1);
+    let activity: Vec<u64> = (0..height).map(|y| row_activity(img, y)).collect();
+    // Cutting above row `y` splits rows `y - 1` and `y`
+    let seam = |y: u32| activity[y as usize - 1] + activity[y as usize];
+    let between_images = |y: u32| !spans.iter().any(|&(top, bottom)| top < y && y < bottom);
+
+    let mut breaks = vec![0];
+    let mut start = 0;
+    while height - start > page_height {
+        let limit = start + page_height;
+        let lowest = limit.saturating_sub(search_rows).max(start + 1);
+        let band = (lowest..=limit).rev();
+        let cut = band
+            .clone()
+            .filter(|&y| between_images(y))
+            .min_by_key(|&y| seam(y))
+            .or_else(|| band.min_by_key(|&y| seam(y)))
+            .unwrap_or(limit);
+        breaks.push(cut);
+        start = cut;
+    }
+    breaks
+}
+
+/// Sum over row `y` of the largest per-channel difference between each pixel
+/// and its left neighbor.
+fn row_activity(img: &DynamicImage, y: u32) -> u64 {
+    if img.width() == 0 {
+        return 0;
+    }
+    let mut previous = img.get_pixel(0, y);
+    (1..img.width())
+        .map(|x| {
+            let pixel = img.get_pixel(x, y);
+            let difference = pixel
+                .0
+                .iter()
+                .zip(previous.0)
+                .map(|(a, b)| a.abs_diff(b))
+                .max()
+                .unwrap_or(0);
+            previous = pixel;
+            difference as u64
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    /// A white page with striped "text lines" on the given row ranges.
+    fn page_with_lines(width: u32, height: u32, lines: &[(u32, u32)]) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            let in_line = lines
+                .iter()
+                .any(|&(top, bottom)| (top..bottom).contains(&y));
+            if in_line && x % 2 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_page_breaks_short_image_is_one_page() {
+        let img = page_with_lines(10, 50, &[]);
+        assert_eq!(page_breaks(&img, 50, 10), vec![0]);
+        assert_eq!(page_breaks(&img, 80, 10), vec![0]);
+    }
+
+    #[test]
+    fn test_page_breaks_fixed_without_search() {
+        let img = page_with_lines(10, 250, &[(95, 105)]);
+        assert_eq!(page_breaks(&img, 100, 0), vec![0, 100, 200]);
+    }
+
+    #[test]
+    fn test_page_breaks_avoid_text_lines() {
+        // Lines straddle both page limits; the gaps above them are taken
+        let img = page_with_lines(20, 250, &[(80, 88), (92, 104), (188, 204)]);
+        assert_eq!(page_breaks(&img, 100, 20), vec![0, 91, 187]);
+    }
+
+    #[test]
+    fn test_page_breaks_flat_band_keeps_full_pages() {
+        let img = page_with_lines(20, 230, &[(10, 20), (130, 140)]);
+        assert_eq!(page_breaks(&img, 100, 30), vec![0, 100, 200]);
+    }
+
+    #[test]
+    fn test_page_breaks_between_images() {
+        // The calm rows inside the lower image lose to the busy seam above it
+        let img = page_with_lines(20, 200, &[(60, 90), (130, 140)]);
+        assert_eq!(page_breaks(&img, 100, 40), vec![0, 100]);
+        assert_eq!(
+            page_breaks_between(&img, 100, 40, &[(0, 75), (75, 200)]),
+            vec![0, 75, 175]
+        );
+    }
+
+    #[test]
+    fn test_page_breaks_never_exceed_the_limit() {
+        // Text everywhere: there is no clean seam, but pages stay in bounds
+        let img = page_with_lines(20, 300, &[(0, 300)]);
+        let breaks = page_breaks(&img, 70, 20);
+        let mut ends = breaks[1..].to_vec();
+        ends.push(300);
+        for (start, end) in breaks.iter().zip(ends) {
+            assert!(end > *start && end - start <= 70);
+        }
+    }
+}
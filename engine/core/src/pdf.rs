@@ -0,0 +1,219 @@
+//! Minimal PDF writer for exporting merged images as a document.
+//!
+//! Each page shows one image filling it exactly. Pixels are stored losslessly
+//! as Flate-compressed RGB, with a soft mask only when the image has
+//! transparency, so text in screenshots stays sharp. No fonts, dates or IDs
+//! are written, so the same pages always produce the same bytes.
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use image::RgbaImage;
+use std::io::Write;
+
+/// Largest page side most viewers accept, in user units (200 inches).
+const MAX_PAGE_SIDE: f32 = 14_400.0;
+
+/// Writes a PDF with one page per image, each `points_per_pixel` points per
+/// pixel in size (1.0 at 72 DPI).
+///
+/// Pages larger than viewers accept, such as one long page of a whole merge,
+/// get a `UserUnit` scale (PDF 1.6) so their physical size is kept.
+pub fn write_pdf(pages: &[RgbaImage], points_per_pixel: f32) -> Vec<u8> {
+    let mut pdf = PdfWriter::default();
+    pdf.bytes
+        .extend_from_slice(b"%PDF-1.6\n%\xE2\xE3\xCF\xD3\n");
+
+    // Objects 1 and 2 are the catalog and page tree; each page then takes a
+    // page, content, image and (optional) mask object, numbered in order
+    let mut next_id = 3;
+    let mut page_ids = Vec::with_capacity(pages.len());
+    let mut page_objects = Vec::new();
+    for (index, image) in pages.iter().enumerate() {
+        let has_alpha = image.pixels().any(|p| p[3] < 255);
+        let ids = (next_id, next_id + 1, next_id + 2, next_id + 3);
+        next_id += if has_alpha { 4 } else { 3 };
+        page_ids.push(ids.0);
+        page_objects.push((index, ids, has_alpha));
+    }
+
+    pdf.object(1, b"<< /Type /Catalog /Pages 2 0 R >>");
+    let kids: Vec<String> = page_ids.iter().map(|id| format!("{id} 0 R")).collect();
+    pdf.object(
+        2,
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        )
+        .as_bytes(),
+    );
+
+    for (index, (page_id, content_id, image_id, mask_id), has_alpha) in page_objects {
+        let image = &pages[index];
+        let (width, height) = image.dimensions();
+        let page_width = width as f32 * points_per_pixel;
+        let page_height = height as f32 * points_per_pixel;
+        let unit = (page_width.max(page_height) / MAX_PAGE_SIDE)
+            .ceil()
+            .max(1.0);
+        let (page_width, page_height) = (page_width / unit, page_height / unit);
+        let user_unit = if unit > 1.0 {
+            format!(" /UserUnit {unit}")
+        } else {
+            String::new()
+        };
+        pdf.object(
+            page_id,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}]{user_unit} \
+                 /Resources << /XObject << /Im0 {image_id} 0 R >> >> /Contents {content_id} 0 R >>",
+                number(page_width),
+                number(page_height),
+            )
+            .as_bytes(),
+        );
+        let content = format!(
+            "q {} 0 0 {} 0 0 cm /Im0 Do Q",
+            number(page_width),
+            number(page_height)
+        );
+        pdf.stream(content_id, "", content.as_bytes());
+
+        let rgb: Vec<u8> = image.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect();
+        let mask = if has_alpha {
+            format!(" /SMask {mask_id} 0 R")
+        } else {
+            String::new()
+        };
+        pdf.stream(
+            image_id,
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {width} /Height {height} \
+                 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode{mask}"
+            ),
+            &deflate(&rgb),
+        );
+        if has_alpha {
+            let alpha: Vec<u8> = image.pixels().map(|p| p[3]).collect();
+            pdf.stream(
+                mask_id,
+                &format!(
+                    "/Type /XObject /Subtype /Image /Width {width} /Height {height} \
+                     /ColorSpace /DeviceGray /BitsPerComponent 8 /Filter /FlateDecode"
+                ),
+                &deflate(&alpha),
+            );
+        }
+    }
+    pdf.finish(next_id - 1)
+}
+
+/// Bytes written so far and the offset of every object, for the xref table.
+#[derive(Default)]
+struct PdfWriter {
+    bytes: Vec<u8>,
+    offsets: Vec<(u32, usize)>,
+}
+
+impl PdfWriter {
+    fn object(&mut self, id: u32, body: &[u8]) {
+        self.offsets.push((id, self.bytes.len()));
+        self.bytes
+            .extend_from_slice(format!("{id} 0 obj\n").as_bytes());
+        self.bytes.extend_from_slice(body);
+        self.bytes.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn stream(&mut self, id: u32, dictionary: &str, data: &[u8]) {
+        let separator = if dictionary.is_empty() { "" } else { " " };
+        let mut body = format!(
+            "<< {dictionary}{separator}/Length {} >>\nstream\n",
+            data.len()
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.object(id, &body);
+    }
+
+    fn finish(mut self, last_id: u32) -> Vec<u8> {
+        self.offsets.sort_unstable();
+        let xref_offset = self.bytes.len();
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", last_id + 1);
+        for (_, offset) in &self.offsets {
+            xref.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        xref.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n",
+            last_id + 1
+        ));
+        self.bytes.extend_from_slice(xref.as_bytes());
+        self.bytes
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing into a `Vec` cannot fail
+    encoder.write_all(data).expect("in-memory write");
+    encoder.finish().expect("in-memory write")
+}
+
+/// A PDF number: at most two decimals, without trailing zeros.
+fn number(value: f32) -> String {
+    let text = format!("{value:.2}");
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn text(pdf: &[u8]) -> String {
+        String::from_utf8_lossy(pdf).into_owned()
+    }
+
+    #[test]
+    fn test_write_pdf_one_page_per_image() {
+        let pages = [
+            RgbaImage::from_pixel(20, 10, Rgba([255, 0, 0, 255])),
+            RgbaImage::from_pixel(30, 15, Rgba([0, 0, 255, 255])),
+        ];
+        let pdf = write_pdf(&pages, 0.5);
+        let text = text(&pdf);
+        assert!(text.starts_with("%PDF-1.6"));
+        assert!(text.ends_with("%%EOF\n"));
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("/MediaBox [0 0 10 5]"));
+        assert!(text.contains("/MediaBox [0 0 15 7.5]"));
+        assert!(!text.contains("/SMask"));
+        assert!(!text.contains("/UserUnit"));
+    }
+
+    #[test]
+    fn test_write_pdf_xref_points_at_objects() {
+        let pages = [RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 128]))];
+        let pdf = write_pdf(&pages, 1.0);
+        // Catalog, page tree, page, content, image and mask
+        assert!(text(&pdf).contains("/Size 7"));
+        assert!(text(&pdf).contains("/SMask 6 0 R"));
+        // Offsets are in bytes, so read them from the raw file
+        let tail = text(&pdf[pdf.len() - 32..]);
+        let xref_offset: usize = tail.lines().rev().nth(1).unwrap().parse().unwrap();
+        let xref = text(&pdf[xref_offset..]);
+        assert!(xref.starts_with("xref\n0 7\n"));
+        for (id, line) in xref.lines().skip(3).take(6).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", id + 1).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_write_pdf_long_page_uses_user_unit() {
+        let pages = [RgbaImage::from_pixel(10, 20_000, Rgba([255; 4]))];
+        let text = text(&write_pdf(&pages, 1.0));
+        assert!(text.contains("/MediaBox [0 0 5 10000] /UserUnit 2"));
+        assert_eq!(write_pdf(&pages, 1.0), write_pdf(&pages, 1.0));
+    }
+}
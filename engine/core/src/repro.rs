@@ -0,0 +1,134 @@
+//! Reproduction bundles for Smart mode pairs that failed overlap matching.
+//!
+//! A bundle holds heavily downscaled grayscale proxies of the two strips the
+//! detector compared (the bottom of the top image and the top of the bottom
+//! image) together with the detection parameters. It is small enough to attach
+//! to a bug report and too coarse to read text from, so failing cases can be
+//! shared for tuning without handing over the original screenshots.
+
+use image::{DynamicImage, GrayImage, imageops::FilterType};
+
+use crate::overlap::OverlapParams;
+
+/// Width in pixels of the strip proxies.
+const PROXY_WIDTH: u32 = 96;
+/// Tallest strip (in scaled image pixels) captured from each image.
+const STRIP_MAX_HEIGHT: u32 = 480;
+
+/// Anonymized inputs and parameters of one failed pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReproBundle {
+    /// Index of the top image; the pair is `(pair_index, pair_index + 1)`.
+    pub pair_index: usize,
+    pub sensitivity: u8,
+    pub min_overlap_pixels: u32,
+    pub width_ratio_threshold: f32,
+    /// Scaled size of the top image as seen by the detector.
+    pub top_size: (u32, u32),
+    /// Scaled size of the bottom image as seen by the detector.
+    pub bottom_size: (u32, u32),
+    /// Chrome rows excluded from the bottom of the top image.
+    pub top_trim_bottom: u32,
+    /// Chrome rows excluded from the top of the bottom image.
+    pub bottom_trim_top: u32,
+    /// Grayscale PNG proxy of the bottom strip of the top image.
+    pub top_strip_png: Vec<u8>,
+    /// Grayscale PNG proxy of the top strip of the bottom image.
+    pub bottom_strip_png: Vec<u8>,
+}
+
+/// Builds the bundle for a failed pair, or `None` if a strip is empty.
+pub fn build_repro_bundle(
+    pair_index: usize,
+    img_top: &DynamicImage,
+    img_bottom: &DynamicImage,
+    params: &OverlapParams,
+    top_trim_bottom: u32,
+    bottom_trim_top: u32,
+) -> Option<ReproBundle> {
+    let top_end = img_top.height().saturating_sub(top_trim_bottom);
+    let top_strip_height = top_end.min(STRIP_MAX_HEIGHT);
+    let top_strip = strip_proxy(img_top, top_end - top_strip_height, top_strip_height)?;
+
+    let bottom_start = bottom_trim_top.min(img_bottom.height());
+    let bottom_strip_height = (img_bottom.height() - bottom_start).min(STRIP_MAX_HEIGHT);
+    let bottom_strip = strip_proxy(img_bottom, bottom_start, bottom_strip_height)?;
+
+    Some(ReproBundle {
+        pair_index,
+        sensitivity: params.sensitivity,
+        min_overlap_pixels: params.min_overlap_pixels,
+        width_ratio_threshold: params.width_ratio_threshold,
+        top_size: (img_top.width(), img_top.height()),
+        bottom_size: (img_bottom.width(), img_bottom.height()),
+        top_trim_bottom,
+        bottom_trim_top,
+        top_strip_png: encode_gray_png(&top_strip)?,
+        bottom_strip_png: encode_gray_png(&bottom_strip)?,
+    })
+}
+
+/// Crops rows `y..y + height` and shrinks them to a `PROXY_WIDTH`-wide grayscale image.
+fn strip_proxy(img: &DynamicImage, y: u32, height: u32) -> Option<GrayImage> {
+    let width = img.width();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let strip = img.crop_imm(0, y, width, height).to_luma8();
+    let proxy_width = PROXY_WIDTH.min(width);
+    let proxy_height = ((height as u64 * proxy_width as u64) / width as u64).max(1) as u32;
+    Some(image::imageops::resize(
+        &strip,
+        proxy_width,
+        proxy_height,
+        FilterType::Triangle,
+    ))
+}
+
+fn encode_gray_png(img: &GrayImage) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+    DynamicImage::ImageLuma8(img.clone())
+        .write_with_encoder(encoder)
+        .ok()?;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            let v = ((x + y) % 256) as u8;
+            Rgba([v, v, v, 255])
+        }))
+    }
+
+    #[test]
+    fn test_bundle_contains_downscaled_strips_and_params() {
+        let top = gradient(960, 1000);
+        let bottom = gradient(960, 300);
+        let params = OverlapParams::with_sensitivity(40);
+
+        let bundle = build_repro_bundle(3, &top, &bottom, &params, 20, 10).unwrap();
+        assert_eq!(bundle.pair_index, 3);
+        assert_eq!(bundle.sensitivity, 40);
+        assert_eq!(bundle.top_size, (960, 1000));
+        assert_eq!(bundle.bottom_trim_top, 10);
+
+        let top_proxy = image::load_from_memory(&bundle.top_strip_png).unwrap();
+        assert_eq!((top_proxy.width(), top_proxy.height()), (96, 48));
+        let bottom_proxy = image::load_from_memory(&bundle.bottom_strip_png).unwrap();
+        assert_eq!((bottom_proxy.width(), bottom_proxy.height()), (96, 29));
+    }
+
+    #[test]
+    fn test_bundle_skips_empty_strip() {
+        let top = gradient(100, 20);
+        let bottom = gradient(100, 20);
+        let params = OverlapParams::with_sensitivity(35);
+        assert!(build_repro_bundle(0, &top, &bottom, &params, 20, 0).is_none());
+    }
+}
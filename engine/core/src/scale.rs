@@ -0,0 +1,559 @@
+use image::{DynamicImage, ImageBuffer, Pixel, imageops::FilterType};
+
+use crate::hooks::Interrupt;
+
+/// Output rows resampled per band. Bands are independent, so with the
+/// `threads` feature they are resampled in parallel.
+const BAND_ROWS: usize = 64;
+
+/// Support of the Lanczos3 kernel, in source pixels at 1:1.
+const LANCZOS3_SUPPORT: f32 = 3.0;
+
+/// Scales an image to the specified dimensions using a deterministic filter.
+///
+/// Uses Lanczos3 filter for high-quality, deterministic resampling.
+///
+/// # Panics
+/// Panics if new_width or new_height is zero.
+pub fn scale_image(img: &DynamicImage, new_width: u32, new_height: u32) -> DynamicImage {
+    scale_image_with(
+        img,
+        new_width,
+        new_height,
+        Resampling::default(),
+        &mut Interrupt::none(),
+    )
+}
+
+/// How [`scale_image_with`] filters 8-bit color. The default filters the
+/// stored values, exactly as [`scale_image`]. Images with more than 8 bits
+/// per sample are always resampled as stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Resampling {
+    /// Decode color from sRGB to linear light before filtering and encode it
+    /// back after, so fine detail (thin text, hairlines) keeps its brightness
+    /// when downscaled instead of averaging too dark. Alpha is filtered as is.
+    pub linear: bool,
+    /// Weight color by alpha while filtering and divide it back out after,
+    /// so the color of transparent pixels (usually black) does not bleed
+    /// into anti-aliased edges as a dark fringe.
+    pub premultiplied: bool,
+}
+
+/// Like [`scale_image`], filtering color as `resampling` asks.
+///
+/// 8-bit images stop being resampled once `interrupt` fires, leaving the
+/// rest of the output blank.
+///
+/// # Panics
+/// Panics if new_width or new_height is zero.
+pub fn scale_image_with(
+    img: &DynamicImage,
+    new_width: u32,
+    new_height: u32,
+    resampling: Resampling,
+    interrupt: &mut Interrupt,
+) -> DynamicImage {
+    assert!(
+        new_width > 0 && new_height > 0,
+        "Scale dimensions must be non-zero"
+    );
+
+    let current_width = img.width();
+    let current_height = img.height();
+
+    // Skip resize if dimensions match
+    if current_width == new_width && current_height == new_height {
+        return img.clone();
+    }
+
+    match img {
+        DynamicImage::ImageLuma8(buffer) => DynamicImage::ImageLuma8(resize_lanczos3(
+            buffer, new_width, new_height, resampling, interrupt,
+        )),
+        DynamicImage::ImageLumaA8(buffer) => DynamicImage::ImageLumaA8(resize_lanczos3(
+            buffer, new_width, new_height, resampling, interrupt,
+        )),
+        DynamicImage::ImageRgb8(buffer) => DynamicImage::ImageRgb8(resize_lanczos3(
+            buffer, new_width, new_height, resampling, interrupt,
+        )),
+        DynamicImage::ImageRgba8(buffer) => DynamicImage::ImageRgba8(resize_lanczos3(
+            buffer, new_width, new_height, resampling, interrupt,
+        )),
+        _ => img.resize_exact(new_width, new_height, FilterType::Lanczos3),
+    }
+}
+
+/// Lanczos3 resize of an 8-bit image, in bands of output rows.
+///
+/// Reproduces `image::imageops::resize` bit for bit (same taps, same `f32`
+/// operation order), but resamples one output row at a time: the vertical
+/// pass for a row feeds the horizontal pass directly, so no full-size `f32`
+/// intermediate is allocated and bands can run on separate threads. The
+/// `resampling` conversions are folded into the passes; by default they are
+/// multiplications by one and identity lookups, so the output is unchanged.
+///
+/// `interrupt` is ticked after every band (with the `threads` feature, after
+/// every group of bands run in parallel), and no further band is resampled
+/// once it fires.
+fn resize_lanczos3<P: Pixel<Subpixel = u8>>(
+    src: &ImageBuffer<P, Vec<u8>>,
+    new_width: u32,
+    new_height: u32,
+    resampling: Resampling,
+    interrupt: &mut Interrupt,
+) -> ImageBuffer<P, Vec<u8>> {
+    let channels = P::CHANNEL_COUNT as usize;
+    let alpha = P::HAS_ALPHA.then_some(channels - 1);
+    let premultiply = resampling.premultiplied && alpha.is_some();
+    let is_color: Vec<bool> = (0..channels).map(|c| Some(c) != alpha).collect();
+    let linear: Vec<bool> = is_color
+        .iter()
+        .map(|&color| color && resampling.linear)
+        .collect();
+    let identity: [f32; 256] = std::array::from_fn(|v| v as f32);
+    let to_linear: [f32; 256] = std::array::from_fn(|v| srgb_to_linear(v as f32 / 255.0) * 255.0);
+    let decode: Vec<&[f32; 256]> = linear
+        .iter()
+        .map(|&linear| if linear { &to_linear } else { &identity })
+        .collect();
+    let (width, height) = src.dimensions();
+    let rows = lanczos3_taps(height, new_height);
+    let columns = lanczos3_taps(width, new_width);
+    let src_stride = width as usize * channels;
+    let out_stride = new_width as usize * channels;
+    let pixels = src.as_raw().as_slice();
+
+    let resample_band = |(band, out): (usize, &mut [u8])| {
+        let mut column = vec![0.0f32; src_stride];
+        let mut sums = vec![0.0f32; channels];
+        for (row, out_row) in out.chunks_exact_mut(out_stride).enumerate() {
+            let taps = &rows[band * BAND_ROWS + row];
+            column.fill(0.0);
+            for (i, w) in taps.weights.iter().enumerate() {
+                let start = (taps.start + i) * src_stride;
+                let src_row = &pixels[start..start + src_stride];
+                for (pixel_sums, src_pixel) in column
+                    .chunks_exact_mut(channels)
+                    .zip(src_row.chunks_exact(channels))
+                {
+                    let coverage = match alpha {
+                        Some(a) if premultiply => src_pixel[a] as f32 / 255.0,
+                        _ => 1.0,
+                    };
+                    for (c, (t, &value)) in pixel_sums.iter_mut().zip(src_pixel).enumerate() {
+                        let scale = if is_color[c] { coverage } else { 1.0 };
+                        *t += decode[c][value as usize] * scale * w;
+                    }
+                }
+            }
+            for (x, out_pixel) in out_row.chunks_exact_mut(channels).enumerate() {
+                let taps = &columns[x];
+                for (c, sum) in sums.iter_mut().enumerate() {
+                    let mut t = 0.0f32;
+                    for (i, w) in taps.weights.iter().enumerate() {
+                        t += column[(taps.start + i) * channels + c] * w;
+                    }
+                    *sum = t;
+                }
+                // Pixels stored as opaque are divided by exactly one, so
+                // opaque images come out as without premultiplication
+                let coverage = match alpha {
+                    Some(a) if premultiply && sums[a].round() < 255.0 => sums[a] / 255.0,
+                    _ => 1.0,
+                };
+                for (c, out_value) in out_pixel.iter_mut().enumerate() {
+                    let mut t = sums[c];
+                    if premultiply && is_color[c] {
+                        t = if coverage > 0.0 { t / coverage } else { 0.0 };
+                    }
+                    if linear[c] {
+                        t = linear_to_srgb(t / 255.0) * 255.0;
+                    }
+                    *out_value = t.clamp(0.0, 255.0).round() as u8;
+                }
+            }
+        }
+    };
+
+    // Multiply-adds of a band: vertical taps over source rows, then
+    // horizontal taps over output rows
+    let taps = |taps: &[Taps]| taps.iter().map(|t| t.weights.len()).max().unwrap_or(0);
+    let band_work = (BAND_ROWS * (taps(&rows) * src_stride + taps(&columns) * out_stride)) as u64;
+
+    let mut out = vec![0u8; out_stride * new_height as usize];
+    #[cfg(feature = "threads")]
+    {
+        use rayon::prelude::*;
+        let group = rayon::current_num_threads();
+        for (first, bands) in out.chunks_mut(out_stride * BAND_ROWS * group).enumerate() {
+            bands
+                .par_chunks_mut(out_stride * BAND_ROWS)
+                .enumerate()
+                .for_each(|(band, out)| resample_band((first * group + band, out)));
+            if interrupt.tick(band_work * group as u64) {
+                break;
+            }
+        }
+    }
+    #[cfg(not(feature = "threads"))]
+    for band in out.chunks_mut(out_stride * BAND_ROWS).enumerate() {
+        resample_band(band);
+        if interrupt.tick(band_work) {
+            break;
+        }
+    }
+
+    ImageBuffer::from_raw(new_width, new_height, out).expect("buffer matches dimensions")
+}
+
+/// Source pixels and normalized weights contributing to one output pixel.
+struct Taps {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Lanczos3 taps for every output position along one axis, computed exactly
+/// as `image::imageops::resize` does.
+fn lanczos3_taps(src_len: u32, dst_len: u32) -> Vec<Taps> {
+    let ratio = src_len as f32 / dst_len as f32;
+    let sratio = if ratio < 1.0 { 1.0 } else { ratio };
+    let src_support = LANCZOS3_SUPPORT * sratio;
+
+    (0..dst_len)
+        .map(|out| {
+            let center = (out as f32 + 0.5) * ratio;
+            let left = ((center - src_support).floor() as i64).clamp(0, src_len as i64 - 1);
+            let right = ((center + src_support).ceil() as i64).clamp(left + 1, src_len as i64);
+            let center = center - 0.5;
+
+            let mut weights: Vec<f32> = (left..right)
+                .map(|i| lanczos3((i as f32 - center) / sratio))
+                .collect();
+            let sum: f32 = weights.iter().fold(0.0, |sum, w| sum + w);
+            for w in &mut weights {
+                *w /= sum;
+            }
+            Taps {
+                start: left as usize,
+                weights,
+            }
+        })
+        .collect()
+}
+
+/// sRGB transfer function, inverted: encoded `[0, 1]` to linear light.
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// sRGB transfer function: linear light to encoded `[0, 1]`.
+fn linear_to_srgb(v: f32) -> f32 {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn lanczos3(x: f32) -> f32 {
+    if x.abs() < LANCZOS3_SUPPORT {
+        sinc(x) * sinc(x / LANCZOS3_SUPPORT)
+    } else {
+        0.0
+    }
+}
+
+fn sinc(t: f32) -> f32 {
+    let a = t * std::f32::consts::PI;
+    if t == 0.0 { 1.0 } else { a.sin() / a }
+}
+
+/// Scales an image with nearest-neighbor sampling, keeping hard pixel edges.
+///
+/// # Panics
+/// Panics if new_width or new_height is zero.
+pub fn scale_image_nearest(img: &DynamicImage, new_width: u32, new_height: u32) -> DynamicImage {
+    assert!(
+        new_width > 0 && new_height > 0,
+        "Scale dimensions must be non-zero"
+    );
+
+    if img.width() == new_width && img.height() == new_height {
+        return img.clone();
+    }
+
+    img.resize_exact(new_width, new_height, FilterType::Nearest)
+}
+
+/// Whether `data` is a PNG with indexed color or fewer than 8 bits per
+/// sample (e.g. 1-bit scanner output), read from its IHDR chunk.
+pub fn is_low_color_png(data: &[u8]) -> bool {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const COLOR_TYPE_INDEXED: u8 = 3;
+    if data.len() < 26 || !data.starts_with(SIGNATURE) || &data[12..16] != b"IHDR" {
+        return false;
+    }
+    let bit_depth = data[24];
+    let color_type = data[25];
+    color_type == COLOR_TYPE_INDEXED || bit_depth < 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn noise_rgba(width: u32, height: u32) -> image::RgbaImage {
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let h = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).rotate_left(11);
+            Rgba([
+                h as u8,
+                (h >> 8) as u8,
+                (h >> 16) as u8,
+                (h >> 24) as u8 | 0x80,
+            ])
+        })
+    }
+
+    #[test]
+    fn test_banded_resize_matches_image_crate() {
+        let src = noise_rgba(97, 150);
+        // Down, up, one axis only, and more output rows than one band
+        for (w, h) in [(40, 61), (211, 330), (97, 75), (50, 150)] {
+            let expected = image::imageops::resize(&src, w, h, FilterType::Lanczos3);
+            assert_eq!(
+                resize_lanczos3(&src, w, h, Resampling::default(), &mut Interrupt::none()),
+                expected,
+                "{w}x{h}"
+            );
+        }
+
+        let rgb = DynamicImage::ImageRgba8(src).to_rgb8();
+        let expected = image::imageops::resize(&rgb, 130, 201, FilterType::Lanczos3);
+        assert_eq!(
+            resize_lanczos3(
+                &rgb,
+                130,
+                201,
+                Resampling::default(),
+                &mut Interrupt::none()
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_scale_image_basic() {
+        // Create a 10x10 red image
+        let mut img = DynamicImage::new_rgba8(10, 10);
+        if let Some(rgba) = img.as_mut_rgba8() {
+            for pixel in rgba.pixels_mut() {
+                *pixel = Rgba([255, 0, 0, 255]);
+            }
+        }
+
+        let scaled = scale_image(&img, 20, 20);
+        assert_eq!(scaled.width(), 20);
+        assert_eq!(scaled.height(), 20);
+    }
+
+    #[test]
+    fn test_scale_image_downscale() {
+        let img = DynamicImage::new_rgba8(100, 100);
+        let scaled = scale_image(&img, 50, 50);
+        assert_eq!(scaled.width(), 50);
+        assert_eq!(scaled.height(), 50);
+    }
+
+    #[test]
+    fn test_scale_image_no_change() {
+        let img = DynamicImage::new_rgba8(100, 200);
+        let scaled = scale_image(&img, 100, 200);
+        assert_eq!(scaled.width(), 100);
+        assert_eq!(scaled.height(), 200);
+    }
+
+    #[test]
+    fn test_scale_image_aspect_change() {
+        // This is resize_exact, so aspect ratio can change
+        let img = DynamicImage::new_rgba8(100, 100);
+        let scaled = scale_image(&img, 200, 100);
+        assert_eq!(scaled.width(), 200);
+        assert_eq!(scaled.height(), 100);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_scale_image_zero_dimensions() {
+        let img = DynamicImage::new_rgba8(100, 100);
+        scale_image(&img, 0, 100);
+    }
+
+    fn scale_image_linear(img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        let linear = Resampling {
+            linear: true,
+            ..Default::default()
+        };
+        scale_image_with(img, width, height, linear, &mut Interrupt::none())
+    }
+
+    #[test]
+    fn test_scale_image_linear_averages_in_linear_light() {
+        // Black and white pixels averaged to one: 50% linear light is sRGB
+        // 188, where averaging the stored values gives a too dark 128.
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(2, 1, |x, _| {
+            let v = if x == 0 { 0 } else { 255 };
+            Rgba([v, v, v, 255])
+        }));
+        assert_eq!(
+            scale_image(&img, 1, 1).to_rgba8().get_pixel(0, 0),
+            &Rgba([128, 128, 128, 255])
+        );
+        assert_eq!(
+            scale_image_linear(&img, 1, 1).to_rgba8().get_pixel(0, 0),
+            &Rgba([188, 188, 188, 255])
+        );
+
+        // One-pixel black-on-white stripes keep their mean brightness
+        let stripes = DynamicImage::ImageLuma8(image::GrayImage::from_fn(64, 8, |x, _| {
+            image::Luma([if x % 2 == 0 { 0 } else { 255 }])
+        }));
+        let scaled = scale_image_linear(&stripes, 16, 2).to_luma8();
+        for pixel in scaled.pixels().skip(2).take(12) {
+            assert!((186..=190).contains(&pixel[0]), "{pixel:?}");
+        }
+    }
+
+    #[test]
+    fn test_scale_image_linear_keeps_flat_colors_and_alpha() {
+        // Every gray level round-trips through linear light unchanged
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(256, 4, |x, _| {
+            Rgba([x as u8, x as u8, x as u8, 255 - x as u8])
+        }));
+        let scaled = scale_image_linear(&img, 256, 2).to_rgba8();
+        for (x, _, pixel) in scaled.enumerate_pixels().filter(|(_, y, _)| *y == 0) {
+            let v = x as u8;
+            assert_eq!(pixel, &Rgba([v, v, v, 255 - v]), "{x}");
+        }
+
+        // Alpha is filtered exactly as in sRGB resampling
+        let src = noise_rgba(40, 30);
+        let alpha = |img: DynamicImage| img.to_rgba8().pixels().map(|p| p[3]).collect::<Vec<_>>();
+        let src = DynamicImage::ImageRgba8(src);
+        assert_eq!(
+            alpha(scale_image_linear(&src, 17, 11)),
+            alpha(scale_image(&src, 17, 11))
+        );
+    }
+
+    #[test]
+    fn test_scale_image_premultiplied_avoids_dark_fringes() {
+        // Opaque red next to transparent black, averaged to one pixel
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        }));
+        let premultiplied = Resampling {
+            premultiplied: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            scale_image(&img, 1, 1).to_rgba8().get_pixel(0, 0),
+            &Rgba([128, 0, 0, 128])
+        );
+        assert_eq!(
+            scale_image_with(&img, 1, 1, premultiplied, &mut Interrupt::none())
+                .to_rgba8()
+                .get_pixel(0, 0),
+            &Rgba([255, 0, 0, 128])
+        );
+
+        // Both together: coverage-weighted in linear light
+        let both = Resampling {
+            linear: true,
+            premultiplied: true,
+        };
+        assert_eq!(
+            scale_image_with(&img, 1, 1, both, &mut Interrupt::none())
+                .to_rgba8()
+                .get_pixel(0, 0),
+            &Rgba([255, 0, 0, 128])
+        );
+    }
+
+    #[test]
+    fn test_scale_image_premultiplied_matches_plain_for_opaque_images() {
+        let src = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(53, 41, |x, y| {
+            let h = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).rotate_left(7);
+            Rgba([h as u8, (h >> 8) as u8, (h >> 16) as u8, 255])
+        }));
+        let premultiplied = Resampling {
+            premultiplied: true,
+            ..Default::default()
+        };
+        for (w, h) in [(20, 17), (120, 90)] {
+            assert_eq!(
+                scale_image_with(&src, w, h, premultiplied, &mut Interrupt::none()),
+                scale_image(&src, w, h),
+                "{w}x{h}"
+            );
+        }
+
+        // Images without alpha are unaffected
+        let rgb = DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(noise_rgba(30, 30)).to_rgb8());
+        assert_eq!(
+            scale_image_with(&rgb, 12, 12, premultiplied, &mut Interrupt::none()),
+            scale_image(&rgb, 12, 12)
+        );
+    }
+
+    #[test]
+    fn test_scale_image_nearest_keeps_hard_edges() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }));
+        let scaled = scale_image_nearest(&img, 8, 4).to_rgba8();
+        assert!(
+            scaled
+                .pixels()
+                .all(|p| *p == Rgba([0, 0, 0, 255]) || *p == Rgba([255, 255, 255, 255]))
+        );
+    }
+
+    #[test]
+    fn test_is_low_color_png() {
+        let encode = |img: DynamicImage| {
+            let mut bytes = Vec::new();
+            img.write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+            bytes
+        };
+        assert!(!is_low_color_png(&encode(DynamicImage::new_rgba8(4, 4))));
+        assert!(!is_low_color_png(&encode(DynamicImage::new_luma8(4, 4))));
+
+        let mut one_bit = encode(DynamicImage::new_luma8(4, 4));
+        one_bit[24] = 1;
+        assert!(is_low_color_png(&one_bit));
+        let mut indexed = encode(DynamicImage::new_rgb8(4, 4));
+        indexed[24] = 8;
+        indexed[25] = 3;
+        assert!(is_low_color_png(&indexed));
+        assert!(!is_low_color_png(b"not a png"));
+    }
+}
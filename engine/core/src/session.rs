@@ -0,0 +1,289 @@
+//! Inputs decoded once and kept across merges.
+//!
+//! Editors re-merge after every change — an option tweak, one more
+//! screenshot, a new order. A session keeps each image's file bytes and its
+//! decoded, orientation-normalized pixels, so only newly added images are
+//! decoded.
+
+use image::{DynamicImage, GenericImageView};
+
+use crate::error::MergeError;
+use crate::merge::{
+    check_animated_inputs, check_input_size, decode_input, flattens_animation, is_permutation,
+    merge_decoded_inputs,
+};
+use crate::types::{ImageInput, InputSource, MergeOptions, MergeOutput};
+
+/// An ordered list of decoded inputs.
+#[derive(Default)]
+pub struct Session {
+    /// Original file bytes of every encoded image, read for per-image facts
+    /// at merge time; `None` for raw RGBA inputs.
+    files: Vec<Option<Vec<u8>>>,
+    /// Decoded images, parallel to `files`.
+    decoded: Vec<DynamicImage>,
+    /// Whether each image is an animation decoded at its first frame because
+    /// no `frame` was chosen, for `strict` merges.
+    flattened: Vec<bool>,
+}
+
+impl Session {
+    /// Number of images in the session.
+    pub fn len(&self) -> usize {
+        self.decoded.len()
+    }
+
+    /// Whether the session has no images.
+    pub fn is_empty(&self) -> bool {
+        self.decoded.is_empty()
+    }
+
+    /// Decodes `input` and appends the image, returning its index.
+    pub fn add_image(&mut self, input: impl Into<ImageInput>) -> Result<usize, MergeError> {
+        let index = self.len();
+        let input = input.into();
+        let decoded = decode_input(&input).map_err(|message| MergeError::DecodeError {
+            index,
+            file_name: input.name.clone(),
+            message,
+        })?;
+        self.flattened.push(flattens_animation(&input));
+        self.files.push(match input.source {
+            InputSource::Encoded(bytes) => Some(bytes),
+            InputSource::Rgba { .. } => None,
+        });
+        self.decoded.push(decoded);
+        Ok(index)
+    }
+
+    /// Removes the image at `index`; `false` if there is none.
+    pub fn remove_image(&mut self, index: usize) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+        self.files.remove(index);
+        self.decoded.remove(index);
+        self.flattened.remove(index);
+        true
+    }
+
+    /// Moves the image at `order[i]` to position `i`; `false`, changing
+    /// nothing, unless `order` is a permutation of the current indices.
+    pub fn reorder(&mut self, order: &[usize]) -> bool {
+        if !is_permutation(order, self.len()) {
+            return false;
+        }
+        let mut entries: Vec<_> = self
+            .files
+            .drain(..)
+            .zip(self.decoded.drain(..))
+            .zip(self.flattened.drain(..))
+            .map(Some)
+            .collect();
+        ((self.files, self.decoded), self.flattened) = order
+            .iter()
+            .map(|&i| entries[i].take().expect("order is a permutation"))
+            .unzip();
+        true
+    }
+
+    /// Merges the session's images in their current order, or in
+    /// `options.order` when set.
+    ///
+    /// Images are decoded when added, before any options are known, so
+    /// `max_input_pixels` and `max_input_dimension` can only refuse them here.
+    pub fn merge(&self, mut options: MergeOptions) -> Result<MergeOutput, MergeError> {
+        let order: Vec<usize> = match options.order.take() {
+            Some(order) if !is_permutation(&order, self.len()) => {
+                return Err(MergeError::InvalidOrder { count: self.len() });
+            }
+            Some(order) => {
+                let mut names = std::mem::take(&mut options.file_names);
+                names.resize(self.len(), None);
+                options.file_names = order.iter().map(|&i| names[i].take()).collect();
+                order
+            }
+            None => (0..self.len()).collect(),
+        };
+        check_animated_inputs(order.iter().map(|&i| self.flattened[i]), &options)?;
+        for (index, &i) in order.iter().enumerate() {
+            let name = options.file_names.get(index).and_then(Option::as_deref);
+            check_input_size(index, name, self.decoded[i].dimensions(), &options)?;
+        }
+        let decoded: Vec<_> = order.iter().map(|&i| &self.decoded[i]).collect();
+        let files: Vec<_> = order.iter().map(|&i| self.files[i].as_deref()).collect();
+        merge_decoded_inputs(&decoded, &files, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::merge_with_metadata;
+    use crate::types::{Direction, TrimMargins};
+    use image::{Rgba, RgbaImage};
+
+    fn create_test_png(width: u32, height: u32, color: Rgba<u8>) -> Vec<u8> {
+        let img = RgbaImage::from_pixel(width, height, color);
+        let mut bytes = Vec::new();
+        let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+        DynamicImage::ImageRgba8(img)
+            .write_with_encoder(encoder)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_session_matches_one_shot_merge() {
+        let red = create_test_png(20, 10, Rgba([255, 0, 0, 255]));
+        let green = create_test_png(20, 30, Rgba([0, 255, 0, 255]));
+        let blue = create_test_png(10, 10, Rgba([0, 0, 255, 255]));
+        let options = MergeOptions {
+            direction: Direction::Vertical,
+            ..Default::default()
+        };
+
+        let mut session = Session::default();
+        assert_eq!(session.merge(options.clone()), Err(MergeError::NoImages));
+        assert_eq!(session.add_image(red.clone()), Ok(0));
+        assert_eq!(session.add_image(green.clone()), Ok(1));
+        assert_eq!(session.add_image(blue.clone()), Ok(2));
+        assert!(session.remove_image(1));
+        assert!(!session.remove_image(2));
+        assert!(session.reorder(&[1, 0]));
+
+        let expected = merge_with_metadata(vec![blue, red], options.clone()).unwrap();
+        assert_eq!(session.merge(options).unwrap().bytes, expected.bytes);
+    }
+
+    #[test]
+    fn test_session_strict_refuses_flattened_animation() {
+        let mut animation = Vec::new();
+        let mut encoder = image::codecs::gif::GifEncoder::new(&mut animation);
+        encoder
+            .encode_frames(
+                [Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255])]
+                    .map(|color| image::Frame::new(RgbaImage::from_pixel(8, 8, color))),
+            )
+            .unwrap();
+        drop(encoder);
+        let strict = MergeOptions {
+            strict: true,
+            ..Default::default()
+        };
+
+        let mut session = Session::default();
+        session
+            .add_image(create_test_png(8, 8, Rgba([0, 255, 0, 255])))
+            .unwrap();
+        session.add_image(animation.clone()).unwrap();
+        assert!(session.merge(MergeOptions::default()).is_ok());
+        let reordered = MergeOptions {
+            order: Some(vec![1, 0]),
+            ..strict.clone()
+        };
+        assert!(matches!(
+            session.merge(reordered),
+            Err(MergeError::StrictViolation { index: 0, .. })
+        ));
+
+        // Choosing a frame, even the first, satisfies strict mode
+        session.remove_image(1);
+        session
+            .add_image(ImageInput {
+                frame: Some(0),
+                ..animation.into()
+            })
+            .unwrap();
+        assert!(session.merge(strict).is_ok());
+    }
+
+    #[test]
+    fn test_session_checks_input_size_at_merge() {
+        let mut session = Session::default();
+        session
+            .add_image(create_test_png(10, 10, Rgba([255, 0, 0, 255])))
+            .unwrap();
+        session
+            .add_image(create_test_png(40, 10, Rgba([0, 0, 255, 255])))
+            .unwrap();
+        let options = MergeOptions {
+            max_input_dimension: Some(20),
+            order: Some(vec![1, 0]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            session.merge(options),
+            Err(MergeError::InputTooLarge {
+                index: 0,
+                width: 40,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_session_rejects_bad_input() {
+        let red = create_test_png(10, 10, Rgba([255, 0, 0, 255]));
+        let mut session = Session::default();
+        session.add_image(red).unwrap();
+        assert!(matches!(
+            session.add_image(vec![1, 2, 3]),
+            Err(MergeError::DecodeError { index: 1, .. })
+        ));
+        assert_eq!(session.len(), 1);
+        assert!(!session.reorder(&[0, 0]));
+        assert!(!session.reorder(&[1]));
+        assert!(!session.reorder(&[]));
+        assert!(session.reorder(&[0]));
+    }
+
+    #[test]
+    fn test_session_merge_rejects_invalid_order() {
+        let mut session = Session::default();
+        for _ in 0..2 {
+            session
+                .add_image(create_test_png(10, 10, Rgba([255, 0, 0, 255])))
+                .unwrap();
+        }
+        for order in [vec![0], vec![0, 0], vec![0, 2], vec![1, 0, 2]] {
+            let options = MergeOptions {
+                order: Some(order),
+                ..Default::default()
+            };
+            assert_eq!(
+                session.merge(options),
+                Err(MergeError::InvalidOrder { count: 2 })
+            );
+        }
+    }
+
+    #[test]
+    fn test_session_order_and_trim_match_one_shot_merge() {
+        let mut framed = RgbaImage::from_pixel(30, 30, Rgba([255, 255, 255, 255]));
+        for x in 10..20 {
+            for y in 5..25 {
+                framed.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            }
+        }
+        let mut framed_png = Vec::new();
+        DynamicImage::ImageRgba8(framed)
+            .write_with_encoder(image::codecs::png::PngEncoder::new(&mut framed_png))
+            .unwrap();
+        let blue = create_test_png(10, 10, Rgba([0, 0, 255, 255]));
+        let options = MergeOptions {
+            direction: Direction::Horizontal,
+            order: Some(vec![1, 0]),
+            trim_margins: Some(TrimMargins::default()),
+            ..Default::default()
+        };
+
+        let mut session = Session::default();
+        session.add_image(framed_png.clone()).unwrap();
+        session.add_image(blue.clone()).unwrap();
+        let expected = merge_with_metadata(vec![framed_png, blue], options.clone()).unwrap();
+        let output = session.merge(options).unwrap();
+        assert_eq!(output.bytes, expected.bytes);
+        assert_eq!((output.width, output.height), (30, 20));
+    }
+}
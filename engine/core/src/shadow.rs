@@ -0,0 +1,291 @@
+//! Soft drop-shadow rasterizer.
+//!
+//! Shadows are rendered from a coverage mask of the shadow caster, softened
+//! with three box-blur passes (a cheap Gaussian approximation), then blended
+//! onto the canvas in the shadow color.
+
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::error::MergeError;
+use crate::types::{BackgroundColor, Shadow};
+
+/// Coverage mask of a framed image: the border ring is solid and the interior
+/// follows the image's alpha channel (so rounded corners cast rounded shadows).
+pub fn frame_mask(img: &RgbaImage, border_width: u32) -> Result<GrayImage, MergeError> {
+    let width = grown(img.width(), border_width).ok_or_else(too_large)?;
+    let height = grown(img.height(), border_width).ok_or_else(too_large)?;
+    let mut mask = GrayImage::from_pixel(width, height, Luma([255]));
+    for (x, y, pixel) in img.enumerate_pixels() {
+        mask.put_pixel(x + border_width, y + border_width, Luma([pixel[3]]));
+    }
+    Ok(mask)
+}
+
+/// Memory used to draw the shadow of a `caster`-sized frame on a `canvas`:
+/// its mask plus the two float buffers alive during each blur pass.
+pub fn shadow_scratch_bytes(caster: (u32, u32), canvas: (u64, u64), blur_radius: u32) -> u64 {
+    let margin = blur_margin(clamp_radius(blur_radius, canvas)) as u64;
+    let (width, height) = (caster.0 as u64, caster.1 as u64);
+    let blurred = (width + margin * 2).saturating_mul(height + margin * 2);
+    (width * height).saturating_add(blurred.saturating_mul(8))
+}
+
+/// Blurs `mask` and blends it onto `canvas` in the shadow color.
+///
+/// `(x, y)` is where the caster's top-left corner sits on the canvas; the
+/// shadow is shifted by the configured offset and clipped to the canvas.
+pub fn render_shadow(
+    canvas: &mut RgbaImage,
+    mask: &GrayImage,
+    x: i64,
+    y: i64,
+    shadow: &Shadow,
+) -> Result<(), MergeError> {
+    let canvas_size = (canvas.width() as u64, canvas.height() as u64);
+    let (blurred, margin) = blur_mask(mask, clamp_radius(shadow.blur_radius, canvas_size))?;
+    let (blurred_width, blurred_height) = blurred.dimensions();
+
+    let origin_x = x + shadow.offset_x as i64 - margin as i64;
+    let origin_y = y + shadow.offset_y as i64 - margin as i64;
+    let color_alpha = shadow.color.a as f32 / 255.0;
+
+    for by in 0..blurred_height {
+        let dest_y = origin_y + by as i64;
+        if dest_y < 0 || dest_y >= canvas.height() as i64 {
+            continue;
+        }
+        for bx in 0..blurred_width {
+            let dest_x = origin_x + bx as i64;
+            if dest_x < 0 || dest_x >= canvas.width() as i64 {
+                continue;
+            }
+            let alpha = blurred.get_pixel(bx, by)[0] * color_alpha;
+            if alpha <= 0.0 {
+                continue;
+            }
+            let dest = canvas.get_pixel_mut(dest_x as u32, dest_y as u32);
+            *dest = blend_over(*dest, &shadow.color, alpha.min(1.0));
+        }
+    }
+    Ok(())
+}
+
+/// Source-over blend of a solid color with coverage `alpha` onto `dest`.
+pub(crate) fn blend_over(dest: Rgba<u8>, color: &BackgroundColor, alpha: f32) -> Rgba<u8> {
+    let dest_alpha = dest[3] as f32 / 255.0;
+    let out_alpha = alpha + dest_alpha * (1.0 - alpha);
+    if out_alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |src: u8, dst: u8| -> u8 {
+        let value = (src as f32 * alpha + dst as f32 * dest_alpha * (1.0 - alpha)) / out_alpha;
+        value.round().clamp(0.0, 255.0) as u8
+    };
+    Rgba([
+        channel(color.r, dest[0]),
+        channel(color.g, dest[1]),
+        channel(color.b, dest[2]),
+        (out_alpha * 255.0).round() as u8,
+    ])
+}
+
+/// A blur wider than the canvas spreads the shadow almost entirely off it,
+/// so the radius is clamped to the canvas size to bound the blur buffer.
+fn clamp_radius(blur_radius: u32, (width, height): (u64, u64)) -> u32 {
+    (blur_radius as u64).min(width.max(height)) as u32
+}
+
+/// How far three box passes spread a blur of `blur_radius`.
+fn blur_margin(blur_radius: u32) -> u32 {
+    // Three box passes of radius k spread coverage by up to 3k pixels.
+    blur_radius.div_ceil(3).saturating_mul(3)
+}
+
+/// `size` grown by `margin` on both sides, or `None` past `u32::MAX`.
+fn grown(size: u32, margin: u32) -> Option<u32> {
+    margin
+        .checked_mul(2)
+        .and_then(|both| size.checked_add(both))
+}
+
+fn too_large() -> MergeError {
+    MergeError::EncodeError {
+        message: "Output dimensions exceed supported size".to_string(),
+    }
+}
+
+/// Blurs a coverage mask into a float buffer (0.0-1.0) grown by a margin on
+/// every side so the soft edge is not clipped. Returns the buffer and margin.
+fn blur_mask(mask: &GrayImage, blur_radius: u32) -> Result<(ImageF32, u32), MergeError> {
+    let box_radius = blur_radius.div_ceil(3);
+    let margin = blur_margin(blur_radius);
+    let width = grown(mask.width(), margin).ok_or_else(too_large)?;
+    let height = grown(mask.height(), margin).ok_or_else(too_large)?;
+
+    let mut buffer = ImageF32::new(width, height);
+    for (x, y, pixel) in mask.enumerate_pixels() {
+        buffer.set(x + margin, y + margin, pixel[0] as f32 / 255.0);
+    }
+
+    if box_radius > 0 {
+        for _ in 0..3 {
+            buffer = box_blur_horizontal(&buffer, box_radius);
+            buffer = box_blur_vertical(&buffer, box_radius);
+        }
+    }
+
+    Ok((buffer, margin))
+}
+
+/// Minimal single-channel float image used as the blur working buffer.
+struct ImageF32 {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl ImageF32 {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0.0; width as usize * height as usize],
+        }
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn get(&self, x: u32, y: u32) -> f32 {
+        self.data[y as usize * self.width as usize + x as usize]
+    }
+
+    fn set(&mut self, x: u32, y: u32, value: f32) {
+        self.data[y as usize * self.width as usize + x as usize] = value;
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> [f32; 1] {
+        [self.get(x, y)]
+    }
+}
+
+fn box_blur_horizontal(src: &ImageF32, radius: u32) -> ImageF32 {
+    let mut dest = ImageF32::new(src.width, src.height);
+    let window = (radius * 2 + 1) as f32;
+    let r = radius as i64;
+    for y in 0..src.height {
+        let sample = |x: i64| -> f32 {
+            if x < 0 || x >= src.width as i64 {
+                0.0
+            } else {
+                src.get(x as u32, y)
+            }
+        };
+        let mut sum: f32 = (-r..=r).map(sample).sum();
+        for x in 0..src.width as i64 {
+            dest.set(x as u32, y, sum / window);
+            sum += sample(x + r + 1) - sample(x - r);
+        }
+    }
+    dest
+}
+
+fn box_blur_vertical(src: &ImageF32, radius: u32) -> ImageF32 {
+    let mut dest = ImageF32::new(src.width, src.height);
+    let window = (radius * 2 + 1) as f32;
+    let r = radius as i64;
+    for x in 0..src.width {
+        let sample = |y: i64| -> f32 {
+            if y < 0 || y >= src.height as i64 {
+                0.0
+            } else {
+                src.get(x, y as u32)
+            }
+        };
+        let mut sum: f32 = (-r..=r).map(sample).sum();
+        for y in 0..src.height as i64 {
+            dest.set(x, y as u32, sum / window);
+            sum += sample(y + r + 1) - sample(y - r);
+        }
+    }
+    dest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn black_shadow(blur_radius: u32, offset_x: i32, offset_y: i32) -> Shadow {
+        Shadow {
+            blur_radius,
+            offset_x,
+            offset_y,
+            color: BackgroundColor::black(),
+        }
+    }
+
+    #[test]
+    fn test_frame_mask_includes_border_and_alpha() {
+        let mut img = RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 255]));
+        img.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+        let mask = frame_mask(&img, 2).unwrap();
+        assert_eq!(mask.dimensions(), (8, 8));
+        assert_eq!(mask.get_pixel(0, 0)[0], 255);
+        assert_eq!(mask.get_pixel(2, 2)[0], 0);
+        assert_eq!(mask.get_pixel(3, 3)[0], 255);
+    }
+
+    #[test]
+    fn test_sharp_shadow_is_offset_copy() {
+        let mut canvas = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        let mask = GrayImage::from_pixel(2, 2, Luma([255]));
+        render_shadow(&mut canvas, &mask, 1, 1, &black_shadow(0, 3, 3)).unwrap();
+
+        assert_eq!(*canvas.get_pixel(4, 4), Rgba([0, 0, 0, 255]));
+        assert_eq!(*canvas.get_pixel(5, 5), Rgba([0, 0, 0, 255]));
+        assert_eq!(*canvas.get_pixel(1, 1), Rgba([255, 255, 255, 255]));
+        assert_eq!(*canvas.get_pixel(6, 6), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_blurred_shadow_fades_out() {
+        let mut canvas = RgbaImage::from_pixel(40, 40, Rgba([255, 255, 255, 255]));
+        let mask = GrayImage::from_pixel(10, 10, Luma([255]));
+        render_shadow(&mut canvas, &mask, 15, 15, &black_shadow(6, 0, 0)).unwrap();
+
+        let center = canvas.get_pixel(20, 20)[0];
+        let edge = canvas.get_pixel(15, 20)[0];
+        let outside = canvas.get_pixel(12, 20)[0];
+        let far = canvas.get_pixel(0, 0)[0];
+        assert!(center < edge && edge < outside && outside < far);
+        assert_eq!(far, 255);
+    }
+
+    #[test]
+    fn test_shadow_clips_to_canvas() {
+        let mut canvas = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        let mask = GrayImage::from_pixel(4, 4, Luma([255]));
+        render_shadow(&mut canvas, &mask, 0, 0, &black_shadow(2, -3, 5)).unwrap();
+        // No panic; the offset pushes the shadow mostly off-canvas.
+        assert_eq!(*canvas.get_pixel(3, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_huge_blur_radius_is_clamped_to_canvas() {
+        let mut canvas = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        let mask = GrayImage::from_pixel(10, 10, Luma([255]));
+        render_shadow(&mut canvas, &mask, 0, 0, &black_shadow(u32::MAX, 0, 0)).unwrap();
+        // Clamped to 10 px, the blur grows a 10x10 mask to a 34x34 buffer
+        assert_eq!(
+            shadow_scratch_bytes((10, 10), (10, 10), u32::MAX),
+            100 + 34 * 34 * 8
+        );
+    }
+
+    #[test]
+    fn test_frame_mask_rejects_overflowing_border() {
+        let img = RgbaImage::new(1, 1);
+        assert!(frame_mask(&img, u32::MAX).is_err());
+    }
+}
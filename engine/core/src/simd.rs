@@ -0,0 +1,153 @@
+//! Byte-slice kernels for the Smart merge hot loops.
+//!
+//! WASM builds with the `simd128` target feature (see AGENTS.md) process 16
+//! pixels per instruction; every other build, native tests included, uses
+//! the scalar loops. Both give identical results. The app also ships a build
+//! without `simd128` for browsers lacking WebAssembly SIMD.
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+pub use wasm::{abs_diff, dot, sum_and_squares};
+
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+pub use scalar::{abs_diff, dot, sum_and_squares};
+
+mod scalar {
+    /// Sum of `a[i] * b[i]`; the slices must have the same length.
+    pub fn dot(a: &[u8], b: &[u8]) -> u64 {
+        assert_eq!(a.len(), b.len());
+        a.iter().zip(b).map(|(&x, &y)| x as u64 * y as u64).sum()
+    }
+
+    /// Sum and sum of squares of `values`.
+    pub fn sum_and_squares(values: &[u8]) -> (u64, u64) {
+        values.iter().fold((0, 0), |(sum, squares), &v| {
+            (sum + v as u64, squares + v as u64 * v as u64)
+        })
+    }
+
+    /// Writes `|a[i] - b[i]|` to `out`; all slices must have the same length.
+    pub fn abs_diff(a: &[u8], b: &[u8], out: &mut [u8]) {
+        assert!(a.len() == b.len() && a.len() == out.len());
+        for ((o, &x), &y) in out.iter_mut().zip(a).zip(b) {
+            *o = x.abs_diff(y);
+        }
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod wasm {
+    use core::arch::wasm32::*;
+
+    use super::scalar;
+
+    const LANES: usize = 16;
+    /// Vectors summed before the 32-bit lanes are flushed; each vector adds
+    /// at most 2 * 2 * 255² per lane, so 4096 of them stay below `u32::MAX`.
+    const FLUSH_VECTORS: usize = 4096;
+
+    fn load(chunk: &[u8]) -> v128 {
+        debug_assert_eq!(chunk.len(), LANES);
+        // SAFETY: `chunk` holds 16 bytes and `v128_load` allows unaligned reads
+        unsafe { v128_load(chunk.as_ptr() as *const v128) }
+    }
+
+    fn lane_sum(v: v128) -> u64 {
+        u32x4_extract_lane::<0>(v) as u64
+            + u32x4_extract_lane::<1>(v) as u64
+            + u32x4_extract_lane::<2>(v) as u64
+            + u32x4_extract_lane::<3>(v) as u64
+    }
+
+    /// Per-lane `a * b` products, pairwise summed into four 32-bit lanes.
+    fn products(a: v128, b: v128) -> v128 {
+        i32x4_add(
+            u32x4_extadd_pairwise_u16x8(u16x8_extmul_low_u8x16(a, b)),
+            u32x4_extadd_pairwise_u16x8(u16x8_extmul_high_u8x16(a, b)),
+        )
+    }
+
+    pub fn dot(a: &[u8], b: &[u8]) -> u64 {
+        assert_eq!(a.len(), b.len());
+        let split = a.len() - a.len() % LANES;
+        let mut total = scalar::dot(&a[split..], &b[split..]);
+        let block = LANES * FLUSH_VECTORS;
+        for (block_a, block_b) in a[..split].chunks(block).zip(b[..split].chunks(block)) {
+            let mut acc = u32x4_splat(0);
+            for (va, vb) in block_a.chunks_exact(LANES).zip(block_b.chunks_exact(LANES)) {
+                acc = i32x4_add(acc, products(load(va), load(vb)));
+            }
+            total += lane_sum(acc);
+        }
+        total
+    }
+
+    pub fn sum_and_squares(values: &[u8]) -> (u64, u64) {
+        let split = values.len() - values.len() % LANES;
+        let (mut sum, mut squares) = scalar::sum_and_squares(&values[split..]);
+        for block in values[..split].chunks(LANES * FLUSH_VECTORS) {
+            let mut sum_acc = u32x4_splat(0);
+            let mut squares_acc = u32x4_splat(0);
+            for chunk in block.chunks_exact(LANES) {
+                let v = load(chunk);
+                let pairs = u16x8_extadd_pairwise_u8x16(v);
+                sum_acc = i32x4_add(sum_acc, u32x4_extadd_pairwise_u16x8(pairs));
+                squares_acc = i32x4_add(squares_acc, products(v, v));
+            }
+            sum += lane_sum(sum_acc);
+            squares += lane_sum(squares_acc);
+        }
+        (sum, squares)
+    }
+
+    pub fn abs_diff(a: &[u8], b: &[u8], out: &mut [u8]) {
+        assert!(a.len() == b.len() && a.len() == out.len());
+        let split = a.len() - a.len() % LANES;
+        scalar::abs_diff(&a[split..], &b[split..], &mut out[split..]);
+        for ((va, vb), o) in a[..split]
+            .chunks_exact(LANES)
+            .zip(b[..split].chunks_exact(LANES))
+            .zip(out[..split].chunks_exact_mut(LANES))
+        {
+            let (va, vb) = (load(va), load(vb));
+            let diff = v128_or(u8x16_sub_sat(va, vb), u8x16_sub_sat(vb, va));
+            // SAFETY: `o` holds 16 bytes and `v128_store` allows unaligned writes
+            unsafe { v128_store(o.as_mut_ptr() as *mut v128, diff) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(len: usize, seed: u32) -> Vec<u8> {
+        (0..len as u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) ^ seed).rotate_left(7) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_dot() {
+        assert_eq!(dot(&[], &[]), 0);
+        assert_eq!(dot(&[255; 37], &[255; 37]), 37 * 255 * 255);
+        let (a, b) = (bytes(100, 1), bytes(100, 2));
+        let expected: u64 = a.iter().zip(&b).map(|(&x, &y)| x as u64 * y as u64).sum();
+        assert_eq!(dot(&a, &b), expected);
+    }
+
+    #[test]
+    fn test_sum_and_squares() {
+        assert_eq!(sum_and_squares(&[1, 2, 3]), (6, 14));
+        assert_eq!(sum_and_squares(&[255; 33]), (33 * 255, 33 * 255 * 255));
+    }
+
+    #[test]
+    fn test_abs_diff() {
+        let (a, b) = (bytes(35, 3), bytes(35, 4));
+        let mut out = vec![0; 35];
+        abs_diff(&a, &b, &mut out);
+        for i in 0..35 {
+            assert_eq!(out[i], a[i].abs_diff(b[i]));
+        }
+    }
+}
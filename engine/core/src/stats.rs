@@ -0,0 +1,79 @@
+//! Wall-clock timing for merge statistics.
+//!
+//! `std::time::Instant` is unavailable on `wasm32-unknown-unknown`, so hosts
+//! there pass a [`Clock`] in through [`MergeOptions::clock`] (the browser
+//! engine reads `performance.now()`); wasm32 merges without one report zero
+//! durations. Durations are for monitoring only and never influence the
+//! output.
+//!
+//! [`MergeOptions::clock`]: crate::MergeOptions::clock
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+/// A host time source returning milliseconds; only differences between
+/// readings are used.
+#[derive(Clone, Copy)]
+pub struct Clock(pub fn() -> f64);
+
+impl std::fmt::Debug for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Clock")
+    }
+}
+
+/// A reading taken by a [`Stopwatch`].
+#[derive(Clone, Copy)]
+enum Mark {
+    Millis(f64),
+    #[cfg(not(target_arch = "wasm32"))]
+    Instant(Instant),
+    /// No clock to read.
+    #[cfg(target_arch = "wasm32")]
+    Unknown,
+}
+
+fn now(clock: Option<Clock>) -> Mark {
+    match clock {
+        Some(Clock(now)) => Mark::Millis(now()),
+        #[cfg(not(target_arch = "wasm32"))]
+        None => Mark::Instant(Instant::now()),
+        #[cfg(target_arch = "wasm32")]
+        None => Mark::Unknown,
+    }
+}
+
+fn elapsed_ms(since: Mark, until: Mark) -> f64 {
+    match (since, until) {
+        (Mark::Millis(since), Mark::Millis(until)) => (until - since).max(0.0),
+        #[cfg(not(target_arch = "wasm32"))]
+        (Mark::Instant(since), Mark::Instant(until)) => {
+            until.duration_since(since).as_secs_f64() * 1000.0
+        }
+        _ => 0.0,
+    }
+}
+
+/// Measures consecutive pipeline stages.
+pub struct Stopwatch {
+    clock: Option<Clock>,
+    last: Mark,
+}
+
+impl Stopwatch {
+    /// Starts timing with `clock`, or the std clock where there is one.
+    pub fn start(clock: Option<Clock>) -> Self {
+        Self {
+            clock,
+            last: now(clock),
+        }
+    }
+
+    /// Milliseconds since the previous lap (or the start).
+    pub fn lap(&mut self) -> f64 {
+        let now = now(self.clock);
+        let elapsed = elapsed_ms(self.last, now);
+        self.last = now;
+        elapsed
+    }
+}
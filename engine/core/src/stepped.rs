@@ -0,0 +1,378 @@
+//! Merges run a bounded step at a time.
+//!
+//! Without a worker, a merge on the browser's main thread freezes the page
+//! until it returns. A stepped merge does the work of
+//! [`merge_with_metadata`](crate::merge::merge_with_metadata) in steps —
+//! decoding one input, laying out, scaling one input, matching one Smart
+//! mode pair, drawing a band of rows, encoding — so a single-threaded host
+//! can return to its event loop between them.
+
+use image::DynamicImage;
+
+use crate::error::MergeError;
+use crate::hooks::NoHooks;
+use crate::merge::{
+    CompositeInfo, Drawing, Planner, begin_composite, begin_drawing, check_inputs, decode_input,
+    encode_merge, finish_inputs,
+};
+use crate::stats::Stopwatch;
+use crate::types::{Direction, ImageInput, MergeOptions, MergeOutput};
+
+/// A merge advanced by [`step`](SteppedMerge::step) until it returns the
+/// output.
+pub struct SteppedMerge {
+    /// Inputs in merge order.
+    inputs: Vec<ImageInput>,
+    options: MergeOptions,
+    stage: Stage,
+    /// Time spent in the steps of each stage so far, for statistics.
+    decode_ms: f64,
+    composite_ms: f64,
+}
+
+enum Stage {
+    /// Decoding the inputs, of which these are done.
+    Decoding(Vec<DynamicImage>),
+    /// Laying out the decoded inputs.
+    Planning(Vec<DynamicImage>),
+    /// Scaling the decoded inputs and Smart mode detection, after
+    /// `layout_ms` of planning so far.
+    Laying {
+        decoded: Vec<DynamicImage>,
+        planner: Box<Planner>,
+        layout_ms: f64,
+    },
+    /// Drawing the images, given the sizes of the decoded inputs.
+    Drawing {
+        drawing: Box<Drawing>,
+        info: CompositeInfo,
+        inputs: Vec<(u32, u32)>,
+    },
+    /// A step failed or the output was returned.
+    Finished,
+}
+
+impl SteppedMerge {
+    /// Checks the inputs against the options like a merge does before
+    /// decoding; nothing is decoded until the first step.
+    pub fn new(
+        images_data: Vec<impl Into<ImageInput>>,
+        options: MergeOptions,
+    ) -> Result<Self, MergeError> {
+        if images_data.is_empty() {
+            return Err(MergeError::NoImages);
+        }
+        let (inputs, options) = check_inputs(images_data, options)?;
+        let decoded = Vec::with_capacity(inputs.len());
+        Ok(Self {
+            inputs,
+            options,
+            stage: Stage::Decoding(decoded),
+            decode_ms: 0.0,
+            composite_ms: 0.0,
+        })
+    }
+
+    /// Number of steps left, the last one encoding the output, for progress
+    /// bars.
+    ///
+    /// Until the layout is known, each input counts as one band of rows to
+    /// draw; inputs whose scaled size is over a million pixels add steps
+    /// once it is. Tiles are drawn whole while laying out; so are 16-bit
+    /// canvases, which until then are counted as drawn one band per input.
+    pub fn remaining_steps(&self) -> usize {
+        let images = self.inputs.len();
+        let (planned, drawn) = match self.options.direction {
+            Direction::Tiles => (0, 0),
+            // Scaling, the chrome trims and each pair, then settling
+            Direction::Smart => (images + 1 + images.saturating_sub(1) + 1, images),
+            _ => (images + 1, images),
+        };
+        match &self.stage {
+            Stage::Decoding(decoded) => images - decoded.len() + 1 + planned + drawn + 1,
+            Stage::Planning(_) => 1 + planned + drawn + 1,
+            Stage::Laying { planner, .. } => planner.remaining(&self.options) + 1 + drawn + 1,
+            Stage::Drawing { drawing, .. } => drawing.remaining(&self.options) + 1,
+            Stage::Finished => 0,
+        }
+    }
+
+    /// Runs the next step, returning the output once the last one ran.
+    ///
+    /// The output and errors are those of
+    /// [`merge_with_metadata`](crate::merge::merge_with_metadata), except
+    /// that inputs are decoded one at a time even with the `threads`
+    /// feature. Once the output or an error was returned, there is nothing
+    /// left to run and further steps return `Ok(None)`.
+    pub fn step(&mut self) -> Result<Option<MergeOutput>, MergeError> {
+        let stage = std::mem::replace(&mut self.stage, Stage::Finished);
+        let mut stopwatch = Stopwatch::start(self.options.clock);
+        match stage {
+            Stage::Decoding(mut decoded) => {
+                let index = decoded.len();
+                let input = &self.inputs[index];
+                let image = decode_input(input).map_err(|message| MergeError::DecodeError {
+                    index,
+                    file_name: input.name.clone(),
+                    message,
+                })?;
+                decoded.push(image);
+                self.decode_ms += stopwatch.lap();
+                self.stage = if decoded.len() == self.inputs.len() {
+                    Stage::Planning(decoded)
+                } else {
+                    Stage::Decoding(decoded)
+                };
+            }
+            Stage::Planning(decoded) => {
+                let options = std::mem::take(&mut self.options);
+                let (decoded, options) = finish_inputs(decoded, &self.inputs, options);
+                self.options = options;
+                if self.options.direction == Direction::Tiles {
+                    let (drawing, info) = begin_composite(&decoded, &self.options, &mut NoHooks)?;
+                    self.composite_ms += stopwatch.lap();
+                    self.stage = Stage::Drawing {
+                        drawing: Box::new(drawing),
+                        info,
+                        inputs: input_sizes(&decoded),
+                    };
+                    return Ok(None);
+                }
+                let planner = Planner::begin(&decoded, &self.options, &mut NoHooks)?;
+                let layout_ms = stopwatch.lap();
+                self.composite_ms += layout_ms;
+                self.stage = Stage::Laying {
+                    decoded,
+                    planner: Box::new(planner),
+                    layout_ms,
+                };
+            }
+            Stage::Laying {
+                decoded,
+                mut planner,
+                mut layout_ms,
+            } => {
+                if planner.remaining(&self.options) > 0 {
+                    planner.step(&decoded, &self.options, &mut NoHooks)?;
+                    let lap = stopwatch.lap();
+                    layout_ms += lap;
+                    self.composite_ms += lap;
+                    self.stage = Stage::Laying {
+                        decoded,
+                        planner,
+                        layout_ms,
+                    };
+                    return Ok(None);
+                }
+                let plan = planner.finish(&self.options, &mut NoHooks)?;
+                let lap = stopwatch.lap();
+                self.composite_ms += lap;
+                let (drawing, info) =
+                    begin_drawing(plan, layout_ms + lap, &self.options, &mut NoHooks)?;
+                self.composite_ms += stopwatch.lap();
+                self.stage = Stage::Drawing {
+                    drawing: Box::new(drawing),
+                    info,
+                    inputs: input_sizes(&decoded),
+                };
+            }
+            Stage::Drawing {
+                mut drawing,
+                info,
+                inputs,
+            } => {
+                if drawing.remaining(&self.options) > 0 {
+                    drawing.draw_next(&self.options);
+                    self.composite_ms += stopwatch.lap();
+                    self.stage = Stage::Drawing {
+                        drawing,
+                        info,
+                        inputs,
+                    };
+                    return Ok(None);
+                }
+                let canvas = drawing.finish(&self.options);
+                self.composite_ms += stopwatch.lap();
+                let mut output = encode_merge(
+                    canvas,
+                    info,
+                    &inputs,
+                    self.composite_ms,
+                    &self.options,
+                    &mut NoHooks,
+                    None,
+                )?;
+                if let Some(stats) = &mut output.stats {
+                    stats.durations.decode_ms = self.decode_ms;
+                    stats.durations.total_ms += self.decode_ms;
+                }
+                return Ok(Some(output));
+            }
+            Stage::Finished => {}
+        }
+        Ok(None)
+    }
+}
+
+fn input_sizes(decoded: &[DynamicImage]) -> Vec<(u32, u32)> {
+    decoded
+        .iter()
+        .map(|img| (img.width(), img.height()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::merge_with_metadata;
+    use crate::types::{BackgroundColor, Border};
+    use image::{Rgba, RgbaImage};
+
+    fn create_test_png(width: u32, height: u32, color: Rgba<u8>) -> Vec<u8> {
+        let img = RgbaImage::from_pixel(width, height, color);
+        let mut bytes = Vec::new();
+        let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+        DynamicImage::ImageRgba8(img)
+            .write_with_encoder(encoder)
+            .unwrap();
+        bytes
+    }
+
+    /// Steps `merge` to the end, checking that each step counts down.
+    fn run(mut merge: SteppedMerge) -> MergeOutput {
+        loop {
+            let remaining = merge.remaining_steps();
+            if let Some(output) = merge.step().unwrap() {
+                assert_eq!(remaining, 1);
+                return output;
+            }
+            assert_eq!(merge.remaining_steps(), remaining - 1);
+        }
+    }
+
+    #[test]
+    fn test_stepped_merge_matches_one_shot_merge() {
+        let images = || {
+            vec![
+                create_test_png(20, 10, Rgba([255, 0, 0, 255])),
+                create_test_png(20, 30, Rgba([0, 255, 0, 255])),
+                create_test_png(10, 10, Rgba([0, 0, 255, 255])),
+            ]
+        };
+        for direction in [
+            Direction::Vertical,
+            Direction::Horizontal,
+            Direction::Grid,
+            Direction::Smart,
+            Direction::Tiles,
+        ] {
+            let options = MergeOptions {
+                direction,
+                spacing: 2,
+                border: Some(Border {
+                    width: 1,
+                    color: BackgroundColor::black(),
+                }),
+                order: Some(vec![2, 0, 1]),
+                ..Default::default()
+            };
+            let expected = merge_with_metadata(images(), options.clone()).unwrap();
+            // Decoding, laying out, scaling (and matching), settling, drawing
+            // and encoding; tiles are drawn whole while laying out
+            let (planned, drawn) = match direction {
+                Direction::Tiles => (0, 0),
+                Direction::Smart => (3 + 1 + 2 + 1, 3),
+                _ => (3 + 1, 3),
+            };
+            let merge = SteppedMerge::new(images(), options).unwrap();
+            assert_eq!(merge.remaining_steps(), 3 + 1 + planned + drawn + 1);
+            let output = run(merge);
+            assert_eq!(output, expected, "{direction:?}");
+        }
+    }
+
+    #[test]
+    fn test_stepped_merge_draws_large_images_in_bands() {
+        // A 2048x600 input is drawn in bands of 512 rows
+        let images = || {
+            vec![
+                create_test_png(2048, 600, Rgba([255, 0, 0, 255])),
+                create_test_png(2048, 600, Rgba([0, 0, 255, 255])),
+            ]
+        };
+        // Smart mode only draws the 500 rows left of the overlapped input
+        for (direction, overlaps, bands) in [
+            (Direction::Vertical, vec![], 4),
+            (Direction::Smart, vec![Some(100)], 3),
+        ] {
+            let options = MergeOptions {
+                direction,
+                overlap_overrides: overlaps,
+                ..Default::default()
+            };
+            let expected = merge_with_metadata(images(), options.clone()).unwrap();
+            let mut merge = SteppedMerge::new(images(), options).unwrap();
+            let mut draws = 0;
+            let output = loop {
+                let drawing = matches!(merge.stage, Stage::Drawing { .. });
+                if let Some(output) = merge.step().unwrap() {
+                    break output;
+                }
+                draws += usize::from(drawing);
+            };
+            assert_eq!(draws, bands, "{direction:?}");
+            assert_eq!(output, expected, "{direction:?}");
+        }
+    }
+
+    #[test]
+    fn test_stepped_merge_errors() {
+        assert!(matches!(
+            SteppedMerge::new(Vec::<Vec<u8>>::new(), MergeOptions::default()),
+            Err(MergeError::NoImages)
+        ));
+        let options = MergeOptions {
+            order: Some(vec![0]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            SteppedMerge::new(vec![b"junk".to_vec(), b"junk".to_vec()], options),
+            Err(MergeError::InvalidOrder { .. })
+        ));
+
+        let images = vec![
+            create_test_png(10, 10, Rgba([255, 0, 0, 255])),
+            b"junk".to_vec(),
+        ];
+        let mut merge = SteppedMerge::new(images, MergeOptions::default()).unwrap();
+        assert_eq!(merge.step(), Ok(None));
+        assert!(matches!(
+            merge.step(),
+            Err(MergeError::DecodeError { index: 1, .. })
+        ));
+        assert_eq!(merge.remaining_steps(), 0);
+        assert_eq!(merge.step(), Ok(None));
+    }
+
+    #[test]
+    fn test_stepped_merge_collects_stats() {
+        let images = vec![
+            create_test_png(10, 10, Rgba([255, 0, 0, 255])),
+            create_test_png(10, 20, Rgba([0, 0, 255, 255])),
+        ];
+        let options = MergeOptions {
+            collect_stats: true,
+            ..Default::default()
+        };
+        let output = run(SteppedMerge::new(images, options).unwrap());
+        let stats = output.stats.unwrap();
+        assert_eq!(stats.inputs.len(), 2);
+        assert_eq!((stats.output_width, stats.output_height), (10, 30));
+        let durations = stats.durations;
+        let stages = durations.decode_ms
+            + durations.layout_ms
+            + durations.composite_ms
+            + durations.encode_ms;
+        assert!((durations.total_ms - stages).abs() < 1e-9);
+    }
+}
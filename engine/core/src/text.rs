@@ -0,0 +1,238 @@
+//! Caption and label rendering with an embedded font.
+//!
+//! Captions are single lines of text drawn into a strip above or below an
+//! image; labels such as page numbers are drawn over a corner of the image. Glyphs are rasterized with `ab_glyph` from a bundled copy of
+//! DejaVu Sans so output does not depend on fonts installed on the host.
+
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont, point};
+use image::RgbaImage;
+
+use crate::shadow::blend_over;
+use crate::types::{BackgroundColor, Corner};
+
+static FONT_DATA: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+
+/// Space above and below the text, as a fraction of the font size.
+const STRIP_PADDING_FRACTION: f32 = 0.4;
+
+fn font() -> FontRef<'static> {
+    FontRef::try_from_slice(FONT_DATA).expect("embedded font is valid")
+}
+
+fn strip_padding(size: f32) -> f32 {
+    (size * STRIP_PADDING_FRACTION).round()
+}
+
+/// Height of the strip holding `text` at `size` px; 0 when there is no text.
+pub fn caption_strip_height(text: Option<&str>, size: f32) -> u32 {
+    match text {
+        Some(text) if !text.trim().is_empty() && size > 0.0 => {
+            let scaled = font().into_scaled(PxScale::from(size));
+            (scaled.height() + strip_padding(size) * 2.0).ceil() as u32
+        }
+        _ => 0,
+    }
+}
+
+/// Advance width of `text` at `size` px, including kerning.
+pub fn measure_text(text: &str, size: f32) -> f32 {
+    let scaled = font().into_scaled(PxScale::from(size));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars().filter(|c| !c.is_control()) {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Draws `text` horizontally centered in the strip at `(x, y)`, clipped to it.
+///
+/// Text wider than the strip starts at its left edge and is cut off on the right.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_caption(
+    canvas: &mut RgbaImage,
+    text: &str,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    size: f32,
+    color: &BackgroundColor,
+) {
+    let text_width = measure_text(text, size);
+    let left = x as f32 + ((width as f32 - text_width) / 2.0).max(0.0).round();
+    let top = y as f32 + strip_padding(size);
+    draw_line(
+        canvas,
+        text,
+        (left, top),
+        (x, y, width, height),
+        size,
+        color,
+    );
+}
+
+/// Draws a short label such as a page number inside a corner of `rect`
+/// (`x, y, width, height`), inset by half the font size and clipped to `rect`.
+///
+/// The size is capped at the height of `rect`: a taller label would be
+/// clipped away, and its glyphs would still be rasterized at full size.
+pub fn draw_corner_label(
+    canvas: &mut RgbaImage,
+    text: &str,
+    rect: (u32, u32, u32, u32),
+    corner: Corner,
+    size: f32,
+    color: &BackgroundColor,
+) {
+    let (x, y, width, height) = rect;
+    let size = size.min(height as f32);
+    let inset = (size / 2.0).round();
+    let text_width = measure_text(text, size);
+    let text_height = font().into_scaled(PxScale::from(size)).height();
+    let left = match corner {
+        Corner::TopLeft | Corner::BottomLeft => x as f32 + inset,
+        Corner::TopRight | Corner::BottomRight => {
+            (x as f32 + width as f32 - inset - text_width).round()
+        }
+    };
+    let top = match corner {
+        Corner::TopLeft | Corner::TopRight => y as f32 + inset,
+        Corner::BottomLeft | Corner::BottomRight => {
+            (y as f32 + height as f32 - inset - text_height).round()
+        }
+    };
+    draw_line(canvas, text, (left, top), rect, size, color);
+}
+
+/// Rasterizes one line of text whose line box starts at `origin`, blending
+/// only the pixels inside `clip` (`x, y, width, height`).
+fn draw_line(
+    canvas: &mut RgbaImage,
+    text: &str,
+    origin: (f32, f32),
+    clip: (u32, u32, u32, u32),
+    size: f32,
+    color: &BackgroundColor,
+) {
+    let scaled = font().into_scaled(PxScale::from(size));
+    let (mut caret, top) = origin;
+    let baseline = top + scaled.ascent();
+
+    let (clip_x, clip_y, clip_width, clip_height) = clip;
+    let clip_right = clip_x.saturating_add(clip_width).min(canvas.width());
+    let clip_bottom = clip_y.saturating_add(clip_height).min(canvas.height());
+    let color_alpha = color.a as f32 / 255.0;
+
+    let mut previous = None;
+    for c in text.chars().filter(|c| !c.is_control()) {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(scaled.scale(), point(caret, baseline));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        let Some(outlined) = scaled.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i64 + gx as i64;
+            let py = bounds.min.y as i64 + gy as i64;
+            if px < clip_x as i64
+                || py < clip_y as i64
+                || px >= clip_right as i64
+                || py >= clip_bottom as i64
+            {
+                return;
+            }
+            let alpha = (coverage * color_alpha).min(1.0);
+            if alpha > 0.0 {
+                let dest = canvas.get_pixel_mut(px as u32, py as u32);
+                *dest = blend_over(*dest, color, alpha);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_caption_strip_height() {
+        assert_eq!(caption_strip_height(None, 16.0), 0);
+        assert_eq!(caption_strip_height(Some("   "), 16.0), 0);
+        let small = caption_strip_height(Some("Label"), 16.0);
+        let large = caption_strip_height(Some("Label"), 32.0);
+        assert!(small > 16);
+        assert!(large > small);
+    }
+
+    #[test]
+    fn test_measure_text_grows_with_length() {
+        let short = measure_text("ab", 16.0);
+        let long = measure_text("abcdef", 16.0);
+        assert!(short > 0.0);
+        assert!(long > short);
+    }
+
+    #[test]
+    fn test_draw_caption_stays_inside_strip() {
+        let white = Rgba([255, 255, 255, 255]);
+        let mut canvas = RgbaImage::from_pixel(200, 60, white);
+        let height = caption_strip_height(Some("Hello"), 16.0);
+        draw_caption(
+            &mut canvas,
+            "Hello",
+            50,
+            10,
+            100,
+            height,
+            16.0,
+            &BackgroundColor::black(),
+        );
+
+        let mut inked = 0;
+        for (x, y, pixel) in canvas.enumerate_pixels() {
+            if *pixel != white {
+                inked += 1;
+                assert!((50..150).contains(&x));
+                assert!((10..10 + height).contains(&y));
+            }
+        }
+        assert!(inked > 0);
+    }
+
+    #[test]
+    fn test_draw_corner_label_stays_in_corner() {
+        let white = Rgba([255, 255, 255, 255]);
+        let mut canvas = RgbaImage::from_pixel(200, 100, white);
+        draw_corner_label(
+            &mut canvas,
+            "2/3",
+            (0, 0, 200, 100),
+            Corner::BottomRight,
+            16.0,
+            &BackgroundColor::black(),
+        );
+
+        let mut inked = 0;
+        for (x, y, pixel) in canvas.enumerate_pixels() {
+            if *pixel != white {
+                inked += 1;
+                assert!((150..192).contains(&x), "ink at x={x}");
+                assert!((70..92).contains(&y), "ink at y={y}");
+            }
+        }
+        assert!(inked > 0);
+    }
+}
@@ -0,0 +1,218 @@
+//! Page selection in multi-page TIFF files (scanned documents, faxes).
+//!
+//! The `image` crate's TIFF decoder only reads the first image file directory
+//! (IFD). Each page is one IFD in a chain starting at the offset in the file
+//! header, and strip and tile offsets are absolute, so pointing the header at
+//! another IFD makes the decoder read that page instead.
+
+use std::borrow::Cow;
+
+/// The bytes of a TIFF whose first page is page `page` (from 0) of `bytes`.
+///
+/// Page 0 borrows `bytes` unchanged, also when they are not a TIFF: still
+/// images of other formats only have page 0.
+pub(crate) fn select_page(bytes: &[u8], page: u32) -> Result<Cow<'_, [u8]>, String> {
+    if page == 0 {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    let Some(header) = Header::parse(bytes) else {
+        return Err(format!(
+            "Page {page} requested, but the image is not a TIFF"
+        ));
+    };
+    let mut offset = header.first_ifd(bytes).filter(|&offset| offset != 0);
+    for count in 1..=page {
+        offset = offset
+            .and_then(|offset| header.next_ifd(bytes, offset))
+            .filter(|&next| next != 0);
+        if offset.is_none() {
+            let pages = if count == 1 { "page" } else { "pages" };
+            return Err(format!(
+                "Page {page} requested, but the TIFF has {count} {pages}"
+            ));
+        }
+    }
+    let offset = offset.expect("checked in the loop");
+
+    let mut patched = bytes.to_vec();
+    header.set_first_ifd(&mut patched, offset);
+    Ok(Cow::Owned(patched))
+}
+
+/// Byte order and offset size of a classic (32-bit) or Big (64-bit) TIFF.
+#[derive(Clone, Copy)]
+struct Header {
+    little_endian: bool,
+    big: bool,
+}
+
+impl Header {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let little_endian = match bytes.get(0..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let header = Header {
+            little_endian,
+            big: false,
+        };
+        match header.read(bytes, 2, 2)? {
+            42 => Some(header),
+            // BigTIFF: 8-byte offsets, announced in the header
+            43 if header.read(bytes, 4, 2)? == 8 => Some(Header {
+                big: true,
+                ..header
+            }),
+            _ => None,
+        }
+    }
+
+    /// Size of an offset or IFD entry count: 4 and 2 bytes, or 8 in BigTIFF.
+    fn sizes(self) -> (usize, usize) {
+        if self.big { (8, 8) } else { (4, 2) }
+    }
+
+    fn first_ifd(self, bytes: &[u8]) -> Option<u64> {
+        let (offset_size, _) = self.sizes();
+        self.read(bytes, offset_size, offset_size)
+    }
+
+    fn set_first_ifd(self, bytes: &mut [u8], offset: u64) {
+        let (offset_size, _) = self.sizes();
+        let value = if self.little_endian {
+            offset.to_le_bytes()[..offset_size].to_vec()
+        } else {
+            offset.to_be_bytes()[8 - offset_size..].to_vec()
+        };
+        bytes[offset_size..2 * offset_size].copy_from_slice(&value);
+    }
+
+    /// Offset of the IFD after the one at `offset`; 0 ends the chain.
+    fn next_ifd(self, bytes: &[u8], offset: u64) -> Option<u64> {
+        let (offset_size, count_size) = self.sizes();
+        let entry_size = if self.big { 20 } else { 12 };
+        let offset = usize::try_from(offset).ok()?;
+        let count = usize::try_from(self.read(bytes, offset, count_size)?).ok()?;
+        let next = count
+            .checked_mul(entry_size)?
+            .checked_add(offset + count_size)?;
+        self.read(bytes, next, offset_size)
+    }
+
+    /// Reads an unsigned integer of `size` bytes at `at`.
+    fn read(self, bytes: &[u8], at: usize, size: usize) -> Option<u64> {
+        let field = bytes.get(at..at.checked_add(size)?)?;
+        let fold = |value: u64, &byte: &u8| value << 8 | byte as u64;
+        Some(if self.little_endian {
+            field.iter().rev().fold(0, fold)
+        } else {
+            field.iter().fold(0, fold)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    /// An uncompressed little-endian TIFF with one 4x2 gray page per level.
+    fn create_test_tiff(levels: &[u8]) -> Vec<u8> {
+        const WIDTH: u32 = 4;
+        const HEIGHT: u32 = 2;
+        const ENTRIES: u32 = 9;
+        let ifd_size = 2 + ENTRIES * 12 + 4;
+        let page_size = ifd_size + WIDTH * HEIGHT;
+
+        let mut bytes = b"II*\0".to_vec();
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        for (index, &level) in levels.iter().enumerate() {
+            let ifd = 8 + index as u32 * page_size;
+            let pixels = ifd + ifd_size;
+            let next = if index + 1 < levels.len() {
+                ifd + page_size
+            } else {
+                0
+            };
+            bytes.extend_from_slice(&(ENTRIES as u16).to_le_bytes());
+            for (tag, kind, value) in [
+                (256, 4, WIDTH),          // ImageWidth
+                (257, 4, HEIGHT),         // ImageLength
+                (258, 3, 8),              // BitsPerSample
+                (259, 3, 1),              // Compression: none
+                (262, 3, 1),              // PhotometricInterpretation: BlackIsZero
+                (273, 4, pixels),         // StripOffsets
+                (277, 3, 1),              // SamplesPerPixel
+                (278, 4, HEIGHT),         // RowsPerStrip
+                (279, 4, WIDTH * HEIGHT), // StripByteCounts
+            ] {
+                bytes.extend_from_slice(&(tag as u16).to_le_bytes());
+                bytes.extend_from_slice(&(kind as u16).to_le_bytes());
+                bytes.extend_from_slice(&1u32.to_le_bytes());
+                if kind == 3 {
+                    bytes.extend_from_slice(&(value as u16).to_le_bytes());
+                    bytes.extend_from_slice(&[0, 0]);
+                } else {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(&next.to_le_bytes());
+            bytes.extend(std::iter::repeat_n(level, (WIDTH * HEIGHT) as usize));
+        }
+        bytes
+    }
+
+    fn level_of(bytes: &[u8]) -> u8 {
+        let img = image::load_from_memory(bytes).unwrap();
+        assert_eq!(img.dimensions(), (4, 2));
+        img.to_luma8().get_pixel(0, 0)[0]
+    }
+
+    #[test]
+    fn test_select_page_decodes_each_page() {
+        let tiff = create_test_tiff(&[10, 20, 30]);
+        assert!(matches!(select_page(&tiff, 0), Ok(Cow::Borrowed(_))));
+        for (page, level) in [(0, 10), (1, 20), (2, 30)] {
+            assert_eq!(level_of(&select_page(&tiff, page).unwrap()), level);
+        }
+        assert_eq!(
+            select_page(&tiff, 3).unwrap_err(),
+            "Page 3 requested, but the TIFF has 3 pages"
+        );
+    }
+
+    #[test]
+    fn test_select_page_of_other_images() {
+        let single = create_test_tiff(&[10]);
+        assert_eq!(
+            select_page(&single, 1).unwrap_err(),
+            "Page 1 requested, but the TIFF has 1 page"
+        );
+        let png = b"\x89PNG\r\n\x1a\n";
+        assert!(select_page(png, 0).is_ok());
+        assert_eq!(
+            select_page(png, 1).unwrap_err(),
+            "Page 1 requested, but the image is not a TIFF"
+        );
+        // A chain pointing past the end of the file
+        let mut truncated = create_test_tiff(&[10, 20]);
+        truncated.truncate(20);
+        assert!(select_page(&truncated, 1).is_err());
+    }
+
+    #[test]
+    fn test_select_page_big_endian_and_bigtiff_headers() {
+        let mut big_endian = b"MM\0*\0\0\0\x08".to_vec();
+        big_endian.extend_from_slice(&[0, 0, 0, 0, 0, 0x20, 0, 0, 0, 0]);
+        let patched = select_page(&big_endian, 1).unwrap();
+        assert_eq!(&patched[4..8], &[0, 0, 0, 0x20]);
+
+        let mut bigtiff = b"II+\0\x08\0\0\0".to_vec();
+        bigtiff.extend_from_slice(&16u64.to_le_bytes());
+        bigtiff.extend_from_slice(&0u64.to_le_bytes());
+        bigtiff.extend_from_slice(&0x40u64.to_le_bytes());
+        let patched = select_page(&bigtiff, 1).unwrap();
+        assert_eq!(&patched[8..16], &0x40u64.to_le_bytes());
+    }
+}
@@ -0,0 +1,1798 @@
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{MergeWarning, OptionsError};
+use crate::repro::ReproBundle;
+use crate::stats::Clock;
+
+/// Merge direction - vertical stacks images top to bottom, horizontal stacks left to right.
+/// Smart mode is vertical with automatic overlap detection and removal.
+/// Tiles mode reassembles exact pre-cut tiles at known offsets, without scaling.
+/// Grid mode scales images to a common width like vertical mode and places
+/// them row by row in equal cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Vertical,
+    Horizontal,
+    Smart,
+    Tiles,
+    Grid,
+}
+
+/// How images are resized along the cross axis before stacking.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleMode {
+    /// Scale every image to the largest width (vertical) or height (horizontal).
+    #[default]
+    Max,
+    /// Scale every image down to the smallest width (vertical) or height (horizontal).
+    Min,
+    /// Keep native pixel sizes; smaller images are padded with the background.
+    None,
+}
+
+/// What happens to the repeated headers/footers found in Smart mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "kebab-case")]
+pub enum ChromeMode {
+    /// Keep the first image's top and the last image's bottom; remove the rest.
+    #[default]
+    KeepEnds,
+    /// Remove every repeated header and footer, including the outer ones.
+    RemoveAll,
+    /// Keep every image's chrome; it is only excluded from overlap matching.
+    KeepAll,
+}
+
+/// Clockwise rotation applied to the finished canvas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    /// Maps 0, 90, 180 or 270 degrees to a rotation; other angles are unsupported.
+    pub fn from_degrees(degrees: u32) -> Option<Self> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Clockwise90),
+            180 => Some(Rotation::Clockwise180),
+            270 => Some(Rotation::Clockwise270),
+            _ => None,
+        }
+    }
+}
+
+/// How 1-bit and paletted ("low-color") inputs are scaled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "lowercase")]
+pub enum LowColorScaling {
+    /// Same smooth resampling as every other image.
+    #[default]
+    Smooth,
+    /// Enlarge with nearest-neighbor so document edges stay crisp; shrinking
+    /// stays smooth.
+    Nearest,
+    /// Keep native size, padded and aligned like a pinned image.
+    Native,
+}
+
+/// Cross-axis alignment of an image narrower (or shorter) than the canvas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    Start,
+    #[default]
+    Center,
+    End,
+}
+
+/// Vertical alignment of shorter images in horizontal mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "lowercase")]
+pub enum VerticalAlign {
+    Top,
+    #[default]
+    Middle,
+    Bottom,
+}
+
+impl From<VerticalAlign> for Align {
+    fn from(value: VerticalAlign) -> Self {
+        match value {
+            VerticalAlign::Top => Align::Start,
+            VerticalAlign::Middle => Align::Center,
+            VerticalAlign::Bottom => Align::End,
+        }
+    }
+}
+
+/// Where a caption strip is placed relative to its image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionPosition {
+    Above,
+    #[default]
+    Below,
+}
+
+/// Corner of an image that an overlay is anchored to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "camelCase")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// Background fill color for transparent areas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackgroundColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Default for BackgroundColor {
+    fn default() -> Self {
+        // Default to opaque white
+        BackgroundColor {
+            r: 255,
+            g: 255,
+            b: 255,
+            a: 255,
+        }
+    }
+}
+
+impl BackgroundColor {
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        BackgroundColor { r, g, b, a }
+    }
+
+    pub fn white() -> Self {
+        Self::default()
+    }
+
+    pub fn black() -> Self {
+        BackgroundColor {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        }
+    }
+
+    pub fn transparent() -> Self {
+        BackgroundColor {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        }
+    }
+}
+
+/// Linear gradient used instead of the solid background color.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Gradient {
+    pub start: BackgroundColor,
+    pub end: BackgroundColor,
+    /// Direction in degrees, as in CSS `linear-gradient` (180 = top to bottom).
+    #[serde(default = "default_gradient_angle")]
+    pub angle: f32,
+}
+
+/// How a background image covers the canvas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundFit {
+    /// Repeat the image at native size from the top-left corner.
+    #[default]
+    Tile,
+    /// Resize the image to exactly the canvas size.
+    Stretch,
+}
+
+/// Encoded image painted over the background color, under all inputs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackgroundImage {
+    /// Encoded image bytes (any supported input format).
+    pub bytes: Vec<u8>,
+    #[serde(default)]
+    pub fit: BackgroundFit,
+}
+
+/// Axis-aligned rectangle in source image pixels.
+///
+/// Used as a user-drawn hint telling Smart mode where the overlap between a
+/// pair lies: coordinates refer to the top image of the pair, before scaling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverlapRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Outer padding in pixels around the merged content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Padding {
+    #[serde(default)]
+    pub top: u32,
+    #[serde(default)]
+    pub right: u32,
+    #[serde(default)]
+    pub bottom: u32,
+    #[serde(default)]
+    pub left: u32,
+}
+
+impl Padding {
+    /// Same padding on all four sides.
+    pub fn uniform(value: u32) -> Self {
+        Padding {
+            top: value,
+            right: value,
+            bottom: value,
+            left: value,
+        }
+    }
+}
+
+/// Frame drawn around each image before stacking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Border {
+    /// Frame thickness in pixels; added on every side of the image.
+    pub width: u32,
+    pub color: BackgroundColor,
+}
+
+/// Separator line drawn between adjacent images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Divider {
+    /// Line thickness in pixels; added to the gap between images.
+    pub width: u32,
+    pub color: BackgroundColor,
+}
+
+/// Line marking every junction between adjacent images, in any mode, so
+/// reviewers can tell where each capture ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeamLines {
+    /// Line thickness in pixels; drawn over the images, taking no room.
+    pub width: u32,
+    pub color: BackgroundColor,
+}
+
+impl Default for SeamLines {
+    fn default() -> Self {
+        SeamLines {
+            width: 1,
+            color: BackgroundColor::new(255, 0, 255, 255),
+        }
+    }
+}
+
+/// Soft shadow rendered beneath each image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shadow {
+    /// How far the soft edge spreads, in pixels; 0 gives a hard shadow.
+    #[serde(default)]
+    pub blur_radius: u32,
+    /// Horizontal shift of the shadow in pixels (positive is right).
+    #[serde(default)]
+    pub offset_x: i32,
+    /// Vertical shift of the shadow in pixels (positive is down).
+    #[serde(default)]
+    pub offset_y: i32,
+    pub color: BackgroundColor,
+}
+
+/// "1/3"-style page number stamped onto each image.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PageNumbers {
+    #[serde(default)]
+    pub corner: Corner,
+    /// Font size in pixels.
+    pub size: f32,
+    pub color: BackgroundColor,
+}
+
+/// Removal of solid-color margins around every input before layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrimMargins {
+    /// Largest per-channel difference from a side's edge color still
+    /// counted as margin (0-255); raise it for noisy or compressed inputs.
+    #[serde(default = "default_trim_tolerance")]
+    pub tolerance: u8,
+}
+
+impl Default for TrimMargins {
+    fn default() -> Self {
+        TrimMargins {
+            tolerance: default_trim_tolerance(),
+        }
+    }
+}
+
+/// Contact sheet preset: a grid of images captioned with their file names,
+/// for reviewing many images at once.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContactSheet {
+    /// Fixed column count; unset, it follows `target_aspect_ratio`.
+    #[serde(default)]
+    pub columns: Option<u32>,
+    /// Line of text drawn above the grid, such as a title or a date.
+    #[serde(default)]
+    pub header: Option<String>,
+}
+
+/// Composition guides drawn over the finished (rotated) output.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Guides {
+    /// Lines at one and two thirds of each axis.
+    #[serde(default)]
+    pub thirds: bool,
+    /// Horizontal and vertical center lines.
+    #[serde(default)]
+    pub center: bool,
+    /// Safe-area rectangle inset by this fraction of each side (0.0-0.5);
+    /// 0 draws none.
+    #[serde(default)]
+    pub safe_margin: f32,
+    /// Line thickness in pixels.
+    pub line_width: u32,
+    pub color: BackgroundColor,
+    /// Return the guides as a separate transparent layer instead of drawing
+    /// them into the output.
+    #[serde(default)]
+    pub separate_layer: bool,
+}
+
+/// EXIF fields copied from the first input into the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExifCopy {
+    /// Capture date and time (`DateTime`, `DateTimeOriginal`,
+    /// `DateTimeDigitized` and their UTC offsets).
+    #[serde(default)]
+    pub capture_time: bool,
+    /// Camera and lens make and model.
+    #[serde(default)]
+    pub camera: bool,
+    /// The whole GPS block, which reveals where the photo was taken.
+    #[serde(default)]
+    pub gps: bool,
+}
+
+/// Per-image overrides; unset fields fall back to the global options.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImageOptions {
+    /// Horizontal alignment in vertical, Smart and grid modes.
+    #[serde(default)]
+    pub align: Option<Align>,
+    /// Keep this image at its native resolution; it is padded instead of scaled.
+    #[serde(default)]
+    pub pin: bool,
+    /// Scale this image per `low_color_scaling`. Set automatically for PNG
+    /// inputs that are paletted or under 8 bits per sample.
+    #[serde(default)]
+    pub low_color: bool,
+    /// Scan resolution in dots per inch; read from JFIF/pHYs metadata when
+    /// unset. Only used when `target_dpi` is set.
+    #[serde(default)]
+    pub dpi: Option<f32>,
+    /// Tiles mode: y position of this tile; defaults to the end of the previous tile.
+    #[serde(default)]
+    pub tile_offset: Option<u32>,
+    /// Tiles mode: rows of this tile to use, from the top; defaults to its full height.
+    #[serde(default)]
+    pub tile_height: Option<u32>,
+    /// Mirror this image left to right before scaling.
+    #[serde(default)]
+    pub flip_h: bool,
+    /// Mirror this image top to bottom before scaling.
+    #[serde(default)]
+    pub flip_v: bool,
+}
+
+/// One merge input: its bytes or pixels, plus what the host knows about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageInput {
+    pub source: InputSource,
+    /// File name, reported in decode errors.
+    pub name: Option<String>,
+    /// Format of encoded bytes; guessed from the bytes when `None`.
+    pub format: Option<ImageFormat>,
+    /// Frame of an animated GIF or WebP to merge, counted from 0 and
+    /// composited as a viewer shows it. Animations use their first frame
+    /// when `None` (refused in `strict` mode); still images only have frame 0.
+    pub frame: Option<u32>,
+    /// Page of a multi-page TIFF to merge, counted from 0 (its first page
+    /// when `None`); other images only have page 0.
+    pub page: Option<u32>,
+    /// Size of an ICO's entry to merge, matched against the larger side of
+    /// each; the largest entry when `None`.
+    pub icon_size: Option<u32>,
+}
+
+/// The bytes or pixels of a merge input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    /// Encoded file bytes (PNG, JPEG, GIF, WebP, TIFF, ICO or BMP).
+    Encoded(Vec<u8>),
+    /// Unencoded pixels, 4 bytes (RGBA) per pixel row by row, such as a
+    /// canvas's `ImageData`. Used as-is: there is no EXIF orientation or file
+    /// metadata to read.
+    Rgba {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+}
+
+impl ImageInput {
+    /// The file bytes of an encoded input.
+    pub fn encoded(&self) -> Option<&[u8]> {
+        match &self.source {
+            InputSource::Encoded(bytes) => Some(bytes),
+            InputSource::Rgba { .. } => None,
+        }
+    }
+}
+
+impl From<InputSource> for ImageInput {
+    fn from(source: InputSource) -> Self {
+        ImageInput {
+            source,
+            name: None,
+            format: None,
+            frame: None,
+            page: None,
+            icon_size: None,
+        }
+    }
+}
+
+impl From<Vec<u8>> for ImageInput {
+    fn from(bytes: Vec<u8>) -> Self {
+        InputSource::Encoded(bytes).into()
+    }
+}
+
+/// Options for the merge operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeOptions {
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default)]
+    pub background: BackgroundColor,
+    /// Gradient filling the canvas instead of the solid `background` color.
+    #[serde(default)]
+    pub background_gradient: Option<Gradient>,
+    /// Image painted over `background` (padding included) before compositing.
+    #[serde(default)]
+    pub background_image: Option<BackgroundImage>,
+    #[serde(default = "default_overlap_sensitivity")]
+    pub overlap_sensitivity: u8,
+    /// Trim repeated headers/footers before overlap detection (Smart mode
+    /// only). Disable when there is no repeated chrome and trimming produces
+    /// false positives.
+    #[serde(default = "default_strip_chrome")]
+    pub strip_chrome: bool,
+    /// Which repeated headers/footers are removed (Smart mode only).
+    #[serde(default)]
+    pub chrome_mode: ChromeMode,
+    /// Width in pixels of the grayscale proxies compared by the chrome-strip
+    /// pre-pass (Smart mode only). Advanced; `None` adapts it to each image's
+    /// width so thin separators survive on high-DPI captures.
+    #[serde(default)]
+    pub chrome_proxy_width: Option<u32>,
+    /// Per-pair sensitivity (Smart mode only); `overlap_sensitivities[i]`
+    /// tunes matching between images `i` and `i + 1`. Missing or `None`
+    /// entries use `overlap_sensitivity`.
+    #[serde(default)]
+    pub overlap_sensitivities: Vec<Option<u8>>,
+    /// Smallest overlap in pixels accepted by Smart mode detection.
+    #[serde(default = "default_min_overlap_pixels")]
+    pub min_overlap_pixels: u32,
+    /// Minimum `narrower / wider` width ratio for Smart mode to compare a pair (0.0-1.0).
+    #[serde(default = "default_width_ratio_threshold")]
+    pub width_ratio_threshold: f32,
+    /// Upscale factor above which a warning (or error) is reported.
+    #[serde(default = "default_max_upscale_factor")]
+    pub max_upscale_factor: f64,
+    /// Fail with `UPSCALE_TOO_LARGE` instead of warning when the factor is exceeded.
+    #[serde(default)]
+    pub error_on_upscale: bool,
+    /// When false, images are never enlarged; smaller ones keep native size and are padded.
+    #[serde(default = "default_allow_upscale")]
+    pub allow_upscale: bool,
+    /// Resample images of known resolution to this many dots per inch before
+    /// layout, so scans stitch at a consistent physical size.
+    #[serde(default)]
+    pub target_dpi: Option<f32>,
+    /// Pixel density in dots per inch recorded in the output (PNG `pHYs`
+    /// chunk), so it prints and pastes into documents at a physical size.
+    #[serde(default)]
+    pub dpi: Option<f32>,
+    /// Scaling of low-color inputs (see [`ImageOptions::low_color`]).
+    #[serde(default)]
+    pub low_color_scaling: LowColorScaling,
+    /// Resample inputs, previews and thumbnails in linear light rather than
+    /// sRGB, so downscaled fine detail keeps its brightness.
+    #[serde(default)]
+    pub linear_scaling: bool,
+    /// Resample inputs, previews and thumbnails with color weighted by alpha,
+    /// so transparent pixels do not darken anti-aliased edges.
+    #[serde(default)]
+    pub premultiplied_alpha: bool,
+    /// Keep 16 bits per sample through scaling and compositing and write a
+    /// 16-bit PNG, when every input has 16 bits per sample and no frame,
+    /// divider, seam line, caption, shadow, rounded corner, page number,
+    /// drawn-in guide or background gradient or image is requested. Otherwise (and in tiles
+    /// mode) the merge runs at 8 bits as usual.
+    #[serde(default)]
+    pub high_bit_depth: bool,
+    /// Split mode only: also split at seams whose match confidence (0.0-1.0) is below this.
+    ///
+    /// Seams where no overlap is found always split.
+    #[serde(default)]
+    pub split_min_confidence: f32,
+    /// Smart mode only: attach an anonymized reproduction bundle for every pair
+    /// where no overlap was found.
+    #[serde(default)]
+    pub repro_bundles: bool,
+    /// Scaling policy applied to all non-pinned images.
+    #[serde(default)]
+    pub scale_mode: ScaleMode,
+    /// Optional search region per adjacent pair (Smart mode only).
+    ///
+    /// `overlap_regions[i]` constrains the search in image `i` when matching
+    /// against image `i + 1`. Missing or `None` entries search the full image.
+    #[serde(default)]
+    pub overlap_regions: Vec<Option<OverlapRegion>>,
+    /// Pinned overlap per adjacent pair (Smart mode only).
+    ///
+    /// `overlap_overrides[i]`, when present, is used as the overlap in scaled
+    /// pixels between images `i` and `i + 1` instead of running detection.
+    /// Missing or `None` entries are detected as usual.
+    #[serde(default)]
+    pub overlap_overrides: Vec<Option<u32>>,
+    /// Background-colored gap in pixels between adjacent images.
+    ///
+    /// Ignored in Smart mode, where segments are stitched seamlessly.
+    #[serde(default)]
+    pub spacing: u32,
+    /// Maximum number of output pixels; larger merges fail with `OUTPUT_TOO_LARGE`.
+    #[serde(default)]
+    pub max_output_pixels: Option<u64>,
+    /// Shrink every image to fit `max_output_pixels` and `max_memory_bytes`
+    /// instead of failing, with a `DOWNSCALED` warning.
+    #[serde(default)]
+    pub downscale_to_fit: bool,
+    /// Estimated peak memory in bytes (decoded inputs, scaled copies and
+    /// canvas) above which the merge fails with `OUT_OF_MEMORY` before
+    /// allocating.
+    #[serde(default)]
+    pub max_memory_bytes: Option<u64>,
+    /// Most pixels any one input may have, read from its header before it
+    /// is decoded; a larger input fails with `INPUT_TOO_LARGE`.
+    #[serde(default)]
+    pub max_input_pixels: Option<u64>,
+    /// Longest side any one input may have, checked like `max_input_pixels`.
+    #[serde(default)]
+    pub max_input_dimension: Option<u32>,
+    /// Frame drawn around every image. Ignored in Smart mode.
+    #[serde(default)]
+    pub border: Option<Border>,
+    /// Radius in pixels of the anti-aliased rounded corners cut from every image,
+    /// revealing the background. Ignored in Smart mode.
+    #[serde(default)]
+    pub corner_radius: u32,
+    /// Line drawn in the middle of each gap between images. Ignored in Smart mode.
+    #[serde(default)]
+    pub divider: Option<Divider>,
+    /// Line drawn at every junction between adjacent images, in every mode.
+    #[serde(default)]
+    pub draw_seams: Option<SeamLines>,
+    /// Caption per image, indexed like the input images; empty or missing
+    /// entries get no strip. Ignored in Smart mode.
+    #[serde(default)]
+    pub captions: Vec<String>,
+    #[serde(default)]
+    pub caption_position: CaptionPosition,
+    /// Caption font size in pixels.
+    #[serde(default = "default_caption_size")]
+    pub caption_size: f32,
+    #[serde(default = "BackgroundColor::black")]
+    pub caption_color: BackgroundColor,
+    /// Page number drawn over a corner of every image, after compositing.
+    /// Smart mode places it on the visible (trimmed) part of each image.
+    #[serde(default)]
+    pub page_numbers: Option<PageNumbers>,
+    /// Drop shadow beneath every image, clipped to the canvas; leave room with
+    /// `spacing` and `padding`. Ignored in Smart mode.
+    #[serde(default)]
+    pub shadow: Option<Shadow>,
+    /// Background-colored margin around the whole merged canvas.
+    #[serde(default)]
+    pub padding: Padding,
+    /// Pad the output (right and bottom) so both dimensions are multiples of this value.
+    #[serde(default)]
+    pub dimension_multiple: Option<u32>,
+    /// Horizontal alignment of narrower images in vertical, Smart and grid
+    /// modes.
+    #[serde(default)]
+    pub align: Align,
+    /// Vertical alignment of shorter images in horizontal and grid modes.
+    #[serde(default)]
+    pub vertical_align: VerticalAlign,
+    /// Width-to-height ratio the grid mode output should come closest to
+    /// (e.g. 16/9 for slides), after padding and output rotation. The column
+    /// count is chosen to match it; unset, the output is kept closest to square.
+    #[serde(default)]
+    pub target_aspect_ratio: Option<f32>,
+    /// Fixed column count for grid mode, for contact sheets. Each row is then
+    /// as tall as its tallest image rather than every cell being equal, and
+    /// `target_aspect_ratio` is ignored.
+    #[serde(default)]
+    pub columns: Option<u32>,
+    /// Lay the images out as a contact sheet: grid mode, each image captioned
+    /// with its file name unless it has a caption, spacing and padding where
+    /// they are 0, and an optional header line above the grid.
+    #[serde(default)]
+    pub contact_sheet: Option<ContactSheet>,
+    /// Rotation of the final output, applied after compositing and padding.
+    #[serde(default)]
+    pub output_rotation: Rotation,
+    /// Layout guides over the final output, for checking a composition.
+    #[serde(default)]
+    pub guides: Option<Guides>,
+    /// Also return a downscaled preview, reusing this merge's decoding and matching.
+    #[serde(default)]
+    pub also_preview: bool,
+    /// Longest side of the preview in pixels.
+    #[serde(default = "default_preview_max_dimension")]
+    pub preview_max_dimension: u32,
+    /// Soft-proof the preview: round-trip it through JPEG at this quality
+    /// (1-100) so compression artifacts can be judged before a full merge.
+    #[serde(default)]
+    pub preview_proof_quality: Option<u8>,
+    /// Also return a BlurHash placeholder string for the output.
+    #[serde(default)]
+    pub blur_hash: bool,
+    /// Also return a thumbnail at most this many pixels wide (height follows
+    /// the aspect ratio), for galleries and history lists.
+    #[serde(default)]
+    pub thumbnail_width: Option<u32>,
+    /// Also return [`MergeStats`] (sizes, seams, stage durations, warnings).
+    #[serde(default)]
+    pub collect_stats: bool,
+    /// Time source for the stage durations in [`MergeStats`]; `None` uses
+    /// `std::time::Instant`, which wasm32 lacks, so its durations are zero
+    /// unless the host passes one.
+    #[serde(skip)]
+    pub clock: Option<Clock>,
+    /// Per-image overrides, indexed like the input images.
+    #[serde(default)]
+    pub image_options: Vec<ImageOptions>,
+    /// File name per input, indexed like the input images; reported in
+    /// decode errors for inputs that carry no name of their own.
+    #[serde(default)]
+    pub file_names: Vec<Option<String>>,
+    /// Crop uniform margins (letterboxing, a flat backdrop around a window)
+    /// off every input after decoding, before layout and scaling.
+    #[serde(default)]
+    pub trim_margins: Option<TrimMargins>,
+    /// Merge order as a permutation of the input indices: the input at
+    /// `order[i]` is placed at position `i`. Every other per-image option
+    /// except `file_names`, and the indices in warnings, errors and results,
+    /// refer to positions after reordering.
+    #[serde(default)]
+    pub order: Option<Vec<usize>>,
+    /// Fail with `STRICT_VIOLATION` instead of silently falling back: Smart
+    /// mode pairs too different in width to be matched, chrome trims dropped
+    /// because they would leave too little content, and animated inputs
+    /// flattened to their first frame because no `frame` was chosen.
+    #[serde(default)]
+    pub strict: bool,
+    /// EXIF fields to copy from the first input into `exif`, unless it is
+    /// already set. Inputs without EXIF (PNG, raw pixels) leave it unset.
+    #[serde(default)]
+    pub copy_exif: Option<ExifCopy>,
+    /// EXIF block (TIFF data) embedded in the output as an `eXIf` chunk.
+    #[serde(default)]
+    pub exif: Option<Vec<u8>>,
+    /// Record the engine version, direction, overlap sensitivity and each
+    /// image's placement in a `merge-images` `tEXt` chunk of the output.
+    #[serde(default)]
+    pub provenance: bool,
+    /// Guarantee the output carries no EXIF, XMP or text chunks, overriding
+    /// `copy_exif`, `exif` and `provenance`. Only the `pHYs` density of `dpi`
+    /// is kept.
+    #[serde(default)]
+    pub strip_metadata: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            direction: Direction::default(),
+            background: BackgroundColor::default(),
+            background_gradient: None,
+            background_image: None,
+            overlap_sensitivity: default_overlap_sensitivity(),
+            overlap_sensitivities: Vec::new(),
+            strip_chrome: default_strip_chrome(),
+            chrome_mode: ChromeMode::default(),
+            chrome_proxy_width: None,
+            min_overlap_pixels: default_min_overlap_pixels(),
+            width_ratio_threshold: default_width_ratio_threshold(),
+            max_upscale_factor: default_max_upscale_factor(),
+            error_on_upscale: false,
+            allow_upscale: default_allow_upscale(),
+            target_dpi: None,
+            dpi: None,
+            low_color_scaling: LowColorScaling::Smooth,
+            linear_scaling: false,
+            premultiplied_alpha: false,
+            high_bit_depth: false,
+            split_min_confidence: 0.0,
+            repro_bundles: false,
+            scale_mode: ScaleMode::default(),
+            overlap_regions: Vec::new(),
+            overlap_overrides: Vec::new(),
+            spacing: 0,
+            max_output_pixels: None,
+            max_memory_bytes: None,
+            max_input_pixels: None,
+            max_input_dimension: None,
+            downscale_to_fit: false,
+            border: None,
+            corner_radius: 0,
+            divider: None,
+            draw_seams: None,
+            captions: Vec::new(),
+            caption_position: CaptionPosition::default(),
+            caption_size: default_caption_size(),
+            caption_color: BackgroundColor::black(),
+            page_numbers: None,
+            shadow: None,
+            padding: Padding::default(),
+            dimension_multiple: None,
+            align: Align::default(),
+            vertical_align: VerticalAlign::default(),
+            target_aspect_ratio: None,
+            columns: None,
+            contact_sheet: None,
+            also_preview: false,
+            output_rotation: Rotation::None,
+            guides: None,
+            preview_max_dimension: default_preview_max_dimension(),
+            preview_proof_quality: None,
+            blur_hash: false,
+            thumbnail_width: None,
+            collect_stats: false,
+            clock: None,
+            image_options: Vec::new(),
+            file_names: Vec::new(),
+            order: None,
+            trim_margins: None,
+            strict: false,
+            copy_exif: None,
+            exif: None,
+            provenance: false,
+            strip_metadata: false,
+        }
+    }
+}
+
+impl MergeOptions {
+    /// Horizontal alignment for image `index` in vertical, Smart and grid modes.
+    pub fn align_for(&self, index: usize) -> Align {
+        self.image_options
+            .get(index)
+            .and_then(|o| o.align)
+            .unwrap_or(self.align)
+    }
+
+    /// Whether image `index` is pinned to its native resolution.
+    pub fn is_pinned(&self, index: usize) -> bool {
+        self.image_options.get(index).is_some_and(|o| o.pin)
+    }
+
+    /// Whether image `index` is treated as a low-color document image.
+    pub fn is_low_color(&self, index: usize) -> bool {
+        self.image_options.get(index).is_some_and(|o| o.low_color)
+    }
+
+    /// Starts a [`MergeOptionsBuilder`] from the defaults.
+    pub fn builder() -> MergeOptionsBuilder {
+        MergeOptionsBuilder::default()
+    }
+}
+
+/// Fluent construction of [`MergeOptions`] for Rust callers, checked by
+/// [`build`](Self::build).
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptionsBuilder {
+    options: MergeOptions,
+}
+
+impl MergeOptionsBuilder {
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.options.direction = direction;
+        self
+    }
+
+    pub fn background(mut self, background: BackgroundColor) -> Self {
+        self.options.background = background;
+        self
+    }
+
+    pub fn background_gradient(mut self, gradient: Gradient) -> Self {
+        self.options.background_gradient = Some(gradient);
+        self
+    }
+
+    pub fn background_image(mut self, image: BackgroundImage) -> Self {
+        self.options.background_image = Some(image);
+        self
+    }
+
+    /// Smart mode overlap sensitivity, 0-100.
+    pub fn sensitivity(mut self, sensitivity: u8) -> Self {
+        self.options.overlap_sensitivity = sensitivity;
+        self
+    }
+
+    /// Per-pair sensitivities, 0-100; `None` entries use [`sensitivity`](Self::sensitivity).
+    pub fn pair_sensitivities(mut self, sensitivities: Vec<Option<u8>>) -> Self {
+        self.options.overlap_sensitivities = sensitivities;
+        self
+    }
+
+    pub fn strip_chrome(mut self, strip_chrome: bool) -> Self {
+        self.options.strip_chrome = strip_chrome;
+        self
+    }
+
+    pub fn chrome_mode(mut self, chrome_mode: ChromeMode) -> Self {
+        self.options.chrome_mode = chrome_mode;
+        self
+    }
+
+    pub fn chrome_proxy_width(mut self, width: u32) -> Self {
+        self.options.chrome_proxy_width = Some(width);
+        self
+    }
+
+    pub fn min_overlap_pixels(mut self, pixels: u32) -> Self {
+        self.options.min_overlap_pixels = pixels;
+        self
+    }
+
+    /// Minimum width ratio for Smart mode to compare a pair, 0.0-1.0.
+    pub fn width_ratio_threshold(mut self, ratio: f32) -> Self {
+        self.options.width_ratio_threshold = ratio;
+        self
+    }
+
+    pub fn overlap_regions(mut self, regions: Vec<Option<OverlapRegion>>) -> Self {
+        self.options.overlap_regions = regions;
+        self
+    }
+
+    pub fn overlap_overrides(mut self, overrides: Vec<Option<u32>>) -> Self {
+        self.options.overlap_overrides = overrides;
+        self
+    }
+
+    /// Upscale factor above which a warning (or error) is reported; at least 1.
+    pub fn max_upscale_factor(mut self, factor: f64) -> Self {
+        self.options.max_upscale_factor = factor;
+        self
+    }
+
+    pub fn error_on_upscale(mut self, error_on_upscale: bool) -> Self {
+        self.options.error_on_upscale = error_on_upscale;
+        self
+    }
+
+    pub fn allow_upscale(mut self, allow_upscale: bool) -> Self {
+        self.options.allow_upscale = allow_upscale;
+        self
+    }
+
+    pub fn target_dpi(mut self, dpi: f32) -> Self {
+        self.options.target_dpi = Some(dpi);
+        self
+    }
+
+    pub fn dpi(mut self, dpi: f32) -> Self {
+        self.options.dpi = Some(dpi);
+        self
+    }
+
+    pub fn low_color_scaling(mut self, scaling: LowColorScaling) -> Self {
+        self.options.low_color_scaling = scaling;
+        self
+    }
+
+    pub fn linear_scaling(mut self, linear: bool) -> Self {
+        self.options.linear_scaling = linear;
+        self
+    }
+
+    pub fn premultiplied_alpha(mut self, premultiplied: bool) -> Self {
+        self.options.premultiplied_alpha = premultiplied;
+        self
+    }
+
+    pub fn high_bit_depth(mut self, high_bit_depth: bool) -> Self {
+        self.options.high_bit_depth = high_bit_depth;
+        self
+    }
+
+    pub fn scale_mode(mut self, scale_mode: ScaleMode) -> Self {
+        self.options.scale_mode = scale_mode;
+        self
+    }
+
+    pub fn spacing(mut self, spacing: u32) -> Self {
+        self.options.spacing = spacing;
+        self
+    }
+
+    pub fn padding(mut self, padding: Padding) -> Self {
+        self.options.padding = padding;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.options.align = align;
+        self
+    }
+
+    pub fn vertical_align(mut self, vertical_align: VerticalAlign) -> Self {
+        self.options.vertical_align = vertical_align;
+        self
+    }
+
+    pub fn target_aspect_ratio(mut self, ratio: f32) -> Self {
+        self.options.target_aspect_ratio = Some(ratio);
+        self
+    }
+
+    pub fn columns(mut self, columns: u32) -> Self {
+        self.options.columns = Some(columns);
+        self
+    }
+
+    pub fn contact_sheet(mut self, sheet: ContactSheet) -> Self {
+        self.options.contact_sheet = Some(sheet);
+        self
+    }
+
+    pub fn border(mut self, border: Border) -> Self {
+        self.options.border = Some(border);
+        self
+    }
+
+    pub fn divider(mut self, divider: Divider) -> Self {
+        self.options.divider = Some(divider);
+        self
+    }
+
+    pub fn draw_seams(mut self, seams: SeamLines) -> Self {
+        self.options.draw_seams = Some(seams);
+        self
+    }
+
+    pub fn corner_radius(mut self, radius: u32) -> Self {
+        self.options.corner_radius = radius;
+        self
+    }
+
+    pub fn shadow(mut self, shadow: Shadow) -> Self {
+        self.options.shadow = Some(shadow);
+        self
+    }
+
+    pub fn captions(mut self, captions: Vec<String>) -> Self {
+        self.options.captions = captions;
+        self
+    }
+
+    pub fn caption_position(mut self, position: CaptionPosition) -> Self {
+        self.options.caption_position = position;
+        self
+    }
+
+    pub fn caption_size(mut self, size: f32) -> Self {
+        self.options.caption_size = size;
+        self
+    }
+
+    pub fn caption_color(mut self, color: BackgroundColor) -> Self {
+        self.options.caption_color = color;
+        self
+    }
+
+    pub fn page_numbers(mut self, page_numbers: PageNumbers) -> Self {
+        self.options.page_numbers = Some(page_numbers);
+        self
+    }
+
+    pub fn output_rotation(mut self, rotation: Rotation) -> Self {
+        self.options.output_rotation = rotation;
+        self
+    }
+
+    pub fn guides(mut self, guides: Guides) -> Self {
+        self.options.guides = Some(guides);
+        self
+    }
+
+    pub fn dimension_multiple(mut self, multiple: u32) -> Self {
+        self.options.dimension_multiple = Some(multiple);
+        self
+    }
+
+    pub fn max_output_pixels(mut self, max_pixels: u64) -> Self {
+        self.options.max_output_pixels = Some(max_pixels);
+        self
+    }
+
+    pub fn downscale_to_fit(mut self, downscale_to_fit: bool) -> Self {
+        self.options.downscale_to_fit = downscale_to_fit;
+        self
+    }
+
+    pub fn max_memory_bytes(mut self, max_bytes: u64) -> Self {
+        self.options.max_memory_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn max_input_pixels(mut self, max_pixels: u64) -> Self {
+        self.options.max_input_pixels = Some(max_pixels);
+        self
+    }
+
+    pub fn max_input_dimension(mut self, max_dimension: u32) -> Self {
+        self.options.max_input_dimension = Some(max_dimension);
+        self
+    }
+
+    pub fn image_options(mut self, image_options: Vec<ImageOptions>) -> Self {
+        self.options.image_options = image_options;
+        self
+    }
+
+    pub fn file_names(mut self, names: Vec<Option<String>>) -> Self {
+        self.options.file_names = names;
+        self
+    }
+
+    pub fn trim_margins(mut self, trim: TrimMargins) -> Self {
+        self.options.trim_margins = Some(trim);
+        self
+    }
+
+    pub fn order(mut self, order: Vec<usize>) -> Self {
+        self.options.order = Some(order);
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    pub fn copy_exif(mut self, copy: ExifCopy) -> Self {
+        self.options.copy_exif = Some(copy);
+        self
+    }
+
+    pub fn exif(mut self, exif: Vec<u8>) -> Self {
+        self.options.exif = Some(exif);
+        self
+    }
+
+    pub fn provenance(mut self, provenance: bool) -> Self {
+        self.options.provenance = provenance;
+        self
+    }
+
+    pub fn strip_metadata(mut self, strip: bool) -> Self {
+        self.options.strip_metadata = strip;
+        self
+    }
+
+    pub fn also_preview(mut self, also_preview: bool) -> Self {
+        self.options.also_preview = also_preview;
+        self
+    }
+
+    pub fn preview_max_dimension(mut self, max_dimension: u32) -> Self {
+        self.options.preview_max_dimension = max_dimension;
+        self
+    }
+
+    /// JPEG quality (1-100) the preview is soft-proofed at.
+    pub fn preview_proof_quality(mut self, quality: u8) -> Self {
+        self.options.preview_proof_quality = Some(quality);
+        self
+    }
+
+    pub fn thumbnail_width(mut self, width: u32) -> Self {
+        self.options.thumbnail_width = Some(width);
+        self
+    }
+
+    pub fn blur_hash(mut self, blur_hash: bool) -> Self {
+        self.options.blur_hash = blur_hash;
+        self
+    }
+
+    pub fn collect_stats(mut self, collect_stats: bool) -> Self {
+        self.options.collect_stats = collect_stats;
+        self
+    }
+
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.options.clock = Some(clock);
+        self
+    }
+
+    pub fn repro_bundles(mut self, repro_bundles: bool) -> Self {
+        self.options.repro_bundles = repro_bundles;
+        self
+    }
+
+    /// Split mode confidence below which seams also split, 0.0-1.0.
+    pub fn split_min_confidence(mut self, confidence: f32) -> Self {
+        self.options.split_min_confidence = confidence;
+        self
+    }
+
+    /// Checks the ranges the JS options parser enforces and returns the options.
+    pub fn build(self) -> Result<MergeOptions, OptionsError> {
+        let o = &self.options;
+        let check = |ok: bool, field: &str, message: &str| {
+            if ok {
+                Ok(())
+            } else {
+                Err(OptionsError::new(field, message))
+            }
+        };
+        let fraction = |value: f32| (0.0..=1.0).contains(&value);
+        let positive = |value: f32| value.is_finite() && value > 0.0;
+
+        check(
+            o.overlap_sensitivity <= 100,
+            "overlap_sensitivity",
+            "expected 0-100",
+        )?;
+        for (index, sensitivity) in o.overlap_sensitivities.iter().enumerate() {
+            check(
+                sensitivity.is_none_or(|s| s <= 100),
+                &format!("overlap_sensitivities[{index}]"),
+                "expected 0-100",
+            )?;
+        }
+        check(
+            fraction(o.width_ratio_threshold),
+            "width_ratio_threshold",
+            "expected 0.0-1.0",
+        )?;
+        check(
+            fraction(o.split_min_confidence),
+            "split_min_confidence",
+            "expected 0.0-1.0",
+        )?;
+        check(
+            o.max_upscale_factor.is_finite() && o.max_upscale_factor >= 1.0,
+            "max_upscale_factor",
+            "expected a factor of at least 1",
+        )?;
+        check(
+            o.target_dpi.is_none_or(positive),
+            "target_dpi",
+            "expected a positive number",
+        )?;
+        check(
+            o.dpi.is_none_or(positive),
+            "dpi",
+            "expected a positive number",
+        )?;
+        check(
+            o.target_aspect_ratio.is_none_or(positive),
+            "target_aspect_ratio",
+            "expected a positive number",
+        )?;
+        check(
+            o.columns != Some(0),
+            "columns",
+            "expected a count of at least 1",
+        )?;
+        check(
+            o.contact_sheet
+                .as_ref()
+                .is_none_or(|s| s.columns != Some(0)),
+            "contact_sheet.columns",
+            "expected a count of at least 1",
+        )?;
+        check(
+            positive(o.caption_size),
+            "caption_size",
+            "expected a positive number",
+        )?;
+        check(
+            o.page_numbers.is_none_or(|p| positive(p.size)),
+            "page_numbers.size",
+            "expected a positive number",
+        )?;
+        check(
+            o.draw_seams.is_none_or(|s| s.width > 0),
+            "draw_seams.width",
+            "expected at least 1 pixel",
+        )?;
+        if let Some(guides) = o.guides {
+            check(
+                (0.0..=0.5).contains(&guides.safe_margin),
+                "guides.safe_margin",
+                "expected 0.0-0.5",
+            )?;
+            check(
+                guides.line_width > 0,
+                "guides.line_width",
+                "expected a size of at least 1",
+            )?;
+        }
+        check(
+            o.background_gradient.is_none_or(|g| g.angle.is_finite()),
+            "background_gradient.angle",
+            "expected a finite number",
+        )?;
+        check(
+            o.preview_max_dimension > 0,
+            "preview_max_dimension",
+            "expected a size of at least 1",
+        )?;
+        check(
+            o.preview_proof_quality
+                .is_none_or(|q| (1..=100).contains(&q)),
+            "preview_proof_quality",
+            "expected 1-100",
+        )?;
+        check(
+            o.chrome_proxy_width != Some(0),
+            "chrome_proxy_width",
+            "expected a size of at least 1",
+        )?;
+        check(
+            o.thumbnail_width != Some(0),
+            "thumbnail_width",
+            "expected a size of at least 1",
+        )?;
+        for (index, image) in o.image_options.iter().enumerate() {
+            check(
+                image.dpi.is_none_or(positive),
+                &format!("image_options[{index}].dpi"),
+                "expected a positive number",
+            )?;
+        }
+
+        Ok(self.options)
+    }
+}
+
+fn default_trim_tolerance() -> u8 {
+    8
+}
+
+fn default_overlap_sensitivity() -> u8 {
+    35
+}
+
+fn default_min_overlap_pixels() -> u32 {
+    crate::overlap::MIN_OVERLAP_PIXELS
+}
+
+fn default_max_upscale_factor() -> f64 {
+    2.0
+}
+
+fn default_allow_upscale() -> bool {
+    true
+}
+
+fn default_strip_chrome() -> bool {
+    true
+}
+
+fn default_preview_max_dimension() -> u32 {
+    1024
+}
+
+fn default_gradient_angle() -> f32 {
+    180.0
+}
+
+fn default_caption_size() -> f32 {
+    16.0
+}
+
+fn default_width_ratio_threshold() -> f32 {
+    crate::overlap::WIDTH_RATIO_THRESHOLD
+}
+
+/// Encoded output of a merge plus metadata about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeOutput {
+    /// Encoded image bytes (PNG).
+    pub bytes: Vec<u8>,
+    /// Output width in pixels.
+    pub width: u32,
+    /// Output height in pixels.
+    pub height: u32,
+    /// Non-fatal degradations encountered during the merge.
+    pub warnings: Vec<MergeWarning>,
+    /// Downscaled rendition, present when `also_preview` was requested.
+    pub preview: Option<PreviewOutput>,
+    /// Fixed-width rendition, present when `thumbnail_width` was set.
+    pub thumbnail: Option<PreviewOutput>,
+    /// BlurHash of the output, present when `blur_hash` was requested.
+    pub blur_hash: Option<String>,
+    /// Transparent full-size guide overlay, present when `guides` asked for a
+    /// separate layer.
+    pub guide_layer: Option<PreviewOutput>,
+    /// Smart mode only: one report per adjacent pair, so hosts can flag
+    /// questionable seams.
+    pub seams: Vec<PairAnalysis>,
+    /// Merge statistics, present when `collect_stats` was set.
+    pub stats: Option<MergeStats>,
+    /// One bundle per Smart mode pair that failed matching, when `repro_bundles` is set.
+    pub repro_bundles: Vec<ReproBundle>,
+    /// Where each input ended up in the output, indexed like the inputs.
+    pub placements: Vec<Placement>,
+}
+
+/// Output rectangle covered by one input image, so hosts can map points on
+/// the output back to their source image.
+///
+/// The rectangle is in output pixels, after `output_rotation`. `crop_top`
+/// and `crop_bottom` count rows of the scaled image that were not drawn
+/// (Smart mode chrome and overlap, unused tile rows); in Smart mode's
+/// keep-all chrome mode the overlap is cut below the kept top bar instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Placement {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub crop_top: u32,
+    pub crop_bottom: u32,
+}
+
+/// Unencoded output of a merge: the final canvas as row-major RGBA8.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawOutput {
+    /// `width * height * 4` bytes, unpremultiplied RGBA.
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Non-fatal degradations encountered during the merge.
+    pub warnings: Vec<MergeWarning>,
+}
+
+/// Auxiliary PNG (preview, thumbnail or guide layer) rendered alongside the full output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreviewOutput {
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MergeOutput {
+    /// Length of the encoded output in bytes.
+    pub fn byte_length(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Lowercase hex SHA-256 digest of the encoded output.
+    pub fn sha256_hex(&self) -> String {
+        to_hex(&Sha256::digest(&self.bytes))
+    }
+}
+
+/// Lowercase hex encoding of `bytes`.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Why a split merge was broken between two images.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitReason {
+    /// No overlap could be detected between the pair.
+    NoOverlap,
+    /// An overlap was found, but with a confidence below the configured minimum.
+    LowConfidence { confidence: f32 },
+}
+
+/// A seam at which a split merge started a new output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeamBreak {
+    /// Index of the last image in the preceding output.
+    pub after_index: usize,
+    pub reason: SplitReason,
+}
+
+/// One page of an image cut by [`paginate`](crate::paginate), or one tile of a
+/// [`merge_tiled`](crate::merge_tiled) output, encoded as PNG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Row of the source image the page starts at.
+    pub y: u32,
+}
+
+/// Result of a tiled merge: the merged image as consecutive tiles, top to
+/// bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledOutput {
+    pub tiles: Vec<Page>,
+    /// Size of the whole merged image.
+    pub width: u32,
+    pub height: u32,
+    pub warnings: Vec<MergeWarning>,
+}
+
+/// How [`merge_to_pdf`](crate::merge_to_pdf) puts a merge on pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PdfLayout {
+    /// The whole merge on a single page of its own size.
+    #[default]
+    LongPage,
+    /// One page per image, showing it as drawn in the merge (without the
+    /// rows Smart mode overlapped away, captions or padding).
+    PerImage,
+}
+
+/// A merge exported as a PDF document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfOutput {
+    pub bytes: Vec<u8>,
+    pub page_count: u32,
+    pub warnings: Vec<MergeWarning>,
+}
+
+/// What [`probe`](crate::probe) reads from an image file's header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageProbe {
+    pub format: ImageFormat,
+    /// Stored size, before `orientation` is applied.
+    pub width: u32,
+    pub height: u32,
+    /// EXIF orientation (1-8); 1 when there is none.
+    pub orientation: u8,
+    pub has_alpha: bool,
+    /// Bits per channel as decoded (palette images count as 8).
+    pub bit_depth: u8,
+}
+
+/// File format of an animation assembled by
+/// [`merge_to_animation`](crate::merge_to_animation).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationFormat {
+    /// Animated PNG: full color and alpha; viewers without APNG support
+    /// show the first frame.
+    #[default]
+    Apng,
+    /// GIF: up to 256 colors per frame, on/off transparency, and delays in
+    /// steps of 10 ms.
+    Gif,
+}
+
+/// Inputs assembled as the frames of an animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationOutput {
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub frame_count: u32,
+    pub warnings: Vec<MergeWarning>,
+}
+
+/// Result of a split merge: one output per run of confidently joined images.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitMergeOutput {
+    pub outputs: Vec<MergeOutput>,
+    /// One entry per boundary between consecutive outputs.
+    pub breaks: Vec<SeamBreak>,
+}
+
+/// Machine-readable summary of one merge, for monitoring batch runs.
+///
+/// Serializes to camelCase JSON.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "camelCase")]
+pub struct MergeStats {
+    pub direction: Direction,
+    /// Size of every input after EXIF orientation.
+    pub inputs: Vec<InputStats>,
+    pub output_width: u32,
+    pub output_height: u32,
+    /// Length of the encoded output.
+    pub output_bytes: usize,
+    /// Smart mode only: one entry per seam.
+    pub seams: Vec<PairAnalysis>,
+    /// Smart mode seams where no overlap was found.
+    pub failed_seams: usize,
+    pub durations: StageDurations,
+    /// Estimated peak memory of decoded inputs, scaled copies and canvas, as
+    /// checked against `max_memory_bytes`.
+    pub peak_bytes_estimate: u64,
+    /// Codes of the warnings raised (e.g. `UPSCALED`).
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+pub struct InputStats {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Wall-clock time spent in each pipeline stage, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "camelCase")]
+pub struct StageDurations {
+    /// Decoding and EXIF orientation; 0 for the outputs of a split merge,
+    /// which share one decode.
+    pub decode_ms: f64,
+    /// Scaling, chrome trimming and overlap detection: `scale_ms` plus
+    /// `analysis_ms`.
+    pub layout_ms: f64,
+    /// Sizing and scaling the inputs.
+    pub scale_ms: f64,
+    /// Smart mode chrome trimming and overlap detection.
+    pub analysis_ms: f64,
+    pub composite_ms: f64,
+    /// Rotation, guides, previews and PNG encoding.
+    pub encode_ms: f64,
+    pub total_ms: f64,
+}
+
+/// Dry-run result: the layout a merge would produce, without compositing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeAnalysis {
+    /// Output width in pixels (padding, snapping and rotation applied).
+    pub width: u32,
+    /// Output height in pixels (padding, snapping and rotation applied).
+    pub height: u32,
+    /// One entry per input image.
+    pub images: Vec<ImageAnalysis>,
+    /// Smart mode only: one entry per adjacent pair.
+    pub pairs: Vec<PairAnalysis>,
+    /// Warnings the merge would report.
+    pub warnings: Vec<MergeWarning>,
+}
+
+/// Planned size and chrome trims of one input image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageAnalysis {
+    /// Size after EXIF orientation.
+    pub source_size: (u32, u32),
+    /// Size the image is drawn at.
+    pub scaled_size: (u32, u32),
+    /// Rows cut from the top (Smart mode chrome).
+    pub trim_top: u32,
+    /// Rows cut from the bottom (Smart mode chrome, or unused tile rows).
+    pub trim_bottom: u32,
+    /// Canvas position of the top-left drawn pixel, before output rotation.
+    pub position: (u32, u32),
+}
+
+/// Detected overlap between images `i` and `i + 1`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[cfg_attr(feature = "tsify", derive(tsify::Tsify))]
+#[serde(rename_all = "camelCase")]
+pub struct PairAnalysis {
+    /// Rows removed from the top of the lower image; 0 if nothing matched.
+    pub overlap: u32,
+    /// Match confidence (0.0-1.0); `None` if nothing matched.
+    pub confidence: Option<f32>,
+    /// Pixels the lower image is shifted right to undo horizontal jitter
+    /// (negative is left); 0 if nothing matched.
+    pub x_shift: i32,
+}
+
+impl PairAnalysis {
+    /// True if the pair was joined by plain concatenation.
+    pub fn fell_back(&self) -> bool {
+        self.overlap == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_sets_fields() {
+        let options = MergeOptions::builder()
+            .direction(Direction::Horizontal)
+            .background(BackgroundColor::black())
+            .sensitivity(70)
+            .spacing(12)
+            .strict(true)
+            .strip_metadata(true)
+            .order(vec![1, 0])
+            .build()
+            .unwrap();
+
+        assert_eq!(options.direction, Direction::Horizontal);
+        assert!(options.strict);
+        assert!(options.strip_metadata);
+        assert_eq!(options.order, Some(vec![1, 0]));
+        assert_eq!(options.background, BackgroundColor::black());
+        assert_eq!(options.overlap_sensitivity, 70);
+        assert_eq!(options.spacing, 12);
+        assert_eq!(
+            options.min_overlap_pixels,
+            MergeOptions::default().min_overlap_pixels
+        );
+    }
+
+    #[test]
+    fn test_builder_rejects_out_of_range_values() {
+        let error = MergeOptions::builder()
+            .sensitivity(101)
+            .build()
+            .unwrap_err();
+        assert_eq!(error.field, "overlap_sensitivity");
+
+        let error = MergeOptions::builder()
+            .pair_sensitivities(vec![Some(50), None, Some(200)])
+            .build()
+            .unwrap_err();
+        assert_eq!(error.field, "overlap_sensitivities[2]");
+
+        let error = MergeOptions::builder()
+            .max_upscale_factor(0.5)
+            .build()
+            .unwrap_err();
+        assert_eq!(error.field, "max_upscale_factor");
+
+        let error = MergeOptions::builder()
+            .image_options(vec![ImageOptions {
+                dpi: Some(0.0),
+                ..ImageOptions::default()
+            }])
+            .build()
+            .unwrap_err();
+        assert_eq!(error.field, "image_options[0].dpi");
+
+        let error = MergeOptions::builder().dpi(-72.0).build().unwrap_err();
+        assert_eq!(error.field, "dpi");
+
+        let error = MergeOptions::builder()
+            .target_aspect_ratio(0.0)
+            .build()
+            .unwrap_err();
+        assert_eq!(error.field, "target_aspect_ratio");
+
+        let error = MergeOptions::builder().columns(0).build().unwrap_err();
+        assert_eq!(error.field, "columns");
+
+        let error = MergeOptions::builder()
+            .contact_sheet(ContactSheet {
+                columns: Some(0),
+                header: None,
+            })
+            .build()
+            .unwrap_err();
+        assert_eq!(error.field, "contact_sheet.columns");
+    }
+
+    #[test]
+    fn test_direction_default() {
+        assert_eq!(Direction::default(), Direction::Vertical);
+    }
+
+    #[test]
+    fn test_background_default() {
+        let bg = BackgroundColor::default();
+        assert_eq!(bg.r, 255);
+        assert_eq!(bg.g, 255);
+        assert_eq!(bg.b, 255);
+        assert_eq!(bg.a, 255);
+    }
+
+    #[test]
+    fn test_background_presets() {
+        let black = BackgroundColor::black();
+        assert_eq!(black.r, 0);
+        assert_eq!(black.a, 255);
+
+        let transparent = BackgroundColor::transparent();
+        assert_eq!(transparent.a, 0);
+    }
+
+    #[test]
+    fn test_options_default() {
+        let opts = MergeOptions::default();
+        assert_eq!(opts.direction, Direction::Vertical);
+        assert_eq!(opts.overlap_sensitivity, default_overlap_sensitivity());
+    }
+
+    #[test]
+    fn test_align_for_prefers_per_image_override() {
+        let opts = MergeOptions {
+            align: Align::Start,
+            image_options: vec![
+                ImageOptions::default(),
+                ImageOptions {
+                    align: Some(Align::End),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(opts.align_for(0), Align::Start);
+        assert_eq!(opts.align_for(1), Align::End);
+        assert_eq!(opts.align_for(5), Align::Start);
+    }
+
+    #[test]
+    fn test_merge_output_checksum() {
+        let output = MergeOutput {
+            bytes: b"abc".to_vec(),
+            width: 1,
+            height: 1,
+            warnings: vec![],
+            preview: None,
+            thumbnail: None,
+            blur_hash: None,
+            guide_layer: None,
+            seams: vec![],
+            stats: None,
+            repro_bundles: vec![],
+            placements: vec![],
+        };
+        assert_eq!(output.byte_length(), 3);
+        assert_eq!(
+            output.sha256_hex(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
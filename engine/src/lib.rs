@@ -1,17 +1,50 @@
-mod chrome_strip;
-mod dimension;
-mod error;
-mod exif;
-mod merge;
-mod overlap;
-mod scale;
-mod types;
-
-pub use error::MergeError;
-pub use types::{BackgroundColor, Direction, MergeOptions};
-
-use js_sys::{Array, Object, Reflect, Uint8Array};
+mod options;
+#[cfg(feature = "threads")]
+mod threads;
+mod typescript;
+
+pub use merge_images_core::{
+    Align, AnimationFormat, AnimationOutput, BackgroundColor, BackgroundFit, BackgroundImage,
+    Border, CaptionPosition, ChromeMode, ContactSheet, Corner, Direction, Divider, ErrorParam,
+    ExifCopy, Gradient, Guides, HookStage, ImageAnalysis, ImageInput, ImageOptions, ImageProbe,
+    InputSource, InputStats, LowColorScaling, MergeAnalysis, MergeError, MergeOptions,
+    MergeOptionsBuilder, MergeOutput, MergeStats, MergeWarning, OptionsError, OverlapRegion,
+    Padding, Page, PageNumbers, PairAnalysis, PdfLayout, PdfOutput, Placement, PreviewOutput,
+    Progress, RawOutput, ReproBundle, Rotation, ScaleMode, SeamBreak, SeamLines, Shadow,
+    SplitMergeOutput, SplitReason, StageDurations, TiledOutput, TrimMargins, VerticalAlign,
+};
+
+use js_sys::{
+    Array, Function, Intl, JSON, Object, Promise, Reflect, SharedArrayBuffer, Uint8Array,
+    Uint8ClampedArray,
+};
+use merge_images_core::{
+    self as merge, ChromeTrim, Clock, ImageFormat, MergeHooks, Session, SteppedMerge,
+    render_template, to_hex,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{JsFuture, future_to_promise};
+
+// The engine's mutable state, besides the `threads` feature's rayon pool:
+// host configuration and the buffers behind input handles and raw outputs.
+// It is thread-local, so each worker sharing the module's memory keeps its
+// own copy and entry points never contend for it; the merge core itself is
+// pure. See `merge_concurrent_safe` for what this means for hosts.
+thread_local! {
+    /// Engine-wide defaults set by `init_engine`; per-call options override them.
+    static ENGINE_DEFAULTS: RefCell<MergeOptions> = RefCell::new(MergeOptions::default());
+    /// Localized error message templates set by `set_message_templates`.
+    static MESSAGE_TEMPLATES: RefCell<Option<MessageTemplates>> = const { RefCell::new(None) };
+    /// Input buffers handed out by `alloc_input`, keyed by their address.
+    static INPUT_BUFFERS: RefCell<HashMap<u32, Vec<u8>>> = RefCell::new(HashMap::new());
+    /// Canvases viewed by `merge_images_raw` results, keyed by their address.
+    static RAW_OUTPUTS: RefCell<HashMap<u32, Vec<u8>>> = RefCell::new(HashMap::new());
+}
 
 /// Initialize the engine (for compatibility check).
 #[wasm_bindgen]
@@ -19,157 +52,1739 @@ pub fn greet() -> String {
     "merge-images-engine initialized".to_string()
 }
 
+/// Reports whether entry points may run concurrently on one module instance.
+///
+/// Always `true`: a host can instantiate the module once and call it from
+/// several workers sharing its memory when threads are enabled. The merge
+/// core keeps no statics or lazily initialized globals, but the bindings
+/// keep per-worker (thread-local) state:
+/// - engine defaults from `init_engine`
+/// - message templates from `set_message_templates`
+/// - input buffers from `alloc_input`, and canvases of `merge_images_raw`
+///   results until `free_raw_output`
+///
+/// Each worker starts from the built-in defaults and templates, so apply
+/// them in every worker that relies on them. They are safe to change at any
+/// time: calls are synchronous and a worker runs one at a time, so a change
+/// applies from that worker's next call on, except that a pending
+/// `merge_images_async` keeps the options it parsed when started. Handles
+/// and raw outputs must be used and freed in the worker that created them.
+/// The `threads` feature's pool is the only state shared between workers;
+/// `initThreadPool` starts it once per module instance.
+#[wasm_bindgen]
+pub fn merge_concurrent_safe() -> bool {
+    true
+}
+
+/// Sets engine-wide default options used by every subsequent merge.
+///
+/// `defaults` is an options object, or its JSON string, with the same shape
+/// as the `options` argument of `merge_images` (e.g. `background`,
+/// `overlapSensitivity`, `maxOutputPixels`). Per-call options override the
+/// defaults field by field. Passing `undefined` or `null` restores the
+/// built-in defaults. The defaults apply to the calling worker only.
+#[wasm_bindgen]
+pub fn init_engine(
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] defaults: &JsValue,
+) -> Result<(), JsValue> {
+    let defaults = match defaults.as_string() {
+        Some(json) => JSON::parse(&json)
+            .map_err(|_| create_code_error("INVALID_OPTIONS", "Defaults are not valid JSON"))?,
+        None => defaults.clone(),
+    };
+    let parsed = parse_options_onto(MergeOptions::default(), &defaults)?;
+    ENGINE_DEFAULTS.with(|d| *d.borrow_mut() = parsed);
+    Ok(())
+}
+
 /// Merges multiple images into a single output image.
 ///
 /// # Arguments
-/// * `images_data` - JS Array of Uint8Array, each containing raw image bytes
+/// * `images_data` - JS Array of inputs, each one of
+///   - a Uint8Array of image file bytes, or an input handle from `alloc_input`
+///     (see there)
+///   - `{ data, name?, mimeType?, frame?, page?, iconSize? }` with `data` as
+///     above; `name` is reported as `fileName` in decode errors, a known
+///     `mimeType` skips format guessing, `frame` picks the frame (from 0) of an
+///     animated GIF or WebP, which otherwise uses its first frame, `page`
+///     likewise picks the page of a multi-page TIFF, and `iconSize` picks the
+///     entry of an ICO whose larger side it is, instead of the largest
+///   - `{ width, height, rgba, name? }` unencoded pixels (`rgba` a Uint8Array
+///     or Uint8ClampedArray of 4 bytes per pixel, such as a canvas's
+///     `ImageData.data`), used without decoding or EXIF orientation
 /// * `options` - JS Object with merge options:
-///   - `direction`: "vertical" | "horizontal" | "smart"
+///   - `direction`: "vertical" | "horizontal" | "smart" | "tiles" | "grid"; grid
+///     scales images to a common width like vertical and places them row by row
+///     in equal cells (`spacing` apart both ways; `divider` is not drawn)
 ///   - `background`: { r, g, b, a } (0-255 each)
-///   - `overlapSensitivity`: 0-100 (smart mode only)
+///   - `backgroundGradient`: `{ start: { r, g, b, a }, end: { r, g, b, a }, angle }`
+///     linear gradient used instead of the solid `background`; `angle` is in
+///     degrees as in CSS `linear-gradient` (default 180, top to bottom)
+///   - `backgroundImage`: `{ bytes: Uint8Array, fit: "tile" | "stretch" }` image
+///     painted over `background` (padding included) under all inputs; tiles
+///     repeat at native size from the top-left (default), stretch covers the
+///     canvas. Fails with `BACKGROUND_DECODE_FAILED` if `bytes` cannot be decoded
+///   - `overlapSensitivity`: 0-100, or an Array of `number | null` with one value
+///     per adjacent pair (`null` keeps the default) (smart mode only)
+///   - `minOverlapPixels`: smallest overlap accepted (smart mode only, default 5)
+///   - `stripChrome`: trim repeated headers/footers before overlap detection
+///     (smart mode only, default true)
+///   - `chromeMode`: "keep-ends" (default) | "remove-all" | "keep-all" — keep
+///     repeated chrome only on the first top and last bottom, strip it
+///     everywhere, or keep all of it (it then only steers overlap matching)
+///     (smart mode only)
+///   - `chromeProxyWidth`: advanced; width of the grayscale proxies used to find
+///     repeated headers/footers (smart mode only, default adapts to the input
+///     width: a third of it, between 320 and 1280)
+///   - `widthRatioThreshold`: 0-1 minimum width ratio for a pair to be matched
+///     (smart mode only, default 0.9)
+///   - `maxUpscaleFactor`: enlargement factor above which an `UPSCALED` warning is
+///     reported (default 2)
+///   - `errorOnUpscale`: fail with `UPSCALE_TOO_LARGE` instead of warning
+///   - `allowUpscale`: `false` keeps smaller images at native size, padded and
+///     aligned like pinned images, instead of enlarging them (default `true`)
+///   - `targetDpi`: resample each image whose resolution is known (per-image
+///     `dpi`, else JFIF/pHYs metadata) to this many dots per inch before
+///     layout, so scans stitch at a consistent physical size; combine with
+///     `scaleMode: "none"` to keep physical sizes in the output
+///   - `dpi`: pixel density recorded in the output's `pHYs` chunk, so it prints
+///     and pastes into documents at that physical size (usually `targetDpi`)
+///   - `lowColorScaling`: "smooth" (default) | "nearest" | "native" — how 1-bit
+///     and paletted PNG inputs (and images with `lowColor: true`) are scaled:
+///     nearest-neighbor when enlarging, keeping edges crisp, or not at all
+///     (padded and aligned like pinned images)
+///   - `linearScaling`: resample inputs, previews and thumbnails in linear light
+///     instead of sRGB (default: false), so downscaled thin text and hairlines
+///     keep their brightness rather than averaging too dark
+///   - `premultipliedAlpha`: weight color by alpha while resampling (default:
+///     false), so transparent inputs keep clean anti-aliased edges instead of
+///     picking up dark fringes from their transparent pixels
+///   - `highBitDepth`: when every input is a 16-bit PNG or TIFF, keep 16 bits
+///     per sample through scaling and compositing and return a 16-bit PNG.
+///     Frames, dividers, seam lines, captions, shadows, `cornerRadius`, page numbers,
+///     drawn-in guides, background gradients and images, and tiles mode are
+///     8-bit only; with any of them (or one 8-bit input) the output is 8-bit.
+///     Previews, thumbnails and `merge_images_raw` stay 8-bit
+///   - `overlapRegions`: Array of `{ x, y, width, height } | null`, one per
+///     adjacent pair, restricting the overlap search in the top image
+///     (smart mode only)
+///   - `overlapOverrides`: Array of `number | null`, one per adjacent pair,
+///     pinning the overlap in scaled pixels instead of detecting it (smart mode only)
+///   - `scaleMode`: "max" (default) | "min" | "none" — scale to the largest or
+///     smallest cross dimension, or keep native sizes and pad
+///   - `spacing`: gap in pixels between adjacent images (not applied in smart mode)
+///   - `maxOutputPixels`: fail with `OUTPUT_TOO_LARGE` above this many pixels
+///   - `downscaleToFit`: instead of failing above `maxOutputPixels` or
+///     `maxMemoryBytes`, shrink every image until the output fits both and report
+///     a `DOWNSCALED` warning (not applied in tiles mode)
+///   - `maxMemoryBytes`: fail with `OUT_OF_MEMORY` (`requiredBytes`, `maxBytes`)
+///     when the estimated peak memory of decoded inputs, scaled copies and the
+///     canvas exceeds this, before anything that large is allocated
+///   - `maxInputPixels`, `maxInputDimension`: fail with `INPUT_TOO_LARGE`
+///     (`fileIndex`, `width`, `height` and the limits set) when any one input's
+///     header reports more pixels, or a longer side, than this, before it is decoded
+///   - `border`: `{ width, color: { r, g, b, a } }` frame drawn around every image
+///     (not applied in smart mode; `color` defaults to opaque black)
+///   - `cornerRadius`: round each image's corners, anti-aliased against the
+///     background (not applied in smart mode)
+///   - `divider`: `{ width, color: { r, g, b, a } }` line between adjacent images,
+///     centered in the `spacing` gap and widening it (not applied in smart mode)
+///   - `drawSeams`: `true` or `{ width, color: { r, g, b, a } }` to draw a line
+///     (default 1 px, opaque magenta) at every junction between adjacent images
+///     in any mode, midway through any gap and over the images otherwise, so
+///     reviewers can see where each capture ends
+///   - `captions`: Array of strings, one label per image drawn in a strip with
+///     the embedded DejaVu Sans font (not applied in smart mode); style with
+///     `captionPosition` ("above" | "below", default below), `captionSize`
+///     (px, default 16) and `captionColor` (`{ r, g, b, a }`, default black)
+///   - `pageNumbers`: `true` or `{ corner, size, color: { r, g, b, a } }` to stamp
+///     "1/3", "2/3", … onto each image; `corner` is "topLeft" | "topRight" |
+///     "bottomLeft" | "bottomRight" (default), `size` is in px (default 16,
+///     capped at the image's height) and `color` defaults to opaque black. In smart mode the number goes on the
+///     visible, trimmed part of each image
+///   - `shadow`: `{ blurRadius, offsetX, offsetY, color: { r, g, b, a } }` soft
+///     shadow beneath every image, clipped to the canvas (leave room with
+///     `spacing`/`padding`; not applied in smart mode; `color` defaults to 50% black)
+///   - `padding`: `{ top, right, bottom, left }` margin around the canvas, or a
+///     single number applied to all sides
+///   - `dimensionMultiple`: pad the output so width and height are multiples of this
+///   - `outputRotation`: 0 | 90 | 180 | 270 degrees clockwise, applied to the
+///     finished canvas (the reported width/height are those of the rotated output)
+///   - `guides`: `true` or `{ thirds?, center?, safeMargin?, lineWidth?, color?,
+///     separateLayer? }` layout guides over the final output: thirds grid (default
+///     on), center lines, and a safe-area rectangle inset by `safeMargin` (0-0.5 of
+///     each side). `color` defaults to translucent magenta; with `separateLayer`
+///     the guides are returned as `guideLayer` instead of drawn into the output
+///   - `align`: "start" | "center" | "end" horizontal alignment in vertical/smart/grid mode
+///   - `verticalAlign`: "top" | "middle" | "bottom" vertical alignment in horizontal/grid mode
+///   - `targetAspectRatio`: width / height the grid mode output should come closest
+///     to, e.g. `16 / 9` for slides; the column count is chosen for it, padding and
+///     `outputRotation` included (default: closest to square)
+///   - `columns`: fixed column count for grid mode (contact sheets); each row is
+///     then as tall as its tallest image, the others padded and aligned in their
+///     cells, and `targetAspectRatio` is ignored
+///   - `contactSheet`: `true` or `{ columns?, header? }` to lay the images out as a
+///     reviewable sheet in one call: grid mode (`columns` as above), every image
+///     without a caption captioned with its file name (`name` or `names`),
+///     `spacing` and `padding` of 16 where they are 0, and `header` drawn above
+///     the grid at 1.5x `captionSize`
+///   - `imageOptions`: Array of per-image overrides `{ align?, pin?, lowColor?,
+///     dpi?, tileOffset?, tileHeight?, flipH?, flipV? }`, indexed like `images_data`; `pin: true` keeps an image at
+///     native resolution (padded, not scaled); `flipH`/`flipV` mirror an image
+///     (after EXIF orientation, before scaling), e.g. for mirrored scans
+///   - `names`: Array of `string | null` file names indexed like `images_data`,
+///     reported as `fileName` in decode errors for inputs given without a `name`
+///   - `order`: Permutation of the indices of `images_data`; the input at
+///     `order[i]` is merged at position `i`, so a host can reorder images without
+///     re-sending them. `names` follow their input; every other per-image option,
+///     and `fileIndex` in errors and warnings, refers to merge positions. Throws
+///     `INVALID_ORDER` (`imageCount`) unless every index is listed exactly once
+///   - `trimMargins`: `true` or `{ tolerance? }` to crop solid-color margins
+///     (letterboxing, a flat backdrop around a window) off every input before
+///     layout; `tolerance` (0-255, default 8) is the largest per-channel
+///     difference from the edge color still counted as margin. Placements
+///     and `imageOptions` offsets refer to the trimmed images
+///   - `strict`: fail with `STRICT_VIOLATION` (`fileIndex`, `reason`) instead of
+///     silently falling back in smart mode, when a pair is too different in width
+///     to be matched or repeated chrome is kept because trimming it would leave
+///     too little content, and when an animated input without a `frame` would be
+///     flattened to its first frame. Unknown options and non-finite numbers are always
+///     rejected with `INVALID_OPTIONS`
+///   - `copyExif`: `true` or `{ captureTime?, camera?, gps? }` to copy those
+///     EXIF fields of the first input (JPEG or WebP) into the output's
+///     `eXIf` chunk; `true` copies capture time and camera, and GPS is only
+///     copied when `gps: true` is given. Otherwise the output carries no EXIF
+///   - `provenance`: record the engine version, direction, overlap sensitivity
+///     and each image's placement (`{ x, y, width, height }`) as JSON in a
+///     `tEXt` chunk with the keyword `merge-images`
+///   - `stripMetadata`: guarantee the output has no EXIF, XMP or text chunks
+///     (privacy mode), even with `copyExif`; only the `dpi` density is kept.
+///     Input metadata such as GPS location is never copied unless asked for
+///
+/// In tiles mode images (e.g. devtools full-page capture tiles) are placed
+/// unscaled at `tileOffset` (default: right after the previous tile) using their
+/// top `tileHeight` rows (default: all), with no overlap detection. Scaling,
+/// spacing, borders, dividers, captions and shadows are ignored.
+///
+/// Options not given fall back to the defaults set by `init_engine`. Unknown
+/// fields, values of the wrong type and unknown keywords throw `INVALID_OPTIONS`
+/// with the path of the offending field in `field` (e.g. `imageOptions[1].dpi`).
 ///
 /// # Returns
 /// * On success: Uint8Array containing PNG-encoded output
 /// * On error: throws a JS error with structured details
 #[wasm_bindgen]
-pub fn merge_images(images_data: &Array, options: &JsValue) -> Result<Uint8Array, JsValue> {
-    // Validate input array
-    let length = images_data.length();
-    if length == 0 {
-        return Err(create_error_object(&MergeError::NoImages));
+pub fn merge_images(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+) -> Result<Uint8Array, JsValue> {
+    let output_bytes = run_merge(images_data, options)?;
+    let result = Uint8Array::new_with_length(output_bytes.len() as u32);
+    result.copy_from(&output_bytes);
+    Ok(result)
+}
+
+/// Encoded merge output kept in wasm memory for piecewise retrieval.
+///
+/// Lets the host copy very large outputs out in chunks instead of allocating
+/// one giant `Uint8Array`. Call `free()` once all chunks have been read.
+#[wasm_bindgen]
+pub struct MergeResult {
+    bytes: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl MergeResult {
+    /// Total length of the encoded output in bytes.
+    pub fn result_len(&self) -> u32 {
+        self.bytes.len() as u32
+    }
+
+    /// Copies up to `len` bytes starting at `offset` into a new `Uint8Array`.
+    ///
+    /// Reads past the end are truncated; an `offset` beyond the end yields an
+    /// empty array.
+    pub fn read_result(&self, offset: u32, len: u32) -> Uint8Array {
+        let start = (offset as usize).min(self.bytes.len());
+        let end = start.saturating_add(len as usize).min(self.bytes.len());
+        Uint8Array::from(&self.bytes[start..end])
+    }
+}
+
+/// Merges images like `merge_images`, but keeps the output inside wasm memory.
+///
+/// The returned `MergeResult` exposes `result_len()` and
+/// `read_result(offset, len)` for chunked retrieval.
+#[wasm_bindgen]
+pub fn merge_images_chunked(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+) -> Result<MergeResult, JsValue> {
+    let bytes = run_merge(images_data, options)?;
+    Ok(MergeResult { bytes })
+}
+
+/// Allocates a `len`-byte input buffer inside wasm memory and returns its
+/// address, which doubles as the input's handle.
+///
+/// Write the file bytes through `new Uint8Array(memory.buffer, ptr, len)`,
+/// then pass `ptr` in place of a `Uint8Array` in `images_data`; the engine
+/// takes the buffer over without copying it. Create the view right before
+/// writing, since any engine call may grow memory and detach older views.
+/// Each handle is consumed by the first call that reads it, unless the call
+/// is rejected for invalid inputs or options, which are checked before any
+/// handle is taken; release unused
+/// ones with `free_input`. Handles belong to the worker that allocated them.
+#[wasm_bindgen]
+pub fn alloc_input(len: u32) -> u32 {
+    // At least one byte of capacity, so every buffer gets a distinct address
+    let mut buffer = Vec::with_capacity((len as usize).max(1));
+    buffer.resize(len as usize, 0);
+    let ptr = buffer.as_ptr() as usize as u32;
+    INPUT_BUFFERS.with(|buffers| buffers.borrow_mut().insert(ptr, buffer));
+    ptr
+}
+
+/// Releases an input buffer from `alloc_input` that was never merged.
+///
+/// Returns `false` if `ptr` is not a live handle.
+#[wasm_bindgen]
+pub fn free_input(ptr: u32) -> bool {
+    INPUT_BUFFERS.with(|buffers| buffers.borrow_mut().remove(&ptr).is_some())
+}
+
+/// Images decoded once and kept in wasm memory across merges.
+///
+/// Editors that re-merge after every change (an option tweak, one more
+/// screenshot, a new order) only pay for decoding the images they add.
+/// Call `free()` when the session is no longer needed.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct MergeSession {
+    inner: Session,
+}
+
+#[wasm_bindgen]
+impl MergeSession {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> MergeSession {
+        MergeSession::default()
     }
 
-    // Parse images array with bounds checking, avoiding unnecessary copies
-    let mut images: Vec<Vec<u8>> = Vec::with_capacity(length as usize);
-    for i in 0..length {
-        let item = images_data.get(i);
-        if !item.is_instance_of::<Uint8Array>() {
+    /// Number of images in the session.
+    pub fn image_count(&self) -> u32 {
+        self.inner.len() as u32
+    }
+
+    /// Decodes `input` (any input `merge_images` accepts) and appends the
+    /// image, returning its index.
+    ///
+    /// Throws `INVALID_INPUT` for anything else, or `DECODE_FAILED` (leaving
+    /// the session unchanged) if the image cannot be decoded.
+    pub fn add_image(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "MergeInput")] input: &JsValue,
+    ) -> Result<u32, JsValue> {
+        let index = self.inner.len();
+        let input = parse_input(input).map_err(|message| invalid_input_error(index, message))?;
+        self.inner
+            .add_image(take_input(input))
+            .map(|index| index as u32)
+            .map_err(|e| create_error_object(&e))
+    }
+
+    /// Removes the image at `index`; throws `INVALID_INDEX` if there is none.
+    pub fn remove_image(&mut self, index: u32) -> Result<(), JsValue> {
+        if !self.inner.remove_image(index as usize) {
+            return Err(create_code_error("INVALID_INDEX", "No image at that index"));
+        }
+        Ok(())
+    }
+
+    /// Reorders the images: `indices[i]` is the current index of the image
+    /// to place at position `i`. Throws `INVALID_ORDER`, changing nothing,
+    /// unless `indices` lists every current index exactly once.
+    pub fn reorder(&mut self, indices: &[u32]) -> Result<(), JsValue> {
+        let order: Vec<usize> = indices.iter().map(|&i| i as usize).collect();
+        if !self.inner.reorder(&order) {
+            return Err(create_code_error(
+                "INVALID_ORDER",
+                "Indices must list every image exactly once",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Merges the session's images in their current order.
+    ///
+    /// Takes the same options and returns the same object as
+    /// `merge_images_ex`; `NO_IMAGES` is thrown for an empty session.
+    #[wasm_bindgen(unchecked_return_type = "MergeOutput")]
+    pub fn merge(
+        &self,
+        #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let merge_options = parse_options(options)?;
+        let output = self
+            .inner
+            .merge(merge_options)
+            .map_err(|e| create_error_object(&e))?;
+        Ok(create_output_object(&output))
+    }
+}
+
+/// Merges images and returns the unencoded RGBA canvas for direct display.
+///
+/// Skips PNG encoding entirely, and the pixels are not copied out of wasm
+/// memory either: `pixels` is a view of the canvas where the engine left it,
+/// which stays allocated until `free_raw_output(ptr)` is called. The view
+/// can be wrapped in an `ImageData` and drawn into an `OffscreenCanvas`.
+///
+/// Lifetime rules:
+/// - Use the view before the next engine call. Any call may grow wasm
+///   memory, which detaches views of an unshared memory (they become empty);
+///   views of a threaded build's shared memory stay valid.
+/// - The view cannot be transferred. To keep the pixels past the next call
+///   or hand them to another thread, copy them with `pixels.slice()`.
+/// - Call `free_raw_output(ptr)` once done; the view must not be read after.
+///   Canvases are never freed otherwise, and belong to the worker that
+///   merged them.
+///
+/// Output rotation and drawn-in guides are applied; preview, thumbnail,
+/// BlurHash and separate guide layer options are ignored.
+///
+/// # Returns
+/// * On success: `{ pixels, ptr, width, height, shared, warnings }` where
+///   `pixels` is a `Uint8ClampedArray` of `width * height * 4` RGBA bytes,
+///   `ptr` the handle to free it with, and `shared` tells whether wasm
+///   memory (and so the view) is backed by a `SharedArrayBuffer`
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "RawOutput")]
+pub fn merge_images_raw(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let output = merge::merge_raw(images, merge_options).map_err(|e| create_error_object(&e))?;
+
+    let obj = Object::new();
+    set_field(&obj, "width", &JsValue::from_f64(output.width as f64));
+    set_field(&obj, "height", &JsValue::from_f64(output.height as f64));
+    let warnings: Array = output.warnings.iter().map(create_warning_object).collect();
+    set_field(&obj, "warnings", &warnings);
+
+    // The view is taken last, so that nothing allocates (and may grow
+    // memory, detaching it) before it is returned
+    let ptr = output.pixels.as_ptr() as usize as u32;
+    let pixels = RAW_OUTPUTS.with(|outputs| {
+        let mut outputs = outputs.borrow_mut();
+        let canvas = outputs.entry(ptr).insert_entry(output.pixels).into_mut();
+        // SAFETY: the canvas stays in `RAW_OUTPUTS`, unmoved, until
+        // `free_raw_output`, and hosts must not read the view after that.
+        unsafe { Uint8ClampedArray::view(canvas) }
+    });
+    let shared = pixels.buffer().is_instance_of::<SharedArrayBuffer>();
+    set_field(&obj, "pixels", &pixels);
+    set_field(&obj, "ptr", &JsValue::from_f64(ptr as f64));
+    set_field(&obj, "shared", &JsValue::from_bool(shared));
+    Ok(obj.into())
+}
+
+/// Releases the canvas of a `merge_images_raw` result, given its `ptr`.
+///
+/// Returns `false` if `ptr` is not a live canvas.
+#[wasm_bindgen]
+pub fn free_raw_output(ptr: u32) -> bool {
+    RAW_OUTPUTS.with(|outputs| outputs.borrow_mut().remove(&ptr).is_some())
+}
+
+/// Merges images and returns the output together with metadata.
+///
+/// # Returns
+/// * On success: `{ bytes, width, height, byteLength, sha256, warnings, seams,
+///   placements }` where `sha256` is the lowercase hex digest of `bytes`, so hosts can verify
+///   integrity after transferring or storing the output, `warnings` is an
+///   array of `{ code, message, ...params }` for non-fatal issues (e.g. `UPSCALED`,
+///   or `OVERLAP_NOT_FOUND` when a smart mode pair was concatenated),
+///   and `seams` holds one `{ overlap, xShift, found, fellBack, confidence? }`
+///   per adjacent pair in smart mode (empty otherwise): `found` is whether an
+///   overlap matched, `fellBack` whether the pair was joined by plain
+///   concatenation, so questionable seams can be flagged. `placements` holds
+///   one `{ x, y, width, height, cropTop, cropBottom }` per input: the output
+///   rectangle it covers (after `outputRotation`) and the rows of the scaled
+///   image not drawn (Smart mode chrome and overlap, unused tile rows), so
+///   clicks on the output can be mapped back to source images.
+///   With `alsoPreview: true` the object also has `preview: { bytes, width, height }`,
+///   a downscaled PNG (longest side `previewMaxDimension`, default 1024) rendered
+///   from the same canvas, so no second decode or overlap search is needed.
+///   With `previewProofQuality: q` (1-100) the preview is round-tripped through
+///   JPEG at quality `q` first, simulating lossy output so its artifacts can be
+///   judged before the full-resolution merge.
+///   With `thumbnailWidth: n` it also has `thumbnail: { bytes, width, height }`,
+///   a PNG at most `n` px wide with the output's aspect ratio, for galleries
+///   and history lists. With `blurHash: true` it also has `blurHash`, a
+///   BlurHash placeholder string for the output.
+///   With `guides: { separateLayer: true }` it also has `guideLayer:
+///   { bytes, width, height }`, a transparent PNG of the guides alone.
+///   With `collectStats: true` it also has `stats: { direction, inputs,
+///   outputWidth, outputHeight, outputBytes, seams, failedSeams, durations,
+///   peakBytesEstimate, warnings }`, a JSON-compatible summary for batch
+///   monitoring: `inputs` holds `{ width, height }` per input, `seams` holds
+///   `{ overlap, xShift, confidence }` per smart mode pair (`confidence` is
+///   `null` where nothing matched), `durations` holds `{ decodeMs, scaleMs,
+///   analysisMs, layoutMs, compositeMs, encodeMs, totalMs }` (`layoutMs` is
+///   scaling and analysis together), `peakBytesEstimate` is the estimated peak
+///   memory checked against `maxMemoryBytes` and `warnings` holds the warning codes
+///   With `reproBundles: true` (smart mode) it also has `reproBundles`, one per
+///   pair where no overlap was found: `{ pairIndex, overlapSensitivity,
+///   minOverlapPixels, widthRatioThreshold, topSize, bottomSize, topTrimBottom,
+///   bottomTrimTop, topStrip, bottomStrip }`, where the strips are 96 px wide
+///   grayscale PNG proxies suitable for attaching to a bug report
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "MergeOutput")]
+pub fn merge_images_ex(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let output =
+        merge::merge_with_metadata(images, merge_options).map_err(|e| create_error_object(&e))?;
+
+    Ok(create_output_object(&output))
+}
+
+/// Milliseconds `merge_images_async` works before yielding to the event loop,
+/// half a frame at 60 Hz.
+const ASYNC_SLICE_MS: f64 = 8.0;
+
+/// Merges images like `merge_images_ex` without blocking the event loop, for
+/// hosts that cannot run the engine in a worker.
+///
+/// The merge runs in steps (decoding or scaling one input, laying out, Smart
+/// mode chrome trims or one pair's overlap, drawing a band of about a
+/// million pixels, encoding) and returns to the event loop whenever a slice
+/// of about 8 ms has been used, so the page keeps rendering and handling
+/// input. Each step still blocks while it runs: decoding a very large input
+/// and encoding are single steps. Inputs and options are read
+/// before the first yield, so `alloc_input` handles are taken immediately and
+/// later changes to the arrays do not affect the merge.
+///
+/// # Returns
+/// * On success: a Promise of the `merge_images_ex` object
+/// * On error: a Promise rejected with the error `merge_images_ex` would throw
+#[wasm_bindgen(unchecked_return_type = "Promise<MergeOutput>")]
+pub fn merge_images_async(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+) -> Promise {
+    let merge = parse_options(options).and_then(|merge_options| {
+        let images = parse_images(images_data)?;
+        SteppedMerge::new(images, merge_options).map_err(|e| create_error_object(&e))
+    });
+    let mut merge = match merge {
+        Ok(merge) => merge,
+        Err(error) => return Promise::reject(&error),
+    };
+    future_to_promise(async move {
+        let mut slice_start = js_sys::Date::now();
+        loop {
+            if let Some(output) = merge.step().map_err(|e| create_error_object(&e))? {
+                return Ok(create_output_object(&output));
+            }
+            if js_sys::Date::now() - slice_start >= ASYNC_SLICE_MS {
+                yield_to_event_loop().await?;
+                slice_start = js_sys::Date::now();
+            }
+        }
+    })
+}
+
+/// Resolves on a later task of the event loop, after pending rendering and
+/// input; resolves right away on hosts without `setTimeout`.
+async fn yield_to_event_loop() -> Result<(), JsValue> {
+    let promise = Promise::new(&mut |resolve, _reject| {
+        let set_timeout = Reflect::get(&js_sys::global(), &JsValue::from_str("setTimeout"))
+            .ok()
+            .and_then(|f| f.dyn_into::<Function>().ok());
+        let scheduled = set_timeout
+            .map(|set_timeout| set_timeout.call2(&JsValue::UNDEFINED, &resolve, &0.into()));
+        if !matches!(scheduled, Some(Ok(_))) {
+            let _ = resolve.call0(&JsValue::UNDEFINED);
+        }
+    });
+    JsFuture::from(promise).await.map(drop)
+}
+
+/// Bytes collected before `merge_images_streaming` hands a chunk to JS.
+const STREAM_CHUNK_BYTES: usize = 1 << 16;
+
+/// Merges images like `merge_images_ex`, streaming the encoded PNG to a
+/// callback instead of returning it.
+///
+/// `on_chunk(chunk)` receives consecutive `Uint8Array` pieces of the PNG (about
+/// 64 KiB each) as they are compressed; concatenated they form the complete
+/// file. A worker can forward them to a `WritableStream` or post them to the
+/// page, so the encoded output never has to fit in memory next to the
+/// canvas. A callback that throws fails the merge with `INTERNAL_ERROR`.
+///
+/// Only the encoding streams; the canvas is composited whole first. Peak
+/// memory is what `maxMemoryBytes` checks (decoded inputs, scaled copies,
+/// canvas), plus any previews and about 128 KiB of encoder and chunk
+/// buffers, however large the PNG.
+///
+/// # Returns
+/// * On success: the `merge_images_ex` object without `bytes`; `byteLength`
+///   and `sha256` describe the streamed PNG
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "Omit<MergeOutput, \"bytes\">")]
+pub fn merge_images_streaming(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+    on_chunk: &Function,
+) -> Result<JsValue, JsValue> {
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let mut sink = JsChunkSink {
+        on_chunk,
+        buffer: Vec::with_capacity(STREAM_CHUNK_BYTES),
+        hasher: Sha256::new(),
+        length: 0,
+    };
+    let output = merge::merge_to_writer(images, merge_options, &mut sink)
+        .map_err(|e| create_error_object(&e))?;
+
+    let obj = Object::new();
+    set_field(&obj, "width", &JsValue::from_f64(output.width as f64));
+    set_field(&obj, "height", &JsValue::from_f64(output.height as f64));
+    set_field(&obj, "byteLength", &JsValue::from_f64(sink.length as f64));
+    set_field(
+        &obj,
+        "sha256",
+        &JsValue::from_str(&to_hex(&sink.hasher.finalize())),
+    );
+    set_output_details(&obj, &output);
+    Ok(obj.into())
+}
+
+/// Hands written bytes to a JS callback in chunks of `STREAM_CHUNK_BYTES`,
+/// hashing and counting them on the way.
+struct JsChunkSink<'a> {
+    on_chunk: &'a Function,
+    buffer: Vec<u8>,
+    hasher: Sha256,
+    length: usize,
+}
+
+impl Write for JsChunkSink<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.hasher.update(buf);
+        self.length += buf.len();
+        if self.buffer.len() >= STREAM_CHUNK_BYTES {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            let chunk = Uint8Array::from(self.buffer.as_slice());
+            self.on_chunk
+                .call1(&JsValue::NULL, &chunk)
+                .map_err(|_| std::io::Error::other("onChunk callback threw"))?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+/// Merges images like `merge_images_ex`, calling host hooks between stages.
+///
+/// `hooks` is an object with optional callbacks, each receiving an
+/// intermediate artifact:
+/// * `afterDecode(dimensions)` — `[{ width, height }]` of the decoded images
+/// * `afterTrims(trims)` — smart mode chrome trims `[{ top, bottom }]`; return
+///   an array of the same shape to replace them
+/// * `afterMatching(overlaps)` — smart mode overlap per adjacent pair in
+///   pixels; return an array to replace them (0 vetoes an overlap)
+/// * `afterAlignment(xShifts)` — smart mode horizontal jitter correction per
+///   adjacent pair, in pixels the lower image shifts right; return an array to
+///   replace them
+///
+/// A hook that returns `false` or throws rejects the merge with
+/// `HOOK_REJECTED` (`stage` and `reason` fields). Any other return value
+/// leaves the artifact unchanged.
+///
+/// An optional `onProgress({ stage, index?, total? })` callback is called as
+/// the merge advances: `stage` is `"decoded"`, `"scaled"` or `"overlap"` with
+/// the 0-based `index` of `total` inputs (or pairs) just finished, then
+/// `"compositing"` and `"encoding"` without `index`/`total`. Its return value
+/// and exceptions are ignored.
+///
+/// An optional `isCancelled()` callback is polled at the same points, and
+/// while scaling an input or matching a pair about every 16 million
+/// multiply-adds; once it returns a truthy value the merge stops with
+/// `CANCELLED`. A worker busy
+/// merging cannot receive messages, so the callback should read a flag the
+/// page sets through shared memory (e.g. `Atomics.load` on a
+/// `SharedArrayBuffer`).
+#[wasm_bindgen(unchecked_return_type = "MergeOutput")]
+pub fn merge_images_with_hooks(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+    hooks: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let mut js_hooks = JsHooks { hooks };
+    let output = merge::merge_with_hooks(images, merge_options, &mut js_hooks)
+        .map_err(|e| create_error_object(&e))?;
+    Ok(create_output_object(&output))
+}
+
+/// Adapts a JS object of optional stage callbacks to `MergeHooks`.
+struct JsHooks<'a> {
+    hooks: &'a JsValue,
+}
+
+impl JsHooks<'_> {
+    /// `hooks[name]`, if it is a function.
+    fn callback(&self, name: &str) -> Option<Function> {
+        Reflect::get(self.hooks, &JsValue::from_str(name))
+            .ok()
+            .and_then(|v| v.dyn_into::<Function>().ok())
+    }
+
+    /// Calls `hooks[name](arg)`; `Ok(None)` when no such callback exists.
+    fn call(&self, name: &str, arg: &JsValue) -> Result<Option<JsValue>, String> {
+        let Some(callback) = self.callback(name) else {
+            return Ok(None);
+        };
+        let result = callback.call1(&JsValue::NULL, arg).map_err(|error| {
+            Reflect::get(&error, &JsValue::from_str("message"))
+                .ok()
+                .and_then(|m| m.as_string())
+                .or_else(|| error.as_string())
+                .unwrap_or_else(|| format!("{} hook threw", name))
+        })?;
+        if result.as_bool() == Some(false) {
+            return Err(format!("{} hook returned false", name));
+        }
+        Ok(Some(result).filter(Array::is_array))
+    }
+}
+
+impl MergeHooks for JsHooks<'_> {
+    fn progress(&mut self, progress: Progress) {
+        let obj = Object::new();
+        set_field(&obj, "stage", &JsValue::from_str(progress.name()));
+        if let Some((index, total)) = progress.position() {
+            set_field(&obj, "index", &JsValue::from_f64(index as f64));
+            set_field(&obj, "total", &JsValue::from_f64(total as f64));
+        }
+        // Progress is informational; a failing callback must not abort the merge
+        let _ = self.call("onProgress", &obj);
+    }
+
+    fn is_cancelled(&mut self) -> bool {
+        self.callback("isCancelled")
+            .and_then(|callback| callback.call0(&JsValue::NULL).ok())
+            .is_some_and(|result| result.is_truthy())
+    }
+
+    fn after_decode(&mut self, dimensions: &[(u32, u32)]) -> Result<(), String> {
+        let arg: Array = dimensions
+            .iter()
+            .map(|(width, height)| {
+                let obj = Object::new();
+                set_field(&obj, "width", &JsValue::from_f64(*width as f64));
+                set_field(&obj, "height", &JsValue::from_f64(*height as f64));
+                JsValue::from(obj)
+            })
+            .collect();
+        self.call("afterDecode", &arg)?;
+        Ok(())
+    }
+
+    fn after_trims(&mut self, trims: &mut [ChromeTrim]) -> Result<(), String> {
+        let arg: Array = trims
+            .iter()
+            .map(|trim| {
+                let obj = Object::new();
+                set_field(&obj, "top", &JsValue::from_f64(trim.top as f64));
+                set_field(&obj, "bottom", &JsValue::from_f64(trim.bottom as f64));
+                JsValue::from(obj)
+            })
+            .collect();
+        if let Some(result) = self.call("afterTrims", &arg)? {
+            for (trim, item) in trims.iter_mut().zip(Array::from(&result).iter()) {
+                trim.top = get_u32_field(&item, "top").unwrap_or(trim.top);
+                trim.bottom = get_u32_field(&item, "bottom").unwrap_or(trim.bottom);
+            }
+        }
+        Ok(())
+    }
+
+    fn after_matching(&mut self, overlaps: &mut [u32]) -> Result<(), String> {
+        let arg: Array = overlaps
+            .iter()
+            .map(|overlap| JsValue::from_f64(*overlap as f64))
+            .collect();
+        if let Some(result) = self.call("afterMatching", &arg)? {
+            for (overlap, item) in overlaps.iter_mut().zip(Array::from(&result).iter()) {
+                if let Some(value) = item.as_f64().filter(|v| v.is_finite() && *v >= 0.0) {
+                    *overlap = value.round().min(u32::MAX as f64) as u32;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn after_alignment(&mut self, x_shifts: &mut [i32]) -> Result<(), String> {
+        let arg: Array = x_shifts
+            .iter()
+            .map(|shift| JsValue::from_f64(*shift as f64))
+            .collect();
+        if let Some(result) = self.call("afterAlignment", &arg)? {
+            for (shift, item) in x_shifts.iter_mut().zip(Array::from(&result).iter()) {
+                if let Some(value) = item.as_f64().filter(|v| v.is_finite()) {
+                    *shift = value.round().clamp(i32::MIN as f64, i32::MAX as f64) as i32;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Plans a merge without compositing it (dry run).
+///
+/// Runs decoding, layout and, in smart mode, chrome trimming and overlap
+/// detection, so UIs can show the planned result and let users adjust
+/// options before running the expensive merge.
+///
+/// # Returns
+/// * On success: `{ width, height, images, pairs, warnings }` where `width` and
+///   `height` are the final output size, `images` holds one `{ sourceWidth,
+///   sourceHeight, width, height, trimTop, trimBottom, x, y }` per input (the
+///   size it is drawn at, the rows cut away and the canvas position of its
+///   top-left drawn pixel before output rotation), `pairs` holds one
+///   `{ overlap, xShift, found, fellBack, confidence? }` per adjacent pair in smart mode
+///   (`xShift` is the horizontal jitter correction applied to the lower image;
+///   `confidence` is absent where nothing matched), and `pairs` and `warnings`
+///   are as for `merge_images_ex`'s `seams` and `warnings`
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "MergeAnalysis")]
+pub fn analyze_images(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let analysis = merge::analyze(images, merge_options).map_err(|e| create_error_object(&e))?;
+    Ok(create_analysis_object(&analysis).into())
+}
+
+/// Plans a merge for [`execute_plan`] (phase one of a two-phase merge).
+///
+/// Returns the same object as `analyze_images`, plus an `options` field
+/// holding the options it was made with. Hosts may edit `pairs[i].overlap`,
+/// `pairs[i].xShift`, `images[i].trimTop` and `images[i].trimBottom` (Smart
+/// mode) before passing the plan to `execute_plan`.
+///
+/// # Returns
+/// * On success: the plan object
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "MergePlan")]
+pub fn plan_images(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let analysis = merge::analyze(images, merge_options).map_err(|e| create_error_object(&e))?;
+    let obj = create_analysis_object(&analysis);
+    set_field(&obj, "options", options);
+    Ok(obj.into())
+}
+
+/// Composites a plan made by `plan_images` (phase two of a two-phase merge).
+///
+/// The chrome trims, overlaps and horizontal shifts in the plan are used as
+/// given, without detecting them again; `images_data` must be the images the
+/// plan was made from.
+///
+/// # Returns
+/// * On success: the same object as `merge_images_ex`
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "MergeOutput")]
+pub fn execute_plan(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergePlan")] plan: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = Reflect::get(plan, &JsValue::from_str("options")).unwrap_or(JsValue::UNDEFINED);
+    let merge_options = parse_options(&options)?;
+    let images = parse_images(images_data)?;
+    let analysis = parse_plan(plan);
+    let output = merge::execute_plan(images, merge_options, &analysis)
+        .map_err(|e| create_error_object(&e))?;
+    Ok(create_output_object(&output))
+}
+
+/// Builds the `{ width, height, images, pairs, warnings }` analysis object.
+fn create_analysis_object(analysis: &MergeAnalysis) -> Object {
+    let number = |value: u32| JsValue::from_f64(value as f64);
+    let image_objects: Array = analysis
+        .images
+        .iter()
+        .map(|image| {
             let obj = Object::new();
-            let _ = Reflect::set(
-                &obj,
-                &JsValue::from_str("code"),
-                &JsValue::from_str("INVALID_INPUT"),
-            );
-            let _ = Reflect::set(
-                &obj,
-                &JsValue::from_str("message"),
-                &JsValue::from_str("Expected Uint8Array at index"),
-            );
-            let _ = Reflect::set(
+            set_field(&obj, "sourceWidth", &number(image.source_size.0));
+            set_field(&obj, "sourceHeight", &number(image.source_size.1));
+            set_field(&obj, "width", &number(image.scaled_size.0));
+            set_field(&obj, "height", &number(image.scaled_size.1));
+            set_field(&obj, "trimTop", &number(image.trim_top));
+            set_field(&obj, "trimBottom", &number(image.trim_bottom));
+            set_field(&obj, "x", &number(image.position.0));
+            set_field(&obj, "y", &number(image.position.1));
+            JsValue::from(obj)
+        })
+        .collect();
+    let pair_objects: Array = analysis.pairs.iter().map(create_pair_object).collect();
+    let warnings: Array = analysis
+        .warnings
+        .iter()
+        .map(create_warning_object)
+        .collect();
+
+    let obj = Object::new();
+    set_field(&obj, "width", &number(analysis.width));
+    set_field(&obj, "height", &number(analysis.height));
+    set_field(&obj, "images", &image_objects);
+    set_field(&obj, "pairs", &pair_objects);
+    set_field(&obj, "warnings", &warnings);
+    obj
+}
+
+/// Reads a plan object back into a [`MergeAnalysis`]; missing numbers are 0.
+fn parse_plan(plan: &JsValue) -> MergeAnalysis {
+    let array = |name: &str| {
+        Reflect::get(plan, &JsValue::from_str(name))
+            .ok()
+            .filter(Array::is_array)
+            .map(|value| Array::from(&value))
+            .unwrap_or_default()
+    };
+    let unsigned = |obj: &JsValue, name: &str| {
+        get_f64_field(obj, name)
+            .filter(|v| v.is_finite())
+            .map_or(0, |v| v.round().clamp(0.0, u32::MAX as f64) as u32)
+    };
+
+    let images = array("images")
+        .iter()
+        .map(|image| ImageAnalysis {
+            source_size: (
+                unsigned(&image, "sourceWidth"),
+                unsigned(&image, "sourceHeight"),
+            ),
+            scaled_size: (unsigned(&image, "width"), unsigned(&image, "height")),
+            trim_top: unsigned(&image, "trimTop"),
+            trim_bottom: unsigned(&image, "trimBottom"),
+            position: (unsigned(&image, "x"), unsigned(&image, "y")),
+        })
+        .collect();
+    let pairs = array("pairs")
+        .iter()
+        .map(|pair| PairAnalysis {
+            overlap: unsigned(&pair, "overlap"),
+            confidence: get_f64_field(&pair, "confidence").map(|c| c as f32),
+            x_shift: get_f64_field(&pair, "xShift")
+                .filter(|v| v.is_finite())
+                .map_or(0, |v| {
+                    v.round().clamp(i32::MIN as f64, i32::MAX as f64) as i32
+                }),
+        })
+        .collect();
+
+    MergeAnalysis {
+        width: unsigned(plan, "width"),
+        height: unsigned(plan, "height"),
+        images,
+        pairs,
+        warnings: Vec::new(),
+    }
+}
+
+/// Computes a BlurHash placeholder string for every input image.
+///
+/// Images are decoded and EXIF-oriented as for a merge, so each hash matches
+/// how the image appears in the output.
+///
+/// # Returns
+/// * On success: Array of strings, indexed like `images_data`
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen]
+pub fn compute_blur_hashes(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+) -> Result<Array, JsValue> {
+    let images = parse_images(images_data)?;
+    let hashes = merge::input_blur_hashes(&images).map_err(|e| create_error_object(&e))?;
+    Ok(hashes.iter().map(|hash| JsValue::from_str(hash)).collect())
+}
+
+/// Reads an image file's header without decoding its pixels, so the UI can
+/// show per-file details and turn away unsupported files before merging.
+///
+/// `width` and `height` are as stored, before `orientation` (EXIF, 1-8, 1
+/// when absent) is applied; `bitDepth` is bits per channel.
+///
+/// # Returns
+/// * On success: `{ format, width, height, orientation, hasAlpha, bitDepth }`
+///   where `format` is "png" | "jpeg" | "gif" | "webp" | "tiff" | "ico" | "bmp";
+///   an ICO reports the entry a merge would use
+/// * On error: throws `DECODE_FAILED` as a merge of the file would
+#[wasm_bindgen(unchecked_return_type = "ImageProbe")]
+pub fn probe_image(bytes: &[u8]) -> Result<JsValue, JsValue> {
+    let info = merge::probe(bytes).map_err(|e| create_error_object(&e))?;
+    let format = match info.format {
+        ImageFormat::Png => "png",
+        ImageFormat::Jpeg => "jpeg",
+        ImageFormat::Gif => "gif",
+        ImageFormat::WebP => "webp",
+        ImageFormat::Tiff => "tiff",
+        ImageFormat::Ico => "ico",
+        ImageFormat::Bmp => "bmp",
+        other => other.extensions_str().first().copied().unwrap_or_default(),
+    };
+    let obj = Object::new();
+    set_field(&obj, "format", &JsValue::from_str(format));
+    set_field(&obj, "width", &JsValue::from_f64(info.width as f64));
+    set_field(&obj, "height", &JsValue::from_f64(info.height as f64));
+    set_field(
+        &obj,
+        "orientation",
+        &JsValue::from_f64(info.orientation as f64),
+    );
+    set_field(&obj, "hasAlpha", &JsValue::from_bool(info.has_alpha));
+    set_field(&obj, "bitDepth", &JsValue::from_f64(info.bit_depth as f64));
+    Ok(obj.into())
+}
+
+/// Hashes an image file's decoded pixels, so tests and hosts can check that
+/// merges produce identical pixels across browsers and engine versions even
+/// when the encoded bytes (and so `sha256`) differ.
+///
+/// The digest covers the width and height as little-endian u32s, then every
+/// pixel as RGBA in row order, one byte per channel (two big-endian bytes for
+/// 16-bit images); EXIF orientation is not applied.
+///
+/// # Returns
+/// * On success: the lowercase hex SHA-256 digest
+/// * On error: throws `DECODE_FAILED` as a merge of the file would
+#[wasm_bindgen]
+pub fn hash_image_pixels(bytes: &[u8]) -> Result<String, JsValue> {
+    merge::pixel_hash(bytes).map_err(|e| create_error_object(&e))
+}
+
+/// Merges images in smart mode, splitting into several outputs at weak seams.
+///
+/// Instead of forcing one image, the sequence is broken wherever no overlap
+/// is detected or its confidence is below `splitMinConfidence` (0-1, default 0).
+///
+/// # Returns
+/// * On success: `{ outputs, breaks }` where each output has the shape returned
+///   by `merge_images_ex`, and `breaks` holds one
+///   `{ afterIndex, reason: "noOverlap" | "lowConfidence", confidence? }` per
+///   boundary between consecutive outputs
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "SplitMergeOutput")]
+pub fn merge_images_split(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let split = merge::merge_split(images, merge_options).map_err(|e| create_error_object(&e))?;
+
+    let outputs: Array = split.outputs.iter().map(create_output_object).collect();
+    let breaks: Array = split
+        .breaks
+        .iter()
+        .map(|seam| {
+            let obj = Object::new();
+            set_field(
                 &obj,
-                &JsValue::from_str("fileIndex"),
-                &JsValue::from_f64(i as f64),
+                "afterIndex",
+                &JsValue::from_f64(seam.after_index as f64),
             );
-            return Err(obj.into());
-        }
-        let uint8_array = Uint8Array::new(&item);
-        let len = uint8_array.length();
-        let mut vec = vec![0u8; len as usize];
-        uint8_array.copy_to(&mut vec);
-        images.push(vec);
+            match seam.reason {
+                SplitReason::NoOverlap => {
+                    set_field(&obj, "reason", &JsValue::from_str("noOverlap"));
+                }
+                SplitReason::LowConfidence { confidence } => {
+                    set_field(&obj, "reason", &JsValue::from_str("lowConfidence"));
+                    set_field(&obj, "confidence", &JsValue::from_f64(confidence as f64));
+                }
+            }
+            JsValue::from(obj)
+        })
+        .collect();
+
+    let obj = Object::new();
+    set_field(&obj, "outputs", &outputs);
+    set_field(&obj, "breaks", &breaks);
+    Ok(obj.into())
+}
+
+/// Cuts one tall image (a merged output or a single long capture) into pages
+/// at most `page_height` rows tall, for platforms with a maximum image height.
+///
+/// Each cut is moved up to `search_rows` rows (default a fifth of the page)
+/// above the page limit, to the calmest seam, so lines of text are not cut
+/// through. Throws `INVALID_OPTIONS` when `page_height` is 0.
+///
+/// # Returns
+/// * On success: Array of `{ bytes, width, height, y }` PNG pages, top to
+///   bottom, where `y` is the page's first row in the source image
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "Page[]")]
+pub fn paginate_image(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput")] image_data: &JsValue,
+    page_height: u32,
+    search_rows: Option<u32>,
+) -> Result<Array, JsValue> {
+    if page_height == 0 {
+        return Err(create_code_error(
+            "INVALID_OPTIONS",
+            "Page height must be at least 1",
+        ));
+    }
+    let input = parse_input(image_data).map_err(|message| invalid_input_error(0, message))?;
+    let pages = merge::paginate(take_input(input), page_height, search_rows)
+        .map_err(|e| create_error_object(&e))?;
+    Ok(pages.iter().map(create_page_object).collect())
+}
+
+/// Merges images and cuts the result into PNG tiles at most `tile_height`
+/// rows tall, for merges too tall for one file or canvas.
+///
+/// Cuts fall between images wherever the layout allows (in spacing rather
+/// than through a caption), and through an image at its calmest row only
+/// when no seam between images lies in the lower half of a tile.
+/// `maxOutputPixels` limits each tile instead of the whole merge. Throws
+/// `INVALID_OPTIONS` when `tile_height` is 0.
+///
+/// # Returns
+/// * On success: `{ tiles, width, height, warnings }` where `tiles` holds
+///   `{ bytes, width, height, y }` PNG tiles, top to bottom, `y` being each
+///   tile's first row in the merged image of size `width` x `height`
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "TiledMergeOutput")]
+pub fn merge_images_tiled(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+    tile_height: u32,
+) -> Result<JsValue, JsValue> {
+    if tile_height == 0 {
+        return Err(create_code_error(
+            "INVALID_OPTIONS",
+            "Tile height must be at least 1",
+        ));
     }
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let tiled = merge::merge_tiled(images, merge_options, tile_height)
+        .map_err(|e| create_error_object(&e))?;
+
+    let tiles: Array = tiled.tiles.iter().map(create_page_object).collect();
+    let warnings: Array = tiled.warnings.iter().map(create_warning_object).collect();
+    let obj = Object::new();
+    set_field(&obj, "tiles", &tiles);
+    set_field(&obj, "width", &JsValue::from_f64(tiled.width as f64));
+    set_field(&obj, "height", &JsValue::from_f64(tiled.height as f64));
+    set_field(&obj, "warnings", &warnings);
+    Ok(obj.into())
+}
 
-    // Parse options
+/// Merges images and exports the result as a PDF document, for sharing a
+/// long conversation or a set of screenshots as one file.
+///
+/// `layout` is `"longPage"` (default) for the whole merge on a single page,
+/// or `"perImage"` for one page per image as drawn in the merge (Smart mode
+/// overlap, captions and padding left out). Pixels are embedded losslessly
+/// and pages are sized from `dpi` (72 when unset). Throws `INVALID_OPTIONS`
+/// for any other layout.
+///
+/// # Returns
+/// * On success: `{ bytes, pageCount, warnings }`
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "PdfOutput")]
+pub fn merge_images_pdf(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+    #[wasm_bindgen(unchecked_param_type = "PdfLayout | undefined")] layout: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let layout = match layout.as_deref() {
+        None | Some("longPage") => PdfLayout::LongPage,
+        Some("perImage") => PdfLayout::PerImage,
+        Some(_) => {
+            return Err(create_code_error(
+                "INVALID_OPTIONS",
+                "PDF layout must be \"longPage\" or \"perImage\"",
+            ));
+        }
+    };
     let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let pdf =
+        merge::merge_to_pdf(images, merge_options, layout).map_err(|e| create_error_object(&e))?;
 
-    // Run merge
-    match merge::merge(images, merge_options) {
-        Ok(output_bytes) => {
-            let result = Uint8Array::new_with_length(output_bytes.len() as u32);
-            result.copy_from(&output_bytes);
-            Ok(result)
+    let warnings: Array = pdf.warnings.iter().map(create_warning_object).collect();
+    let obj = Object::new();
+    set_field(&obj, "bytes", &Uint8Array::from(pdf.bytes.as_slice()));
+    set_field(&obj, "pageCount", &JsValue::from_f64(pdf.page_count as f64));
+    set_field(&obj, "warnings", &warnings);
+    Ok(obj.into())
+}
+
+/// Assembles the inputs as the frames of a looping animation instead of
+/// stacking them, turning a sequence of screenshots into a simple clip.
+///
+/// Every frame is rendered as a merge of that image alone (padding, borders,
+/// captions and the like apply to each), and smaller frames are centered on
+/// the largest, over `background`. `format` is `"apng"` (default) or `"gif"`
+/// (256 colors per frame, delays in 10 ms steps); each frame is shown for
+/// `frame_delay_ms` (default 500). Throws `INVALID_OPTIONS` for any other
+/// format.
+///
+/// # Returns
+/// * On success: `{ bytes, width, height, frameCount, warnings }`
+/// * On error: throws a JS error with structured details
+#[wasm_bindgen(unchecked_return_type = "AnimationOutput")]
+pub fn merge_images_animation(
+    #[wasm_bindgen(unchecked_param_type = "MergeInput[]")] images_data: &Array,
+    #[wasm_bindgen(unchecked_param_type = "MergeOptions")] options: &JsValue,
+    #[wasm_bindgen(unchecked_param_type = "AnimationFormat | undefined")] format: Option<String>,
+    frame_delay_ms: Option<u32>,
+) -> Result<JsValue, JsValue> {
+    let format = match format.as_deref() {
+        None | Some("apng") => AnimationFormat::Apng,
+        Some("gif") => AnimationFormat::Gif,
+        Some(_) => {
+            return Err(create_code_error(
+                "INVALID_OPTIONS",
+                "Animation format must be \"apng\" or \"gif\"",
+            ));
         }
-        Err(e) => Err(create_error_object(&e)),
+    };
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    let animation =
+        merge::merge_to_animation(images, merge_options, format, frame_delay_ms.unwrap_or(500))
+            .map_err(|e| create_error_object(&e))?;
+
+    let warnings: Array = animation
+        .warnings
+        .iter()
+        .map(create_warning_object)
+        .collect();
+    let obj = Object::new();
+    set_field(&obj, "bytes", &Uint8Array::from(animation.bytes.as_slice()));
+    set_field(&obj, "width", &JsValue::from_f64(animation.width as f64));
+    set_field(&obj, "height", &JsValue::from_f64(animation.height as f64));
+    set_field(
+        &obj,
+        "frameCount",
+        &JsValue::from_f64(animation.frame_count as f64),
+    );
+    set_field(&obj, "warnings", &warnings);
+    Ok(obj.into())
+}
+
+/// Builds the `{ bytes, width, height, y }` object for one page or tile.
+fn create_page_object(page: &Page) -> JsValue {
+    let obj = Object::new();
+    set_field(&obj, "bytes", &Uint8Array::from(page.bytes.as_slice()));
+    set_field(&obj, "width", &JsValue::from_f64(page.width as f64));
+    set_field(&obj, "height", &JsValue::from_f64(page.height as f64));
+    set_field(&obj, "y", &JsValue::from_f64(page.y as f64));
+    obj.into()
+}
+
+/// Builds the `{ overlap, xShift, found, fellBack, confidence? }` report for one pair.
+fn create_pair_object(pair: &PairAnalysis) -> JsValue {
+    let obj = Object::new();
+    set_field(&obj, "overlap", &JsValue::from_f64(pair.overlap as f64));
+    set_field(&obj, "xShift", &JsValue::from_f64(pair.x_shift as f64));
+    set_field(
+        &obj,
+        "found",
+        &JsValue::from_bool(pair.confidence.is_some()),
+    );
+    set_field(&obj, "fellBack", &JsValue::from_bool(pair.fell_back()));
+    if let Some(confidence) = pair.confidence {
+        set_field(&obj, "confidence", &JsValue::from_f64(confidence as f64));
     }
+    obj.into()
 }
 
-/// Parses JS options object into MergeOptions.
-fn parse_options(options: &JsValue) -> Result<MergeOptions, JsValue> {
-    let mut merge_options = MergeOptions::default();
+/// Builds the `{ bytes, width, height, byteLength, sha256, warnings, seams, placements }`
+/// result object.
+fn create_output_object(output: &MergeOutput) -> JsValue {
+    let obj = Object::new();
+    set_field(&obj, "bytes", &Uint8Array::from(output.bytes.as_slice()));
+    set_field(&obj, "width", &JsValue::from_f64(output.width as f64));
+    set_field(&obj, "height", &JsValue::from_f64(output.height as f64));
+    set_field(
+        &obj,
+        "byteLength",
+        &JsValue::from_f64(output.byte_length() as f64),
+    );
+    set_field(&obj, "sha256", &JsValue::from_str(&output.sha256_hex()));
+    set_output_details(&obj, output);
+    obj.into()
+}
 
-    if options.is_undefined() || options.is_null() {
-        return Ok(merge_options);
+/// Sets everything but the encoded bytes and their length and digest on a
+/// merge result object.
+fn set_output_details(obj: &Object, output: &MergeOutput) {
+    let warnings: Array = output.warnings.iter().map(create_warning_object).collect();
+    set_field(obj, "warnings", &warnings);
+    let seams: Array = output.seams.iter().map(create_pair_object).collect();
+    set_field(obj, "seams", &seams);
+    let placements: Array = output
+        .placements
+        .iter()
+        .map(create_placement_object)
+        .collect();
+    set_field(obj, "placements", &placements);
+    if let Some(preview) = &output.preview {
+        set_field(obj, "preview", &create_preview_object(preview));
+    }
+    if let Some(thumbnail) = &output.thumbnail {
+        set_field(obj, "thumbnail", &create_preview_object(thumbnail));
+    }
+    if let Some(hash) = &output.blur_hash {
+        set_field(obj, "blurHash", &JsValue::from_str(hash));
     }
+    if let Some(layer) = &output.guide_layer {
+        set_field(obj, "guideLayer", &create_preview_object(layer));
+    }
+    if let Some(stats) = &output.stats {
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        if let Ok(value) = stats.serialize(&serializer) {
+            set_field(obj, "stats", &value);
+        }
+    }
+    if !output.repro_bundles.is_empty() {
+        let bundles: Array = output
+            .repro_bundles
+            .iter()
+            .map(create_repro_bundle_object)
+            .collect();
+        set_field(obj, "reproBundles", &bundles);
+    }
+}
 
-    // Parse direction
-    if let Ok(dir_val) = Reflect::get(options, &JsValue::from_str("direction"))
-        && let Some(dir_str) = dir_val.as_string()
-    {
-        merge_options.direction = match dir_str.as_str() {
-            "horizontal" => Direction::Horizontal,
-            "smart" => Direction::Smart,
-            _ => Direction::Vertical,
-        };
+/// Builds the `{ x, y, width, height, cropTop, cropBottom }` object for a placement.
+fn create_placement_object(placement: &Placement) -> JsValue {
+    let number = |value: u32| JsValue::from_f64(value as f64);
+    let obj = Object::new();
+    set_field(&obj, "x", &number(placement.x));
+    set_field(&obj, "y", &number(placement.y));
+    set_field(&obj, "width", &number(placement.width));
+    set_field(&obj, "height", &number(placement.height));
+    set_field(&obj, "cropTop", &number(placement.crop_top));
+    set_field(&obj, "cropBottom", &number(placement.crop_bottom));
+    obj.into()
+}
+
+/// Builds the `{ bytes, width, height }` object for a preview, thumbnail or guide layer.
+fn create_preview_object(preview: &PreviewOutput) -> JsValue {
+    let obj = Object::new();
+    set_field(&obj, "bytes", &Uint8Array::from(preview.bytes.as_slice()));
+    set_field(&obj, "width", &JsValue::from_f64(preview.width as f64));
+    set_field(&obj, "height", &JsValue::from_f64(preview.height as f64));
+    obj.into()
+}
+
+/// Builds the JS shape of a failed-pair reproduction bundle.
+fn create_repro_bundle_object(bundle: &ReproBundle) -> JsValue {
+    let number = |value: f64| JsValue::from_f64(value);
+    let size = |(width, height): (u32, u32)| {
+        let obj = Object::new();
+        set_field(&obj, "width", &number(width as f64));
+        set_field(&obj, "height", &number(height as f64));
+        JsValue::from(obj)
+    };
+
+    let obj = Object::new();
+    set_field(&obj, "pairIndex", &number(bundle.pair_index as f64));
+    set_field(
+        &obj,
+        "overlapSensitivity",
+        &number(bundle.sensitivity as f64),
+    );
+    set_field(
+        &obj,
+        "minOverlapPixels",
+        &number(bundle.min_overlap_pixels as f64),
+    );
+    set_field(
+        &obj,
+        "widthRatioThreshold",
+        &number(bundle.width_ratio_threshold as f64),
+    );
+    set_field(&obj, "topSize", &size(bundle.top_size));
+    set_field(&obj, "bottomSize", &size(bundle.bottom_size));
+    set_field(
+        &obj,
+        "topTrimBottom",
+        &number(bundle.top_trim_bottom as f64),
+    );
+    set_field(
+        &obj,
+        "bottomTrimTop",
+        &number(bundle.bottom_trim_top as f64),
+    );
+    set_field(
+        &obj,
+        "topStrip",
+        &Uint8Array::from(bundle.top_strip_png.as_slice()),
+    );
+    set_field(
+        &obj,
+        "bottomStrip",
+        &Uint8Array::from(bundle.bottom_strip_png.as_slice()),
+    );
+    obj.into()
+}
+
+/// Parses JS inputs and options, then runs the merge.
+fn run_merge(images_data: &Array, options: &JsValue) -> Result<Vec<u8>, JsValue> {
+    let merge_options = parse_options(options)?;
+    let images = parse_images(images_data)?;
+    merge::merge(images, merge_options).map_err(|e| create_error_object(&e))
+}
+
+/// Collects a JS Array of inputs into owned buffers: file bytes and pixels
+/// are copied, `alloc_input` handles are taken over. Callers parse their
+/// options first, so invalid options leave the handles live.
+fn parse_images(images_data: &Array) -> Result<Vec<ImageInput>, JsValue> {
+    // Validate input array
+    let length = images_data.length();
+    if length == 0 {
+        return Err(create_error_object(&MergeError::NoImages));
+    }
+
+    // Check every item before taking any handle, so a bad input leaves them all live
+    let items = images_data
+        .iter()
+        .enumerate()
+        .map(|(i, item)| parse_input(&item).map_err(|message| invalid_input_error(i, message)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(items.into_iter().map(take_input).collect())
+}
+
+/// Error message for an input item of no accepted shape.
+const UNEXPECTED_INPUT: &str = "Expected Uint8Array, input handle, { data, name?, mimeType?, frame?, page?, iconSize? } or { width, height, rgba } at index";
+
+/// Reads one input item:
+/// - a Uint8Array of file bytes, or an `alloc_input` handle to them
+/// - `{ data, name?, mimeType?, frame?, page?, iconSize? }`, where `data` is
+///   either of the above, `name` is reported in decode errors, a known
+///   `mimeType` skips format guessing, `frame` picks a frame of an animation,
+///   `page` a page of a multi-page TIFF and `iconSize` an entry of an ICO
+/// - `{ width, height, rgba, name? }` unencoded pixels, where `rgba` is a
+///   Uint8Array or Uint8ClampedArray (such as `ImageData.data`)
+///
+/// Handles are only checked here; the returned handle is taken by [`take_input`].
+fn parse_input(item: &JsValue) -> Result<(ImageInput, Option<u32>), &'static str> {
+    if let Some(ptr) = input_handle(item) {
+        if !INPUT_BUFFERS.with(|buffers| buffers.borrow().contains_key(&ptr)) {
+            return Err("Unknown input handle at index");
+        }
+        return Ok((Vec::new().into(), Some(ptr)));
     }
+    if item.is_instance_of::<Uint8Array>() {
+        return Ok((Uint8Array::new(item).to_vec().into(), None));
+    }
+    if !item.is_object() {
+        return Err(UNEXPECTED_INPUT);
+    }
+
+    let data = Reflect::get(item, &JsValue::from_str("data")).unwrap_or(JsValue::UNDEFINED);
+    let (mut input, handle) = if data.is_instance_of::<Uint8Array>() || data.as_f64().is_some() {
+        parse_input(&data)?
+    } else {
+        (parse_rgba_input(item).ok_or(UNEXPECTED_INPUT)?.into(), None)
+    };
+    input.name = get_string_field(item, "name");
+    input.format = get_string_field(item, "mimeType").and_then(ImageFormat::from_mime_type);
+    input.frame = get_index_field(item, "frame")
+        .map_err(|_| "Expected a non-negative integer frame at index")?;
+    input.page = get_index_field(item, "page")
+        .map_err(|_| "Expected a non-negative integer page at index")?;
+    input.icon_size = get_index_field(item, "iconSize")
+        .map_err(|_| "Expected a non-negative integer iconSize at index")?;
+    Ok((input, handle))
+}
 
-    // Parse background
-    if let Ok(bg_val) = Reflect::get(options, &JsValue::from_str("background"))
-        && !bg_val.is_undefined()
-        && !bg_val.is_null()
-    {
-        let r = get_u8_field(&bg_val, "r").unwrap_or(255);
-        let g = get_u8_field(&bg_val, "g").unwrap_or(255);
-        let b = get_u8_field(&bg_val, "b").unwrap_or(255);
-        let a = get_u8_field(&bg_val, "a").unwrap_or(255);
-        merge_options.background = BackgroundColor::new(r, g, b, a);
+/// Reads an optional non-negative integer field such as an input's `frame`.
+fn get_index_field(item: &JsValue, name: &str) -> Result<Option<u32>, ()> {
+    let value = Reflect::get(item, &JsValue::from_str(name)).unwrap_or(JsValue::UNDEFINED);
+    if value.is_undefined() || value.is_null() {
+        return Ok(None);
     }
+    value
+        .as_f64()
+        .filter(|n| n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(n))
+        .map(|n| Some(n as u32))
+        .ok_or(())
+}
+
+/// Reads the pixels of a `{ width, height, rgba }` input.
+fn parse_rgba_input(item: &JsValue) -> Option<InputSource> {
+    let rgba = Reflect::get(item, &JsValue::from_str("rgba")).ok()?;
+    let pixels = if rgba.is_instance_of::<Uint8Array>() {
+        Uint8Array::new(&rgba).to_vec()
+    } else if rgba.is_instance_of::<Uint8ClampedArray>() {
+        Uint8ClampedArray::new(&rgba).to_vec()
+    } else {
+        return None;
+    };
+    let dimension = |name| {
+        get_f64_field(item, name)
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .map(|v| v.min(u32::MAX as f64) as u32)
+    };
+    Some(InputSource::Rgba {
+        width: dimension("width")?,
+        height: dimension("height")?,
+        pixels,
+    })
+}
 
-    if let Ok(sensitivity_val) = Reflect::get(options, &JsValue::from_str("overlapSensitivity"))
-        && !sensitivity_val.is_undefined()
-        && !sensitivity_val.is_null()
-        && let Some(sensitivity) = sensitivity_val
-            .as_f64()
-            .filter(|value| value.is_finite())
-            .map(|value| value.round() as i64)
-    {
-        merge_options.overlap_sensitivity = sensitivity.clamp(0, 100) as u8;
+/// Completes an input from [`parse_input`], taking over its handle's buffer.
+fn take_input((mut input, handle): (ImageInput, Option<u32>)) -> ImageInput {
+    if let Some(ptr) = handle {
+        let bytes = INPUT_BUFFERS.with(|buffers| buffers.borrow_mut().remove(&ptr));
+        input.source = InputSource::Encoded(bytes.unwrap_or_default());
     }
+    input
+}
+
+/// The `alloc_input` handle an input item stands for, if it is a number.
+fn input_handle(item: &JsValue) -> Option<u32> {
+    item.as_f64()
+        .filter(|n| n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(n))
+        .map(|n| n as u32)
+}
+
+/// Builds the `INVALID_INPUT` error for the input at `index`.
+fn invalid_input_error(index: usize, message: &str) -> JsValue {
+    let obj = Object::new();
+    set_field(&obj, "code", &JsValue::from_str("INVALID_INPUT"));
+    set_field(&obj, "message", &JsValue::from_str(message));
+    set_field(&obj, "fileIndex", &JsValue::from_f64(index as f64));
+    obj.into()
+}
+
+/// Parses JS options object into MergeOptions, starting from the engine defaults.
+fn parse_options(options: &JsValue) -> Result<MergeOptions, JsValue> {
+    let defaults = ENGINE_DEFAULTS.with(|d| d.borrow().clone());
+    parse_options_onto(defaults, options)
+}
+
+/// Parses JS options object on top of `base`, overriding only the fields present.
+///
+/// Throws `INVALID_OPTIONS` naming the offending field (`field`) for unknown
+/// fields, values of the wrong type and unknown keywords.
+fn parse_options_onto(base: MergeOptions, options: &JsValue) -> Result<MergeOptions, JsValue> {
+    let invalid = |field: &str, message: &str| {
+        let obj = Object::new();
+        set_field(&obj, "code", &JsValue::from_str("INVALID_OPTIONS"));
+        set_field(&obj, "message", &JsValue::from_str(message));
+        if !field.is_empty() {
+            set_field(&obj, "field", &JsValue::from_str(field));
+        }
+        JsValue::from(obj)
+    };
+    let value: serde_value::Value = serde_wasm_bindgen::from_value(options.clone())
+        .map_err(|e| invalid("", &format!("Invalid options: {e}")))?;
+    let mut options =
+        options::parse_onto(base, value).map_err(|e| invalid(&e.field, &e.to_string()))?;
+    options.clock = Some(Clock(js_now));
+    Ok(options)
+}
+
+/// Milliseconds from `performance.now()`, or the whole milliseconds of
+/// `Date.now()` where a host has no `performance`; the core's stage timings
+/// cannot read a clock on wasm32 by themselves.
+fn js_now() -> f64 {
+    let performance = Reflect::get(&js_sys::global(), &"performance".into());
+    performance
+        .ok()
+        .filter(|performance| performance.is_object())
+        .and_then(|performance| {
+            let now = Reflect::get(&performance, &"now".into()).ok()?;
+            if !now.is_function() {
+                return None;
+            }
+            Function::from(now).call0(&performance).ok()?.as_f64()
+        })
+        .unwrap_or_else(js_sys::Date::now)
+}
 
-    Ok(merge_options)
+/// Gets a finite number field from a JS object.
+fn get_f64_field(obj: &JsValue, field: &str) -> Option<f64> {
+    Reflect::get(obj, &JsValue::from_str(field))
+        .ok()
+        .and_then(|v| v.as_f64())
+        .filter(|n| n.is_finite())
+}
+
+/// Gets a string field from a JS object.
+fn get_string_field(obj: &JsValue, field: &str) -> Option<String> {
+    Reflect::get(obj, &JsValue::from_str(field))
+        .ok()
+        .and_then(|v| v.as_string())
 }
 
-/// Gets a u8 field from a JS object.
-fn get_u8_field(obj: &JsValue, field: &str) -> Option<u8> {
+/// Gets a non-negative integer field from a JS object.
+fn get_u32_field(obj: &JsValue, field: &str) -> Option<u32> {
     Reflect::get(obj, &JsValue::from_str(field))
         .ok()
         .and_then(|v| v.as_f64())
         .filter(|n| n.is_finite())
-        .map(|n| n.clamp(0.0, 255.0) as u8)
+        .map(|n| n.round().clamp(0.0, u32::MAX as f64) as u32)
+}
+
+/// Sets a named field on a JS object, ignoring failures.
+fn set_field(obj: &Object, field: &str, value: &JsValue) {
+    let _ = Reflect::set(obj, &JsValue::from_str(field), value);
+}
+
+/// Creates a structured JS error object with only a code and message.
+fn create_code_error(code: &str, message: &str) -> JsValue {
+    let obj = Object::new();
+    set_field(&obj, "code", &JsValue::from_str(code));
+    set_field(&obj, "message", &JsValue::from_str(message));
+    obj.into()
 }
 
 /// Creates a structured JS error object from a MergeError.
+///
+/// The object carries `code`, `message` and every machine-readable parameter
+/// of the error (e.g. `fileIndex`, `fileName`, `maxPixels`). `message` is
+/// rendered from the registered template for the code when one exists.
 fn create_error_object(error: &MergeError) -> JsValue {
+    create_coded_object(error.code(), &error.to_string(), &error.params())
+}
+
+/// Creates a `{ code, message, ...params }` object for a MergeWarning.
+fn create_warning_object(warning: &MergeWarning) -> JsValue {
+    create_coded_object(warning.code(), &warning.to_string(), &warning.params())
+}
+
+/// Builds the shared `{ code, message, ...params }` shape of errors and warnings.
+fn create_coded_object(
+    code: &str,
+    fallback_message: &str,
+    params: &[(&'static str, ErrorParam)],
+) -> JsValue {
     let obj = Object::new();
 
-    let _ = Reflect::set(
-        &obj,
-        &JsValue::from_str("code"),
-        &JsValue::from_str(error.code()),
-    );
-    let _ = Reflect::set(
-        &obj,
-        &JsValue::from_str("message"),
-        &JsValue::from_str(&error.to_string()),
-    );
+    let message = localized_message(code, params).unwrap_or_else(|| fallback_message.to_string());
+    set_field(&obj, "code", &JsValue::from_str(code));
+    set_field(&obj, "message", &JsValue::from_str(&message));
 
     // Add error-specific details
-    if let MergeError::DecodeError {
-        index, file_name, ..
-    } = error
-    {
-        let _ = Reflect::set(
-            &obj,
-            &JsValue::from_str("fileIndex"),
-            &JsValue::from_f64(*index as f64),
-        );
-        if let Some(name) = file_name {
-            let _ = Reflect::set(
-                &obj,
-                &JsValue::from_str("fileName"),
-                &JsValue::from_str(name),
-            );
-        }
+    for (name, value) in params {
+        let value = match value {
+            ErrorParam::Number(n) => JsValue::from_f64(*n),
+            ErrorParam::Text(text) => JsValue::from_str(text),
+        };
+        set_field(&obj, name, &value);
     }
 
     obj.into()
 }
+
+/// Registered message templates plus the locale used to format numbers.
+struct MessageTemplates {
+    locale: Option<String>,
+    templates: HashMap<String, String>,
+}
+
+/// Registers localized message templates for engine errors.
+///
+/// `registry` is `{ locale?: string, templates: { [code]: string } }`, e.g.
+/// `{ locale: "de-DE", templates: { OUTPUT_TOO_LARGE: "Zu groß: {width}×{height}" } }`.
+/// Placeholders name error parameters; numbers are formatted with
+/// `Intl.NumberFormat` for `locale`. Error codes and parameters stay
+/// machine-readable; only `message` changes. Passing `undefined` or `null`
+/// restores the built-in English messages. The templates apply to the calling
+/// worker only.
+#[wasm_bindgen]
+pub fn set_message_templates(registry: &JsValue) -> Result<(), JsValue> {
+    if registry.is_undefined() || registry.is_null() {
+        MESSAGE_TEMPLATES.with(|t| *t.borrow_mut() = None);
+        return Ok(());
+    }
+
+    let locale = Reflect::get(registry, &JsValue::from_str("locale"))
+        .ok()
+        .and_then(|v| v.as_string());
+
+    let templates_val = Reflect::get(registry, &JsValue::from_str("templates"))
+        .ok()
+        .filter(|v| v.is_object())
+        .ok_or_else(|| create_code_error("INVALID_OPTIONS", "Expected a templates object"))?;
+
+    let mut templates = HashMap::new();
+    for entry in Object::entries(&Object::from(templates_val)).iter() {
+        let pair = Array::from(&entry);
+        if let (Some(code), Some(template)) = (pair.get(0).as_string(), pair.get(1).as_string()) {
+            templates.insert(code, template);
+        }
+    }
+
+    MESSAGE_TEMPLATES.with(|t| *t.borrow_mut() = Some(MessageTemplates { locale, templates }));
+    Ok(())
+}
+
+/// Renders the registered template for `code`, if any.
+fn localized_message(code: &str, params: &[(&'static str, ErrorParam)]) -> Option<String> {
+    MESSAGE_TEMPLATES.with(|t| {
+        let registry = t.borrow();
+        let registry = registry.as_ref()?;
+        let template = registry.templates.get(code)?;
+        Some(render_template(template, |name| {
+            params
+                .iter()
+                .find(|(param, _)| *param == name)
+                .map(|(_, value)| match value {
+                    ErrorParam::Number(n) => format_number(*n, registry.locale.as_deref()),
+                    ErrorParam::Text(text) => text.clone(),
+                })
+        }))
+    })
+}
+
+/// Formats a number with `Intl.NumberFormat`, falling back to plain digits.
+fn format_number(value: f64, locale: Option<&str>) -> String {
+    let locales = Array::new();
+    if let Some(locale) = locale {
+        locales.push(&JsValue::from_str(locale));
+    }
+    Intl::NumberFormat::new(&locales, &Object::new())
+        .format()
+        .call1(&JsValue::NULL, &JsValue::from_f64(value))
+        .ok()
+        .and_then(|v| v.as_string())
+        .unwrap_or_else(|| value.to_string())
+}
@@ -0,0 +1,1016 @@
+//! The JS options object, deserialized and layered onto a base `MergeOptions`.
+//!
+//! Every field is optional: absent (or `null`) fields keep the base value,
+//! except the nullable ones (`backgroundGradient`, `border`, `guides`, ...)
+//! where `null` clears the base value. Unknown fields, values of the wrong
+//! type and unknown keywords are rejected with the path of the offending
+//! field, so typos are reported instead of silently ignored.
+//!
+//! The TypeScript declarations of the options are derived from these types
+//! with `Tsify`; each value type declares as a `number` alias named for its
+//! domain (`Pixels`, `Positive`, ...).
+
+use serde::{Deserialize, Deserializer};
+use tsify::Tsify;
+
+use merge_images_core::{
+    Align, BackgroundColor, BackgroundFit, BackgroundImage, Border, CaptionPosition, ChromeMode,
+    ContactSheet, Corner, Direction, Divider, ExifCopy, Gradient, Guides, ImageOptions,
+    LowColorScaling, MergeOptions, OptionsError, OverlapRegion, Padding, PageNumbers, Rotation,
+    ScaleMode, SeamLines, Shadow, TrimMargins, VerticalAlign,
+};
+
+/// Deserializes an options object and applies it on top of `base`.
+pub fn parse_onto<'de, D: Deserializer<'de>>(
+    base: MergeOptions,
+    deserializer: D,
+) -> Result<MergeOptions, OptionsError> {
+    let patch: Option<OptionsPatch> =
+        serde_path_to_error::deserialize(deserializer).map_err(|error| {
+            let field = error.path().to_string();
+            OptionsError {
+                field: if field == "." { String::new() } else { field },
+                message: error.into_inner().to_string(),
+            }
+        })?;
+    Ok(match patch {
+        Some(patch) => patch.apply(base),
+        None => base,
+    })
+}
+
+/// A finite JS number.
+#[derive(Debug, Clone, Copy, Tsify)]
+struct Finite(f64);
+
+impl<'de> Deserialize<'de> for Finite {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FiniteVisitor;
+
+        impl serde::de::Visitor<'_> for FiniteVisitor {
+            type Value = Finite;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a finite number")
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Finite, E> {
+                Ok(Finite(value as f64))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Finite, E> {
+                Ok(Finite(value as f64))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<Finite, E> {
+                if value.is_finite() {
+                    Ok(Finite(value))
+                } else {
+                    Err(E::invalid_value(serde::de::Unexpected::Float(value), &self))
+                }
+            }
+        }
+
+        deserializer.deserialize_f64(FiniteVisitor)
+    }
+}
+
+/// A pixel count: rounded, and clamped to `0..=u32::MAX`.
+#[derive(Debug, Clone, Copy, Deserialize, Tsify)]
+#[serde(from = "Finite")]
+struct Pixels(u32);
+
+impl From<Finite> for Pixels {
+    fn from(Finite(value): Finite) -> Self {
+        Pixels(value.round().clamp(0.0, u32::MAX as f64) as u32)
+    }
+}
+
+/// A pixel count that must round to at least 1.
+#[derive(Debug, Clone, Copy, Deserialize, Tsify)]
+#[serde(try_from = "Finite")]
+struct Size(u32);
+
+impl TryFrom<Finite> for Size {
+    type Error = &'static str;
+
+    fn try_from(value: Finite) -> Result<Self, Self::Error> {
+        match Pixels::from(value) {
+            Pixels(0) => Err("expected a size of at least 1"),
+            Pixels(size) => Ok(Size(size)),
+        }
+    }
+}
+
+/// A count that may exceed `u32`: rounded, and clamped to `0..=u64::MAX`.
+#[derive(Debug, Clone, Copy, Deserialize, Tsify)]
+#[serde(from = "Finite")]
+struct Count(u64);
+
+impl From<Finite> for Count {
+    fn from(Finite(value): Finite) -> Self {
+        Count(value.round().clamp(0.0, u64::MAX as f64) as u64)
+    }
+}
+
+/// An array index: a non-negative integer, never rounded.
+#[derive(Debug, Clone, Copy, Deserialize, Tsify)]
+#[serde(try_from = "Finite")]
+struct Index(usize);
+
+impl TryFrom<Finite> for Index {
+    type Error = &'static str;
+
+    fn try_from(Finite(value): Finite) -> Result<Self, Self::Error> {
+        if value >= 0.0 && value.fract() == 0.0 && value <= u32::MAX as f64 {
+            Ok(Index(value as usize))
+        } else {
+            Err("expected a non-negative integer index")
+        }
+    }
+}
+
+/// A strictly positive number (font sizes, resolutions).
+#[derive(Debug, Clone, Copy, Deserialize, Tsify)]
+#[serde(try_from = "Finite")]
+struct Positive(f32);
+
+impl TryFrom<Finite> for Positive {
+    type Error = &'static str;
+
+    fn try_from(Finite(value): Finite) -> Result<Self, Self::Error> {
+        if value > 0.0 {
+            Ok(Positive(value as f32))
+        } else {
+            Err("expected a positive number")
+        }
+    }
+}
+
+/// A color channel, clamped to `0..=255`.
+#[derive(Debug, Clone, Copy, Deserialize, Tsify)]
+#[serde(from = "Finite")]
+struct Channel(u8);
+
+impl From<Finite> for Channel {
+    fn from(Finite(value): Finite) -> Self {
+        Channel(value.clamp(0.0, 255.0) as u8)
+    }
+}
+
+/// A 0-100 overlap sensitivity, rounded and clamped.
+#[derive(Debug, Clone, Copy, Deserialize, Tsify)]
+#[serde(from = "Finite")]
+struct Sensitivity(u8);
+
+impl From<Finite> for Sensitivity {
+    fn from(Finite(value): Finite) -> Self {
+        Sensitivity(value.round().clamp(0.0, 100.0) as u8)
+    }
+}
+
+/// An output rotation in degrees: 0, 90, 180 or 270.
+#[derive(Debug, Clone, Copy, Deserialize, Tsify)]
+#[serde(try_from = "Finite")]
+struct Degrees(#[tsify(type = "0 | 90 | 180 | 270")] Rotation);
+
+impl TryFrom<Finite> for Degrees {
+    type Error = &'static str;
+
+    fn try_from(value: Finite) -> Result<Self, Self::Error> {
+        let Pixels(degrees) = value.into();
+        Rotation::from_degrees(degrees)
+            .map(Degrees)
+            .ok_or("expected 0, 90, 180 or 270")
+    }
+}
+
+/// A maximum upscale factor, at least 1.
+#[derive(Debug, Clone, Copy, Deserialize, Tsify)]
+#[serde(try_from = "Finite")]
+struct Factor(f64);
+
+impl TryFrom<Finite> for Factor {
+    type Error = &'static str;
+
+    fn try_from(Finite(value): Finite) -> Result<Self, Self::Error> {
+        if value >= 1.0 {
+            Ok(Factor(value))
+        } else {
+            Err("expected a factor of at least 1")
+        }
+    }
+}
+
+/// Encoded file bytes, from a `Uint8Array`.
+#[derive(Debug, Clone, Tsify)]
+struct Bytes(#[tsify(type = "Uint8Array")] Vec<u8>);
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl serde::de::Visitor<'_> for BytesVisitor {
+            type Value = Bytes;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a Uint8Array")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, value: &[u8]) -> Result<Bytes, E> {
+                Ok(Bytes(value.to_vec()))
+            }
+
+            fn visit_byte_buf<E: serde::de::Error>(self, value: Vec<u8>) -> Result<Bytes, E> {
+                Ok(Bytes(value))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+/// Deserializes a field where `null` is meaningful: absent stays `None`,
+/// `null` becomes `Some(None)`.
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// `{ r, g, b, a }`; missing channels come from a default that depends on the field.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Tsify)]
+#[serde(default, deny_unknown_fields)]
+struct ColorPatch {
+    r: Option<Channel>,
+    g: Option<Channel>,
+    b: Option<Channel>,
+    a: Option<Channel>,
+}
+
+impl ColorPatch {
+    fn over(self, default: BackgroundColor) -> BackgroundColor {
+        let channel = |value: Option<Channel>, default| value.map_or(default, |Channel(v)| v);
+        BackgroundColor::new(
+            channel(self.r, default.r),
+            channel(self.g, default.g),
+            channel(self.b, default.b),
+            channel(self.a, default.a),
+        )
+    }
+}
+
+/// Resolves an optional color patch over `default`.
+fn color(patch: Option<ColorPatch>, default: BackgroundColor) -> BackgroundColor {
+    patch.map_or(default, |patch| patch.over(default))
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(default, deny_unknown_fields)]
+struct GradientPatch {
+    start: Option<ColorPatch>,
+    end: Option<ColorPatch>,
+    angle: Option<Finite>,
+}
+
+#[derive(Debug, Deserialize, Tsify)]
+#[serde(deny_unknown_fields)]
+struct BackgroundImagePatch {
+    bytes: Bytes,
+    #[serde(default)]
+    fit: Option<BackgroundFit>,
+}
+
+/// Shape of `border` and `divider`.
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(default, deny_unknown_fields)]
+struct LinePatch {
+    width: Option<Pixels>,
+    color: Option<ColorPatch>,
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(default, deny_unknown_fields)]
+struct SeamLinesPatch {
+    width: Option<Size>,
+    color: Option<ColorPatch>,
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+struct ShadowPatch {
+    blur_radius: Option<Pixels>,
+    offset_x: Option<Finite>,
+    offset_y: Option<Finite>,
+    color: Option<ColorPatch>,
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(default, deny_unknown_fields)]
+struct PageNumbersPatch {
+    corner: Option<Corner>,
+    size: Option<Positive>,
+    color: Option<ColorPatch>,
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+struct GuidesPatch {
+    thirds: Option<bool>,
+    center: Option<bool>,
+    safe_margin: Option<Finite>,
+    line_width: Option<Size>,
+    color: Option<ColorPatch>,
+    separate_layer: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+struct ExifCopyPatch {
+    capture_time: Option<bool>,
+    camera: Option<bool>,
+    gps: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(default, deny_unknown_fields)]
+struct ContactSheetPatch {
+    columns: Option<Size>,
+    header: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(default, deny_unknown_fields)]
+struct TrimMarginsPatch {
+    tolerance: Option<Channel>,
+}
+
+/// `true` for the defaults, `false` to turn off, or an object of settings.
+#[derive(Debug, Deserialize, Tsify)]
+#[serde(untagged, expecting = "a boolean or an object")]
+enum Toggle<T> {
+    Flag(bool),
+    Settings(T),
+}
+
+impl<T: Default> Toggle<T> {
+    fn settings(self) -> Option<T> {
+        match self {
+            Toggle::Flag(false) => None,
+            Toggle::Flag(true) => Some(T::default()),
+            Toggle::Settings(settings) => Some(settings),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Tsify)]
+#[serde(untagged, expecting = "a number or an array of numbers")]
+enum SensitivityPatch {
+    Global(Sensitivity),
+    PerPair(Vec<Option<Sensitivity>>),
+}
+
+#[derive(Debug, Deserialize, Tsify)]
+#[serde(deny_unknown_fields)]
+struct RegionPatch {
+    x: Pixels,
+    y: Pixels,
+    width: Pixels,
+    height: Pixels,
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(default, deny_unknown_fields)]
+struct SidesPatch {
+    top: Option<Pixels>,
+    right: Option<Pixels>,
+    bottom: Option<Pixels>,
+    left: Option<Pixels>,
+}
+
+#[derive(Debug, Deserialize, Tsify)]
+#[serde(
+    untagged,
+    expecting = "a number or a { top, right, bottom, left } object"
+)]
+enum PaddingPatch {
+    Uniform(Pixels),
+    Sides(SidesPatch),
+}
+
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+struct ImageOptionsPatch {
+    align: Option<Align>,
+    pin: Option<bool>,
+    low_color: Option<bool>,
+    dpi: Option<Positive>,
+    tile_offset: Option<Pixels>,
+    tile_height: Option<Pixels>,
+    flip_h: Option<bool>,
+    flip_v: Option<bool>,
+}
+
+/// The options object as sent by the host; see `merge_images` for the fields.
+#[derive(Debug, Default, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+struct OptionsPatch {
+    direction: Option<Direction>,
+    background: Option<ColorPatch>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    background_gradient: Option<Option<GradientPatch>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    background_image: Option<Option<BackgroundImagePatch>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    border: Option<Option<LinePatch>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    divider: Option<Option<LinePatch>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    draw_seams: Option<Option<Toggle<SeamLinesPatch>>>,
+    captions: Option<Vec<String>>,
+    caption_position: Option<CaptionPosition>,
+    caption_size: Option<Positive>,
+    caption_color: Option<ColorPatch>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    page_numbers: Option<Option<Toggle<PageNumbersPatch>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    shadow: Option<Option<ShadowPatch>>,
+    corner_radius: Option<Pixels>,
+    overlap_sensitivity: Option<SensitivityPatch>,
+    strip_chrome: Option<bool>,
+    chrome_mode: Option<ChromeMode>,
+    chrome_proxy_width: Option<Size>,
+    min_overlap_pixels: Option<Pixels>,
+    width_ratio_threshold: Option<Finite>,
+    max_upscale_factor: Option<Factor>,
+    error_on_upscale: Option<bool>,
+    allow_upscale: Option<bool>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    target_dpi: Option<Option<Positive>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    dpi: Option<Option<Positive>>,
+    low_color_scaling: Option<LowColorScaling>,
+    linear_scaling: Option<bool>,
+    premultiplied_alpha: Option<bool>,
+    high_bit_depth: Option<bool>,
+    repro_bundles: Option<bool>,
+    output_rotation: Option<Degrees>,
+    also_preview: Option<bool>,
+    preview_max_dimension: Option<Size>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    preview_proof_quality: Option<Option<Finite>>,
+    blur_hash: Option<bool>,
+    collect_stats: Option<bool>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    guides: Option<Option<Toggle<GuidesPatch>>>,
+    thumbnail_width: Option<Size>,
+    split_min_confidence: Option<Finite>,
+    overlap_regions: Option<Vec<Option<RegionPatch>>>,
+    overlap_overrides: Option<Vec<Option<Pixels>>>,
+    scale_mode: Option<ScaleMode>,
+    spacing: Option<Pixels>,
+    padding: Option<PaddingPatch>,
+    align: Option<Align>,
+    vertical_align: Option<VerticalAlign>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    target_aspect_ratio: Option<Option<Positive>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    columns: Option<Option<Size>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    contact_sheet: Option<Option<Toggle<ContactSheetPatch>>>,
+    image_options: Option<Vec<Option<ImageOptionsPatch>>>,
+    dimension_multiple: Option<Pixels>,
+    max_output_pixels: Option<Count>,
+    downscale_to_fit: Option<bool>,
+    max_memory_bytes: Option<Count>,
+    max_input_pixels: Option<Count>,
+    max_input_dimension: Option<Pixels>,
+    names: Option<Vec<Option<String>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    order: Option<Option<Vec<Index>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    trim_margins: Option<Option<Toggle<TrimMarginsPatch>>>,
+    strict: Option<bool>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[tsify(optional)]
+    copy_exif: Option<Option<Toggle<ExifCopyPatch>>>,
+    provenance: Option<bool>,
+    strip_metadata: Option<bool>,
+}
+
+impl OptionsPatch {
+    /// Overrides the fields of `base` that are present in the patch.
+    fn apply(self, base: MergeOptions) -> MergeOptions {
+        let mut options = base;
+
+        if let Some(direction) = self.direction {
+            options.direction = direction;
+        }
+        if let Some(background) = self.background {
+            options.background = background.over(BackgroundColor::white());
+        }
+        if let Some(gradient) = self.background_gradient {
+            options.background_gradient = gradient.map(|gradient| Gradient {
+                start: color(gradient.start, options.background),
+                end: color(gradient.end, options.background),
+                angle: gradient.angle.map_or(180.0, |Finite(v)| v as f32),
+            });
+        }
+        if let Some(image) = self.background_image {
+            options.background_image = image.map(|image| BackgroundImage {
+                bytes: image.bytes.0,
+                fit: image.fit.unwrap_or_default(),
+            });
+        }
+        if let Some(border) = self.border {
+            options.border = border.map(|line| Border {
+                width: line.width.map_or(1, |Pixels(v)| v),
+                color: color(line.color, BackgroundColor::black()),
+            });
+        }
+        if let Some(divider) = self.divider {
+            options.divider = divider.map(|line| Divider {
+                width: line.width.map_or(1, |Pixels(v)| v),
+                color: color(line.color, BackgroundColor::black()),
+            });
+        }
+        if let Some(seams) = self.draw_seams {
+            let default = SeamLines::default();
+            options.draw_seams = seams.and_then(Toggle::settings).map(|seams| SeamLines {
+                width: seams.width.map_or(default.width, |Size(v)| v),
+                color: color(seams.color, default.color),
+            });
+        }
+        if let Some(captions) = self.captions {
+            options.captions = captions;
+        }
+        if let Some(position) = self.caption_position {
+            options.caption_position = position;
+        }
+        if let Some(Positive(size)) = self.caption_size {
+            options.caption_size = size;
+        }
+        options.caption_color = color(self.caption_color, options.caption_color);
+        if let Some(numbers) = self.page_numbers {
+            options.page_numbers = numbers
+                .and_then(Toggle::settings)
+                .map(|numbers| PageNumbers {
+                    corner: numbers.corner.unwrap_or_default(),
+                    size: numbers.size.map_or(16.0, |Positive(v)| v),
+                    color: color(numbers.color, BackgroundColor::black()),
+                });
+        }
+        if let Some(shadow) = self.shadow {
+            let offset = |value: Option<Finite>| {
+                value.map_or(0, |Finite(v)| {
+                    v.round().clamp(i32::MIN as f64, i32::MAX as f64) as i32
+                })
+            };
+            options.shadow = shadow.map(|shadow| Shadow {
+                blur_radius: shadow.blur_radius.map_or(0, |Pixels(v)| v),
+                offset_x: offset(shadow.offset_x),
+                offset_y: offset(shadow.offset_y),
+                color: color(shadow.color, BackgroundColor::new(0, 0, 0, 128)),
+            });
+        }
+        if let Some(Pixels(radius)) = self.corner_radius {
+            options.corner_radius = radius;
+        }
+        match self.overlap_sensitivity {
+            Some(SensitivityPatch::Global(Sensitivity(sensitivity))) => {
+                options.overlap_sensitivity = sensitivity;
+            }
+            Some(SensitivityPatch::PerPair(sensitivities)) => {
+                options.overlap_sensitivities = sensitivities
+                    .into_iter()
+                    .map(|item| item.map(|Sensitivity(v)| v))
+                    .collect();
+            }
+            None => {}
+        }
+        if let Some(strip_chrome) = self.strip_chrome {
+            options.strip_chrome = strip_chrome;
+        }
+        if let Some(chrome_mode) = self.chrome_mode {
+            options.chrome_mode = chrome_mode;
+        }
+        if let Some(Size(width)) = self.chrome_proxy_width {
+            options.chrome_proxy_width = Some(width);
+        }
+        if let Some(Pixels(min_overlap)) = self.min_overlap_pixels {
+            options.min_overlap_pixels = min_overlap;
+        }
+        if let Some(Finite(ratio)) = self.width_ratio_threshold {
+            options.width_ratio_threshold = ratio.clamp(0.0, 1.0) as f32;
+        }
+        if let Some(Factor(factor)) = self.max_upscale_factor {
+            options.max_upscale_factor = factor;
+        }
+        if let Some(error_on_upscale) = self.error_on_upscale {
+            options.error_on_upscale = error_on_upscale;
+        }
+        if let Some(allow_upscale) = self.allow_upscale {
+            options.allow_upscale = allow_upscale;
+        }
+        if let Some(dpi) = self.target_dpi {
+            options.target_dpi = dpi.map(|Positive(v)| v);
+        }
+        if let Some(dpi) = self.dpi {
+            options.dpi = dpi.map(|Positive(v)| v);
+        }
+        if let Some(scaling) = self.low_color_scaling {
+            options.low_color_scaling = scaling;
+        }
+        if let Some(linear) = self.linear_scaling {
+            options.linear_scaling = linear;
+        }
+        if let Some(premultiplied) = self.premultiplied_alpha {
+            options.premultiplied_alpha = premultiplied;
+        }
+        if let Some(high_bit_depth) = self.high_bit_depth {
+            options.high_bit_depth = high_bit_depth;
+        }
+        if let Some(repro_bundles) = self.repro_bundles {
+            options.repro_bundles = repro_bundles;
+        }
+        if let Some(Degrees(rotation)) = self.output_rotation {
+            options.output_rotation = rotation;
+        }
+        if let Some(also_preview) = self.also_preview {
+            options.also_preview = also_preview;
+        }
+        if let Some(Size(max_dimension)) = self.preview_max_dimension {
+            options.preview_max_dimension = max_dimension;
+        }
+        if let Some(quality) = self.preview_proof_quality {
+            options.preview_proof_quality =
+                quality.map(|Finite(v)| v.round().clamp(1.0, 100.0) as u8);
+        }
+        if let Some(blur_hash) = self.blur_hash {
+            options.blur_hash = blur_hash;
+        }
+        if let Some(collect_stats) = self.collect_stats {
+            options.collect_stats = collect_stats;
+        }
+        if let Some(guides) = self.guides {
+            options.guides = guides.and_then(Toggle::settings).map(|guides| Guides {
+                thirds: guides.thirds.unwrap_or(true),
+                center: guides.center.unwrap_or(false),
+                safe_margin: guides
+                    .safe_margin
+                    .map_or(0.0, |Finite(v)| v.clamp(0.0, 0.5) as f32),
+                line_width: guides.line_width.map_or(1, |Size(v)| v),
+                color: color(guides.color, BackgroundColor::new(255, 0, 255, 192)),
+                separate_layer: guides.separate_layer.unwrap_or(false),
+            });
+        }
+        if let Some(Size(width)) = self.thumbnail_width {
+            options.thumbnail_width = Some(width);
+        }
+        if let Some(Finite(confidence)) = self.split_min_confidence {
+            options.split_min_confidence = confidence.clamp(0.0, 1.0) as f32;
+        }
+        if let Some(regions) = self.overlap_regions {
+            options.overlap_regions = regions
+                .into_iter()
+                .map(|region| {
+                    region.map(|region| OverlapRegion {
+                        x: region.x.0,
+                        y: region.y.0,
+                        width: region.width.0,
+                        height: region.height.0,
+                    })
+                })
+                .collect();
+        }
+        if let Some(overrides) = self.overlap_overrides {
+            options.overlap_overrides = overrides
+                .into_iter()
+                .map(|item| item.map(|Pixels(v)| v))
+                .collect();
+        }
+        if let Some(scale_mode) = self.scale_mode {
+            options.scale_mode = scale_mode;
+        }
+        if let Some(Pixels(spacing)) = self.spacing {
+            options.spacing = spacing;
+        }
+        match self.padding {
+            Some(PaddingPatch::Uniform(Pixels(value))) => {
+                options.padding = Padding::uniform(value);
+            }
+            Some(PaddingPatch::Sides(sides)) => {
+                let side = |value: Option<Pixels>| value.map_or(0, |Pixels(v)| v);
+                options.padding = Padding {
+                    top: side(sides.top),
+                    right: side(sides.right),
+                    bottom: side(sides.bottom),
+                    left: side(sides.left),
+                };
+            }
+            None => {}
+        }
+        if let Some(align) = self.align {
+            options.align = align;
+        }
+        if let Some(vertical_align) = self.vertical_align {
+            options.vertical_align = vertical_align;
+        }
+        if let Some(ratio) = self.target_aspect_ratio {
+            options.target_aspect_ratio = ratio.map(|Positive(v)| v);
+        }
+        if let Some(columns) = self.columns {
+            options.columns = columns.map(|Size(v)| v);
+        }
+        if let Some(sheet) = self.contact_sheet {
+            options.contact_sheet = sheet.and_then(Toggle::settings).map(|sheet| ContactSheet {
+                columns: sheet.columns.map(|Size(v)| v),
+                header: sheet.header,
+            });
+        }
+        if let Some(per_image) = self.image_options {
+            options.image_options = per_image
+                .into_iter()
+                .map(|item| {
+                    item.map_or_else(ImageOptions::default, |item| ImageOptions {
+                        align: item.align,
+                        pin: item.pin.unwrap_or(false),
+                        low_color: item.low_color.unwrap_or(false),
+                        dpi: item.dpi.map(|Positive(v)| v),
+                        tile_offset: item.tile_offset.map(|Pixels(v)| v),
+                        tile_height: item.tile_height.map(|Pixels(v)| v),
+                        flip_h: item.flip_h.unwrap_or(false),
+                        flip_v: item.flip_v.unwrap_or(false),
+                    })
+                })
+                .collect();
+        }
+        if let Some(Pixels(multiple)) = self.dimension_multiple {
+            options.dimension_multiple = Some(multiple);
+        }
+        if let Some(Count(max_pixels)) = self.max_output_pixels {
+            options.max_output_pixels = Some(max_pixels);
+        }
+        if let Some(downscale_to_fit) = self.downscale_to_fit {
+            options.downscale_to_fit = downscale_to_fit;
+        }
+        if let Some(Count(max_bytes)) = self.max_memory_bytes {
+            options.max_memory_bytes = Some(max_bytes);
+        }
+        if let Some(Count(max_pixels)) = self.max_input_pixels {
+            options.max_input_pixels = Some(max_pixels);
+        }
+        if let Some(Pixels(max_dimension)) = self.max_input_dimension {
+            options.max_input_dimension = Some(max_dimension);
+        }
+        if let Some(names) = self.names {
+            options.file_names = names;
+        }
+        if let Some(order) = self.order {
+            options.order = order.map(|order| order.into_iter().map(|Index(i)| i).collect());
+        }
+        if let Some(trim) = self.trim_margins {
+            options.trim_margins = trim.and_then(Toggle::settings).map(|trim| TrimMargins {
+                tolerance: trim
+                    .tolerance
+                    .map_or(TrimMargins::default().tolerance, |Channel(v)| v),
+            });
+        }
+        if let Some(strict) = self.strict {
+            options.strict = strict;
+        }
+        if let Some(copy) = self.copy_exif {
+            // GPS is only copied when asked for by name
+            options.copy_exif = copy.and_then(Toggle::settings).map(|copy| ExifCopy {
+                capture_time: copy.capture_time.unwrap_or(true),
+                camera: copy.camera.unwrap_or(true),
+                gps: copy.gps.unwrap_or(false),
+            });
+        }
+        if let Some(provenance) = self.provenance {
+            options.provenance = provenance;
+        }
+        if let Some(strip) = self.strip_metadata {
+            options.strip_metadata = strip;
+        }
+
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse<'de>(value: impl Deserializer<'de>) -> Result<MergeOptions, OptionsError> {
+        parse_onto(MergeOptions::default(), value)
+    }
+
+    #[test]
+    fn test_parse_overrides_only_present_fields() {
+        let base = MergeOptions {
+            spacing: 8,
+            ..MergeOptions::default()
+        };
+        let options = parse_onto(
+            base,
+            json!({ "direction": "smart", "overlapSensitivity": 62.4, "background": { "r": 0 } }),
+        )
+        .unwrap();
+
+        assert_eq!(options.direction, Direction::Smart);
+        assert_eq!(options.overlap_sensitivity, 62);
+        assert_eq!(options.background, BackgroundColor::new(0, 255, 255, 255));
+        assert_eq!(options.spacing, 8);
+    }
+
+    #[test]
+    fn test_parse_null_clears_nullable_fields() {
+        let base =
+            parse(json!({ "border": { "width": 3 }, "guides": true, "targetDpi": 300 })).unwrap();
+        assert_eq!(base.border.map(|b| b.width), Some(3));
+        assert_eq!(base.guides.map(|g| g.thirds), Some(true));
+
+        let options = parse_onto(
+            base,
+            json!({ "border": null, "guides": false, "targetDpi": null }),
+        )
+        .unwrap();
+        assert_eq!(options.border, None);
+        assert_eq!(options.guides, None);
+        assert_eq!(options.target_dpi, None);
+    }
+
+    #[test]
+    fn test_parse_accepts_alternative_shapes() {
+        let options = parse(json!({
+            "overlapSensitivity": [10, null, 90],
+            "padding": 4,
+            "pageNumbers": { "corner": "topLeft" },
+            "outputRotation": 90,
+            "imageOptions": [null, { "pin": true, "dpi": 150, "flipH": true }],
+            "names": ["a.png", null],
+            "strict": true,
+            "copyExif": { "camera": false },
+            "dpi": 144,
+            "stripMetadata": true,
+            "linearScaling": true,
+            "premultipliedAlpha": true,
+            "highBitDepth": true,
+            "trimMargins": { "tolerance": 20 },
+            "order": [1, 0],
+            "targetAspectRatio": 1.5,
+            "columns": 4,
+            "contactSheet": { "header": "Review" },
+            "maxInputPixels": 1e8,
+            "maxInputDimension": 16384,
+            "drawSeams": { "width": 2 },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            options.overlap_sensitivities,
+            vec![Some(10), None, Some(90)]
+        );
+        assert_eq!(options.padding, Padding::uniform(4));
+        assert_eq!(
+            options.page_numbers.map(|p| p.corner),
+            Some(Corner::TopLeft)
+        );
+        assert_eq!(options.output_rotation, Rotation::Clockwise90);
+        assert!(options.image_options[1].pin);
+        assert_eq!(options.image_options[1].dpi, Some(150.0));
+        assert!(options.image_options[1].flip_h && !options.image_options[1].flip_v);
+        assert_eq!(options.file_names, vec![Some("a.png".to_string()), None]);
+        assert!(options.strict);
+        assert_eq!(options.dpi, Some(144.0));
+        assert!(options.strip_metadata);
+        assert!(options.linear_scaling);
+        assert!(options.premultiplied_alpha);
+        assert!(options.high_bit_depth);
+        assert_eq!(options.trim_margins, Some(TrimMargins { tolerance: 20 }));
+        assert_eq!(options.order, Some(vec![1, 0]));
+        assert_eq!(options.target_aspect_ratio, Some(1.5));
+        assert_eq!(options.columns, Some(4));
+        assert_eq!(options.max_input_pixels, Some(100_000_000));
+        assert_eq!(options.max_input_dimension, Some(16_384));
+        assert_eq!(
+            options.draw_seams,
+            Some(SeamLines {
+                width: 2,
+                color: BackgroundColor::new(255, 0, 255, 255),
+            })
+        );
+        assert_eq!(
+            options.contact_sheet,
+            Some(ContactSheet {
+                columns: None,
+                header: Some("Review".to_string()),
+            })
+        );
+        assert_eq!(
+            options.copy_exif,
+            Some(ExifCopy {
+                capture_time: true,
+                camera: false,
+                gps: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_field() {
+        let error = parse(json!({ "overlapSensitivty": 50 })).unwrap_err();
+        assert_eq!(error.field, "overlapSensitivty");
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_keyword_naming_field() {
+        let error = parse(json!({ "direction": "diagonal" })).unwrap_err();
+        assert_eq!(error.field, "direction");
+        assert!(error.message.contains("diagonal"), "{error:?}");
+    }
+
+    #[test]
+    fn test_parse_reports_nested_field_path() {
+        let error = parse(json!({ "imageOptions": [{}, { "dpi": -1 }] })).unwrap_err();
+        assert_eq!(error.field, "imageOptions[1].dpi");
+
+        let error = parse(json!({ "shadow": { "color": { "r": "red" } } })).unwrap_err();
+        assert_eq!(error.field, "shadow.color.r");
+    }
+
+    #[test]
+    fn test_parse_rejects_out_of_domain_values() {
+        assert_eq!(
+            parse(json!({ "outputRotation": 45 })).unwrap_err().field,
+            "outputRotation"
+        );
+        assert_eq!(
+            parse(json!({ "maxUpscaleFactor": 0.5 })).unwrap_err().field,
+            "maxUpscaleFactor"
+        );
+        assert_eq!(
+            parse(json!({ "thumbnailWidth": 0 })).unwrap_err().field,
+            "thumbnailWidth"
+        );
+    }
+
+    #[test]
+    fn test_parse_values_shaped_like_js() {
+        // What serde-wasm-bindgen produces: `null` as unit, a Uint8Array as bytes.
+        use serde_value::Value;
+        let field = |name: &str| Value::String(name.to_string());
+        let image = Value::Map(
+            [
+                (field("bytes"), Value::Bytes(vec![1, 2, 3])),
+                (field("fit"), field("stretch")),
+            ]
+            .into(),
+        );
+        let value = Value::Map(
+            [
+                (field("backgroundImage"), image),
+                (field("border"), Value::Unit),
+                (field("spacing"), Value::F64(2.6)),
+            ]
+            .into(),
+        );
+
+        let options = parse(value).unwrap();
+        let image = options.background_image.unwrap();
+        assert_eq!(image.bytes, vec![1, 2, 3]);
+        assert_eq!(image.fit, BackgroundFit::Stretch);
+        assert_eq!(options.border, None);
+        assert_eq!(options.spacing, 3);
+    }
+
+    #[test]
+    fn test_parse_null_options_keep_base() {
+        let base = MergeOptions {
+            spacing: 5,
+            ..MergeOptions::default()
+        };
+        assert_eq!(parse_onto(base, json!(null)).unwrap().spacing, 5);
+        assert_eq!(parse(json!(42)).unwrap_err().field, "");
+    }
+}
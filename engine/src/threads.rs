@@ -0,0 +1,89 @@
+//! Rayon thread pool for threaded WASM builds.
+//!
+//! A threaded build shares its memory with Web Workers, but WASM cannot spawn
+//! them itself: `initThreadPool` has `workerHelpers.js` start one worker per
+//! thread, each instantiating this module on the shared memory and running
+//! one of rayon's threads until the page goes away. Merges called before the
+//! pool is up, or in a build without the `threads` feature, run sequentially.
+
+use js_sys::Promise;
+use rayon::{ThreadBuilder, ThreadPoolBuilder};
+use std::sync::{Mutex, mpsc};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(module = "/src/workerHelpers.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = startWorkers)]
+    fn start_workers(module: JsValue, memory: JsValue, builder: PoolBuilder) -> Promise;
+}
+
+/// Threads waiting for a worker to run them. Leaked so workers can reach it
+/// through its address for as long as the module lives.
+type ThreadQueue = Mutex<mpsc::Receiver<ThreadBuilder>>;
+
+/// Hands rayon's threads to the workers `startWorkers` spawns.
+#[doc(hidden)]
+#[wasm_bindgen]
+pub struct PoolBuilder {
+    num_threads: usize,
+    sender: mpsc::Sender<ThreadBuilder>,
+    queue: &'static ThreadQueue,
+}
+
+#[wasm_bindgen]
+impl PoolBuilder {
+    #[wasm_bindgen(js_name = numThreads)]
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    /// Address of the thread queue, passed to each worker.
+    pub fn queue(&self) -> usize {
+        self.queue as *const ThreadQueue as usize
+    }
+
+    /// Builds the global pool once every worker is listening.
+    pub fn build(&self) -> Result<(), JsValue> {
+        let sender = self.sender.clone();
+        ThreadPoolBuilder::new()
+            .num_threads(self.num_threads)
+            .spawn_handler(move |thread| {
+                sender
+                    .send(thread)
+                    .map_err(|_| std::io::Error::other("thread queue closed"))
+            })
+            .build_global()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
+/// Starts a pool of `num_threads` workers for the `threads` feature.
+///
+/// Resolves once the pool is running; call it once per module instance,
+/// from a worker (the main thread may not block on shared memory), before
+/// the first merge. Only a build with `+atomics,+bulk-memory` loaded in a
+/// `crossOriginIsolated` context can share its memory; hosts fall back to the
+/// sequential build otherwise. Rejects if a pool was already started.
+#[wasm_bindgen(js_name = initThreadPool)]
+pub fn init_thread_pool(num_threads: usize) -> Promise {
+    let (sender, receiver) = mpsc::channel();
+    let builder = PoolBuilder {
+        num_threads: num_threads.max(1),
+        sender,
+        queue: Box::leak(Box::new(Mutex::new(receiver))),
+    };
+    start_workers(wasm_bindgen::module(), wasm_bindgen::memory(), builder)
+}
+
+/// Runs the next pool thread on the calling worker; never returns while the
+/// pool lives. Called by `workerHelpers.js` with `PoolBuilder::queue`.
+#[doc(hidden)]
+#[wasm_bindgen(js_name = startPoolWorker)]
+pub fn start_pool_worker(queue: usize) {
+    // SAFETY: `queue` is the address of the leaked queue from `init_thread_pool`.
+    let queue = unsafe { &*(queue as *const ThreadQueue) };
+    let thread = queue.lock().ok().and_then(|receiver| receiver.recv().ok());
+    if let Some(thread) = thread {
+        thread.run();
+    }
+}
@@ -0,0 +1,189 @@
+//! TypeScript declarations for the JS-facing shapes.
+//!
+//! Options and stats are serde types and derive their declarations with
+//! `Tsify` (see `options.rs` and the core's `tsify` feature). Inputs, results
+//! and errors are read and built by hand in `lib.rs`, so theirs are written
+//! here and must be kept in step with those functions.
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const MERGE_TYPES: &str = r#"
+/** One input: file bytes, an `alloc_input` handle, file bytes with metadata, or raw pixels. */
+export type MergeInput =
+    | Uint8Array
+    | number
+    | { data: Uint8Array | number; name?: string; mimeType?: string; frame?: number; page?: number; iconSize?: number }
+    | { width: number; height: number; rgba: Uint8Array | Uint8ClampedArray; name?: string };
+
+/** Options accepted by every merge entry point; see `merge_images` for their meaning. */
+export type MergeOptions = OptionsPatch;
+
+export type ErrorCode =
+    | "NO_IMAGES"
+    | "DECODE_FAILED"
+    | "BACKGROUND_DECODE_FAILED"
+    | "INTERNAL_ERROR"
+    | "OUTPUT_TOO_LARGE"
+    | "OUT_OF_MEMORY"
+    | "INPUT_TOO_LARGE"
+    | "UPSCALE_TOO_LARGE"
+    | "HOOK_REJECTED"
+    | "CANCELLED"
+    | "INVALID_INPUT"
+    | "INVALID_OPTIONS"
+    | "INVALID_INDEX"
+    | "INVALID_ORDER"
+    | "STRICT_VIOLATION";
+
+/** Thrown by every entry point: `code`, `message` and the error's parameters. */
+export interface EngineError {
+    code: ErrorCode;
+    message: string;
+    fileIndex?: number;
+    fileName?: string;
+    field?: string;
+    [param: string]: unknown;
+}
+
+export interface MergeWarning {
+    code: "UPSCALED" | "DOWNSCALED" | "OVERLAP_NOT_FOUND";
+    message: string;
+    [param: string]: unknown;
+}
+
+export interface Seam {
+    overlap: number;
+    xShift: number;
+    found: boolean;
+    fellBack: boolean;
+    confidence?: number;
+}
+
+export interface Placement {
+    x: number;
+    y: number;
+    width: number;
+    height: number;
+    cropTop: number;
+    cropBottom: number;
+}
+
+export interface PreviewOutput {
+    bytes: Uint8Array;
+    width: number;
+    height: number;
+}
+
+export interface ReproBundle {
+    pairIndex: number;
+    overlapSensitivity: number;
+    minOverlapPixels: number;
+    widthRatioThreshold: number;
+    topSize: { width: number; height: number };
+    bottomSize: { width: number; height: number };
+    topTrimBottom: number;
+    bottomTrimTop: number;
+    topStrip: Uint8Array;
+    bottomStrip: Uint8Array;
+}
+
+/** Result of `merge_images_ex` and the other encoding entry points. */
+export interface MergeOutput {
+    bytes: Uint8Array;
+    width: number;
+    height: number;
+    byteLength: number;
+    sha256: string;
+    warnings: MergeWarning[];
+    seams: Seam[];
+    placements: Placement[];
+    preview?: PreviewOutput;
+    thumbnail?: PreviewOutput;
+    blurHash?: string;
+    guideLayer?: PreviewOutput;
+    stats?: MergeStats;
+    reproBundles?: ReproBundle[];
+}
+
+/** Pixels are a view of wasm memory: use them before the next engine call, then `free_raw_output(ptr)`. */
+export interface RawOutput {
+    pixels: Uint8ClampedArray;
+    ptr: number;
+    width: number;
+    height: number;
+    shared: boolean;
+    warnings: MergeWarning[];
+}
+
+export interface SplitMergeOutput {
+    outputs: MergeOutput[];
+    breaks: { afterIndex: number; reason: "noOverlap" | "lowConfidence"; confidence?: number }[];
+}
+
+/** One page from `paginate_image` or tile from `merge_images_tiled`; `y` is its first source row. */
+export interface Page {
+    bytes: Uint8Array;
+    width: number;
+    height: number;
+    y: number;
+}
+
+export interface TiledMergeOutput {
+    tiles: Page[];
+    width: number;
+    height: number;
+    warnings: MergeWarning[];
+}
+
+export type PdfLayout = "longPage" | "perImage";
+export type AnimationFormat = "apng" | "gif";
+
+export interface AnimationOutput {
+    bytes: Uint8Array;
+    width: number;
+    height: number;
+    frameCount: number;
+    warnings: MergeWarning[];
+}
+
+export interface PdfOutput {
+    bytes: Uint8Array;
+    pageCount: number;
+    warnings: MergeWarning[];
+}
+
+/** Header facts from `probe_image`; `width` and `height` are before `orientation`. */
+export interface ImageProbe {
+    format: "png" | "jpeg" | "gif" | "webp" | "tiff" | "ico" | "bmp";
+    width: number;
+    height: number;
+    orientation: number;
+    hasAlpha: boolean;
+    bitDepth: number;
+}
+
+export interface ImageAnalysis {
+    sourceWidth: number;
+    sourceHeight: number;
+    width: number;
+    height: number;
+    trimTop: number;
+    trimBottom: number;
+    x: number;
+    y: number;
+}
+
+export interface MergeAnalysis {
+    width: number;
+    height: number;
+    images: ImageAnalysis[];
+    pairs: Seam[];
+    warnings: MergeWarning[];
+}
+
+/** A plan from `plan_images`, optionally edited before `execute_plan`. */
+export interface MergePlan extends MergeAnalysis {
+    options?: MergeOptions;
+}
+"#;
@@ -0,0 +1,41 @@
+// Web Workers for the rayon pool of a threaded build (see `threads.rs`).
+//
+// wasm-bindgen copies this file to `snippets/<crate>-<hash>/src/`, three
+// levels below the generated module it imports.
+
+const waitForMessage = (target, type) =>
+	new Promise((resolve) => {
+		target.addEventListener('message', function onMessage({ data }) {
+			if (data?.type !== type) return;
+			target.removeEventListener('message', onMessage);
+			resolve(data);
+		});
+	});
+
+// In a pool worker: instantiate the module on the shared memory, then run
+// one rayon thread for the life of the page.
+if (typeof WorkerGlobalScope !== 'undefined' && self.name === 'merge-images-pool') {
+	waitForMessage(self, 'merge_images_pool_init').then(async ({ module, memory, queue }) => {
+		const engine = await import('../../../merge_images_engine.js');
+		await engine.default({ module_or_path: module, memory });
+		postMessage({ type: 'merge_images_pool_ready' });
+		engine.startPoolWorker(queue);
+	});
+}
+
+// Spawns `builder.numThreads()` pool workers and builds the pool once they
+// are all listening.
+export async function startWorkers(module, memory, builder) {
+	const init = { type: 'merge_images_pool_init', module, memory, queue: builder.queue() };
+	await Promise.all(
+		Array.from({ length: builder.numThreads() }, async () => {
+			const worker = new Worker(new URL('./workerHelpers.js', import.meta.url), {
+				type: 'module',
+				name: 'merge-images-pool'
+			});
+			worker.postMessage(init);
+			await waitForMessage(worker, 'merge_images_pool_ready');
+		})
+	);
+	builder.build();
+}
@@ -29,6 +29,11 @@ fn test_greet_initializes() {
     assert_eq!(result, "merge-images-engine initialized");
 }
 
+#[wasm_bindgen_test]
+fn test_merge_concurrent_safe() {
+    assert!(merge_images_engine::merge_concurrent_safe());
+}
+
 #[wasm_bindgen_test]
 fn test_merge_empty_array_returns_error() {
     use js_sys::Array;
@@ -199,3 +204,901 @@ fn test_merge_default_options() {
     let result2 = merge_images_engine::merge_images(&images, &JsValue::null());
     assert!(result2.is_ok());
 }
+
+#[wasm_bindgen_test]
+fn test_merge_chunked_reads_match_full_output() {
+    use js_sys::{Array, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let blue_png = create_test_png(10, 10, 0, 0, 255);
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(red_png.as_slice()));
+    images.push(&Uint8Array::from(blue_png.as_slice()));
+
+    let full = merge_images_engine::merge_images(&images, &JsValue::undefined())
+        .unwrap()
+        .to_vec();
+    let result = merge_images_engine::merge_images_chunked(&images, &JsValue::undefined()).unwrap();
+    assert_eq!(result.result_len() as usize, full.len());
+
+    // Reassemble from small chunks.
+    let mut reassembled = Vec::new();
+    let mut offset = 0;
+    while offset < result.result_len() {
+        let chunk = result.read_result(offset, 16).to_vec();
+        offset += chunk.len() as u32;
+        reassembled.extend(chunk);
+    }
+    assert_eq!(reassembled, full);
+
+    // Reads past the end are empty.
+    assert_eq!(result.read_result(result.result_len() + 5, 10).length(), 0);
+}
+
+#[wasm_bindgen_test]
+fn test_merge_ex_returns_checksum_metadata() {
+    use js_sys::{Array, Reflect, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let images = Array::new();
+    images.push(&Uint8Array::from(red_png.as_slice()));
+
+    let result = merge_images_engine::merge_images_ex(&images, &JsValue::undefined()).unwrap();
+    let bytes = Uint8Array::new(&Reflect::get(&result, &JsValue::from_str("bytes")).unwrap());
+    let byte_length = Reflect::get(&result, &JsValue::from_str("byteLength"))
+        .unwrap()
+        .as_f64()
+        .unwrap();
+    let sha256 = Reflect::get(&result, &JsValue::from_str("sha256"))
+        .unwrap()
+        .as_string()
+        .unwrap();
+
+    assert_eq!(byte_length as u32, bytes.length());
+    assert_eq!(sha256.len(), 64);
+    assert!(sha256.chars().all(|c| c.is_ascii_hexdigit()));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_ex_reports_upscale_warning() {
+    use js_sys::{Array, Reflect, Uint8Array};
+
+    let small_png = create_test_png(10, 10, 255, 0, 0);
+    let wide_png = create_test_png(30, 10, 0, 255, 0);
+    let images = Array::new();
+    images.push(&Uint8Array::from(small_png.as_slice()));
+    images.push(&Uint8Array::from(wide_png.as_slice()));
+
+    let result = merge_images_engine::merge_images_ex(&images, &JsValue::undefined()).unwrap();
+    let warnings = Array::from(&Reflect::get(&result, &JsValue::from_str("warnings")).unwrap());
+    assert_eq!(warnings.length(), 1);
+    let code = Reflect::get(&warnings.get(0), &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().unwrap(), "UPSCALED");
+}
+
+#[wasm_bindgen_test]
+fn test_merge_ex_reports_overlap_not_found_warning() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(20, 20, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(20, 20, 0, 255, 0).as_slice(),
+    ));
+    let options = Object::new();
+    Reflect::set(
+        &options,
+        &JsValue::from_str("direction"),
+        &JsValue::from_str("smart"),
+    )
+    .unwrap();
+
+    let result = merge_images_engine::merge_images_ex(&images, &options).unwrap();
+    let warnings = Array::from(&Reflect::get(&result, &JsValue::from_str("warnings")).unwrap());
+    assert_eq!(warnings.length(), 1);
+    let warning = warnings.get(0);
+    let code = Reflect::get(&warning, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().unwrap(), "OVERLAP_NOT_FOUND");
+    let pair = Reflect::get(&warning, &JsValue::from_str("pairIndex")).unwrap();
+    assert_eq!(pair.as_f64(), Some(0.0));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_ex_reports_placements() {
+    use js_sys::{Array, Reflect, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(10, 10, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(10, 20, 0, 255, 0).as_slice(),
+    ));
+
+    let result = merge_images_engine::merge_images_ex(&images, &JsValue::undefined()).unwrap();
+    let placements = Array::from(&Reflect::get(&result, &JsValue::from_str("placements")).unwrap());
+    assert_eq!(placements.length(), 2);
+    let get = |key: &str| {
+        Reflect::get(&placements.get(1), &JsValue::from_str(key))
+            .unwrap()
+            .as_f64()
+    };
+    assert_eq!(get("y"), Some(10.0));
+    assert_eq!(get("height"), Some(20.0));
+    assert_eq!(get("cropTop"), Some(0.0));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_split_returns_outputs_and_breaks() {
+    use js_sys::{Array, Reflect, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let images = Array::new();
+    images.push(&Uint8Array::from(red_png.as_slice()));
+
+    let result = merge_images_engine::merge_images_split(&images, &JsValue::undefined()).unwrap();
+    let outputs = Array::from(&Reflect::get(&result, &JsValue::from_str("outputs")).unwrap());
+    let breaks = Array::from(&Reflect::get(&result, &JsValue::from_str("breaks")).unwrap());
+    assert_eq!(outputs.length(), 1);
+    assert_eq!(breaks.length(), 0);
+}
+
+#[wasm_bindgen_test]
+fn test_merge_streaming_delivers_chunks() {
+    use js_sys::{Array, Function, Reflect, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let images = Array::new();
+    images.push(&Uint8Array::from(red_png.as_slice()));
+    images.push(&Uint8Array::from(red_png.as_slice()));
+
+    let chunks = Array::new();
+    let collect = Function::new_with_args("chunk", "this.push(chunk);").bind(&chunks);
+    let result =
+        merge_images_engine::merge_images_streaming(&images, &JsValue::undefined(), &collect)
+            .unwrap();
+
+    let streamed: Vec<u8> = chunks
+        .iter()
+        .flat_map(|chunk| Uint8Array::from(chunk).to_vec())
+        .collect();
+    let byte_length = Reflect::get(&result, &JsValue::from_str("byteLength")).unwrap();
+    assert_eq!(byte_length.as_f64().unwrap() as usize, streamed.len());
+    assert!(
+        Reflect::get(&result, &JsValue::from_str("bytes"))
+            .unwrap()
+            .is_undefined()
+    );
+    let img = image::load_from_memory(&streamed).unwrap();
+    assert_eq!((img.width(), img.height()), (10, 20));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_session_caches_inputs() {
+    use js_sys::{Reflect, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let blue_png = create_test_png(10, 20, 0, 0, 255);
+    let mut session = merge_images_engine::MergeSession::new();
+    assert_eq!(
+        session
+            .add_image(&Uint8Array::from(red_png.as_slice()))
+            .unwrap(),
+        0
+    );
+    session
+        .add_image(&Uint8Array::from(blue_png.as_slice()))
+        .unwrap();
+    session.reorder(&[1, 0]).unwrap();
+    assert!(session.reorder(&[0, 0]).is_err());
+    assert!(session.remove_image(5).is_err());
+
+    let result = session.merge(&JsValue::undefined()).unwrap();
+    let height = Reflect::get(&result, &JsValue::from_str("height")).unwrap();
+    assert_eq!(height.as_f64().unwrap(), 30.0);
+    session.remove_image(0).unwrap();
+    assert_eq!(session.image_count(), 1);
+}
+
+#[wasm_bindgen_test]
+fn test_merge_hooks_can_reject() {
+    use js_sys::{Array, Function, Object, Reflect, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let images = Array::new();
+    images.push(&Uint8Array::from(red_png.as_slice()));
+
+    let hooks = Object::new();
+    let reject = Function::new_no_args("return false;");
+    Reflect::set(&hooks, &JsValue::from_str("afterDecode"), &reject).unwrap();
+
+    let error =
+        merge_images_engine::merge_images_with_hooks(&images, &JsValue::undefined(), &hooks)
+            .unwrap_err();
+    let code = Reflect::get(&error, &JsValue::from_str("code")).unwrap();
+    let stage = Reflect::get(&error, &JsValue::from_str("stage")).unwrap();
+    assert_eq!(code.as_string().unwrap(), "HOOK_REJECTED");
+    assert_eq!(stage.as_string().unwrap(), "afterDecode");
+}
+
+#[wasm_bindgen_test]
+fn test_merge_hooks_report_progress() {
+    use js_sys::{Array, Function, Object, Reflect, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let images = Array::new();
+    images.push(&Uint8Array::from(red_png.as_slice()));
+    images.push(&Uint8Array::from(red_png.as_slice()));
+
+    let stages = Array::new();
+    let hooks = Object::new();
+    let record = Function::new_with_args("event", "this.push(event.stage);").bind(&stages);
+    Reflect::set(&hooks, &JsValue::from_str("onProgress"), &record).unwrap();
+
+    merge_images_engine::merge_images_with_hooks(&images, &JsValue::undefined(), &hooks).unwrap();
+    let stages: Vec<String> = stages.iter().filter_map(|s| s.as_string()).collect();
+    assert_eq!(
+        stages,
+        [
+            "decoded",
+            "decoded",
+            "scaled",
+            "scaled",
+            "compositing",
+            "encoding"
+        ]
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_merge_hooks_can_cancel() {
+    use js_sys::{Array, Function, Object, Reflect, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let images = Array::new();
+    images.push(&Uint8Array::from(red_png.as_slice()));
+
+    let hooks = Object::new();
+    let cancelled = Function::new_no_args("return true;");
+    Reflect::set(&hooks, &JsValue::from_str("isCancelled"), &cancelled).unwrap();
+
+    let error =
+        merge_images_engine::merge_images_with_hooks(&images, &JsValue::undefined(), &hooks)
+            .unwrap_err();
+    let code = Reflect::get(&error, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().unwrap(), "CANCELLED");
+}
+
+#[wasm_bindgen_test]
+fn test_init_engine_defaults_apply_to_merges() {
+    use js_sys::{Array, Reflect, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let images = Array::new();
+    images.push(&Uint8Array::from(red_png.as_slice()));
+    images.push(&Uint8Array::from(red_png.as_slice()));
+
+    merge_images_engine::init_engine(&JsValue::from_str(r#"{"maxOutputPixels": 150}"#)).unwrap();
+    let result = merge_images_engine::merge_images(&images, &JsValue::undefined());
+    let err = result.unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().unwrap(), "OUTPUT_TOO_LARGE");
+
+    // Restoring built-in defaults lifts the limit again.
+    merge_images_engine::init_engine(&JsValue::undefined()).unwrap();
+    assert!(merge_images_engine::merge_images(&images, &JsValue::undefined()).is_ok());
+
+    let invalid = merge_images_engine::init_engine(&JsValue::from_str("{not json"));
+    assert!(invalid.is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_merge_invalid_options_names_field() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let red_png = create_test_png(10, 10, 255, 0, 0);
+    let images = Array::new();
+    images.push(&Uint8Array::from(red_png.as_slice()));
+
+    let options = Object::new();
+    Reflect::set(
+        &options,
+        &JsValue::from_str("direction"),
+        &JsValue::from_str("diagonal"),
+    )
+    .unwrap();
+    let err = merge_images_engine::merge_images(&images, &options).unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().unwrap(), "INVALID_OPTIONS");
+    let field = Reflect::get(&err, &JsValue::from_str("field")).unwrap();
+    assert_eq!(field.as_string().unwrap(), "direction");
+
+    // Misspelled fields are rejected instead of ignored.
+    let options = Object::new();
+    Reflect::set(
+        &options,
+        &JsValue::from_str("spacng"),
+        &JsValue::from_f64(4.0),
+    )
+    .unwrap();
+    let err = merge_images_engine::merge_images(&images, &options).unwrap_err();
+    let field = Reflect::get(&err, &JsValue::from_str("field")).unwrap();
+    assert_eq!(field.as_string().unwrap(), "spacng");
+}
+
+#[wasm_bindgen_test]
+fn test_message_templates_localize_error_message() {
+    use js_sys::{Array, Object, Reflect};
+
+    let templates = Object::new();
+    Reflect::set(
+        &templates,
+        &JsValue::from_str("NO_IMAGES"),
+        &JsValue::from_str("Keine Bilder"),
+    )
+    .unwrap();
+    let registry = Object::new();
+    Reflect::set(&registry, &JsValue::from_str("locale"), &"de-DE".into()).unwrap();
+    Reflect::set(&registry, &JsValue::from_str("templates"), &templates).unwrap();
+    merge_images_engine::set_message_templates(&registry.into()).unwrap();
+
+    let err = merge_images_engine::merge_images(&Array::new(), &JsValue::undefined()).unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    let message = Reflect::get(&err, &JsValue::from_str("message")).unwrap();
+    assert_eq!(code.as_string().unwrap(), "NO_IMAGES");
+    assert_eq!(message.as_string().unwrap(), "Keine Bilder");
+
+    merge_images_engine::set_message_templates(&JsValue::null()).unwrap();
+    let err = merge_images_engine::merge_images(&Array::new(), &JsValue::undefined()).unwrap_err();
+    let message = Reflect::get(&err, &JsValue::from_str("message")).unwrap();
+    assert_eq!(message.as_string().unwrap(), "No images provided");
+}
+
+#[wasm_bindgen_test]
+fn test_compute_blur_hashes_per_input() {
+    use js_sys::{Array, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(20, 10, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(20, 10, 0, 0, 255).as_slice(),
+    ));
+
+    let hashes = merge_images_engine::compute_blur_hashes(&images).unwrap();
+    assert_eq!(hashes.length(), 2);
+    let first = hashes.get(0).as_string().unwrap();
+    let second = hashes.get(1).as_string().unwrap();
+    assert!(!first.is_empty());
+    assert_ne!(first, second);
+}
+
+#[wasm_bindgen_test]
+fn test_paginate_image_returns_pages() {
+    use js_sys::{Reflect, Uint8Array};
+
+    let tall = Uint8Array::from(create_test_png(10, 250, 255, 0, 0).as_slice());
+    let pages = merge_images_engine::paginate_image(&tall, 100, None).unwrap();
+    assert_eq!(pages.length(), 3);
+    let field = |index: u32, name: &str| {
+        Reflect::get(&pages.get(index), &JsValue::from_str(name))
+            .unwrap()
+            .as_f64()
+            .unwrap()
+    };
+    assert_eq!(field(0, "height"), 100.0);
+    assert_eq!(field(2, "y"), 200.0);
+    assert_eq!(field(2, "height"), 50.0);
+
+    let error = merge_images_engine::paginate_image(&tall, 0, None).unwrap_err();
+    let code = Reflect::get(&error, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("INVALID_OPTIONS"));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_images_tiled_cuts_between_images() {
+    use js_sys::{Array, Reflect, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(10, 60, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(10, 60, 0, 0, 255).as_slice(),
+    ));
+    let tiled = merge_images_engine::merge_images_tiled(&images, &JsValue::UNDEFINED, 100).unwrap();
+    let get =
+        |target: &JsValue, name: &str| Reflect::get(target, &JsValue::from_str(name)).unwrap();
+    assert_eq!(get(&tiled, "height").as_f64(), Some(120.0));
+    let tiles = Array::from(&get(&tiled, "tiles"));
+    assert_eq!(tiles.length(), 2);
+    assert_eq!(get(&tiles.get(1), "y").as_f64(), Some(60.0));
+    assert!(get(&tiles.get(0), "bytes").is_instance_of::<Uint8Array>());
+}
+
+#[wasm_bindgen_test]
+fn test_merge_images_pdf_layouts() {
+    use js_sys::{Array, Reflect, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(10, 20, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(10, 20, 0, 0, 255).as_slice(),
+    ));
+    let page_count = |layout: Option<&str>| {
+        let pdf = merge_images_engine::merge_images_pdf(
+            &images,
+            &JsValue::UNDEFINED,
+            layout.map(str::to_string),
+        )
+        .unwrap();
+        Reflect::get(&pdf, &JsValue::from_str("pageCount"))
+            .unwrap()
+            .as_f64()
+    };
+    assert_eq!(page_count(None), Some(1.0));
+    assert_eq!(page_count(Some("perImage")), Some(2.0));
+
+    let error = merge_images_engine::merge_images_pdf(
+        &images,
+        &JsValue::UNDEFINED,
+        Some("booklet".to_string()),
+    )
+    .unwrap_err();
+    let code = Reflect::get(&error, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("INVALID_OPTIONS"));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_images_animation_frames() {
+    use js_sys::{Array, Reflect, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(10, 20, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(10, 20, 0, 0, 255).as_slice(),
+    ));
+    let get =
+        |target: &JsValue, name: &str| Reflect::get(target, &JsValue::from_str(name)).unwrap();
+    for format in [None, Some("gif")] {
+        let animation = merge_images_engine::merge_images_animation(
+            &images,
+            &JsValue::UNDEFINED,
+            format.map(str::to_string),
+            Some(100),
+        )
+        .unwrap();
+        assert_eq!(get(&animation, "frameCount").as_f64(), Some(2.0));
+        assert_eq!(get(&animation, "height").as_f64(), Some(20.0));
+    }
+
+    let error = merge_images_engine::merge_images_animation(
+        &images,
+        &JsValue::UNDEFINED,
+        Some("webm".to_string()),
+        None,
+    )
+    .unwrap_err();
+    assert_eq!(
+        get(&error, "code").as_string().as_deref(),
+        Some("INVALID_OPTIONS")
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_probe_image_reads_header() {
+    use js_sys::Reflect;
+
+    let info = merge_images_engine::probe_image(&create_test_png(12, 7, 255, 0, 0)).unwrap();
+    let get = |name: &str| Reflect::get(&info, &JsValue::from_str(name)).unwrap();
+    assert_eq!(get("format").as_string().as_deref(), Some("png"));
+    assert_eq!(get("width").as_f64(), Some(12.0));
+    assert_eq!(get("height").as_f64(), Some(7.0));
+    assert_eq!(get("orientation").as_f64(), Some(1.0));
+    assert_eq!(get("bitDepth").as_f64(), Some(8.0));
+
+    let error = merge_images_engine::probe_image(b"junk").unwrap_err();
+    let code = Reflect::get(&error, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("DECODE_FAILED"));
+}
+
+#[wasm_bindgen_test]
+async fn test_merge_images_async_matches_merge_images_ex() {
+    use js_sys::{Array, Reflect, Uint8Array};
+    use wasm_bindgen_futures::JsFuture;
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(10, 10, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(10, 20, 0, 0, 255).as_slice(),
+    ));
+    let expected = merge_images_engine::merge_images_ex(&images, &JsValue::undefined()).unwrap();
+    let promise = merge_images_engine::merge_images_async(&images, &JsValue::undefined());
+    let output = JsFuture::from(promise).await.unwrap();
+    let get = |obj: &JsValue, name: &str| Reflect::get(obj, &JsValue::from_str(name)).unwrap();
+    assert_eq!(get(&output, "height").as_f64(), Some(30.0));
+    assert_eq!(get(&output, "sha256"), get(&expected, "sha256"));
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(b"junk".as_slice()));
+    let promise = merge_images_engine::merge_images_async(&images, &JsValue::undefined());
+    let error = JsFuture::from(promise).await.unwrap_err();
+    assert_eq!(
+        get(&error, "code").as_string().as_deref(),
+        Some("DECODE_FAILED")
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_hash_image_pixels_matches_merged_pixels() {
+    use js_sys::{Array, Reflect, Uint8Array};
+
+    let png = create_test_png(12, 7, 255, 0, 0);
+    let hash = merge_images_engine::hash_image_pixels(&png).unwrap();
+    assert_eq!(hash.len(), 64);
+
+    // A merge of one image re-encodes the same pixels
+    let images = Array::new();
+    images.push(&Uint8Array::from(png.as_slice()));
+    let merged = merge_images_engine::merge_images(&images, &JsValue::undefined()).unwrap();
+    assert_eq!(
+        merge_images_engine::hash_image_pixels(&merged.to_vec()).unwrap(),
+        hash
+    );
+
+    let error = merge_images_engine::hash_image_pixels(b"junk").unwrap_err();
+    let code = Reflect::get(&error, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("DECODE_FAILED"));
+}
+
+#[wasm_bindgen_test]
+fn test_analyze_images_reports_plan() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(10, 10, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(20, 10, 0, 0, 255).as_slice(),
+    ));
+
+    let options = Object::new();
+    Reflect::set(
+        &options,
+        &JsValue::from_str("spacing"),
+        &JsValue::from_f64(4.0),
+    )
+    .unwrap();
+    let analysis = merge_images_engine::analyze_images(&images, &options.into()).unwrap();
+
+    let get = |obj: &JsValue, key: &str| Reflect::get(obj, &JsValue::from_str(key)).unwrap();
+    assert_eq!(get(&analysis, "width").as_f64(), Some(20.0));
+    assert_eq!(get(&analysis, "height").as_f64(), Some(34.0));
+    let first = Array::from(&get(&analysis, "images")).get(0);
+    assert_eq!(get(&first, "sourceWidth").as_f64(), Some(10.0));
+    assert_eq!(get(&first, "width").as_f64(), Some(20.0));
+    assert_eq!(Array::from(&get(&analysis, "pairs")).length(), 0);
+}
+
+#[wasm_bindgen_test]
+fn test_plan_then_execute() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(10, 10, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(10, 10, 0, 0, 255).as_slice(),
+    ));
+
+    let options = Object::new();
+    Reflect::set(
+        &options,
+        &JsValue::from_str("spacing"),
+        &JsValue::from_f64(5.0),
+    )
+    .unwrap();
+    let plan = merge_images_engine::plan_images(&images, &options.into()).unwrap();
+
+    let get = |obj: &JsValue, key: &str| Reflect::get(obj, &JsValue::from_str(key)).unwrap();
+    let second = Array::from(&get(&plan, "images")).get(1);
+    assert_eq!(get(&second, "y").as_f64(), Some(15.0));
+
+    let output = merge_images_engine::execute_plan(&images, &plan).unwrap();
+    assert_eq!(get(&output, "width").as_f64(), Some(10.0));
+    assert_eq!(get(&output, "height").as_f64(), Some(25.0));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_images_takes_input_handles() {
+    use js_sys::{Array, Uint8Array, WebAssembly};
+    use wasm_bindgen::JsCast;
+
+    let png = create_test_png(10, 10, 0, 255, 0);
+    let ptr = merge_images_engine::alloc_input(png.len() as u32);
+    let memory: WebAssembly::Memory = wasm_bindgen::memory().dyn_into().unwrap();
+    Uint8Array::new_with_byte_offset_and_length(&memory.buffer(), ptr, png.len() as u32)
+        .copy_from(&png);
+
+    let images = Array::new();
+    images.push(&JsValue::from_f64(ptr as f64));
+    images.push(&Uint8Array::from(
+        create_test_png(10, 10, 0, 0, 255).as_slice(),
+    ));
+    let result = merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).unwrap();
+    assert!(result.length() > 0);
+
+    // The merge consumed the handle
+    assert!(!merge_images_engine::free_input(ptr));
+    assert!(merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_invalid_options_leave_input_handles_live() {
+    use js_sys::{Array, Object, Reflect, Uint8Array, WebAssembly};
+    use wasm_bindgen::JsCast;
+
+    let png = create_test_png(10, 10, 0, 255, 0);
+    let ptr = merge_images_engine::alloc_input(png.len() as u32);
+    let memory: WebAssembly::Memory = wasm_bindgen::memory().dyn_into().unwrap();
+    Uint8Array::new_with_byte_offset_and_length(&memory.buffer(), ptr, png.len() as u32)
+        .copy_from(&png);
+    let images = Array::new();
+    images.push(&JsValue::from_f64(ptr as f64));
+
+    let options = Object::new();
+    Reflect::set(
+        &options,
+        &JsValue::from_str("direction"),
+        &JsValue::from_str("diagonal"),
+    )
+    .unwrap();
+    let err = merge_images_engine::merge_images(&images, &options).unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("INVALID_OPTIONS"));
+    assert!(merge_images_engine::merge_images_ex(&images, &options).is_err());
+
+    // The handle is still there for a corrected call
+    let output = merge_images_engine::merge_images_ex(&images, &JsValue::UNDEFINED).unwrap();
+    let width = Reflect::get(&output, &JsValue::from_str("width")).unwrap();
+    assert_eq!(width.as_f64(), Some(10.0));
+    assert!(!merge_images_engine::free_input(ptr));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_images_accepts_rgba_pixels() {
+    use js_sys::{Array, Object, Reflect, Uint8Array, Uint8ClampedArray};
+
+    let pixels: Vec<u8> = [0, 255, 0, 255].repeat(10 * 10);
+    let raw = Object::new();
+    Reflect::set(&raw, &JsValue::from_str("width"), &JsValue::from_f64(10.0)).unwrap();
+    Reflect::set(&raw, &JsValue::from_str("height"), &JsValue::from_f64(10.0)).unwrap();
+    Reflect::set(
+        &raw,
+        &JsValue::from_str("rgba"),
+        &Uint8ClampedArray::from(pixels.as_slice()),
+    )
+    .unwrap();
+
+    let from_raw = Array::new();
+    from_raw.push(&raw);
+    let encoded = Array::new();
+    encoded.push(&Uint8Array::from(
+        create_test_png(10, 10, 0, 255, 0).as_slice(),
+    ));
+
+    let a = merge_images_engine::merge_images(&from_raw, &JsValue::UNDEFINED).unwrap();
+    let b = merge_images_engine::merge_images(&encoded, &JsValue::UNDEFINED).unwrap();
+    assert_eq!(a.to_vec(), b.to_vec());
+}
+
+#[wasm_bindgen_test]
+fn test_merge_images_named_input_reports_file_name() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let named = Object::new();
+    Reflect::set(
+        &named,
+        &JsValue::from_str("data"),
+        &Uint8Array::from(&b"not an image"[..]),
+    )
+    .unwrap();
+    Reflect::set(
+        &named,
+        &JsValue::from_str("name"),
+        &JsValue::from_str("broken.png"),
+    )
+    .unwrap();
+    Reflect::set(
+        &named,
+        &JsValue::from_str("mimeType"),
+        &JsValue::from_str("image/png"),
+    )
+    .unwrap();
+
+    let images = Array::new();
+    images.push(&named);
+    let err = merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).unwrap_err();
+
+    let get = |key: &str| Reflect::get(&err, &JsValue::from_str(key)).unwrap();
+    assert_eq!(get("code").as_string().as_deref(), Some("DECODE_FAILED"));
+    assert_eq!(get("fileName").as_string().as_deref(), Some("broken.png"));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_images_names_option_reports_file_name() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(4, 4, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(&b"not an image"[..]));
+
+    let names = Array::new();
+    names.push(&JsValue::from_str("first.png"));
+    names.push(&JsValue::from_str("second.png"));
+    let options = Object::new();
+    Reflect::set(&options, &JsValue::from_str("names"), &names).unwrap();
+
+    let err = merge_images_engine::merge_images(&images, &options).unwrap_err();
+    let get = |key: &str| Reflect::get(&err, &JsValue::from_str(key)).unwrap();
+    assert_eq!(get("code").as_string().as_deref(), Some("DECODE_FAILED"));
+    assert_eq!(get("fileIndex").as_f64(), Some(1.0));
+    assert_eq!(get("fileName").as_string().as_deref(), Some("second.png"));
+}
+
+#[wasm_bindgen_test]
+fn test_merge_images_raw_returns_rgba_pixels() {
+    use js_sys::{Array, Reflect, Uint8Array, Uint8ClampedArray};
+    use wasm_bindgen::JsCast;
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(4, 2, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(4, 2, 0, 0, 255).as_slice(),
+    ));
+
+    let result = merge_images_engine::merge_images_raw(&images, &JsValue::undefined()).unwrap();
+
+    let get = |key: &str| Reflect::get(&result, &JsValue::from_str(key)).unwrap();
+    assert_eq!(get("width").as_f64(), Some(4.0));
+    assert_eq!(get("height").as_f64(), Some(4.0));
+    assert!(get("shared").as_bool().is_some());
+    let pixels = Uint8ClampedArray::new(&get("pixels")).to_vec();
+    assert_eq!(pixels.len(), 4 * 4 * 4);
+    assert_eq!(&pixels[..4], &[255, 0, 0, 255]);
+    assert_eq!(&pixels[pixels.len() - 4..], &[0, 0, 255, 255]);
+
+    // The pixels are a view of the canvas in wasm memory, freed once
+    let ptr = get("ptr").as_f64().unwrap() as u32;
+    let view: Uint8ClampedArray = get("pixels").dyn_into().unwrap();
+    assert_eq!(view.byte_offset(), ptr);
+    assert!(merge_images_engine::free_raw_output(ptr));
+    assert!(!merge_images_engine::free_raw_output(ptr));
+}
+
+#[wasm_bindgen_test]
+fn test_input_frame_must_be_an_index() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let input = Object::new();
+    let png = Uint8Array::from(create_test_png(10, 10, 255, 0, 0).as_slice());
+    Reflect::set(&input, &JsValue::from_str("data"), &png).unwrap();
+    let images = Array::new();
+    images.push(&input);
+
+    // A still image has frame 0 only
+    Reflect::set(&input, &JsValue::from_str("frame"), &JsValue::from_f64(0.0)).unwrap();
+    assert!(merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).is_ok());
+    Reflect::set(&input, &JsValue::from_str("frame"), &JsValue::from_f64(1.0)).unwrap();
+    let err = merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("DECODE_FAILED"));
+
+    Reflect::set(
+        &input,
+        &JsValue::from_str("frame"),
+        &JsValue::from_f64(-1.0),
+    )
+    .unwrap();
+    let err = merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("INVALID_INPUT"));
+}
+
+#[wasm_bindgen_test]
+fn test_input_page_must_be_an_index() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let input = Object::new();
+    let png = Uint8Array::from(create_test_png(10, 10, 255, 0, 0).as_slice());
+    Reflect::set(&input, &JsValue::from_str("data"), &png).unwrap();
+    let images = Array::new();
+    images.push(&input);
+
+    // Only a TIFF has pages past the first
+    Reflect::set(&input, &JsValue::from_str("page"), &JsValue::from_f64(0.0)).unwrap();
+    assert!(merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).is_ok());
+    Reflect::set(&input, &JsValue::from_str("page"), &JsValue::from_f64(2.0)).unwrap();
+    let err = merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("DECODE_FAILED"));
+
+    Reflect::set(&input, &JsValue::from_str("page"), &JsValue::from_f64(0.5)).unwrap();
+    let err = merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("INVALID_INPUT"));
+}
+
+#[wasm_bindgen_test]
+fn test_input_icon_size_needs_an_ico() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let input = Object::new();
+    let png = Uint8Array::from(create_test_png(10, 10, 255, 0, 0).as_slice());
+    Reflect::set(&input, &JsValue::from_str("data"), &png).unwrap();
+    let images = Array::new();
+    images.push(&input);
+
+    Reflect::set(
+        &input,
+        &JsValue::from_str("iconSize"),
+        &JsValue::from_f64(10.0),
+    )
+    .unwrap();
+    let err = merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("DECODE_FAILED"));
+
+    Reflect::set(
+        &input,
+        &JsValue::from_str("iconSize"),
+        &JsValue::from_str("large"),
+    )
+    .unwrap();
+    let err = merge_images_engine::merge_images(&images, &JsValue::UNDEFINED).unwrap_err();
+    let code = Reflect::get(&err, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().as_deref(), Some("INVALID_INPUT"));
+}
+
+#[wasm_bindgen_test]
+fn test_input_too_large_error_names_the_input() {
+    use js_sys::{Array, Object, Reflect, Uint8Array};
+
+    let images = Array::new();
+    images.push(&Uint8Array::from(
+        create_test_png(10, 10, 255, 0, 0).as_slice(),
+    ));
+    images.push(&Uint8Array::from(
+        create_test_png(40, 10, 0, 0, 255).as_slice(),
+    ));
+    let options = Object::new();
+    Reflect::set(
+        &options,
+        &JsValue::from_str("maxInputDimension"),
+        &JsValue::from_f64(20.0),
+    )
+    .unwrap();
+
+    let err = merge_images_engine::merge_images(&images, &options).unwrap_err();
+    let get = |name: &str| Reflect::get(&err, &JsValue::from_str(name)).unwrap();
+    assert_eq!(get("code").as_string().as_deref(), Some("INPUT_TOO_LARGE"));
+    assert_eq!(get("fileIndex").as_f64(), Some(1.0));
+    assert_eq!(get("width").as_f64(), Some(40.0));
+    assert_eq!(get("maxDimension").as_f64(), Some(20.0));
+}